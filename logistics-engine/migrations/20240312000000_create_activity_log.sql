@@ -0,0 +1,17 @@
+-- Audit trail behind the dashboard activity feed. Rows are written by the
+-- services as changes happen and never updated.
+CREATE TABLE IF NOT EXISTS activity_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    entity_type VARCHAR(50) NOT NULL,
+    entity_id UUID,
+    action VARCHAR(100) NOT NULL,
+    severity VARCHAR(20) NOT NULL DEFAULT 'info',
+    message TEXT NOT NULL,
+    actor VARCHAR(255),
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_activity_log_created_at ON activity_log(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_activity_log_entity ON activity_log(entity_type, entity_id);
+CREATE INDEX IF NOT EXISTS idx_activity_log_severity ON activity_log(severity);
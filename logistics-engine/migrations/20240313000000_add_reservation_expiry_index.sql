@@ -0,0 +1,4 @@
+-- Lets the reservation sweeper find expired pending reservations without
+-- scanning the whole table
+CREATE INDEX IF NOT EXISTS idx_inventory_reservations_status_expires_at
+    ON inventory_reservations(status, expires_at);
@@ -0,0 +1,27 @@
+-- Stock is accounted per warehouse in inventory_levels: reservations move units
+-- from available to reserved, committing an order takes them off hand.
+ALTER TABLE IF EXISTS inventory_reservations
+ADD COLUMN IF NOT EXISTS warehouse_id UUID REFERENCES warehouses(id);
+
+-- Seed a level for every item in its home warehouse
+INSERT INTO inventory_levels (item_id, warehouse_id, quantity, reserved, available, last_updated)
+SELECT id, warehouse_id, quantity, 0, quantity, NOW()
+FROM inventory_items
+ON CONFLICT (item_id, warehouse_id) DO NOTHING;
+
+-- Units held by reservations that are still pending count as reserved
+UPDATE inventory_levels l
+SET reserved = pending.quantity,
+    available = l.quantity - pending.quantity,
+    last_updated = NOW()
+FROM (
+    SELECT r.product_id, i.warehouse_id, SUM(r.quantity)::INTEGER AS quantity
+    FROM inventory_reservations r
+    JOIN inventory_items i ON i.id = r.product_id
+    WHERE r.status = 'pending'
+    GROUP BY r.product_id, i.warehouse_id
+) pending
+WHERE l.item_id = pending.product_id AND l.warehouse_id = pending.warehouse_id;
+
+CREATE INDEX IF NOT EXISTS idx_inventory_levels_warehouse_id ON inventory_levels(warehouse_id);
+CREATE INDEX IF NOT EXISTS idx_inventory_reservations_product_id ON inventory_reservations(product_id);
@@ -0,0 +1,4 @@
+-- The gRPC inventory service reserves stock for orders owned by other
+-- services, so reservations can no longer require a local order row
+ALTER TABLE inventory_reservations
+    DROP CONSTRAINT IF EXISTS inventory_reservations_order_id_fkey;
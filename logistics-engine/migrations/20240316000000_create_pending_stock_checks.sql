@@ -0,0 +1,9 @@
+-- Orders accepted while the inventory service was unreachable; their stock is
+-- checked when they move to processing
+CREATE TABLE IF NOT EXISTS pending_stock_checks (
+    order_id UUID PRIMARY KEY REFERENCES orders(id) ON DELETE CASCADE,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_pending_stock_checks_created_at ON pending_stock_checks(created_at);
@@ -0,0 +1,38 @@
+-- Cancellation workflow state, so a cancellation that failed halfway can be
+-- resumed from the first step that did not finish
+CREATE TABLE IF NOT EXISTS order_cancellations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL UNIQUE REFERENCES orders(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'in_progress',
+    reason TEXT NOT NULL,
+    requested_by VARCHAR(255),
+    attempts INTEGER NOT NULL DEFAULT 1,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_order_cancellations_status ON order_cancellations(status);
+
+CREATE TABLE IF NOT EXISTS order_cancellation_steps (
+    cancellation_id UUID NOT NULL REFERENCES order_cancellations(id) ON DELETE CASCADE,
+    step VARCHAR(50) NOT NULL,
+    position INTEGER NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    detail JSONB,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (cancellation_id, step)
+);
+
+-- Reservations the inventory service holds for an order, so they can be
+-- released when the order is cancelled. The stock check runs before the order
+-- exists, so the inventory service knows it under a different order ID.
+CREATE TABLE IF NOT EXISTS order_remote_reservations (
+    order_id UUID PRIMARY KEY REFERENCES orders(id) ON DELETE CASCADE,
+    reservation_id VARCHAR(255) NOT NULL,
+    remote_order_id VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
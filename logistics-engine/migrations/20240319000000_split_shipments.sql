@@ -0,0 +1,29 @@
+-- An order can be fulfilled by several shipments, e.g. when its items come
+-- from different warehouses or a line is backordered
+ALTER TABLE shipping_info DROP CONSTRAINT IF EXISTS shipping_info_order_id_key;
+
+CREATE INDEX IF NOT EXISTS idx_shipping_info_order_id ON shipping_info(order_id);
+
+-- The order lines each shipment carries and how many units of each
+CREATE TABLE IF NOT EXISTS shipment_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    shipping_id UUID NOT NULL REFERENCES shipping_info(id) ON DELETE CASCADE,
+    order_item_id UUID NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (shipping_id, order_item_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_shipment_items_order_item_id ON shipment_items(order_item_id);
+
+-- Existing shipments carry their whole order
+INSERT INTO shipment_items (shipping_id, order_item_id, quantity)
+SELECT s.id, oi.id, oi.quantity
+FROM shipping_info s
+JOIN order_items oi ON oi.order_id = s.order_id
+WHERE oi.quantity > 0
+ON CONFLICT (shipping_id, order_item_id) DO NOTHING;
+
+-- Order statuses derived from the progress of its shipments
+ALTER TYPE order_status ADD VALUE IF NOT EXISTS 'partially_shipped' AFTER 'processing';
+ALTER TYPE order_status ADD VALUE IF NOT EXISTS 'partially_delivered' AFTER 'shipped';
@@ -0,0 +1,20 @@
+-- Order lines that could not be fully reserved when the order was placed. The
+-- shortfall is allocated oldest order first as stock comes in.
+CREATE TABLE IF NOT EXISTS backorders (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    order_item_id UUID NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES inventory_items(id),
+    sku VARCHAR(100) NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    allocated INTEGER NOT NULL DEFAULT 0 CHECK (allocated >= 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    expected_restock_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    fulfilled_at TIMESTAMPTZ,
+    CHECK (allocated <= quantity)
+);
+
+CREATE INDEX IF NOT EXISTS idx_backorders_product_status ON backorders(product_id, status);
+CREATE INDEX IF NOT EXISTS idx_backorders_order_id ON backorders(order_id);
@@ -0,0 +1,41 @@
+-- Return authorizations (RMAs) against delivered orders and the order lines
+-- each one covers
+CREATE TABLE IF NOT EXISTS return_authorizations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    rma_number VARCHAR(50) NOT NULL UNIQUE,
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'authorized',
+    reason_code VARCHAR(50) NOT NULL,
+    notes TEXT,
+    requested_by VARCHAR(255),
+    refund_amount DECIMAL(10, 2),
+    payment_id UUID REFERENCES payment_info(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    received_at TIMESTAMPTZ,
+    refunded_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_return_authorizations_order_id ON return_authorizations(order_id);
+CREATE INDEX IF NOT EXISTS idx_return_authorizations_status ON return_authorizations(status);
+
+CREATE TABLE IF NOT EXISTS return_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    return_id UUID NOT NULL REFERENCES return_authorizations(id) ON DELETE CASCADE,
+    order_item_id UUID NOT NULL REFERENCES order_items(id) ON DELETE CASCADE,
+    product_id UUID NOT NULL REFERENCES inventory_items(id),
+    sku VARCHAR(100) NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    received_quantity INTEGER CHECK (received_quantity >= 0 AND received_quantity <= quantity),
+    unit_price DECIMAL(10, 2) NOT NULL,
+    reason_code VARCHAR(50) NOT NULL,
+    condition VARCHAR(20),
+    disposition VARCHAR(20),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (return_id, order_item_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_return_items_order_item_id ON return_items(order_item_id);
+
+-- Running total of what has been refunded, so a payment can be refunded in parts
+ALTER TABLE payment_info ADD COLUMN IF NOT EXISTS refunded_amount DECIMAL(10, 2) NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Which warehouses the allocation engine picked for each order line, and why.
+-- Warehouses carry the per-unit cost the lowest-cost strategy compares.
+ALTER TABLE warehouses
+ADD COLUMN IF NOT EXISTS fulfillment_cost DECIMAL(10, 2) NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS order_allocations (
+    order_id UUID PRIMARY KEY REFERENCES orders(id) ON DELETE CASCADE,
+    strategy VARCHAR(50) NOT NULL,
+    split BOOLEAN NOT NULL DEFAULT false,
+    explanation TEXT NOT NULL,
+    lines JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,18 @@
+-- Warehouses carry coordinates, and the postal codes we ship to are geocoded
+-- offline from a supplied CSV (scripts/load_postal_codes.sh) so distances can
+-- be computed without calling out to a geocoding service.
+ALTER TABLE warehouses
+ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION,
+ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
+
+CREATE TABLE IF NOT EXISTS postal_code_locations (
+    country VARCHAR(100) NOT NULL,
+    postal_code VARCHAR(20) NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL CHECK (latitude BETWEEN -90 AND 90),
+    longitude DOUBLE PRECISION NOT NULL CHECK (longitude BETWEEN -180 AND 180),
+    city VARCHAR(100),
+    state VARCHAR(100),
+    PRIMARY KEY (country, postal_code)
+);
+
+CREATE INDEX IF NOT EXISTS idx_postal_code_locations_postal_code ON postal_code_locations(UPPER(postal_code));
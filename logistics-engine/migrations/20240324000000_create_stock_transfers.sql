@@ -0,0 +1,36 @@
+-- Transfer orders that move stock from one warehouse to another, and the
+-- items each one carries
+CREATE TABLE IF NOT EXISTS stock_transfers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    transfer_number VARCHAR(50) NOT NULL UNIQUE,
+    source_warehouse_id UUID NOT NULL REFERENCES warehouses(id),
+    destination_warehouse_id UUID NOT NULL REFERENCES warehouses(id),
+    status VARCHAR(20) NOT NULL DEFAULT 'requested',
+    notes TEXT,
+    requested_by VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    dispatched_at TIMESTAMPTZ,
+    received_at TIMESTAMPTZ,
+    CHECK (source_warehouse_id <> destination_warehouse_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stock_transfers_status ON stock_transfers(status);
+CREATE INDEX IF NOT EXISTS idx_stock_transfers_source ON stock_transfers(source_warehouse_id);
+CREATE INDEX IF NOT EXISTS idx_stock_transfers_destination ON stock_transfers(destination_warehouse_id);
+
+CREATE TABLE IF NOT EXISTS stock_transfer_lines (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    transfer_id UUID NOT NULL REFERENCES stock_transfers(id) ON DELETE CASCADE,
+    item_id UUID NOT NULL REFERENCES inventory_items(id),
+    sku VARCHAR(100) NOT NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    -- Units that left the source; the rest of the line is in transit until received
+    shipped_quantity INTEGER CHECK (shipped_quantity >= 0),
+    received_quantity INTEGER CHECK (received_quantity >= 0),
+    discrepancy_reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (transfer_id, item_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_stock_transfer_lines_item_id ON stock_transfer_lines(item_id);
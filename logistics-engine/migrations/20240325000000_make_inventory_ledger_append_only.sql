@@ -0,0 +1,82 @@
+-- Turns inventory_transactions into an append-only ledger: every entry keeps
+-- the item's running balance and entries can no longer be changed or removed
+ALTER TABLE inventory_transactions
+    ADD COLUMN IF NOT EXISTS entry_number BIGSERIAL,
+    ADD COLUMN IF NOT EXISTS balance_after INTEGER,
+    ADD COLUMN IF NOT EXISTS "timestamp" TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+-- Postings record whoever made the change, which is not always a UUID
+ALTER TABLE inventory_transactions
+    ALTER COLUMN user_id TYPE VARCHAR(255) USING user_id::text;
+
+-- Stock on hand before the ledger was never fully posted: items were created
+-- and adjusted without entries, and legacy allocate and release rows took units
+-- off hand and put them back, where they now only move units between available
+-- and reserved. One opening entry per item and warehouse posts what the
+-- existing entries leave unaccounted for, so each item's entries sum to its
+-- on-hand quantity. Running this again posts nothing once they agree.
+WITH stock AS (
+    -- Units in the item's other warehouses, and the rest of its on-hand
+    -- quantity in its home warehouse
+    SELECT l.item_id, l.warehouse_id, l.quantity
+    FROM inventory_levels l
+    JOIN inventory_items i ON i.id = l.item_id
+    WHERE l.warehouse_id <> i.warehouse_id
+    UNION ALL
+    SELECT i.id, i.warehouse_id,
+           (COALESCE(i.quantity, 0) - COALESCE((
+                SELECT SUM(o.quantity) FROM inventory_levels o
+                WHERE o.item_id = i.id AND o.warehouse_id <> i.warehouse_id
+            ), 0))::INTEGER
+    FROM inventory_items i
+),
+posted AS (
+    SELECT item_id, warehouse_id,
+           SUM(CASE
+               WHEN LOWER("type") IN ('add', 'transfer_in') THEN quantity
+               WHEN LOWER("type") IN ('remove', 'transfer_out') THEN -quantity
+               ELSE 0
+           END)::INTEGER AS quantity
+    FROM inventory_transactions
+    GROUP BY item_id, warehouse_id
+),
+opening AS (
+    SELECT COALESCE(s.item_id, p.item_id) AS item_id,
+           COALESCE(s.warehouse_id, p.warehouse_id) AS warehouse_id,
+           COALESCE(s.quantity, 0) - COALESCE(p.quantity, 0) AS quantity,
+           SUM(COALESCE(p.quantity, 0)) OVER (PARTITION BY COALESCE(s.item_id, p.item_id))
+               AS posted_balance
+    FROM stock s
+    FULL JOIN posted p ON p.item_id = s.item_id AND p.warehouse_id = s.warehouse_id
+)
+INSERT INTO inventory_transactions
+    (id, item_id, warehouse_id, quantity, "type", reference, user_id, balance_after, "timestamp")
+SELECT gen_random_uuid(), o.item_id, o.warehouse_id, ABS(o.quantity),
+       CASE WHEN o.quantity > 0 THEN 'add' ELSE 'remove' END,
+       'opening-balance', 'ledger-migration',
+       (o.posted_balance + SUM(o.quantity) OVER (
+            PARTITION BY o.item_id ORDER BY o.warehouse_id
+       ))::INTEGER,
+       NOW()
+FROM opening o
+WHERE o.quantity <> 0
+ORDER BY o.item_id, o.warehouse_id;
+
+CREATE INDEX IF NOT EXISTS idx_inventory_transactions_item_entry
+    ON inventory_transactions(item_id, entry_number);
+
+DROP TRIGGER IF EXISTS update_inventory_transactions_updated_at ON inventory_transactions;
+
+CREATE OR REPLACE FUNCTION reject_inventory_transaction_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'inventory_transactions is append-only; post a correcting entry instead';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS inventory_transactions_append_only ON inventory_transactions;
+
+CREATE TRIGGER inventory_transactions_append_only
+    BEFORE UPDATE OR DELETE ON inventory_transactions
+    FOR EACH ROW
+EXECUTE FUNCTION reject_inventory_transaction_change();
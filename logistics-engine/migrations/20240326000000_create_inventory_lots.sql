@@ -0,0 +1,32 @@
+-- Lots (batches) of an item held in a warehouse, with their manufacture and
+-- expiry dates, and the lots each reservation draws its units from
+CREATE TABLE IF NOT EXISTS inventory_lots (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    item_id UUID NOT NULL REFERENCES inventory_items(id),
+    warehouse_id UUID NOT NULL REFERENCES warehouses(id),
+    lot_number VARCHAR(100) NOT NULL,
+    manufactured_at DATE,
+    expires_at DATE,
+    quantity INTEGER NOT NULL DEFAULT 0 CHECK (quantity >= 0),
+    reserved INTEGER NOT NULL DEFAULT 0 CHECK (reserved >= 0),
+    status VARCHAR(20) NOT NULL DEFAULT 'active',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (reserved <= quantity),
+    UNIQUE (item_id, warehouse_id, lot_number)
+);
+
+-- Lots are picked first-expired-first-out within an item's warehouse
+CREATE INDEX IF NOT EXISTS idx_inventory_lots_fefo
+    ON inventory_lots(item_id, warehouse_id, expires_at NULLS LAST);
+CREATE INDEX IF NOT EXISTS idx_inventory_lots_expiry
+    ON inventory_lots(expires_at) WHERE status = 'active';
+
+CREATE TABLE IF NOT EXISTS reservation_lots (
+    reservation_id UUID NOT NULL REFERENCES inventory_reservations(id) ON DELETE CASCADE,
+    lot_id UUID NOT NULL REFERENCES inventory_lots(id),
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (reservation_id, lot_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_reservation_lots_lot_id ON reservation_lots(lot_id);
@@ -0,0 +1,35 @@
+-- Serial numbers of units of serial-tracked items (`"serial_tracked": true` in
+-- the item's attributes) and every step each one went through
+CREATE TABLE IF NOT EXISTS serial_numbers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    item_id UUID NOT NULL REFERENCES inventory_items(id),
+    serial_number VARCHAR(100) NOT NULL,
+    warehouse_id UUID REFERENCES warehouses(id),
+    status VARCHAR(20) NOT NULL DEFAULT 'in_stock',
+    order_item_id UUID REFERENCES order_items(id) ON DELETE SET NULL,
+    shipping_id UUID REFERENCES shipping_info(id) ON DELETE SET NULL,
+    return_id UUID REFERENCES return_authorizations(id) ON DELETE SET NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (item_id, serial_number)
+);
+
+CREATE INDEX IF NOT EXISTS idx_serial_numbers_serial_number ON serial_numbers(serial_number);
+CREATE INDEX IF NOT EXISTS idx_serial_numbers_order_item_id ON serial_numbers(order_item_id);
+CREATE INDEX IF NOT EXISTS idx_serial_numbers_shipping_id ON serial_numbers(shipping_id);
+
+CREATE TABLE IF NOT EXISTS serial_number_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    serial_id UUID NOT NULL REFERENCES serial_numbers(id) ON DELETE CASCADE,
+    event VARCHAR(20) NOT NULL,
+    reference VARCHAR(255),
+    -- Where the serial was when the event happened
+    warehouse_id UUID,
+    order_item_id UUID,
+    shipping_id UUID,
+    return_id UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_serial_number_events_serial_id
+    ON serial_number_events(serial_id, created_at);
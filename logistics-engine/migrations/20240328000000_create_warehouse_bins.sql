@@ -0,0 +1,63 @@
+-- Zones, aisles and bins inside each warehouse, the units of each item stored
+-- in each bin and every putaway, move and pick between them
+CREATE TABLE IF NOT EXISTS warehouse_bins (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    warehouse_id UUID NOT NULL REFERENCES warehouses(id) ON DELETE CASCADE,
+    zone VARCHAR(50) NOT NULL,
+    aisle VARCHAR(50) NOT NULL,
+    bin VARCHAR(50) NOT NULL,
+    code VARCHAR(160) NOT NULL,
+    -- Overrides the bin's place on the walking path
+    pick_sequence INTEGER,
+    -- Units the bin holds at most; NULL for no limit
+    capacity INTEGER CHECK (capacity > 0),
+    active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (warehouse_id, code)
+);
+
+-- The order a picker walks a warehouse's bins in: bins with a pick sequence
+-- first, then zone by zone, up one aisle and down the next
+CREATE OR REPLACE VIEW warehouse_bin_walk AS
+SELECT id, warehouse_id,
+       ROW_NUMBER() OVER (
+           PARTITION BY warehouse_id
+           ORDER BY pick_sequence NULLS LAST, zone, aisle,
+                    CASE WHEN aisle_rank % 2 = 1 THEN bin END,
+                    CASE WHEN aisle_rank % 2 = 0 THEN bin END DESC
+       ) AS walk_order
+FROM (
+    SELECT id, warehouse_id, zone, aisle, bin, pick_sequence,
+           DENSE_RANK() OVER (PARTITION BY warehouse_id, zone ORDER BY aisle) AS aisle_rank
+    FROM warehouse_bins
+) ranked;
+
+CREATE TABLE IF NOT EXISTS bin_stock (
+    bin_id UUID NOT NULL REFERENCES warehouse_bins(id) ON DELETE CASCADE,
+    item_id UUID NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (bin_id, item_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_bin_stock_item_id ON bin_stock(item_id);
+
+CREATE TABLE IF NOT EXISTS bin_movements (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    warehouse_id UUID NOT NULL REFERENCES warehouses(id) ON DELETE CASCADE,
+    item_id UUID NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+    -- NULL on a putaway, which comes from the warehouse's unbinned stock
+    from_bin_id UUID REFERENCES warehouse_bins(id) ON DELETE SET NULL,
+    -- NULL on a pick, which takes the units out of the warehouse
+    to_bin_id UUID REFERENCES warehouse_bins(id) ON DELETE SET NULL,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    movement_type VARCHAR(20) NOT NULL,
+    reference VARCHAR(255),
+    user_id VARCHAR(255),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_bin_movements_warehouse_id ON bin_movements(warehouse_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_bin_movements_from_bin_id ON bin_movements(from_bin_id);
+CREATE INDEX IF NOT EXISTS idx_bin_movements_to_bin_id ON bin_movements(to_bin_id);
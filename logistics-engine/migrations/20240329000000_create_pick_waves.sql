@@ -0,0 +1,63 @@
+-- Wave picking: Processing orders batched per warehouse into waves, each wave's
+-- pick list aggregated by bin and item, and the pick confirmed line by line and
+-- the packing order by order
+ALTER TABLE orders ADD COLUMN IF NOT EXISTS priority INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS pick_waves (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    warehouse_id UUID NOT NULL REFERENCES warehouses(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'picking',
+    -- What the orders were batched by; NULL when not filtered on
+    carrier VARCHAR(100),
+    zone VARCHAR(50),
+    min_priority INTEGER,
+    -- When the carrier collects the wave
+    cutoff_at TIMESTAMPTZ,
+    created_by VARCHAR(255),
+    completed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_pick_waves_warehouse_id ON pick_waves(warehouse_id, status);
+
+CREATE TABLE IF NOT EXISTS pick_wave_orders (
+    wave_id UUID NOT NULL REFERENCES pick_waves(id) ON DELETE CASCADE,
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    -- Orders earlier in the wave get picked units first when a line is short
+    sequence INTEGER NOT NULL,
+    packed_at TIMESTAMPTZ,
+    packed_by VARCHAR(255),
+    PRIMARY KEY (wave_id, order_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_pick_wave_orders_order_id ON pick_wave_orders(order_id);
+
+CREATE TABLE IF NOT EXISTS pick_wave_lines (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    wave_id UUID NOT NULL REFERENCES pick_waves(id) ON DELETE CASCADE,
+    -- Position on the pick list, in walking order
+    line_number INTEGER NOT NULL,
+    -- NULL for units that are not in any bin
+    bin_id UUID REFERENCES warehouse_bins(id) ON DELETE SET NULL,
+    bin_code VARCHAR(160),
+    item_id UUID NOT NULL REFERENCES inventory_items(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    -- NULL until the line is confirmed; less than quantity on a short pick
+    picked_quantity INTEGER CHECK (picked_quantity >= 0 AND picked_quantity <= quantity),
+    confirmed_at TIMESTAMPTZ,
+    confirmed_by VARCHAR(255),
+    UNIQUE (wave_id, line_number)
+);
+
+-- Which orders each line picks for
+CREATE TABLE IF NOT EXISTS pick_wave_line_orders (
+    line_id UUID NOT NULL REFERENCES pick_wave_lines(id) ON DELETE CASCADE,
+    order_id UUID NOT NULL REFERENCES orders(id) ON DELETE CASCADE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (line_id, order_id)
+);
+
+-- Waves look up what each order took out of the warehouse by its reference
+CREATE INDEX IF NOT EXISTS idx_bin_movements_reference ON bin_movements(reference);
+CREATE INDEX IF NOT EXISTS idx_inventory_transactions_reference ON inventory_transactions(reference);
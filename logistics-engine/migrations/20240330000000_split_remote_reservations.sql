@@ -0,0 +1,5 @@
+-- The inventory service reserves an order's stock in each warehouse the
+-- allocation plan draws on, so an order can hold several remote reservations
+ALTER TABLE order_remote_reservations DROP CONSTRAINT IF EXISTS order_remote_reservations_pkey;
+ALTER TABLE order_remote_reservations ADD PRIMARY KEY (order_id, remote_order_id);
+ALTER TABLE order_remote_reservations ADD COLUMN IF NOT EXISTS warehouse_id UUID;
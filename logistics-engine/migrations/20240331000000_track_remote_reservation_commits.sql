@@ -0,0 +1,2 @@
+-- Remote reservations are committed when their order moves to processing
+ALTER TABLE order_remote_reservations ADD COLUMN IF NOT EXISTS committed_at TIMESTAMPTZ;
@@ -0,0 +1,13 @@
+-- When each carrier collects from a warehouse every day. Waves are built for
+-- the carrier collecting next first.
+CREATE TABLE IF NOT EXISTS carrier_cutoffs (
+    warehouse_id UUID NOT NULL REFERENCES warehouses(id) ON DELETE CASCADE,
+    carrier VARCHAR(100) NOT NULL,
+    -- Time of day in UTC
+    cutoff_time TIME NOT NULL,
+    updated_by VARCHAR(255),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_carrier_cutoffs_carrier
+    ON carrier_cutoffs(warehouse_id, LOWER(carrier));
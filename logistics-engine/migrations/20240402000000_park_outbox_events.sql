@@ -0,0 +1,17 @@
+-- Events that keep failing to publish are parked after the relay's maximum
+-- number of attempts instead of being retried forever
+ALTER TABLE outbox_events
+    ADD COLUMN IF NOT EXISTS parked_at TIMESTAMPTZ;
+
+-- Events are ordered by created_at within their aggregate; NOW() would give
+-- every event written in one transaction the same timestamp
+ALTER TABLE outbox_events
+    ALTER COLUMN created_at SET DEFAULT clock_timestamp();
+
+DROP INDEX IF EXISTS idx_outbox_events_unpublished;
+CREATE INDEX IF NOT EXISTS idx_outbox_events_unpublished
+    ON outbox_events(created_at)
+    WHERE published_at IS NULL AND parked_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_outbox_events_parked
+    ON outbox_events(parked_at)
+    WHERE parked_at IS NOT NULL;
@@ -0,0 +1,10 @@
+-- The lots each transfer line's units were dispatched from, so the same lots
+-- can be recreated at the destination when the transfer is received
+CREATE TABLE IF NOT EXISTS stock_transfer_lots (
+    transfer_line_id UUID NOT NULL REFERENCES stock_transfer_lines(id) ON DELETE CASCADE,
+    lot_number VARCHAR(100) NOT NULL,
+    manufactured_at DATE,
+    expires_at DATE,
+    quantity INTEGER NOT NULL CHECK (quantity > 0),
+    PRIMARY KEY (transfer_line_id, lot_number)
+);
@@ -0,0 +1,8 @@
+-- Serialized units dispatched on a transfer line are in transit, in no
+-- warehouse, until the line is received at the destination
+ALTER TABLE serial_numbers
+ADD COLUMN IF NOT EXISTS transfer_line_id UUID REFERENCES stock_transfer_lines(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_serial_numbers_transfer_line_id ON serial_numbers(transfer_line_id);
+CREATE INDEX IF NOT EXISTS idx_serial_numbers_item_warehouse
+    ON serial_numbers(item_id, warehouse_id, status);
@@ -0,0 +1,63 @@
+#!/bin/bash
+
+# Load the offline postal code geocoding table from a CSV with the header
+#   postal_code,country,latitude,longitude,city,state
+# city and state may be empty. Rows replace existing ones for the same country
+# and postal code, and warehouses without coordinates are placed at their
+# postal code.
+
+CSV_FILE=$1
+if [ -z "$CSV_FILE" ] || [ ! -f "$CSV_FILE" ]; then
+    echo "Usage: $0 <postal_codes.csv>"
+    exit 1
+fi
+
+# Get database connection parameters from environment or use defaults
+DB_HOST=${DB_HOST:-localhost}
+DB_PORT=${DB_PORT:-5433}
+DB_NAME=${DB_NAME:-logistics_engine}
+DB_USER=${DB_USER:-logistics}
+
+psql -h $DB_HOST -p $DB_PORT -d $DB_NAME -U $DB_USER -v ON_ERROR_STOP=1 <<EOF
+BEGIN;
+
+CREATE TEMP TABLE postal_code_import (
+    postal_code TEXT,
+    country TEXT,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    city TEXT,
+    state TEXT
+) ON COMMIT DROP;
+
+\copy postal_code_import FROM '$CSV_FILE' WITH (FORMAT csv, HEADER true)
+
+INSERT INTO postal_code_locations (country, postal_code, latitude, longitude, city, state)
+SELECT DISTINCT ON (TRIM(country), TRIM(postal_code))
+    TRIM(country), TRIM(postal_code), latitude, longitude,
+    NULLIF(TRIM(city), ''), NULLIF(TRIM(state), '')
+FROM postal_code_import
+WHERE postal_code IS NOT NULL AND country IS NOT NULL
+  AND latitude IS NOT NULL AND longitude IS NOT NULL
+ON CONFLICT (country, postal_code) DO UPDATE
+SET latitude = EXCLUDED.latitude,
+    longitude = EXCLUDED.longitude,
+    city = EXCLUDED.city,
+    state = EXCLUDED.state;
+
+UPDATE warehouses w
+SET latitude = p.latitude, longitude = p.longitude, updated_at = NOW()
+FROM postal_code_locations p
+WHERE w.latitude IS NULL
+  AND UPPER(p.postal_code) = UPPER(w.postal_code)
+  AND UPPER(p.country) = UPPER(w.country);
+
+COMMIT;
+EOF
+
+if [ $? -eq 0 ]; then
+    echo "Postal codes loaded successfully!"
+else
+    echo "Error: Loading postal codes failed!"
+    exit 1
+fi
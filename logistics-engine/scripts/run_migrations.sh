@@ -10,6 +10,33 @@ psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/2024030
 psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240306000000_fix_schema.sql
 psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240306000000_fix_schema_2.sql
 
+# Feature migrations
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240310000000_create_outbox_events.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240312000000_create_activity_log.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240313000000_add_reservation_expiry_index.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240314000000_track_inventory_levels.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240315000000_allow_external_order_reservations.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240316000000_create_pending_stock_checks.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240317000000_create_order_cancellations.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240318000000_create_idempotency_keys.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240319000000_split_shipments.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240320000000_create_backorders.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240321000000_create_return_authorizations.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240322000000_create_order_allocations.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240323000000_add_warehouse_geolocation.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240324000000_create_stock_transfers.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240325000000_make_inventory_ledger_append_only.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240326000000_create_inventory_lots.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240327000000_create_serial_numbers.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240328000000_create_warehouse_bins.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240329000000_create_pick_waves.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240330000000_split_remote_reservations.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240331000000_track_remote_reservation_commits.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240401000000_create_carrier_cutoffs.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240402000000_park_outbox_events.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240403000000_track_transfer_lots.sql
+psql -U logistics -h localhost -p 5433 -d logistics_engine -f migrations/20240404000000_track_serial_transfers.sql
+
 # Check if migrations were successful
 if [ $? -eq 0 ]; then
     echo "Migrations completed successfully!"
@@ -0,0 +1,144 @@
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
+use crate::api::SharedState;
+use crate::errors::LogisticsError;
+use crate::models::bin::{CreateBinDto, MoveBinStockDto, PutawayDto, UpdateBinDto};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct BinListParams {
+    /// Only bins holding the item
+    pub item_id: Option<String>,
+}
+
+pub async fn list_bins(
+    Path(id): Path<String>,
+    Query(params): Query<BinListParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let item_id = params.item_id.as_deref().map(parse_uuid).transpose()?;
+    let bins = state.bin_service.get_bins(id, item_id).await?;
+
+    Ok((StatusCode::OK, success(bins)))
+}
+
+pub async fn get_bin(
+    Path((id, bin_id)): Path<(String, String)>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let bin_id = parse_uuid(&bin_id)?;
+    let bin = state.bin_service.get_bin(id, bin_id).await?;
+
+    Ok((StatusCode::OK, success(bin)))
+}
+
+pub async fn create_bin(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    Json(payload): Json<CreateBinDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let bin = state.bin_service.create_bin(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(bin)))
+}
+
+pub async fn update_bin(
+    Path((id, bin_id)): Path<(String, String)>,
+    State(state): State<SharedState>,
+    Json(payload): Json<UpdateBinDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let bin_id = parse_uuid(&bin_id)?;
+    let bin = state.bin_service.update_bin(id, bin_id, payload).await?;
+
+    Ok((StatusCode::OK, success(bin)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutawayParams {
+    pub item_id: String,
+    /// Defaults to the item's unbinned units in the warehouse
+    pub quantity: Option<i32>,
+}
+
+pub async fn suggest_putaway(
+    Path(id): Path<String>,
+    Query(params): Query<PutawayParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let item_id = parse_uuid(&params.item_id)?;
+    let plan = state
+        .bin_service
+        .suggest_putaway(id, item_id, params.quantity)
+        .await?;
+
+    Ok((StatusCode::OK, success(plan)))
+}
+
+pub async fn put_away(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<PutawayDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
+    let movement = state.bin_service.put_away(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(movement)))
+}
+
+pub async fn move_bin_stock(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<MoveBinStockDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
+    let movement = state.bin_service.move_stock(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(movement)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BinMovementParams {
+    pub bin_id: Option<String>,
+    pub item_id: Option<String>,
+}
+
+pub async fn list_bin_movements(
+    Path(id): Path<String>,
+    pagination: Query<PaginationParams>,
+    Query(params): Query<BinMovementParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let bin_id = params.bin_id.as_deref().map(parse_uuid).transpose()?;
+    let item_id = params.item_id.as_deref().map(parse_uuid).transpose()?;
+    let movements = state
+        .bin_service
+        .get_movements(id, bin_id, item_id, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(movements)))
+}
+
+pub async fn get_pick_list(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let pick_list = state.bin_service.get_pick_list(id).await?;
+
+    Ok((StatusCode::OK, success(pick_list)))
+}
@@ -6,10 +6,19 @@ use axum::{
 use chrono::{DateTime, Utc};
 use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::str::FromStr;
 use uuid::Uuid;
 
-use crate::{api::SharedState, errors::LogisticsError, models::entities::order::OrderStatus};
+use crate::{
+    api::SharedState,
+    errors::LogisticsError,
+    models::{
+        activity::{ActivityEntry, ActivityFilter, ActivitySeverity},
+        entities::order::OrderStatus,
+    },
+    services::inventory_service::is_low_stock,
+};
 
 #[derive(Serialize)]
 pub struct InventoryOverview {
@@ -17,6 +26,8 @@ pub struct InventoryOverview {
     low_stock_count: i64,
     overstock_count: i64,
     total_quantity: i64,
+    total_reserved: i64,
+    total_available: i64,
     total_value: f64,
 }
 
@@ -40,29 +51,52 @@ pub struct DashboardOverview {
 
 #[derive(Deserialize)]
 pub struct ActivityQueryParams {
-    limit: Option<usize>,
+    limit: Option<u32>,
+    /// Cursor returned as `next_cursor` by the previous page
+    cursor: Option<String>,
+    entity_type: Option<String>,
+    severity: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
 }
 
 #[derive(Serialize)]
 pub struct ActivityItem {
     id: String,
     activity_type: String,
+    action: String,
     message: String,
     timestamp: DateTime<Utc>,
     severity: String,
     entity_id: Option<String>,
     entity_type: Option<String>,
+    actor: Option<String>,
+    metadata: serde_json::Value,
+}
+
+impl From<ActivityEntry> for ActivityItem {
+    fn from(entry: ActivityEntry) -> Self {
+        Self {
+            id: entry.id.to_string(),
+            activity_type: entry.entity_type.clone(),
+            action: entry.action,
+            message: entry.message,
+            timestamp: entry.created_at,
+            severity: entry.severity,
+            entity_id: entry.entity_id.map(|id| id.to_string()),
+            entity_type: Some(entry.entity_type),
+            actor: entry.actor,
+            metadata: entry.metadata,
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct RecentActivitiesResponse {
     activities: Vec<ActivityItem>,
+    next_cursor: Option<String>,
 }
 
-// Threshold for low stock and overstock
-const LOW_STOCK_THRESHOLD: i32 = 10;
-const OVERSTOCK_THRESHOLD: i32 = 100;
-
 pub async fn get_dashboard_overview(
     State(state): State<SharedState>,
 ) -> Result<Json<DashboardOverview>, (StatusCode, String)> {
@@ -86,8 +120,22 @@ async fn get_inventory_overview(state: &SharedState) -> Result<InventoryOverview
     // Get all inventory items from the inventory service
     let items = state.inventory_service.get_all_items(1, 1000, None).await?;
 
+    // Reserved and available units per item across its warehouses
+    let mut levels: HashMap<Uuid, (i64, i64)> = HashMap::new();
+    for level in state
+        .inventory_service
+        .get_inventory_levels(&[], &[], None)
+        .await?
+    {
+        let entry = levels.entry(level.item_id).or_default();
+        entry.0 += level.reserved as i64;
+        entry.1 += level.available as i64;
+    }
+
     let total_items = items.len() as i64;
     let mut total_quantity = 0;
+    let mut total_reserved = 0;
+    let mut total_available = 0;
     let mut total_value = 0.0;
     let mut low_stock_count = 0;
     let mut overstock_count = 0;
@@ -97,7 +145,15 @@ async fn get_inventory_overview(state: &SharedState) -> Result<InventoryOverview
         let price_f64 = item.price.to_f64().unwrap_or(0.0);
         total_value += (price_f64 * item.quantity as f64);
 
-        if item.quantity < item.low_stock_threshold.unwrap_or(0) {
+        // Items without a level yet have nothing reserved
+        let (reserved, available) = levels
+            .get(&item.id)
+            .copied()
+            .unwrap_or((0, item.quantity as i64));
+        total_reserved += reserved;
+        total_available += available;
+
+        if is_low_stock(available as i32, item.low_stock_threshold) {
             low_stock_count += 1;
         }
 
@@ -111,6 +167,8 @@ async fn get_inventory_overview(state: &SharedState) -> Result<InventoryOverview
         low_stock_count,
         overstock_count,
         total_quantity,
+        total_reserved,
+        total_available,
         total_value,
     })
 }
@@ -128,7 +186,9 @@ async fn get_order_status_overview(
     let statuses = vec![
         OrderStatus::Pending,
         OrderStatus::Processing,
+        OrderStatus::PartiallyShipped,
         OrderStatus::Shipped,
+        OrderStatus::PartiallyDelivered,
         OrderStatus::Delivered,
         OrderStatus::Cancelled,
         OrderStatus::Returned,
@@ -171,79 +231,33 @@ pub async fn get_recent_activities_handler(
     Query(params): Query<ActivityQueryParams>,
     State(state): State<SharedState>,
 ) -> Result<Json<RecentActivitiesResponse>, (StatusCode, String)> {
-    // For now, return some mock activities until we implement actual activity tracking
-    let limit = params.limit.unwrap_or(10).min(50); // Limit to 50 max
-
-    // Create some mock activities based on recent orders and inventory
-    let mut activities = Vec::new();
-
-    // Get a few recent orders and create activities based on their status
-    if let Ok(recent_orders) = state.order_service.get_all_orders(1, 5, None).await {
-        for order in recent_orders {
-            let activity_type = match order.status {
-                OrderStatus::Pending => "order",
-                OrderStatus::Processing => "order",
-                OrderStatus::Shipped => "shipment",
-                OrderStatus::Delivered => "shipment",
-                OrderStatus::Cancelled => "order",
-                OrderStatus::Returned => "order",
-                OrderStatus::OutOfStock => "order",
-            };
-
-            let message = match order.status {
-                OrderStatus::Pending => format!("New order #{} received", order.id),
-                OrderStatus::Processing => format!("Order #{} being processed", order.id),
-                OrderStatus::Shipped => format!("Order #{} has been shipped", order.id),
-                OrderStatus::Delivered => format!("Order #{} has been delivered", order.id),
-                OrderStatus::Cancelled => format!("Order #{} has been cancelled", order.id),
-                OrderStatus::Returned => format!("Order #{} has been returned", order.id),
-                OrderStatus::OutOfStock => format!("Order #{} is out of stock", order.id),
-            };
-
-            let severity = match order.status {
-                OrderStatus::Cancelled => "warning",
-                OrderStatus::Returned => "warning",
-                OrderStatus::Delivered => "success",
-                _ => "info",
-            };
-
-            activities.push(ActivityItem {
-                id: Uuid::new_v4().to_string(),
-                activity_type: activity_type.to_string(),
-                message,
-                timestamp: order.updated_at,
-                severity: severity.to_string(),
-                entity_id: Some(order.id.to_string()),
-                entity_type: Some("order".to_string()),
-            });
-        }
-    }
-
-    // Get a few low stock items and create alert activities
-    if let Ok(inventory_items) = state.inventory_service.get_all_items(1, 1000, None).await {
-        for item in inventory_items {
-            if item.quantity <= LOW_STOCK_THRESHOLD {
-                activities.push(ActivityItem {
-                    id: Uuid::new_v4().to_string(),
-                    activity_type: "inventory".to_string(),
-                    message: format!(
-                        "Low stock alert: {} (only {} left)",
-                        item.name, item.quantity
-                    ),
-                    timestamp: item.updated_at,
-                    severity: "warning".to_string(),
-                    entity_id: Some(item.id.to_string()),
-                    entity_type: Some("inventory".to_string()),
-                });
-            }
-        }
-    }
-
-    // Sort activities by timestamp (newest first)
-    activities.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-
-    // Apply limit
-    activities.truncate(limit);
-
-    Ok(Json(RecentActivitiesResponse { activities }))
+    let limit = params.limit.unwrap_or(10).clamp(1, 50);
+
+    let severity = params
+        .severity
+        .as_deref()
+        .map(ActivitySeverity::from_str)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let filter = ActivityFilter {
+        entity_type: params.entity_type,
+        severity,
+        from: params.from,
+        to: params.to,
+    };
+
+    let (activities, next_cursor) = state
+        .activity_service
+        .list_activities(&filter, params.cursor.as_deref(), limit)
+        .await
+        .map_err(|e| match e {
+            LogisticsError::ValidationError(message) => (StatusCode::BAD_REQUEST, message),
+            e => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+        })?;
+
+    Ok(Json(RecentActivitiesResponse {
+        activities: activities.into_iter().map(ActivityItem::from).collect(),
+        next_cursor,
+    }))
 }
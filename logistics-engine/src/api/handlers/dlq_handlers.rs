@@ -0,0 +1,101 @@
+use axum::{
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::info;
+
+use crate::api::utils::success;
+use crate::error::AppError;
+use crate::errors::LogisticsError;
+use crate::mq::dlq::{self, ReplaySelection};
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterListParams {
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_limit() -> u32 {
+    50
+}
+
+fn map_dlq_error(err: AppError) -> LogisticsError {
+    match err {
+        AppError::NotFound(queue) => LogisticsError::NotFound("Dead letter queue", queue),
+        other => other.into(),
+    }
+}
+
+fn actor(headers: &HeaderMap) -> &str {
+    headers
+        .get("X-User-ID")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown")
+}
+
+pub async fn list_dead_letters(
+    Path(queue): Path<String>,
+    Query(params): Query<DeadLetterListParams>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let messages = dlq::list_dead_letters(&queue, params.limit.clamp(1, 500))
+        .await
+        .map_err(map_dlq_error)?;
+
+    Ok((StatusCode::OK, success(messages)))
+}
+
+pub async fn replay_dead_letters(
+    Path(queue): Path<String>,
+    headers: HeaderMap,
+    Json(selection): Json<ReplaySelection>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    if selection
+        .message_ids
+        .as_ref()
+        .map_or(false, |ids| ids.is_empty())
+    {
+        return Err(LogisticsError::ValidationError(
+            "message_ids must not be empty when provided".to_string(),
+        ));
+    }
+
+    info!(
+        "User {} replaying dead letters from {} ({:?})",
+        actor(&headers),
+        queue,
+        selection
+    );
+
+    let summary = dlq::replay_dead_letters(&queue, &selection)
+        .await
+        .map_err(map_dlq_error)?;
+
+    Ok((StatusCode::OK, success(summary)))
+}
+
+pub async fn purge_dead_letters(
+    Path(queue): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, LogisticsError> {
+    info!(
+        "User {} purging dead letters from {}",
+        actor(&headers),
+        queue
+    );
+
+    let purged = dlq::purge_dead_letters(&queue)
+        .await
+        .map_err(map_dlq_error)?;
+
+    Ok((
+        StatusCode::OK,
+        success(json!({
+            "queue": dlq::dead_letter_queue_name(&queue),
+            "purged": purged,
+        })),
+    ))
+}
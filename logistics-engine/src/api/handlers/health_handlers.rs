@@ -0,0 +1,51 @@
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::api::SharedState;
+use crate::config::get as get_config;
+use crate::grpc::{
+    self,
+    circuit_breaker::{CircuitBreakerSnapshot, CircuitState},
+};
+
+#[derive(Debug, Serialize)]
+pub struct InventoryServiceHealth {
+    pub circuit_breaker: Option<CircuitBreakerSnapshot>,
+    pub unavailable_policy: &'static str,
+    pub orders_awaiting_stock_check: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    /// `ok`, or `degraded` while a dependency is failing fast
+    pub status: &'static str,
+    pub inventory_service: InventoryServiceHealth,
+}
+
+/// `GET /health`: always answers 200 so the process counts as alive, with the
+/// state of downstream dependencies in the body.
+pub async fn health_check(State(state): State<SharedState>) -> impl IntoResponse {
+    let circuit_breaker = grpc::inventory_circuit_breaker();
+
+    let orders_awaiting_stock_check = match state.order_service.count_pending_stock_checks().await {
+        Ok(count) => Some(count),
+        Err(e) => {
+            warn!("Failed to count orders awaiting a stock check: {}", e);
+            None
+        }
+    };
+
+    let degraded = circuit_breaker
+        .as_ref()
+        .map_or(false, |breaker| breaker.state != CircuitState::Closed);
+
+    Json(HealthResponse {
+        status: if degraded { "degraded" } else { "ok" },
+        inventory_service: InventoryServiceHealth {
+            circuit_breaker,
+            unavailable_policy: get_config().inventory_client.unavailable_policy.as_str(),
+            orders_awaiting_stock_check,
+        },
+    })
+}
@@ -1,17 +1,138 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
+use serde::Deserialize;
 
-use crate::api::utils::{parse_uuid, success, PaginationParams};
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
 use crate::api::SharedState;
 use crate::errors::LogisticsError;
+use crate::models::backorder::UpdateBackorderDto;
 use crate::models::inventory::{
     CreateInventoryItemDto, CreateReservationDto, CreateTransactionDto, ReservationStatus,
     UpdateInventoryItemDto, UpdateReservationDto,
 };
+use crate::models::lot::ReceiveLotDto;
+
+#[derive(Debug, Deserialize)]
+pub struct InventoryLevelParams {
+    pub item_id: Option<String>,
+    pub sku: Option<String>,
+    pub warehouse_id: Option<String>,
+}
+
+pub async fn list_inventory_levels(
+    Query(params): Query<InventoryLevelParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let item_ids = params
+        .item_id
+        .as_deref()
+        .map(parse_uuid)
+        .transpose()?
+        .into_iter()
+        .collect::<Vec<_>>();
+    let skus = params.sku.into_iter().collect::<Vec<_>>();
+    let warehouse_id = params.warehouse_id.as_deref().map(parse_uuid).transpose()?;
+
+    let levels = state
+        .inventory_service
+        .get_inventory_levels(&item_ids, &skus, warehouse_id)
+        .await?;
+
+    Ok((StatusCode::OK, success(levels)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExpiringLotParams {
+    #[serde(default = "default_expiring_days")]
+    pub days: i32,
+    pub warehouse_id: Option<String>,
+}
+
+fn default_expiring_days() -> i32 {
+    30
+}
+
+pub async fn list_expiring_lots(
+    Query(params): Query<ExpiringLotParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let warehouse_id = params.warehouse_id.as_deref().map(parse_uuid).transpose()?;
+    let lots = state
+        .inventory_service
+        .get_expiring_lots(params.days, warehouse_id)
+        .await?;
+
+    Ok((StatusCode::OK, success(lots)))
+}
+
+pub async fn list_item_lots(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let lots = state.inventory_service.get_item_lots(id).await?;
+
+    Ok((StatusCode::OK, success(lots)))
+}
+
+pub async fn receive_lot(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<ReceiveLotDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    payload.received_by = payload.received_by.or_else(|| request_user_id(&headers));
+    let lot = state.inventory_service.receive_lot(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(lot)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BacklogParams {
+    pub product_id: Option<String>,
+}
+
+pub async fn list_backorders(
+    pagination: Query<PaginationParams>,
+    Query(params): Query<BacklogParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let product_id = params.product_id.as_deref().map(parse_uuid).transpose()?;
+    let backlog = state
+        .inventory_service
+        .get_backlog(product_id, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(backlog)))
+}
+
+pub async fn update_backorder(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    Json(payload): Json<UpdateBackorderDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let backorder = state
+        .inventory_service
+        .update_backorder(id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, success(backorder)))
+}
+
+pub async fn get_reservation_sweeper_metrics(
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    Ok((
+        StatusCode::OK,
+        success(state.reservation_sweeper_metrics.snapshot()),
+    ))
+}
 
 pub async fn list_inventory_items(
     pagination: Query<PaginationParams>,
@@ -37,9 +158,13 @@ pub async fn get_inventory_item(
 
 pub async fn create_inventory_item(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateInventoryItemDto>,
 ) -> Result<impl IntoResponse, LogisticsError> {
-    let item = state.inventory_service.create_item(payload).await?;
+    let item = state
+        .inventory_service
+        .create_item(payload, request_user_id(&headers))
+        .await?;
 
     Ok((StatusCode::CREATED, success(item)))
 }
@@ -47,10 +172,14 @@ pub async fn create_inventory_item(
 pub async fn update_inventory_item(
     Path(id): Path<String>,
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateInventoryItemDto>,
 ) -> Result<impl IntoResponse, LogisticsError> {
     let id = parse_uuid(&id)?;
-    let item = state.inventory_service.update_item(id, payload).await?;
+    let item = state
+        .inventory_service
+        .update_item(id, payload, request_user_id(&headers))
+        .await?;
 
     Ok((StatusCode::OK, success(item)))
 }
@@ -71,6 +200,7 @@ pub async fn delete_inventory_item(
 pub async fn adjust_quantity(
     Path(id): Path<String>,
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<impl IntoResponse, LogisticsError> {
     let id = parse_uuid(&id)?;
@@ -85,9 +215,14 @@ pub async fn adjust_quantity(
             )
         })?;
 
+    let reference = payload
+        .get("reference")
+        .and_then(|r| r.as_str())
+        .map(|r| r.to_string());
+
     let item = state
         .inventory_service
-        .adjust_quantity(id, quantity)
+        .adjust_quantity(id, quantity, reference, request_user_id(&headers))
         .await?;
 
     Ok((StatusCode::OK, success(item)))
@@ -117,9 +252,13 @@ pub async fn get_reservation(
 
 pub async fn create_reservation(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateReservationDto>,
 ) -> Result<impl IntoResponse, LogisticsError> {
-    let reservation = state.inventory_service.create_reservation(payload).await?;
+    let reservation = state
+        .inventory_service
+        .create_reservation(payload, request_user_id(&headers))
+        .await?;
 
     Ok((StatusCode::CREATED, success(reservation)))
 }
@@ -141,9 +280,13 @@ pub async fn update_reservation(
 pub async fn delete_reservation(
     Path(id): Path<String>,
     State(state): State<SharedState>,
+    headers: HeaderMap,
 ) -> Result<impl IntoResponse, LogisticsError> {
     let id = parse_uuid(&id)?;
-    let result = state.inventory_service.delete_reservation(id).await?;
+    let result = state
+        .inventory_service
+        .delete_reservation(id, request_user_id(&headers))
+        .await?;
 
     Ok((
         StatusCode::OK,
@@ -154,6 +297,7 @@ pub async fn delete_reservation(
 pub async fn update_reservation_status(
     Path(id): Path<String>,
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<serde_json::Value>,
 ) -> Result<impl IntoResponse, LogisticsError> {
     let id = parse_uuid(&id)?;
@@ -166,7 +310,7 @@ pub async fn update_reservation_status(
     let status = ReservationStatus::from(status_value.to_string());
     let reservation = state
         .inventory_service
-        .update_reservation_status(id, status)
+        .update_reservation_status(id, status, request_user_id(&headers))
         .await?;
 
     Ok((StatusCode::OK, success(reservation)))
@@ -196,9 +340,33 @@ pub async fn get_transaction(
 
 pub async fn create_transaction(
     State(state): State<SharedState>,
-    Json(payload): Json<CreateTransactionDto>,
+    headers: HeaderMap,
+    Json(mut payload): Json<CreateTransactionDto>,
 ) -> Result<impl IntoResponse, LogisticsError> {
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
     let transaction = state.inventory_service.create_transaction(payload).await?;
 
     Ok((StatusCode::CREATED, success(transaction)))
 }
+
+pub async fn get_item_ledger(
+    Path(id): Path<String>,
+    pagination: Query<PaginationParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let entries = state
+        .inventory_service
+        .get_item_ledger(id, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(entries)))
+}
+
+pub async fn get_ledger_reconciliation(
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let reconciliation = state.inventory_service.reconcile_ledger().await?;
+
+    Ok((StatusCode::OK, success(reconciliation)))
+}
@@ -1,8 +1,16 @@
 pub mod analytics_handlers;
+pub mod bin_handlers;
 pub mod customer_handlers;
 pub mod dashboard_handlers;
+pub mod dlq_handlers;
+pub mod health_handlers;
 pub mod inventory_handlers;
 pub mod order_handlers;
 pub mod payment_handlers;
+pub mod return_handlers;
+pub mod serial_handlers;
 pub mod shipping_handlers;
+pub mod stream_handlers;
+pub mod transfer_handlers;
 pub mod warehouse_handlers;
+pub mod wave_handlers;
@@ -1,19 +1,21 @@
 use axum::{
     extract::{Path, Query, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 
-use crate::api::utils::{parse_uuid, success, PaginationParams};
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
 use crate::models::entities::order::OrderStatus;
 use crate::models::order_item::OrderItem;
 use crate::models::{
-    dto::order::{CreateOrderDto, UpdateOrderDto},
+    dto::order::{CreateOrderDto, UpdateOrderDto, UpdateOrderPriorityDto, UpdateOrderStatusDto},
+    dto::shipping::ShipmentDto,
     order_item::UpdateOrderItemDto,
 };
 use crate::{api::SharedState, errors::LogisticsError};
 use sqlx::types::BigDecimal;
+use std::str::FromStr;
 
 pub async fn list_orders(
     pagination: Query<PaginationParams>,
@@ -39,9 +41,13 @@ pub async fn get_order(
 
 pub async fn create_order(
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<CreateOrderDto>,
 ) -> Result<Response, LogisticsError> {
-    let order = state.order_service.create_order(payload).await?;
+    let order = state
+        .order_service
+        .create_order(payload, request_user_id(&headers))
+        .await?;
 
     Ok((StatusCode::CREATED, success(order)).into_response())
 }
@@ -49,10 +55,14 @@ pub async fn create_order(
 pub async fn update_order(
     Path(id): Path<String>,
     State(state): State<SharedState>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateOrderDto>,
 ) -> Result<Response, LogisticsError> {
     let id = parse_uuid(&id)?;
-    let order = state.order_service.update_order(id, payload).await?;
+    let order = state
+        .order_service
+        .update_order(id, payload, request_user_id(&headers))
+        .await?;
 
     Ok((StatusCode::OK, success(order)).into_response())
 }
@@ -60,41 +70,114 @@ pub async fn update_order(
 pub async fn update_order_status(
     Path(id): Path<String>,
     State(state): State<SharedState>,
-    Json(payload): Json<serde_json::Value>,
+    headers: HeaderMap,
+    Json(payload): Json<UpdateOrderStatusDto>,
 ) -> Result<Response, LogisticsError> {
     let id = parse_uuid(&id)?;
 
-    let status_str = payload
-        .get("status")
-        .and_then(|s| s.as_str())
-        .ok_or_else(|| {
-            LogisticsError::ValidationError("Status is required and must be a string".to_string())
-        })?;
-
-    let status = match status_str {
-        "pending" => OrderStatus::Pending,
-        "processing" => OrderStatus::Processing,
-        "shipped" => OrderStatus::Shipped,
-        "delivered" => OrderStatus::Delivered,
-        "cancelled" => OrderStatus::Cancelled,
-        "returned" => OrderStatus::Returned,    
-        "out_of_stock" => OrderStatus::OutOfStock,
-        _ => {
-            return Err(LogisticsError::ValidationError(format!(
-                "Invalid status: {}. Must be one of: pending, processing, shipped, delivered, cancelled, returned, out_of_stock",
-                status_str
-            )))
-        }
+    let status =
+        OrderStatus::from_str(&payload.status).map_err(LogisticsError::ValidationError)?;
+
+    // The history records the authenticated user as the actor; a name given in
+    // the body is only kept in the notes
+    let changed_by = request_user_id(&headers);
+    let reported_by = payload
+        .changed_by
+        .filter(|name| !name.trim().is_empty() && Some(name) != changed_by.as_ref());
+    let notes = match (payload.status_notes, reported_by) {
+        (Some(notes), Some(name)) => Some(format!("{} (reported by {})", notes, name)),
+        (None, Some(name)) => Some(format!("Reported by {}", name)),
+        (notes, None) => notes,
     };
 
     let order = state
         .order_service
-        .update_order_status(id, status, None)
+        .update_order_status(id, status, notes, changed_by)
         .await?;
 
     Ok((StatusCode::OK, success(order)).into_response())
 }
 
+pub async fn update_order_priority(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    Json(payload): Json<UpdateOrderPriorityDto>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let priority = state
+        .order_service
+        .update_order_priority(id, payload.priority)
+        .await?;
+
+    Ok((StatusCode::OK, success(priority)).into_response())
+}
+
+pub async fn get_order_transitions(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let transitions = state.order_service.get_allowed_transitions(id).await?;
+
+    Ok((StatusCode::OK, success(transitions)).into_response())
+}
+
+pub async fn get_order_cancellation(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let cancellation = state.order_service.get_cancellation(id).await?;
+
+    Ok((StatusCode::OK, success(cancellation)).into_response())
+}
+
+pub async fn retry_order_cancellation(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let cancellation = state.order_service.resume_cancellation(id).await?;
+
+    Ok((StatusCode::OK, success(cancellation)).into_response())
+}
+
+pub async fn get_order_shipments(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let shipments: Vec<ShipmentDto> = state
+        .order_service
+        .get_order_shipments(id)
+        .await?
+        .into_iter()
+        .map(ShipmentDto::from)
+        .collect();
+
+    Ok((StatusCode::OK, success(shipments)).into_response())
+}
+
+pub async fn get_order_backorders(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let backorders = state.order_service.get_order_backorders(id).await?;
+
+    Ok((StatusCode::OK, success(backorders)).into_response())
+}
+
+pub async fn get_order_allocation(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<Response, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let allocation = state.order_service.get_order_allocation(id).await?;
+
+    Ok((StatusCode::OK, success(allocation)).into_response())
+}
+
 pub async fn get_order_items(
     Path(id): Path<String>,
     State(state): State<SharedState>,
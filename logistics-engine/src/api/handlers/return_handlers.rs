@@ -0,0 +1,102 @@
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
+use crate::api::SharedState;
+use crate::errors::LogisticsError;
+use crate::models::rma::{CancelReturnDto, CreateReturnDto, ReceiveReturnDto, ReturnStatus};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct ReturnListParams {
+    pub status: Option<String>,
+}
+
+pub async fn list_returns(
+    pagination: Query<PaginationParams>,
+    Query(params): Query<ReturnListParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let status = params
+        .status
+        .as_deref()
+        .map(ReturnStatus::from_str)
+        .transpose()
+        .map_err(LogisticsError::BadRequest)?;
+    let returns = state
+        .return_service
+        .get_returns(status, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(returns)))
+}
+
+pub async fn get_return(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let rma = state.return_service.get_return(id).await?;
+
+    Ok((StatusCode::OK, success(rma)))
+}
+
+pub async fn get_order_returns(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let returns = state.return_service.get_order_returns(id).await?;
+
+    Ok((StatusCode::OK, success(returns)))
+}
+
+pub async fn create_return(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    Json(payload): Json<CreateReturnDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let rma = state.return_service.create_return(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(rma)))
+}
+
+pub async fn receive_return(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<ReceiveReturnDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    payload.received_by = payload.received_by.or_else(|| request_user_id(&headers));
+    let rma = state.return_service.receive_return(id, payload).await?;
+
+    Ok((StatusCode::OK, success(rma)))
+}
+
+pub async fn refund_return(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let rma = state.return_service.refund_return(id).await?;
+
+    Ok((StatusCode::OK, success(rma)))
+}
+
+pub async fn cancel_return(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    payload: Option<Json<CancelReturnDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let reason = payload.and_then(|Json(dto)| dto.reason);
+    let rma = state.return_service.cancel_return(id, reason).await?;
+
+    Ok((StatusCode::OK, success(rma)))
+}
@@ -0,0 +1,95 @@
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
+use crate::api::SharedState;
+use crate::errors::LogisticsError;
+use crate::models::serial::{AllocateSerialsDto, ReceiveSerialsDto, SerialStatus};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct SerialListParams {
+    pub status: Option<String>,
+}
+
+pub async fn list_item_serials(
+    Path(id): Path<String>,
+    pagination: Query<PaginationParams>,
+    Query(params): Query<SerialListParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let status = params
+        .status
+        .as_deref()
+        .map(SerialStatus::from_str)
+        .transpose()
+        .map_err(LogisticsError::BadRequest)?;
+    let serials = state
+        .serial_service
+        .get_item_serials(id, status, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(serials)))
+}
+
+pub async fn receive_serials(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<ReceiveSerialsDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    payload.received_by = payload.received_by.or_else(|| request_user_id(&headers));
+    let serials = state.serial_service.receive_serials(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(serials)))
+}
+
+pub async fn get_serial_history(
+    Path(serial_number): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let history = state
+        .serial_service
+        .get_serial_history(&serial_number)
+        .await?;
+
+    Ok((StatusCode::OK, success(history)))
+}
+
+pub async fn allocate_serials(
+    Path((order_id, item_id)): Path<(String, String)>,
+    State(state): State<SharedState>,
+    Json(payload): Json<AllocateSerialsDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let order_id = parse_uuid(&order_id)?;
+    let item_id = parse_uuid(&item_id)?;
+    let serials = state
+        .serial_service
+        .allocate_serials(order_id, item_id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, success(serials)))
+}
+
+pub async fn release_serials(
+    Path((order_id, item_id)): Path<(String, String)>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let order_id = parse_uuid(&order_id)?;
+    let item_id = parse_uuid(&item_id)?;
+    let released = state
+        .serial_service
+        .release_serials(order_id, item_id)
+        .await?;
+
+    Ok((
+        StatusCode::OK,
+        success(serde_json::json!({ "released": released })),
+    ))
+}
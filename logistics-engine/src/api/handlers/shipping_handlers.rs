@@ -1,7 +1,9 @@
 use crate::api::utils::{parse_uuid, success, PaginationParams};
 use crate::api::SharedState;
 use crate::errors::LogisticsError;
-use crate::models::dto::shipping::{CreateShippingInfoDto, UpdateShippingInfoDto};
+use crate::models::dto::shipping::{
+    CreateShippingInfoDto, UpdateShipmentItemsDto, UpdateShippingInfoDto,
+};
 use crate::models::shipping::ShippingStatus;
 use axum::{
     extract::{Path, Query, State},
@@ -64,6 +66,20 @@ pub async fn update_shipment(
     Ok((StatusCode::OK, success(shipment)))
 }
 
+pub async fn update_shipment_items(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    Json(payload): Json<UpdateShipmentItemsDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let shipment = state
+        .shipping_service
+        .update_shipment_items(&id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, success(shipment)))
+}
+
 pub async fn update_shipment_status(
     Path(id): Path<String>,
     State(state): State<SharedState>,
@@ -0,0 +1,186 @@
+use axum::{
+    extract::{
+        ws::{rejection::WebSocketUpgradeRejection, Message, WebSocket, WebSocketUpgrade},
+        Query,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::str::FromStr;
+use tracing::{debug, warn};
+
+use crate::errors::LogisticsError;
+use crate::realtime::live_feed::{self, LiveEvent, LiveMessage, LiveSubscription, LiveTopic};
+
+#[derive(Debug, Deserialize)]
+pub struct StreamParams {
+    /// Comma-separated topics; all topics when omitted
+    pub topics: Option<String>,
+    pub last_event_id: Option<u64>,
+}
+
+/// Messages a WebSocket client can send to change its subscription.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { topics: Vec<String> },
+    Unsubscribe { topics: Vec<String> },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Event(&'a LiveEvent),
+    Reset { missed: u64 },
+    Subscribed { topics: Vec<LiveTopic> },
+    Error { message: String },
+}
+
+fn parse_topics<'a>(
+    topics: impl IntoIterator<Item = &'a str>,
+) -> Result<HashSet<LiveTopic>, String> {
+    topics
+        .into_iter()
+        .filter(|topic| !topic.trim().is_empty())
+        .map(LiveTopic::from_str)
+        .collect()
+}
+
+fn active_topics(subscription: &LiveSubscription) -> Vec<LiveTopic> {
+    LiveTopic::ALL
+        .into_iter()
+        .filter(|topic| subscription.topics().contains(topic))
+        .collect()
+}
+
+/// `GET /api/stream`: upgrades to a WebSocket when requested, otherwise serves
+/// the same feed as Server-Sent Events. Resuming uses `last_event_id` or the
+/// `Last-Event-ID` header that EventSource sends on reconnect.
+pub async fn stream_events(
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    Query(params): Query<StreamParams>,
+    headers: HeaderMap,
+) -> Result<Response, LogisticsError> {
+    let topics = parse_topics(params.topics.as_deref().unwrap_or_default().split(','))
+        .map_err(LogisticsError::ValidationError)?;
+
+    let last_event_id = params.last_event_id.or_else(|| {
+        headers
+            .get("Last-Event-ID")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+    });
+
+    let subscription = live_feed::subscribe(topics, last_event_id);
+
+    match ws {
+        Ok(ws) => Ok(ws
+            .on_upgrade(move |socket| handle_socket(socket, subscription))
+            .into_response()),
+        Err(_) => Ok(Sse::new(sse_stream(subscription))
+            .keep_alive(KeepAlive::default())
+            .into_response()),
+    }
+}
+
+fn sse_stream(subscription: LiveSubscription) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold(subscription, |mut subscription| async move {
+        let event = match subscription.recv().await? {
+            LiveMessage::Event(event) => Event::default()
+                .id(event.id.to_string())
+                .data(serde_json::to_string(&*event).unwrap_or_default()),
+            LiveMessage::Reset { missed } => Event::default()
+                .event("reset")
+                .data(serde_json::json!({ "missed": missed }).to_string()),
+        };
+
+        Some((Ok(event), subscription))
+    })
+}
+
+async fn send_message(socket: &mut WebSocket, message: &ServerMessage<'_>) -> bool {
+    let text = match serde_json::to_string(message) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Failed to serialize stream message: {}", e);
+            return true;
+        }
+    };
+
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+async fn handle_socket(mut socket: WebSocket, mut subscription: LiveSubscription) {
+    let subscribed = ServerMessage::Subscribed {
+        topics: active_topics(&subscription),
+    };
+    if !send_message(&mut socket, &subscribed).await {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            message = subscription.recv() => {
+                let sent = match message {
+                    Some(LiveMessage::Event(event)) => {
+                        send_message(&mut socket, &ServerMessage::Event(&event)).await
+                    }
+                    Some(LiveMessage::Reset { missed }) => {
+                        send_message(&mut socket, &ServerMessage::Reset { missed }).await
+                    }
+                    None => break,
+                };
+
+                if !sent {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(e)) => {
+                        debug!("Stream client disconnected: {}", e);
+                        break;
+                    }
+                };
+
+                let reply = match serde_json::from_str::<ClientMessage>(text.as_str()) {
+                    Ok(ClientMessage::Subscribe { topics }) => {
+                        match parse_topics(topics.iter().map(String::as_str)) {
+                            Ok(topics) => {
+                                subscription.topics_mut().extend(topics);
+                                ServerMessage::Subscribed { topics: active_topics(&subscription) }
+                            }
+                            Err(message) => ServerMessage::Error { message },
+                        }
+                    }
+                    Ok(ClientMessage::Unsubscribe { topics }) => {
+                        match parse_topics(topics.iter().map(String::as_str)) {
+                            Ok(topics) => {
+                                subscription.topics_mut().retain(|topic| !topics.contains(topic));
+                                ServerMessage::Subscribed { topics: active_topics(&subscription) }
+                            }
+                            Err(message) => ServerMessage::Error { message },
+                        }
+                    }
+                    Err(e) => ServerMessage::Error {
+                        message: format!("Invalid message: {}", e),
+                    },
+                };
+
+                if !send_message(&mut socket, &reply).await {
+                    break;
+                }
+            }
+        }
+    }
+}
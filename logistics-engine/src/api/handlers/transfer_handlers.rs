@@ -0,0 +1,100 @@
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
+use crate::api::SharedState;
+use crate::errors::LogisticsError;
+use crate::models::transfer::{
+    CancelTransferDto, CreateTransferDto, ReceiveTransferDto, TransferStatus,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct TransferListParams {
+    pub status: Option<String>,
+    /// Matches transfers leaving or arriving at the warehouse
+    pub warehouse_id: Option<String>,
+}
+
+pub async fn list_transfers(
+    pagination: Query<PaginationParams>,
+    Query(params): Query<TransferListParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let status = params
+        .status
+        .as_deref()
+        .map(TransferStatus::from_str)
+        .transpose()
+        .map_err(LogisticsError::BadRequest)?;
+    let warehouse_id = params.warehouse_id.as_deref().map(parse_uuid).transpose()?;
+    let transfers = state
+        .transfer_service
+        .get_transfers(status, warehouse_id, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(transfers)))
+}
+
+pub async fn get_transfer(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let transfer = state.transfer_service.get_transfer(id).await?;
+
+    Ok((StatusCode::OK, success(transfer)))
+}
+
+pub async fn create_transfer(
+    State(state): State<SharedState>,
+    Json(payload): Json<CreateTransferDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let transfer = state.transfer_service.create_transfer(payload).await?;
+
+    Ok((StatusCode::CREATED, success(transfer)))
+}
+
+pub async fn dispatch_transfer(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let transfer = state
+        .transfer_service
+        .dispatch_transfer(id, request_user_id(&headers))
+        .await?;
+
+    Ok((StatusCode::OK, success(transfer)))
+}
+
+pub async fn receive_transfer(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    payload: Option<Json<ReceiveTransferDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let mut dto = payload.map(|Json(dto)| dto).unwrap_or_default();
+    dto.received_by = dto.received_by.or_else(|| request_user_id(&headers));
+    let transfer = state.transfer_service.receive_transfer(id, dto).await?;
+
+    Ok((StatusCode::OK, success(transfer)))
+}
+
+pub async fn cancel_transfer(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    payload: Option<Json<CancelTransferDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let reason = payload.and_then(|Json(dto)| dto.reason);
+    let transfer = state.transfer_service.cancel_transfer(id, reason).await?;
+
+    Ok((StatusCode::OK, success(transfer)))
+}
@@ -5,6 +5,8 @@ use axum::{
     Json,
 };
 
+use serde::Deserialize;
+
 use crate::api::utils::{parse_uuid, success, PaginationParams};
 use crate::api::SharedState;
 use crate::errors::LogisticsError;
@@ -22,6 +24,47 @@ pub async fn list_warehouses(
     Ok((StatusCode::OK, success(warehouses)))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct NearestWarehouseParams {
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub limit: Option<u32>,
+}
+
+pub async fn list_nearest_warehouses(
+    Query(params): Query<NearestWarehouseParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let limit = params.limit.unwrap_or(5).clamp(1, 50);
+    let warehouses = match (
+        params.postal_code.as_deref(),
+        params.latitude,
+        params.longitude,
+    ) {
+        (Some(postal_code), _, _) => {
+            state
+                .warehouse_service
+                .get_nearest_to_postal_code(postal_code, params.country.as_deref(), limit)
+                .await?
+        }
+        (None, Some(latitude), Some(longitude)) => {
+            state
+                .warehouse_service
+                .get_nearest_warehouses(latitude, longitude, limit)
+                .await?
+        }
+        _ => {
+            return Err(LogisticsError::BadRequest(
+                "Either postal_code or latitude and longitude are required".to_string(),
+            ))
+        }
+    };
+
+    Ok((StatusCode::OK, success(warehouses)))
+}
+
 pub async fn get_warehouse(
     Path(id): Path<String>,
     State(state): State<SharedState>,
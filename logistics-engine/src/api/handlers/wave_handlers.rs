@@ -0,0 +1,161 @@
+use crate::api::utils::{parse_uuid, request_user_id, success, PaginationParams};
+use crate::api::SharedState;
+use crate::errors::LogisticsError;
+use crate::models::wave::{
+    ConfirmPickDto, CreateWaveDto, SetCarrierCutoffDto, WaveActionDto, WaveStatus,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct WaveListParams {
+    pub status: Option<String>,
+}
+
+pub async fn list_waves(
+    Path(id): Path<String>,
+    pagination: Query<PaginationParams>,
+    Query(params): Query<WaveListParams>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let status = params
+        .status
+        .as_deref()
+        .map(WaveStatus::from_str)
+        .transpose()
+        .map_err(LogisticsError::BadRequest)?;
+    let waves = state
+        .wave_service
+        .get_waves(id, status, pagination.page, pagination.limit)
+        .await?;
+
+    Ok((StatusCode::OK, success(waves)))
+}
+
+pub async fn create_wave(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    payload: Option<Json<CreateWaveDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let mut payload = payload.map(|Json(dto)| dto).unwrap_or_default();
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
+    let wave = state.wave_service.create_wave(id, payload).await?;
+
+    Ok((StatusCode::CREATED, success(wave)))
+}
+
+pub async fn get_wave(
+    Path((id, wave_id)): Path<(String, String)>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let wave_id = parse_uuid(&wave_id)?;
+    let wave = state.wave_service.get_wave(id, wave_id).await?;
+
+    Ok((StatusCode::OK, success(wave)))
+}
+
+pub async fn confirm_pick(
+    Path((id, wave_id, line_id)): Path<(String, String, String)>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    payload: Option<Json<ConfirmPickDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let wave_id = parse_uuid(&wave_id)?;
+    let line_id = parse_uuid(&line_id)?;
+    let mut payload = payload.map(|Json(dto)| dto).unwrap_or(ConfirmPickDto {
+        picked_quantity: None,
+        user_id: None,
+    });
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
+    let wave = state
+        .wave_service
+        .confirm_pick(id, wave_id, line_id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, success(wave)))
+}
+
+pub async fn pack_wave_order(
+    Path((id, wave_id, order_id)): Path<(String, String, String)>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    payload: Option<Json<WaveActionDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let wave_id = parse_uuid(&wave_id)?;
+    let order_id = parse_uuid(&order_id)?;
+    let mut payload = payload.map(|Json(dto)| dto).unwrap_or_default();
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
+    let wave = state
+        .wave_service
+        .pack_order(id, wave_id, order_id, payload)
+        .await?;
+
+    Ok((StatusCode::OK, success(wave)))
+}
+
+pub async fn cancel_wave(
+    Path((id, wave_id)): Path<(String, String)>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    payload: Option<Json<WaveActionDto>>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let wave_id = parse_uuid(&wave_id)?;
+    let user_id = payload
+        .and_then(|Json(dto)| dto.user_id)
+        .or_else(|| request_user_id(&headers));
+    let wave = state.wave_service.cancel_wave(id, wave_id, user_id).await?;
+
+    Ok((StatusCode::OK, success(wave)))
+}
+
+pub async fn list_carrier_cutoffs(
+    Path(id): Path<String>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    let cutoffs = state.wave_service.get_carrier_cutoffs(id).await?;
+
+    Ok((StatusCode::OK, success(cutoffs)))
+}
+
+pub async fn set_carrier_cutoff(
+    Path((id, carrier)): Path<(String, String)>,
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Json(mut payload): Json<SetCarrierCutoffDto>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    payload.user_id = payload.user_id.or_else(|| request_user_id(&headers));
+    let cutoff = state
+        .wave_service
+        .set_carrier_cutoff(id, &carrier, payload)
+        .await?;
+
+    Ok((StatusCode::OK, success(cutoff)))
+}
+
+pub async fn delete_carrier_cutoff(
+    Path((id, carrier)): Path<(String, String)>,
+    State(state): State<SharedState>,
+) -> Result<impl IntoResponse, LogisticsError> {
+    let id = parse_uuid(&id)?;
+    state
+        .wave_service
+        .delete_carrier_cutoff(id, &carrier)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,9 +1,26 @@
-use axum::{extract::Request, http::StatusCode, middleware::Next, response::Response};
-use http::header::HeaderValue;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{OriginalUri, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use http::header::{HeaderValue, CONTENT_TYPE, COOKIE};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::env;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+use crate::api::SharedState;
+use crate::errors::LogisticsError;
+use crate::models::idempotency::StoredResponse;
+use crate::services::IdempotencyService;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+// Matches axum's default limit for JSON bodies
+const MAX_IDEMPOTENT_BODY_BYTES: usize = 2 * 1024 * 1024;
 
 #[derive(Serialize)]
 struct ValidateTokenRequest {
@@ -18,24 +35,69 @@ struct ValidateTokenResponse {
     user_id: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct TokenParams {
+    token: Option<String>,
+}
+
+fn bearer_token(request: &Request) -> Option<String> {
+    request
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .filter(|auth| auth.starts_with("Bearer "))
+        .map(|auth| auth.trim_start_matches("Bearer ").trim().to_string())
+}
+
+fn query_token(request: &Request) -> Option<String> {
+    Query::<TokenParams>::try_from_uri(request.uri())
+        .ok()
+        .and_then(|Query(params)| params.token)
+}
+
+fn cookie_token(request: &Request) -> Option<String> {
+    let cookie_name =
+        env::var("AUTH_COOKIE_NAME").unwrap_or_else(|_| "synkro_auth_token".to_string());
+
+    request
+        .headers()
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == cookie_name)
+        .map(|(_, token)| token.trim().to_string())
+}
+
 pub async fn auth_middleware(request: Request, next: Next) -> Result<Response, StatusCode> {
+    let token = bearer_token(&request);
+    authenticate(token, request, next).await
+}
+
+/// Like `auth_middleware`, but also takes the token from a `?token=` query
+/// parameter or the auth cookie: browsers cannot set headers on an
+/// `EventSource` or a WebSocket handshake.
+pub async fn stream_auth_middleware(request: Request, next: Next) -> Result<Response, StatusCode> {
+    let token = bearer_token(&request)
+        .or_else(|| query_token(&request))
+        .or_else(|| cookie_token(&request));
+    authenticate(token, request, next).await
+}
+
+async fn authenticate(
+    token: Option<String>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
     if request.method() == http::Method::OPTIONS {
         return Ok(next.run(request).await);
     }
 
     println!("auth_middleware");
 
-    let headers = request.headers();
-
-    let auth_header = headers
-        .get("Authorization")
-        .and_then(|value| value.to_str().ok());
-
-    match auth_header {
-        Some(auth) if auth.starts_with("Bearer ") => {
-            let token = auth.trim_start_matches("Bearer ").trim();
-            println!("token: {}", token);
-
+    match token.filter(|token| !token.is_empty()) {
+        Some(token) => {
             let auth_service_url = env::var("AUTH_SERVICE_URL")
                 .unwrap_or_else(|_| "http://api-gateway-auth:3000".to_string());
 
@@ -47,9 +109,7 @@ pub async fn auth_middleware(request: Request, next: Next) -> Result<Response, S
 
             match client
                 .post(&validate_endpoint)
-                .json(&ValidateTokenRequest {
-                    token: token.to_string(),
-                })
+                .json(&ValidateTokenRequest { token })
                 .send()
                 .await
             {
@@ -60,7 +120,9 @@ pub async fn auth_middleware(request: Request, next: Next) -> Result<Response, S
                                 if validate_result.is_valid {
                                     info!("Token validated successfully");
 
+                                    // Only the validated token says who the caller is
                                     let mut request = request;
+                                    request.headers_mut().remove("X-User-ID");
                                     if let Some(user_id) = validate_result.user_id {
                                         let headers = request.headers_mut();
                                         headers.insert(
@@ -97,8 +159,162 @@ pub async fn auth_middleware(request: Request, next: Next) -> Result<Response, S
             }
         }
         _ => {
-            error!("Unauthorized access attempt: missing or invalid Authorization header");
+            error!("Unauthorized access attempt: missing or invalid token");
             Err(StatusCode::UNAUTHORIZED)
         }
     }
 }
+
+/// Restricts a router to the users listed in `ADMIN_USER_IDS`. Must run
+/// inside `auth_middleware`, which sets `X-User-ID` from the validated token.
+/// With no admins configured every request is refused.
+pub async fn admin_middleware(request: Request, next: Next) -> Result<Response, StatusCode> {
+    if request.method() == http::Method::OPTIONS {
+        return Ok(next.run(request).await);
+    }
+
+    let user_id = request
+        .headers()
+        .get("X-User-ID")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|user_id| !user_id.is_empty());
+
+    let Some(user_id) = user_id else {
+        error!("Admin access attempt without an authenticated user");
+        return Err(StatusCode::FORBIDDEN);
+    };
+
+    let admin_user_ids = env::var("ADMIN_USER_IDS").unwrap_or_default();
+    let is_admin = admin_user_ids
+        .split(',')
+        .map(str::trim)
+        .any(|admin| !admin.is_empty() && admin.eq_ignore_ascii_case(user_id));
+
+    if !is_admin {
+        warn!("User {} is not allowed to use admin routes", user_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Makes retries of a create request safe. A request with an `Idempotency-Key`
+/// header runs once; retries by the same user with the same key and payload
+/// get the stored response back, and reusing the key with a different payload
+/// is rejected with 422. Requests without the header are passed through
+/// untouched. Must run inside `auth_middleware`, which sets `X-User-ID`.
+pub async fn idempotency_middleware(
+    State(state): State<SharedState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, LogisticsError> {
+    let key = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .map(str::trim)
+            .filter(|key| !key.is_empty() && key.len() <= 255)
+            .ok_or_else(|| {
+                LogisticsError::BadRequest(
+                    "Idempotency-Key must be between 1 and 255 visible characters".to_string(),
+                )
+            })?
+            .to_string(),
+        None => return Ok(next.run(request).await),
+    };
+
+    // Nested routers only see the path below their prefix
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map(|uri| uri.path().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    // Keys belong to the caller, so two users picking the same key neither
+    // replay each other's responses nor block each other
+    let user_id = request
+        .headers()
+        .get("X-User-ID")
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|user_id| !user_id.is_empty())
+        .unwrap_or("-");
+    let scope = format!("{} {} {}", user_id, request.method(), path);
+
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_IDEMPOTENT_BODY_BYTES)
+        .await
+        .map_err(|e| LogisticsError::BadRequest(format!("Failed to read request body: {}", e)))?;
+
+    let fingerprint = IdempotencyService::fingerprint(&scope, &body);
+    let idempotency_service = &state.idempotency_service;
+
+    if let Some(stored) = idempotency_service
+        .begin(&key, &scope, &fingerprint)
+        .await?
+    {
+        info!("Replaying stored response for idempotency key {}", key);
+        return Ok(replay_response(stored));
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            idempotency_service.abandon(&key, &scope).await;
+            return Err(LogisticsError::InternalError(format!(
+                "Failed to read response body: {}",
+                e
+            )));
+        }
+    };
+
+    // Server errors are not stored, so the client can retry them for real
+    if parts.status.is_server_error() {
+        idempotency_service.abandon(&key, &scope).await;
+    } else {
+        let stored = StoredResponse {
+            status: parts.status.as_u16(),
+            content_type: parts
+                .headers
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string()),
+            body: body.to_vec(),
+        };
+
+        // The request already ran, so its response goes out even if it could
+        // not be stored; the key frees itself once it goes stale
+        if let Err(e) = idempotency_service.complete(&key, &scope, &stored).await {
+            warn!(
+                "Failed to store response for idempotency key {}: {}",
+                key, e
+            );
+            idempotency_service.abandon(&key, &scope).await;
+        }
+    }
+
+    parts.headers.insert(
+        IDEMPOTENT_REPLAYED_HEADER,
+        HeaderValue::from_static("false"),
+    );
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+fn replay_response(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+
+    let headers = response.headers_mut();
+    if let Some(content_type) = stored
+        .content_type
+        .and_then(|value| HeaderValue::from_str(&value).ok())
+    {
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+    headers.insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+
+    response
+}
@@ -7,8 +7,9 @@ use axum::Router;
 use std::sync::Arc;
 
 use crate::services::{
-    AnalyticsService, CustomerService, InventoryService, OrderService, PaymentService,
-    ShippingService, WarehouseService,
+    reservation_sweeper_service::ReservationSweeperMetrics, ActivityService, AnalyticsService,
+    BinService, CustomerService, IdempotencyService, InventoryService, OrderService, PaymentService,
+    ReturnService, SerialService, ShippingService, TransferService, WarehouseService, WaveService,
 };
 
 #[derive(Clone)]
@@ -18,8 +19,16 @@ pub struct AppState {
     pub order_service: Arc<OrderService>,
     pub payment_service: Arc<PaymentService>,
     pub shipping_service: Arc<ShippingService>,
+    pub return_service: Arc<ReturnService>,
+    pub transfer_service: Arc<TransferService>,
+    pub serial_service: Arc<SerialService>,
+    pub bin_service: Arc<BinService>,
+    pub wave_service: Arc<WaveService>,
     pub warehouse_service: Arc<WarehouseService>,
     pub analytics_service: Arc<AnalyticsService>,
+    pub activity_service: Arc<ActivityService>,
+    pub idempotency_service: Arc<IdempotencyService>,
+    pub reservation_sweeper_metrics: Arc<ReservationSweeperMetrics>,
 }
 
 pub type SharedState = Arc<AppState>;
@@ -1,20 +1,31 @@
 use axum::{
     extract::Path,
-    middleware::from_fn,
+    middleware::{from_fn, from_fn_with_state},
     routing::{delete, get, post, put},
     Router,
 };
 use http::header::HeaderName;
 use tower_http::cors::CorsLayer;
 
-use crate::api::{handlers::customer_handlers, middleware::auth_middleware, SharedState};
+use crate::api::{
+    handlers::customer_handlers,
+    middleware::{
+        admin_middleware, auth_middleware, idempotency_middleware, stream_auth_middleware,
+        IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER,
+    },
+    SharedState,
+};
 
 use super::handlers::{
-    analytics_handlers, dashboard_handlers, inventory_handlers, order_handlers, payment_handlers,
-    shipping_handlers, warehouse_handlers,
+    analytics_handlers, bin_handlers, dashboard_handlers, dlq_handlers, health_handlers,
+    inventory_handlers, order_handlers, payment_handlers, return_handlers, serial_handlers,
+    shipping_handlers, stream_handlers, transfer_handlers, warehouse_handlers, wave_handlers,
 };
 
 pub fn create_router(state: SharedState) -> Router {
+    // Create endpoints that clients retry on timeouts honour Idempotency-Key
+    let idempotent = || from_fn_with_state(state.clone(), idempotency_middleware);
+
     let customer_routes = Router::new()
         .route("/", get(customer_handlers::list_customers))
         .route("/", post(customer_handlers::create_customer))
@@ -25,13 +36,50 @@ pub fn create_router(state: SharedState) -> Router {
     let warehouse_routes = Router::new()
         .route("/", get(warehouse_handlers::list_warehouses))
         .route("/", post(warehouse_handlers::create_warehouse))
+        .route("/nearest", get(warehouse_handlers::list_nearest_warehouses))
         .route("/{id}", get(warehouse_handlers::get_warehouse))
         .route("/{id}", put(warehouse_handlers::update_warehouse))
-        .route("/{id}", delete(warehouse_handlers::delete_warehouse));
+        .route("/{id}", delete(warehouse_handlers::delete_warehouse))
+        .route("/{id}/bins", get(bin_handlers::list_bins))
+        .route("/{id}/bins", post(bin_handlers::create_bin))
+        .route("/{id}/bins/{bin_id}", get(bin_handlers::get_bin))
+        .route("/{id}/bins/{bin_id}", put(bin_handlers::update_bin))
+        .route("/{id}/putaway", get(bin_handlers::suggest_putaway))
+        .route("/{id}/putaway", post(bin_handlers::put_away))
+        .route("/{id}/bin-moves", post(bin_handlers::move_bin_stock))
+        .route("/{id}/bin-movements", get(bin_handlers::list_bin_movements))
+        .route(
+            "/{id}/carrier-cutoffs",
+            get(wave_handlers::list_carrier_cutoffs),
+        )
+        .route(
+            "/{id}/carrier-cutoffs/{carrier}",
+            put(wave_handlers::set_carrier_cutoff),
+        )
+        .route(
+            "/{id}/carrier-cutoffs/{carrier}",
+            delete(wave_handlers::delete_carrier_cutoff),
+        )
+        .route("/{id}/waves", get(wave_handlers::list_waves))
+        .route("/{id}/waves", post(wave_handlers::create_wave))
+        .route("/{id}/waves/{wave_id}", get(wave_handlers::get_wave))
+        .route(
+            "/{id}/waves/{wave_id}/cancel",
+            post(wave_handlers::cancel_wave),
+        )
+        .route(
+            "/{id}/waves/{wave_id}/lines/{line_id}/confirm",
+            post(wave_handlers::confirm_pick),
+        )
+        .route(
+            "/{id}/waves/{wave_id}/orders/{order_id}/pack",
+            post(wave_handlers::pack_wave_order),
+        );
 
     let inventory_routes = Router::new()
         .route("/", get(inventory_handlers::list_inventory_items))
         .route("/", post(inventory_handlers::create_inventory_item))
+        .route("/levels", get(inventory_handlers::list_inventory_levels))
         .route(
             "/transactions",
             get(inventory_handlers::list_inventory_transactions),
@@ -44,6 +92,19 @@ pub fn create_router(state: SharedState) -> Router {
             "/transactions/{id}",
             get(inventory_handlers::get_transaction),
         )
+        .route(
+            "/reconciliation",
+            get(inventory_handlers::get_ledger_reconciliation),
+        )
+        .route(
+            "/lots/expiring",
+            get(inventory_handlers::list_expiring_lots),
+        )
+        .route("/backorders", get(inventory_handlers::list_backorders))
+        .route(
+            "/backorders/{id}",
+            put(inventory_handlers::update_backorder),
+        )
         .route("/reservations", get(inventory_handlers::list_reservations))
         .route(
             "/reservations",
@@ -65,17 +126,66 @@ pub fn create_router(state: SharedState) -> Router {
             "/reservations/{id}/status",
             put(inventory_handlers::update_reservation_status),
         )
+        .route("/transfers", get(transfer_handlers::list_transfers))
+        .route("/transfers", post(transfer_handlers::create_transfer))
+        .route("/transfers/{id}", get(transfer_handlers::get_transfer))
+        .route(
+            "/transfers/{id}/dispatch",
+            post(transfer_handlers::dispatch_transfer),
+        )
+        .route(
+            "/transfers/{id}/receive",
+            post(transfer_handlers::receive_transfer),
+        )
+        .route(
+            "/transfers/{id}/cancel",
+            post(transfer_handlers::cancel_transfer),
+        )
+        .route(
+            "/serials/{serial_number}",
+            get(serial_handlers::get_serial_history),
+        )
         .route("/{id}", get(inventory_handlers::get_inventory_item))
         .route("/{id}", put(inventory_handlers::update_inventory_item))
         .route("/{id}", delete(inventory_handlers::delete_inventory_item))
-        .route("/{id}/adjust", put(inventory_handlers::adjust_quantity));
+        .route("/{id}/adjust", put(inventory_handlers::adjust_quantity))
+        .route("/{id}/ledger", get(inventory_handlers::get_item_ledger))
+        .route("/{id}/lots", get(inventory_handlers::list_item_lots))
+        .route("/{id}/lots", post(inventory_handlers::receive_lot))
+        .route("/{id}/serials", get(serial_handlers::list_item_serials))
+        .route("/{id}/serials", post(serial_handlers::receive_serials));
 
     let order_routes = Router::new()
         .route("/", get(order_handlers::list_orders))
-        .route("/", post(order_handlers::create_order))
+        .route("/", post(order_handlers::create_order).layer(idempotent()))
         .route("/{id}", get(order_handlers::get_order))
         .route("/{id}", put(order_handlers::update_order))
         .route("/{id}/status", put(order_handlers::update_order_status))
+        .route("/{id}/priority", put(order_handlers::update_order_priority))
+        .route(
+            "/{id}/transitions",
+            get(order_handlers::get_order_transitions),
+        )
+        .route(
+            "/{id}/cancellation",
+            get(order_handlers::get_order_cancellation),
+        )
+        .route(
+            "/{id}/cancellation/retry",
+            post(order_handlers::retry_order_cancellation),
+        )
+        .route("/{id}/shipments", get(order_handlers::get_order_shipments))
+        .route("/{id}/pick-list", get(bin_handlers::get_pick_list))
+        .route(
+            "/{id}/backorders",
+            get(order_handlers::get_order_backorders),
+        )
+        .route(
+            "/{id}/allocation",
+            get(order_handlers::get_order_allocation),
+        )
+        .route("/{id}/returns", get(return_handlers::get_order_returns))
+        .route("/{id}/returns", post(return_handlers::create_return))
         .route(
             "/{id}/items",
             get(|path, state| order_handlers::get_order_items(path, state)),
@@ -97,11 +207,29 @@ pub fn create_router(state: SharedState) -> Router {
                     order_handlers::delete_order_item(path, state).await
                 },
             ),
+        )
+        .route(
+            "/{order_id}/items/{item_id}/serials",
+            post(serial_handlers::allocate_serials),
+        )
+        .route(
+            "/{order_id}/items/{item_id}/serials",
+            delete(serial_handlers::release_serials),
         );
 
+    let return_routes = Router::new()
+        .route("/", get(return_handlers::list_returns))
+        .route("/{id}", get(return_handlers::get_return))
+        .route("/{id}/receive", post(return_handlers::receive_return))
+        .route("/{id}/refund", post(return_handlers::refund_return))
+        .route("/{id}/cancel", post(return_handlers::cancel_return));
+
     let payment_routes = Router::new()
         .route("/", get(payment_handlers::list_payments))
-        .route("/", post(payment_handlers::create_payment))
+        .route(
+            "/",
+            post(payment_handlers::create_payment).layer(idempotent()),
+        )
         .route("/{id}", get(payment_handlers::get_payment))
         .route("/{id}", put(payment_handlers::update_payment))
         .route("/{id}", delete(payment_handlers::delete_payment))
@@ -110,7 +238,10 @@ pub fn create_router(state: SharedState) -> Router {
 
     let shipping_routes = Router::new()
         .route("/", get(shipping_handlers::list_shipments))
-        .route("/", post(shipping_handlers::create_shipment))
+        .route(
+            "/",
+            post(shipping_handlers::create_shipment).layer(idempotent()),
+        )
         .route("/{id}", get(shipping_handlers::get_shipment))
         .route("/{id}", put(shipping_handlers::update_shipment))
         .route("/{id}", delete(shipping_handlers::delete_shipment))
@@ -118,6 +249,7 @@ pub fn create_router(state: SharedState) -> Router {
             "/{id}/status",
             put(shipping_handlers::update_shipment_status),
         )
+        .route("/{id}/items", put(shipping_handlers::update_shipment_items))
         .route("/{id}/deliver", post(shipping_handlers::mark_as_delivered))
         .route(
             "/tracking/{number}",
@@ -258,6 +390,20 @@ pub fn create_router(state: SharedState) -> Router {
             get(analytics_handlers::get_trend_predictions),
         );
 
+    // Dead letter queue and background job administration
+    let admin_routes = Router::new()
+        .route("/dlq/{queue}", get(dlq_handlers::list_dead_letters))
+        .route("/dlq/{queue}", delete(dlq_handlers::purge_dead_letters))
+        .route(
+            "/dlq/{queue}/replay",
+            post(dlq_handlers::replay_dead_letters),
+        )
+        .route(
+            "/reservations/sweeper",
+            get(inventory_handlers::get_reservation_sweeper_metrics),
+        )
+        .route_layer(from_fn(admin_middleware));
+
     let cors = CorsLayer::new()
         .allow_origin([
             "http://localhost:3000".parse().unwrap(),
@@ -289,15 +435,22 @@ pub fn create_router(state: SharedState) -> Router {
             http::header::CACHE_CONTROL,
             HeaderName::from_static("x-requested-with"),
             HeaderName::from_static("x-user-id"),
+            HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
         ])
         .expose_headers([
             http::header::AUTHORIZATION,
             http::header::CONTENT_TYPE,
             HeaderName::from_static("x-user-id"),
+            HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER),
         ])
         .allow_credentials(true)
         .max_age(std::time::Duration::from_secs(3600));
 
+    // Browser clients of the live feed cannot send an Authorization header
+    let stream_routes = Router::new()
+        .route("/stream", get(stream_handlers::stream_events))
+        .layer(from_fn(stream_auth_middleware));
+
     let api_routes = Router::new()
         .nest("/customers", customer_routes)
         .nest("/warehouses", warehouse_routes)
@@ -305,12 +458,15 @@ pub fn create_router(state: SharedState) -> Router {
         .nest("/orders", order_routes)
         .nest("/shipping", shipping_routes)
         .nest("/payments", payment_routes)
+        .nest("/returns", return_routes)
         .nest("/dashboard", dashboard_routes)
         .nest("/analytics", analytics_routes)
-        .layer(from_fn(auth_middleware));
+        .nest("/admin", admin_routes)
+        .layer(from_fn(auth_middleware))
+        .merge(stream_routes);
 
     Router::new()
-        .route("/health", get(|| async { "OK" }))
+        .route("/health", get(health_handlers::health_check))
         .nest("/api", api_routes)
         .layer(cors)
         .with_state(state)
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
@@ -29,6 +29,17 @@ impl IntoResponse for LogisticsError {
             ),
             LogisticsError::InternalError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
             LogisticsError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            LogisticsError::InvalidStatusTransition(from, to) => (
+                StatusCode::CONFLICT,
+                format!(
+                    "Cannot move order from {} to {}",
+                    from.to_string(),
+                    to.to_string()
+                ),
+            ),
+            LogisticsError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            LogisticsError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            LogisticsError::UnprocessableEntity(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg),
         };
 
         let body = Json(ApiError {
@@ -45,6 +56,15 @@ pub fn parse_uuid(id: &str) -> Result<Uuid, LogisticsError> {
         .map_err(|_| LogisticsError::ValidationError(format!("Invalid UUID format: {}", id)))
 }
 
+/// The user the auth middleware put in `X-User-ID`, if any.
+pub fn request_user_id(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("X-User-ID")
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PaginationParams {
     #[serde(default = "default_page")]
@@ -1,5 +1,6 @@
 use dotenv::dotenv;
 use std::env;
+use std::str::FromStr;
 use std::sync::OnceLock;
 
 static CONFIG: OnceLock<AppConfig> = OnceLock::new();
@@ -11,8 +12,17 @@ pub struct AppConfig {
     pub auth: AuthConfig,
     pub rabbitmq: RabbitMQConfig,
     pub grpc: GrpcConfig,
+    pub inventory_client: InventoryClientConfig,
     pub tracing: TracingConfig,
     pub order_producer: OrderProducerConfig,
+    pub outbox: OutboxConfig,
+    pub realtime: RealtimeConfig,
+    pub reservation_sweeper: ReservationSweeperConfig,
+    pub idempotency: IdempotencyConfig,
+    pub backorder: BackorderConfig,
+    pub allocation: AllocationConfig,
+    pub ledger: LedgerConfig,
+    pub lots: LotConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +57,8 @@ pub struct RabbitMQConfig {
     pub order_exchange: String,
     pub order_queue: String,
     pub retry_attempts: u32,
+    pub retry_base_delay_ms: u64,
+    pub retry_max_delay_ms: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -56,6 +68,57 @@ pub struct GrpcConfig {
     pub inventory_url: String,
 }
 
+/// What order creation does when the inventory service cannot be reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryUnavailablePolicy {
+    /// Refuse the order until the service is back
+    Reject,
+    /// Accept the order as `Pending` and check stock when it is processed
+    AcceptPending,
+    /// Reserve against the engine's own inventory levels
+    LocalCheck,
+}
+
+impl InventoryUnavailablePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InventoryUnavailablePolicy::Reject => "reject",
+            InventoryUnavailablePolicy::AcceptPending => "accept_pending",
+            InventoryUnavailablePolicy::LocalCheck => "local_check",
+        }
+    }
+}
+
+impl FromStr for InventoryUnavailablePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "reject" => Ok(InventoryUnavailablePolicy::Reject),
+            "accept_pending" => Ok(InventoryUnavailablePolicy::AcceptPending),
+            "local_check" => Ok(InventoryUnavailablePolicy::LocalCheck),
+            _ => Err(format!(
+                "Invalid inventory unavailable policy: {}. Must be one of: reject, accept_pending, local_check",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InventoryClientConfig {
+    pub call_timeout_ms: u64,
+    pub connect_timeout_ms: u64,
+    pub retry_attempts: u32,
+    pub retry_base_delay_ms: u64,
+    pub breaker_failure_threshold: u32,
+    pub breaker_open_seconds: u64,
+    pub unavailable_policy: InventoryUnavailablePolicy,
+    /// Whether this engine's stock levels are the system of record, in which
+    /// case new orders are not reserved with the inventory service as well
+    pub engine_authoritative: bool,
+}
+
 #[derive(Debug, Clone)]
 pub struct TracingConfig {
     pub environment: String,
@@ -76,6 +139,92 @@ pub struct OrderProducerConfig {
     pub randomize_interval: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    pub poll_interval_ms: u64,
+    pub batch_size: u32,
+    pub retention_hours: i32,
+    pub max_attempts: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RealtimeConfig {
+    pub channel_capacity: usize,
+    pub subscriber_buffer: usize,
+    pub history_size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReservationSweeperConfig {
+    pub enabled: bool,
+    pub interval_seconds: u64,
+    pub batch_size: u32,
+    pub reservation_ttl_minutes: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdempotencyConfig {
+    pub key_ttl_hours: i32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BackorderConfig {
+    pub default_restock_days: i64,
+}
+
+/// How order lines are assigned to the warehouses that fulfil them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationStrategyKind {
+    /// The warehouse that can supply the most of the order
+    SingleWarehouse,
+    /// The warehouse closest to the shipping address
+    Nearest,
+    /// The warehouse with the lowest fulfillment cost per unit
+    LowestCost,
+}
+
+impl AllocationStrategyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AllocationStrategyKind::SingleWarehouse => "single_warehouse",
+            AllocationStrategyKind::Nearest => "nearest",
+            AllocationStrategyKind::LowestCost => "lowest_cost",
+        }
+    }
+}
+
+impl FromStr for AllocationStrategyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "single_warehouse" => Ok(AllocationStrategyKind::SingleWarehouse),
+            "nearest" => Ok(AllocationStrategyKind::Nearest),
+            "lowest_cost" => Ok(AllocationStrategyKind::LowestCost),
+            _ => Err(format!(
+                "Invalid allocation strategy: {}. Must be one of: single_warehouse, nearest, lowest_cost",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AllocationConfig {
+    pub strategy: AllocationStrategyKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct LedgerConfig {
+    pub reconciliation_enabled: bool,
+    pub reconciliation_interval_seconds: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LotConfig {
+    pub expiry_sweep_interval_seconds: u64,
+}
+
 pub fn init() {
     dotenv().ok();
 
@@ -142,6 +291,14 @@ pub fn init() {
             .unwrap_or_else(|_| "3".to_string())
             .parse::<u32>()
             .unwrap_or(3),
+        retry_base_delay_ms: env::var("RABBITMQ_RETRY_BASE_DELAY_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<u64>()
+            .unwrap_or(1000),
+        retry_max_delay_ms: env::var("RABBITMQ_RETRY_MAX_DELAY_MS")
+            .unwrap_or_else(|_| "60000".to_string())
+            .parse::<u64>()
+            .unwrap_or(60000),
     };
 
     let grpc_config = GrpcConfig {
@@ -154,6 +311,41 @@ pub fn init() {
             .unwrap_or_else(|_| "http://localhost:50052".to_string()),
     };
 
+    let inventory_client_config = InventoryClientConfig {
+        call_timeout_ms: env::var("INVENTORY_CALL_TIMEOUT_MS")
+            .unwrap_or_else(|_| "2000".to_string())
+            .parse::<u64>()
+            .unwrap_or(2000),
+        connect_timeout_ms: env::var("INVENTORY_CONNECT_TIMEOUT_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<u64>()
+            .unwrap_or(1000),
+        retry_attempts: env::var("INVENTORY_RETRY_ATTEMPTS")
+            .unwrap_or_else(|_| "2".to_string())
+            .parse::<u32>()
+            .unwrap_or(2),
+        retry_base_delay_ms: env::var("INVENTORY_RETRY_BASE_DELAY_MS")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse::<u64>()
+            .unwrap_or(100),
+        breaker_failure_threshold: env::var("INVENTORY_BREAKER_FAILURE_THRESHOLD")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<u32>()
+            .unwrap_or(5),
+        breaker_open_seconds: env::var("INVENTORY_BREAKER_OPEN_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .unwrap_or(30),
+        unavailable_policy: env::var("INVENTORY_UNAVAILABLE_POLICY")
+            .unwrap_or_else(|_| "local_check".to_string())
+            .parse::<InventoryUnavailablePolicy>()
+            .unwrap_or(InventoryUnavailablePolicy::LocalCheck),
+        engine_authoritative: env::var("INVENTORY_ENGINE_AUTHORITATIVE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse::<bool>()
+            .unwrap_or(false),
+    };
+
     let cors_origins = env::var("CORS_ALLOWED_ORIGINS")
         .unwrap_or_else(|_| "*".to_string())
         .split(',')
@@ -206,14 +398,115 @@ pub fn init() {
             .unwrap_or(true),
     };
 
+    let outbox_config = OutboxConfig {
+        poll_interval_ms: env::var("OUTBOX_POLL_INTERVAL_MS")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<u64>()
+            .unwrap_or(1000),
+        batch_size: env::var("OUTBOX_BATCH_SIZE")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse::<u32>()
+            .unwrap_or(100),
+        retention_hours: env::var("OUTBOX_RETENTION_HOURS")
+            .unwrap_or_else(|_| "72".to_string())
+            .parse::<i32>()
+            .unwrap_or(72),
+        max_attempts: env::var("OUTBOX_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "10".to_string())
+            .parse::<i32>()
+            .unwrap_or(10),
+    };
+
+    let realtime_config = RealtimeConfig {
+        channel_capacity: env::var("ORDER_UPDATES_CHANNEL_CAPACITY")
+            .unwrap_or_else(|_| "1024".to_string())
+            .parse::<usize>()
+            .unwrap_or(1024),
+        subscriber_buffer: env::var("ORDER_UPDATES_SUBSCRIBER_BUFFER")
+            .unwrap_or_else(|_| "64".to_string())
+            .parse::<usize>()
+            .unwrap_or(64),
+        history_size: env::var("LIVE_FEED_HISTORY_SIZE")
+            .unwrap_or_else(|_| "1000".to_string())
+            .parse::<usize>()
+            .unwrap_or(1000),
+    };
+
+    let reservation_sweeper_config = ReservationSweeperConfig {
+        enabled: env::var("RESERVATION_SWEEPER_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .unwrap_or(true),
+        interval_seconds: env::var("RESERVATION_SWEEP_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .unwrap_or(30),
+        batch_size: env::var("RESERVATION_SWEEP_BATCH_SIZE")
+            .unwrap_or_else(|_| "100".to_string())
+            .parse::<u32>()
+            .unwrap_or(100),
+        reservation_ttl_minutes: env::var("RESERVATION_TTL_MINUTES")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<i64>()
+            .unwrap_or(30),
+    };
+
+    let idempotency_config = IdempotencyConfig {
+        key_ttl_hours: env::var("IDEMPOTENCY_KEY_TTL_HOURS")
+            .unwrap_or_else(|_| "24".to_string())
+            .parse::<i32>()
+            .unwrap_or(24),
+    };
+
+    let backorder_config = BackorderConfig {
+        default_restock_days: env::var("BACKORDER_DEFAULT_RESTOCK_DAYS")
+            .unwrap_or_else(|_| "14".to_string())
+            .parse::<i64>()
+            .unwrap_or(14),
+    };
+
+    let allocation_config = AllocationConfig {
+        strategy: env::var("ALLOCATION_STRATEGY")
+            .unwrap_or_else(|_| "single_warehouse".to_string())
+            .parse::<AllocationStrategyKind>()
+            .unwrap_or(AllocationStrategyKind::SingleWarehouse),
+    };
+
+    let ledger_config = LedgerConfig {
+        reconciliation_enabled: env::var("LEDGER_RECONCILIATION_ENABLED")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse::<bool>()
+            .unwrap_or(true),
+        reconciliation_interval_seconds: env::var("LEDGER_RECONCILIATION_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse::<u64>()
+            .unwrap_or(3600),
+    };
+
+    let lot_config = LotConfig {
+        expiry_sweep_interval_seconds: env::var("LOT_EXPIRY_SWEEP_INTERVAL_SECONDS")
+            .unwrap_or_else(|_| "900".to_string())
+            .parse::<u64>()
+            .unwrap_or(900),
+    };
+
     let app_config = AppConfig {
         server: server_config,
         database: database_config,
         auth: auth_config,
         rabbitmq: rabbitmq_config,
         grpc: grpc_config,
+        inventory_client: inventory_client_config,
         tracing: tracing_config,
         order_producer: order_producer_config,
+        outbox: outbox_config,
+        realtime: realtime_config,
+        reservation_sweeper: reservation_sweeper_config,
+        idempotency: idempotency_config,
+        backorder: backorder_config,
+        allocation: allocation_config,
+        ledger: ledger_config,
+        lots: lot_config,
     };
 
     CONFIG.set(app_config).expect("Failed to set app config");
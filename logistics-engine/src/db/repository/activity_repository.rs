@@ -0,0 +1,113 @@
+use crate::models::activity::{ActivityCursor, ActivityEntry, ActivityFilter, NewActivity};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Postgres, Row, Transaction};
+
+pub struct ActivityRepository {
+    pool: PgPool,
+}
+
+impl ActivityRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn to_offset_datetime(dt: DateTime<Utc>) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp_nanos(dt.timestamp_nanos_opt().unwrap_or(0) as i128)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    }
+
+    fn map_row_to_activity(row: sqlx::postgres::PgRow) -> Result<ActivityEntry, Error> {
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+
+        Ok(ActivityEntry {
+            id: row.try_get("id")?,
+            entity_type: row.try_get("entity_type")?,
+            entity_id: row.try_get("entity_id")?,
+            action: row.try_get("action")?,
+            severity: row.try_get("severity")?,
+            message: row.try_get("message")?,
+            actor: row.try_get("actor")?,
+            metadata: row.try_get("metadata")?,
+            created_at: Self::convert_datetime(created_at),
+        })
+    }
+
+    const INSERT: &'static str = r#"
+        INSERT INTO activity_log
+            (entity_type, entity_id, action, severity, message, actor, metadata)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#;
+
+    pub async fn create(&self, activity: &NewActivity) -> Result<(), Error> {
+        sqlx::query(Self::INSERT)
+            .bind(&activity.entity_type)
+            .bind(activity.entity_id)
+            .bind(&activity.action)
+            .bind(activity.severity.as_str())
+            .bind(&activity.message)
+            .bind(&activity.actor)
+            .bind(&activity.metadata)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        activity: &NewActivity,
+    ) -> Result<(), Error> {
+        sqlx::query(Self::INSERT)
+            .bind(&activity.entity_type)
+            .bind(activity.entity_id)
+            .bind(&activity.action)
+            .bind(activity.severity.as_str())
+            .bind(&activity.message)
+            .bind(&activity.actor)
+            .bind(&activity.metadata)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Activities matching `filter`, newest first, strictly after `cursor`.
+    pub async fn find_page(
+        &self,
+        filter: &ActivityFilter,
+        cursor: Option<ActivityCursor>,
+        limit: i64,
+    ) -> Result<Vec<ActivityEntry>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, entity_type, entity_id, action, severity, message, actor, metadata,
+                   created_at
+            FROM activity_log
+            WHERE ($1::text IS NULL OR entity_type = $1)
+              AND ($2::text IS NULL OR severity = $2)
+              AND ($3::timestamptz IS NULL OR created_at >= $3)
+              AND ($4::timestamptz IS NULL OR created_at < $4)
+              AND ($5::timestamptz IS NULL OR (created_at, id) < ($5, $6))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $7
+            "#,
+        )
+        .bind(&filter.entity_type)
+        .bind(filter.severity.map(|severity| severity.as_str()))
+        .bind(filter.from.map(Self::to_offset_datetime))
+        .bind(filter.to.map(Self::to_offset_datetime))
+        .bind(cursor.map(|cursor| Self::to_offset_datetime(cursor.created_at)))
+        .bind(cursor.map(|cursor| cursor.id))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Self::map_row_to_activity).collect()
+    }
+}
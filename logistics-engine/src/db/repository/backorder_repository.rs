@@ -0,0 +1,265 @@
+use crate::models::backorder::{BacklogEntry, Backorder, BackorderStatus};
+use crate::models::entities::order::OrderStatus;
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Postgres, Row, Transaction};
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct BackorderRepository {
+    pool: PgPool,
+}
+
+impl BackorderRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn to_offset_datetime(dt: DateTime<Utc>) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(dt.timestamp())
+            .unwrap_or_else(|_| OffsetDateTime::now_utc())
+    }
+
+    fn map_row_to_backorder(row: &sqlx::postgres::PgRow) -> Result<Backorder, Error> {
+        let status: String = row.try_get("status")?;
+        let expected_restock_at: Option<OffsetDateTime> = row.try_get("expected_restock_at")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+        let fulfilled_at: Option<OffsetDateTime> = row.try_get("fulfilled_at")?;
+
+        Ok(Backorder {
+            id: row.try_get("id")?,
+            order_id: row.try_get("order_id")?,
+            order_item_id: row.try_get("order_item_id")?,
+            product_id: row.try_get("product_id")?,
+            sku: row.try_get("sku")?,
+            quantity: row.try_get("quantity")?,
+            allocated: row.try_get("allocated")?,
+            status: BackorderStatus::from_str(&status).map_err(|e| Error::Decode(e.into()))?,
+            expected_restock_at: expected_restock_at.map(Self::convert_datetime),
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+            fulfilled_at: fulfilled_at.map(Self::convert_datetime),
+        })
+    }
+
+    const BACKORDER_COLUMNS: &'static str = r#"
+        b.id, b.order_id, b.order_item_id, b.product_id, b.sku, b.quantity, b.allocated,
+        b.status, b.expected_restock_at, b.created_at, b.updated_at, b.fulfilled_at
+        "#;
+
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        order_item_id: Uuid,
+        product_id: Uuid,
+        sku: &str,
+        quantity: i32,
+        expected_restock_at: Option<DateTime<Utc>>,
+    ) -> Result<Backorder, Error> {
+        let query = format!(
+            r#"
+            INSERT INTO backorders AS b
+                (order_id, order_item_id, product_id, sku, quantity, status, expected_restock_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING {}
+            "#,
+            Self::BACKORDER_COLUMNS
+        );
+
+        let row = sqlx::query(&query)
+            .bind(order_id)
+            .bind(order_item_id)
+            .bind(product_id)
+            .bind(sku)
+            .bind(quantity)
+            .bind(BackorderStatus::Pending.as_str())
+            .bind(expected_restock_at.map(Self::to_offset_datetime))
+            .fetch_one(&mut **tx)
+            .await?;
+
+        Self::map_row_to_backorder(&row)
+    }
+
+    pub async fn find_by_order_id(&self, order_id: Uuid) -> Result<Vec<Backorder>, Error> {
+        let query = format!(
+            "SELECT {} FROM backorders b WHERE b.order_id = $1 ORDER BY b.created_at",
+            Self::BACKORDER_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(order_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(Self::map_row_to_backorder).collect()
+    }
+
+    /// Pending backorders, oldest order first, optionally for one product.
+    pub async fn find_backlog(
+        &self,
+        product_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<BacklogEntry>, Error> {
+        let query = format!(
+            r#"
+            SELECT {}, i.name AS product_name, o.customer_id, o.created_at AS order_created_at
+            FROM backorders b
+            JOIN orders o ON o.id = b.order_id
+            JOIN inventory_items i ON i.id = b.product_id
+            WHERE b.status = $1 AND ($2::uuid IS NULL OR b.product_id = $2)
+            ORDER BY o.created_at, b.created_at
+            LIMIT $3 OFFSET $4
+            "#,
+            Self::BACKORDER_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(BackorderStatus::Pending.as_str())
+            .bind(product_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| -> Result<BacklogEntry, Error> {
+                let backorder = Self::map_row_to_backorder(row)?;
+                let order_created_at: OffsetDateTime = row.try_get("order_created_at")?;
+
+                Ok(BacklogEntry {
+                    outstanding: backorder.outstanding(),
+                    backorder,
+                    product_name: row.try_get("product_name")?,
+                    customer_id: row.try_get("customer_id")?,
+                    order_created_at: Self::convert_datetime(order_created_at),
+                })
+            })
+            .collect()
+    }
+
+    /// Pending backorders for the product with the status of their order,
+    /// oldest order first. The rows stay locked until the transaction ends, so
+    /// two restocks cannot allocate the same backorder.
+    pub async fn find_pending_for_product_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        product_id: Uuid,
+    ) -> Result<Vec<(Backorder, OrderStatus)>, Error> {
+        let query = format!(
+            r#"
+            SELECT {}, o.status AS order_status
+            FROM backorders b
+            JOIN orders o ON o.id = b.order_id
+            WHERE b.product_id = $1 AND b.status = $2
+            ORDER BY o.created_at, b.created_at
+            FOR UPDATE OF b
+            "#,
+            Self::BACKORDER_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(product_id)
+            .bind(BackorderStatus::Pending.as_str())
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.iter()
+            .map(|row| -> Result<(Backorder, OrderStatus), Error> {
+                Ok((
+                    Self::map_row_to_backorder(row)?,
+                    row.try_get("order_status")?,
+                ))
+            })
+            .collect()
+    }
+
+    /// Counts `quantity` more units as allocated; the backorder is fulfilled
+    /// once all of its units are.
+    pub async fn allocate_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        quantity: i32,
+    ) -> Result<Backorder, Error> {
+        let query = format!(
+            r#"
+            UPDATE backorders AS b
+            SET allocated = b.allocated + $2,
+                status = CASE WHEN b.allocated + $2 >= b.quantity THEN $3 ELSE b.status END,
+                fulfilled_at = CASE WHEN b.allocated + $2 >= b.quantity THEN NOW() ELSE b.fulfilled_at END,
+                updated_at = NOW()
+            WHERE b.id = $1
+            RETURNING {}
+            "#,
+            Self::BACKORDER_COLUMNS
+        );
+
+        let row = sqlx::query(&query)
+            .bind(id)
+            .bind(quantity)
+            .bind(BackorderStatus::Fulfilled.as_str())
+            .fetch_one(&mut **tx)
+            .await?;
+
+        Self::map_row_to_backorder(&row)
+    }
+
+    /// Cancels the order's pending backorders and returns how many backorders
+    /// the order has in any status.
+    pub async fn cancel_for_order_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<i64, Error> {
+        sqlx::query(
+            r#"
+            UPDATE backorders
+            SET status = $2, updated_at = NOW()
+            WHERE order_id = $1 AND status = $3
+            "#,
+        )
+        .bind(order_id)
+        .bind(BackorderStatus::Cancelled.as_str())
+        .bind(BackorderStatus::Pending.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM backorders WHERE order_id = $1")
+            .bind(order_id)
+            .fetch_one(&mut **tx)
+            .await?;
+
+        row.try_get("count")
+    }
+
+    pub async fn update_expected_restock(
+        &self,
+        id: Uuid,
+        expected_restock_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<Backorder>, Error> {
+        let query = format!(
+            r#"
+            UPDATE backorders AS b
+            SET expected_restock_at = $2, updated_at = NOW()
+            WHERE b.id = $1
+            RETURNING {}
+            "#,
+            Self::BACKORDER_COLUMNS
+        );
+
+        let row = sqlx::query(&query)
+            .bind(id)
+            .bind(expected_restock_at.map(Self::to_offset_datetime))
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(Self::map_row_to_backorder).transpose()
+    }
+}
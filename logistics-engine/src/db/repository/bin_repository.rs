@@ -0,0 +1,588 @@
+use crate::models::bin::{
+    bin_code, BinLocation, BinMovement, BinMovementType, BinStock, CreateBinDto, UpdateBinDto,
+    WarehouseBin,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Postgres, Row, Transaction};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct BinRepository {
+    pool: PgPool,
+}
+
+impl BinRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn map_row_to_bin(row: &sqlx::postgres::PgRow) -> Result<WarehouseBin, Error> {
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+        Ok(WarehouseBin {
+            id: row.try_get("id")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            zone: row.try_get("zone")?,
+            aisle: row.try_get("aisle")?,
+            bin: row.try_get("bin")?,
+            code: row.try_get("code")?,
+            pick_sequence: row.try_get("pick_sequence")?,
+            capacity: row.try_get("capacity")?,
+            active: row.try_get("active")?,
+            walk_order: row.try_get("walk_order")?,
+            stored: row.try_get("stored")?,
+            stock: Vec::new(),
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+        })
+    }
+
+    fn map_row_to_movement(row: &sqlx::postgres::PgRow) -> Result<BinMovement, Error> {
+        let movement_type: String = row.try_get("movement_type")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+
+        Ok(BinMovement {
+            id: row.try_get("id")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            item_id: row.try_get("item_id")?,
+            sku: row.try_get("sku")?,
+            from_bin_id: row.try_get("from_bin_id")?,
+            from_code: row.try_get("from_code")?,
+            to_bin_id: row.try_get("to_bin_id")?,
+            to_code: row.try_get("to_code")?,
+            quantity: row.try_get("quantity")?,
+            movement_type: BinMovementType::from_str(&movement_type)
+                .map_err(|e| Error::Decode(e.into()))?,
+            reference: row.try_get("reference")?,
+            user_id: row.try_get("user_id")?,
+            created_at: Self::convert_datetime(created_at),
+        })
+    }
+
+    const BIN_COLUMNS: &'static str = r#"
+        SELECT b.id, b.warehouse_id, b.zone, b.aisle, b.bin, b.code, b.pick_sequence,
+               b.capacity, b.active, w.walk_order,
+               COALESCE((SELECT SUM(s.quantity) FROM bin_stock s WHERE s.bin_id = b.id), 0)::INTEGER AS stored,
+               b.created_at, b.updated_at
+        FROM warehouse_bins b
+        JOIN warehouse_bin_walk w ON w.id = b.id
+        "#;
+
+    const MOVEMENT_COLUMNS: &'static str = r#"
+        SELECT m.id, m.warehouse_id, m.item_id, i.sku, m.from_bin_id, fb.code AS from_code,
+               m.to_bin_id, tb.code AS to_code, m.quantity, m.movement_type, m.reference,
+               m.user_id, m.created_at
+        FROM bin_movements m
+        JOIN inventory_items i ON i.id = m.item_id
+        LEFT JOIN warehouse_bins fb ON fb.id = m.from_bin_id
+        LEFT JOIN warehouse_bins tb ON tb.id = m.to_bin_id
+        "#;
+
+    // Fills in what each bin holds
+    async fn with_stock<'e, E>(
+        executor: E,
+        mut bins: Vec<WarehouseBin>,
+    ) -> Result<Vec<WarehouseBin>, Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        let ids = bins.iter().map(|bin| bin.id).collect::<Vec<_>>();
+        let rows = sqlx::query(
+            r#"
+            SELECT s.bin_id, s.item_id, i.sku, s.quantity
+            FROM bin_stock s
+            JOIN inventory_items i ON i.id = s.item_id
+            WHERE s.bin_id = ANY($1)
+            ORDER BY i.sku
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(executor)
+        .await?;
+
+        let mut stock: HashMap<Uuid, Vec<BinStock>> = HashMap::new();
+        for row in &rows {
+            stock
+                .entry(row.try_get("bin_id")?)
+                .or_default()
+                .push(BinStock {
+                    item_id: row.try_get("item_id")?,
+                    sku: row.try_get("sku")?,
+                    quantity: row.try_get("quantity")?,
+                });
+        }
+        for bin in &mut bins {
+            bin.stock = stock.remove(&bin.id).unwrap_or_default();
+        }
+
+        Ok(bins)
+    }
+
+    /// The warehouse's bins in walking order, optionally only those holding
+    /// `item_id`.
+    pub async fn find_by_warehouse(
+        &self,
+        warehouse_id: Uuid,
+        item_id: Option<Uuid>,
+    ) -> Result<Vec<WarehouseBin>, Error> {
+        let query = format!(
+            r#"{}
+            WHERE b.warehouse_id = $1
+              AND ($2::uuid IS NULL
+                   OR EXISTS (SELECT 1 FROM bin_stock s WHERE s.bin_id = b.id AND s.item_id = $2))
+            ORDER BY w.walk_order"#,
+            Self::BIN_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(warehouse_id)
+            .bind(item_id)
+            .fetch_all(&self.pool)
+            .await?;
+        let bins = rows
+            .iter()
+            .map(Self::map_row_to_bin)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Self::with_stock(&self.pool, bins).await
+    }
+
+    pub async fn find_by_id(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<WarehouseBin>, Error> {
+        let query = format!(
+            "{} WHERE b.warehouse_id = $1 AND b.id = $2",
+            Self::BIN_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(warehouse_id)
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => {
+                let bins = Self::with_stock(&self.pool, vec![Self::map_row_to_bin(&row)?]).await?;
+                Ok(bins.into_iter().next())
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Creates the bin, or returns `None` when the warehouse already has a bin
+    /// with its code.
+    pub async fn create(
+        &self,
+        warehouse_id: Uuid,
+        dto: &CreateBinDto,
+    ) -> Result<Option<WarehouseBin>, Error> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO warehouse_bins (warehouse_id, zone, aisle, bin, code, pick_sequence, capacity)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (warehouse_id, code) DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(&dto.zone)
+        .bind(&dto.aisle)
+        .bind(&dto.bin)
+        .bind(bin_code(&dto.zone, &dto.aisle, &dto.bin))
+        .bind(dto.pick_sequence)
+        .bind(dto.capacity)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => self.find_by_id(warehouse_id, row.try_get("id")?).await,
+            None => Ok(None),
+        }
+    }
+
+    pub async fn update(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+        dto: &UpdateBinDto,
+    ) -> Result<Option<WarehouseBin>, Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE warehouse_bins
+            SET pick_sequence = COALESCE($3, pick_sequence),
+                capacity = COALESCE($4, capacity),
+                active = COALESCE($5, active),
+                updated_at = NOW()
+            WHERE warehouse_id = $1 AND id = $2
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(id)
+        .bind(dto.pick_sequence)
+        .bind(dto.capacity)
+        .bind(dto.active)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.find_by_id(warehouse_id, id).await
+    }
+
+    /// Locks the bin until the transaction ends and returns it with what it
+    /// holds.
+    pub async fn lock_bin_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<WarehouseBin>, Error> {
+        let query = format!(
+            "{} WHERE b.warehouse_id = $1 AND b.id = $2 FOR UPDATE OF b",
+            Self::BIN_COLUMNS
+        );
+        let row = sqlx::query(&query)
+            .bind(warehouse_id)
+            .bind(id)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        match row {
+            Some(row) => {
+                let bins = Self::with_stock(&mut **tx, vec![Self::map_row_to_bin(&row)?]).await?;
+                Ok(bins.into_iter().next())
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Units of the item on hand in the warehouse that are not in any bin. The
+    /// item's level in the warehouse stays locked until the transaction ends,
+    /// so stock cannot leave the warehouse while it is being put away.
+    pub async fn lock_unbinned_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        item_id: Uuid,
+    ) -> Result<i32, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT quantity
+            FROM inventory_levels
+            WHERE item_id = $1 AND warehouse_id = $2
+            FOR UPDATE
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let on_hand: i32 = match row {
+            Some(row) => row.try_get("quantity")?,
+            None => return Ok(0),
+        };
+
+        let row = sqlx::query(
+            r#"
+            SELECT COALESCE(SUM(s.quantity), 0)::INTEGER AS binned
+            FROM bin_stock s
+            JOIN warehouse_bins b ON b.id = s.bin_id
+            WHERE b.warehouse_id = $1 AND s.item_id = $2
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(item_id)
+        .fetch_one(&mut **tx)
+        .await?;
+        let binned: i32 = row.try_get("binned")?;
+
+        Ok((on_hand - binned).max(0))
+    }
+
+    /// Units of the item on hand in the warehouse that are not in any bin.
+    pub async fn find_unbinned(&self, warehouse_id: Uuid, item_id: Uuid) -> Result<i32, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT GREATEST(
+                COALESCE((SELECT quantity FROM inventory_levels
+                          WHERE item_id = $2 AND warehouse_id = $1), 0)
+                - COALESCE((SELECT SUM(s.quantity) FROM bin_stock s
+                            JOIN warehouse_bins b ON b.id = s.bin_id
+                            WHERE b.warehouse_id = $1 AND s.item_id = $2), 0),
+                0
+            )::INTEGER AS unbinned
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(item_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        row.try_get("unbinned")
+    }
+
+    pub async fn add_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        bin_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO bin_stock (bin_id, item_id, quantity)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (bin_id, item_id) DO UPDATE
+            SET quantity = bin_stock.quantity + EXCLUDED.quantity, updated_at = NOW()
+            "#,
+        )
+        .bind(bin_id)
+        .bind(item_id)
+        .bind(quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    // Takes units of the item out of the bin, dropping its row once it is
+    // empty. Returns false when the bin holds fewer units.
+    async fn take_stock_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        bin_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+    ) -> Result<bool, Error> {
+        let row = sqlx::query(
+            r#"
+            UPDATE bin_stock
+            SET quantity = quantity - $3, updated_at = NOW()
+            WHERE bin_id = $1 AND item_id = $2 AND quantity > $3
+            RETURNING quantity
+            "#,
+        )
+        .bind(bin_id)
+        .bind(item_id)
+        .bind(quantity)
+        .fetch_optional(&mut **tx)
+        .await?;
+        if row.is_some() {
+            return Ok(true);
+        }
+
+        let result = sqlx::query(
+            "DELETE FROM bin_stock WHERE bin_id = $1 AND item_id = $2 AND quantity = $3",
+        )
+        .bind(bin_id)
+        .bind(item_id)
+        .bind(quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        bin_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+    ) -> Result<bool, Error> {
+        Self::take_stock_with_transaction(tx, bin_id, item_id, quantity).await
+    }
+
+    async fn insert_movement_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        item_id: Uuid,
+        from_bin_id: Option<Uuid>,
+        to_bin_id: Option<Uuid>,
+        quantity: i32,
+        movement_type: BinMovementType,
+        reference: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<Uuid, Error> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO bin_movements
+                (warehouse_id, item_id, from_bin_id, to_bin_id, quantity, movement_type, reference, user_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING id
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(item_id)
+        .bind(from_bin_id)
+        .bind(to_bin_id)
+        .bind(quantity)
+        .bind(movement_type.as_str())
+        .bind(reference)
+        .bind(user_id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        row.try_get("id")
+    }
+
+    /// Records a putaway or bin-to-bin move and returns it.
+    pub async fn record_movement_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        item_id: Uuid,
+        from_bin_id: Option<Uuid>,
+        to_bin_id: Option<Uuid>,
+        quantity: i32,
+        movement_type: BinMovementType,
+        reference: Option<&str>,
+        user_id: Option<&str>,
+    ) -> Result<BinMovement, Error> {
+        let id = Self::insert_movement_with_transaction(
+            tx,
+            warehouse_id,
+            item_id,
+            from_bin_id,
+            to_bin_id,
+            quantity,
+            movement_type,
+            reference,
+            user_id,
+        )
+        .await?;
+
+        let query = format!("{} WHERE m.id = $1", Self::MOVEMENT_COLUMNS);
+        let row = sqlx::query(&query).bind(id).fetch_one(&mut **tx).await?;
+
+        Self::map_row_to_movement(&row)
+    }
+
+    /// Takes units leaving the warehouse out of its bins in walking order, the
+    /// way a pick list sends pickers, and records each pick. Units beyond what
+    /// the bins hold come from unbinned stock.
+    pub async fn pick_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+        reference: &str,
+        user_id: Option<&str>,
+    ) -> Result<(), Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.bin_id, s.quantity
+            FROM bin_stock s
+            JOIN warehouse_bin_walk w ON w.id = s.bin_id
+            WHERE w.warehouse_id = $1 AND s.item_id = $2
+            ORDER BY w.walk_order
+            FOR UPDATE OF s
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(item_id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let mut remaining = quantity;
+        for row in &rows {
+            if remaining == 0 {
+                break;
+            }
+            let bin_id: Uuid = row.try_get("bin_id")?;
+            let held: i32 = row.try_get("quantity")?;
+            let take = held.min(remaining);
+
+            Self::take_stock_with_transaction(tx, bin_id, item_id, take).await?;
+            Self::insert_movement_with_transaction(
+                tx,
+                warehouse_id,
+                item_id,
+                Some(bin_id),
+                None,
+                take,
+                BinMovementType::Pick,
+                Some(reference),
+                user_id,
+            )
+            .await?;
+            remaining -= take;
+        }
+
+        Ok(())
+    }
+
+    /// Putaways, moves and picks in the warehouse, latest first.
+    pub async fn find_movements(
+        &self,
+        warehouse_id: Uuid,
+        bin_id: Option<Uuid>,
+        item_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<BinMovement>, Error> {
+        let query = format!(
+            r#"{}
+            WHERE m.warehouse_id = $1
+              AND ($2::uuid IS NULL OR m.from_bin_id = $2 OR m.to_bin_id = $2)
+              AND ($3::uuid IS NULL OR m.item_id = $3)
+            ORDER BY m.created_at DESC
+            LIMIT $4 OFFSET $5"#,
+            Self::MOVEMENT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(warehouse_id)
+            .bind(bin_id)
+            .bind(item_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(Self::map_row_to_movement).collect()
+    }
+
+    /// Where the items are stored, bin by bin in each warehouse's walking
+    /// order.
+    pub async fn find_locations(
+        &self,
+        item_ids: &[Uuid],
+        warehouse_ids: &[Uuid],
+    ) -> Result<Vec<BinLocation>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.bin_id, b.warehouse_id, b.code, s.item_id, s.quantity, w.walk_order
+            FROM bin_stock s
+            JOIN warehouse_bins b ON b.id = s.bin_id
+            JOIN warehouse_bin_walk w ON w.id = s.bin_id
+            WHERE s.item_id = ANY($1) AND b.warehouse_id = ANY($2)
+            ORDER BY b.warehouse_id, w.walk_order
+            "#,
+        )
+        .bind(item_ids)
+        .bind(warehouse_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(BinLocation {
+                    bin_id: row.try_get("bin_id")?,
+                    warehouse_id: row.try_get("warehouse_id")?,
+                    code: row.try_get("code")?,
+                    item_id: row.try_get("item_id")?,
+                    quantity: row.try_get("quantity")?,
+                    walk_order: row.try_get("walk_order")?,
+                })
+            })
+            .collect()
+    }
+}
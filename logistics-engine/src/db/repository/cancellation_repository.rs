@@ -0,0 +1,279 @@
+use crate::models::cancellation::{
+    CancellationStatus, CancellationStep, CancellationStepState, OrderCancellation, StepStatus,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Postgres, Row, Transaction};
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct CancellationRepository {
+    pool: PgPool,
+}
+
+impl CancellationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn decode_error(message: String) -> Error {
+        Error::Decode(message.into())
+    }
+
+    fn map_row_to_step(row: sqlx::postgres::PgRow) -> Result<CancellationStepState, Error> {
+        let step: String = row.try_get("step")?;
+        let status: String = row.try_get("status")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+        Ok(CancellationStepState {
+            step: CancellationStep::from_str(&step).map_err(Self::decode_error)?,
+            status: StepStatus::from_str(&status).map_err(Self::decode_error)?,
+            attempts: row.try_get("attempts")?,
+            last_error: row.try_get("last_error")?,
+            detail: row.try_get("detail")?,
+            updated_at: Self::convert_datetime(updated_at),
+        })
+    }
+
+    fn map_row_to_cancellation(
+        row: sqlx::postgres::PgRow,
+        steps: Vec<CancellationStepState>,
+    ) -> Result<OrderCancellation, Error> {
+        let status: String = row.try_get("status")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+        let completed_at: Option<OffsetDateTime> = row.try_get("completed_at")?;
+
+        Ok(OrderCancellation {
+            id: row.try_get("id")?,
+            order_id: row.try_get("order_id")?,
+            status: CancellationStatus::from_str(&status).map_err(Self::decode_error)?,
+            reason: row.try_get("reason")?,
+            requested_by: row.try_get("requested_by")?,
+            attempts: row.try_get("attempts")?,
+            last_error: row.try_get("last_error")?,
+            steps,
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+            completed_at: completed_at.map(Self::convert_datetime),
+        })
+    }
+
+    const SELECT_CANCELLATION: &'static str = r#"
+        SELECT id, order_id, status, reason, requested_by, attempts, last_error,
+               created_at, updated_at, completed_at
+        FROM order_cancellations
+        WHERE order_id = $1
+        "#;
+
+    const SELECT_STEPS: &'static str = r#"
+        SELECT step, status, attempts, last_error, detail, updated_at
+        FROM order_cancellation_steps
+        WHERE cancellation_id = $1
+        ORDER BY position
+        "#;
+
+    /// Starts a cancellation with every step pending.
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        reason: &str,
+        requested_by: Option<&str>,
+    ) -> Result<OrderCancellation, Error> {
+        let id: Uuid = sqlx::query(
+            r#"
+            INSERT INTO order_cancellations (order_id, status, reason, requested_by)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(order_id)
+        .bind(CancellationStatus::InProgress.as_str())
+        .bind(reason)
+        .bind(requested_by)
+        .fetch_one(&mut **tx)
+        .await?
+        .try_get("id")?;
+
+        let steps = CancellationStep::ALL
+            .iter()
+            .map(|step| step.as_str().to_string())
+            .collect::<Vec<_>>();
+        let positions = (0..steps.len() as i32).collect::<Vec<_>>();
+
+        sqlx::query(
+            r#"
+            INSERT INTO order_cancellation_steps (cancellation_id, step, position, status)
+            SELECT $1, step, position, $4
+            FROM UNNEST($2::text[], $3::int[]) AS s(step, position)
+            "#,
+        )
+        .bind(id)
+        .bind(&steps)
+        .bind(&positions)
+        .bind(StepStatus::Pending.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        let row = sqlx::query(Self::SELECT_CANCELLATION)
+            .bind(order_id)
+            .fetch_one(&mut **tx)
+            .await?;
+        let step_rows = sqlx::query(Self::SELECT_STEPS)
+            .bind(id)
+            .fetch_all(&mut **tx)
+            .await?;
+
+        let steps = step_rows
+            .into_iter()
+            .map(Self::map_row_to_step)
+            .collect::<Result<Vec<_>, Error>>()?;
+        Self::map_row_to_cancellation(row, steps)
+    }
+
+    pub async fn find_by_order_id(
+        &self,
+        order_id: Uuid,
+    ) -> Result<Option<OrderCancellation>, Error> {
+        let row = match sqlx::query(Self::SELECT_CANCELLATION)
+            .bind(order_id)
+            .fetch_optional(&self.pool)
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let id: Uuid = row.try_get("id")?;
+        let steps = sqlx::query(Self::SELECT_STEPS)
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Self::map_row_to_step)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Self::map_row_to_cancellation(row, steps).map(Some)
+    }
+
+    /// Takes over a failed cancellation, or one whose runner went away without
+    /// finishing it. Returns `false` when the cancellation is complete or still
+    /// being worked on.
+    pub async fn claim_for_retry(&self, order_id: Uuid) -> Result<bool, Error> {
+        let result = sqlx::query(
+            r#"
+            UPDATE order_cancellations
+            SET status = $2, attempts = attempts + 1, updated_at = NOW()
+            WHERE order_id = $1
+              AND (status = $3 OR (status = $2 AND updated_at < NOW() - INTERVAL '5 minutes'))
+            "#,
+        )
+        .bind(order_id)
+        .bind(CancellationStatus::InProgress.as_str())
+        .bind(CancellationStatus::Failed.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    const FINISH_STEP: &'static str = r#"
+        UPDATE order_cancellation_steps
+        SET status = $3, detail = $4, last_error = NULL, attempts = attempts + 1, updated_at = NOW()
+        WHERE cancellation_id = $1 AND step = $2
+        "#;
+
+    /// Marks a step `Completed` or `Skipped`.
+    pub async fn finish_step(
+        &self,
+        cancellation_id: Uuid,
+        step: CancellationStep,
+        status: StepStatus,
+        detail: Option<serde_json::Value>,
+    ) -> Result<(), Error> {
+        sqlx::query(Self::FINISH_STEP)
+            .bind(cancellation_id)
+            .bind(step.as_str())
+            .bind(status.as_str())
+            .bind(detail)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Marks a step done together with the changes it made, so it cannot run
+    /// twice.
+    pub async fn finish_step_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        cancellation_id: Uuid,
+        step: CancellationStep,
+        status: StepStatus,
+        detail: Option<serde_json::Value>,
+    ) -> Result<(), Error> {
+        sqlx::query(Self::FINISH_STEP)
+            .bind(cancellation_id)
+            .bind(step.as_str())
+            .bind(status.as_str())
+            .bind(detail)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn fail_step(
+        &self,
+        cancellation_id: Uuid,
+        step: CancellationStep,
+        error: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE order_cancellation_steps
+            SET status = $3, last_error = $4, attempts = attempts + 1, updated_at = NOW()
+            WHERE cancellation_id = $1 AND step = $2
+            "#,
+        )
+        .bind(cancellation_id)
+        .bind(step.as_str())
+        .bind(StepStatus::Failed.as_str())
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records how a run of the cancellation ended.
+    pub async fn finish(
+        &self,
+        cancellation_id: Uuid,
+        status: CancellationStatus,
+        last_error: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE order_cancellations
+            SET status = $2,
+                last_error = $3,
+                updated_at = NOW(),
+                completed_at = CASE WHEN $2 = 'completed' THEN NOW() ELSE NULL END
+            WHERE id = $1
+            "#,
+        )
+        .bind(cancellation_id)
+        .bind(status.as_str())
+        .bind(last_error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::models::idempotency::{IdempotencyRecord, IdempotencyStatus};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Row};
+use std::str::FromStr;
+
+pub struct IdempotencyRepository {
+    pool: PgPool,
+}
+
+impl IdempotencyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn map_row_to_record(row: sqlx::postgres::PgRow) -> Result<IdempotencyRecord, Error> {
+        let status: String = row.try_get("status")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let expires_at: OffsetDateTime = row.try_get("expires_at")?;
+
+        Ok(IdempotencyRecord {
+            idempotency_key: row.try_get("idempotency_key")?,
+            scope: row.try_get("scope")?,
+            fingerprint: row.try_get("fingerprint")?,
+            status: IdempotencyStatus::from_str(&status).map_err(|e| Error::Decode(e.into()))?,
+            response_status: row.try_get("response_status")?,
+            response_content_type: row.try_get("response_content_type")?,
+            response_body: row.try_get("response_body")?,
+            created_at: Self::convert_datetime(created_at),
+            expires_at: Self::convert_datetime(expires_at),
+        })
+    }
+
+    /// Records the key as in progress. Takes over keys that expired and keys
+    /// whose request has been in progress for longer than `stale_after_seconds`,
+    /// which only happens when it never finished. Returns `false` when the key
+    /// is held by another request.
+    pub async fn claim(
+        &self,
+        idempotency_key: &str,
+        scope: &str,
+        fingerprint: &str,
+        ttl_hours: i32,
+        stale_after_seconds: f64,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO idempotency_keys (idempotency_key, scope, fingerprint, status, expires_at)
+            VALUES ($1, $2, $3, $4, NOW() + make_interval(hours => $5))
+            ON CONFLICT (idempotency_key, scope) DO UPDATE
+            SET fingerprint = EXCLUDED.fingerprint,
+                status = EXCLUDED.status,
+                response_status = NULL,
+                response_content_type = NULL,
+                response_body = NULL,
+                created_at = NOW(),
+                expires_at = EXCLUDED.expires_at
+            WHERE idempotency_keys.expires_at <= NOW()
+               OR (idempotency_keys.status = $4
+                   AND idempotency_keys.created_at < NOW() - make_interval(secs => $6))
+            "#,
+        )
+        .bind(idempotency_key)
+        .bind(scope)
+        .bind(fingerprint)
+        .bind(IdempotencyStatus::InProgress.as_str())
+        .bind(ttl_hours)
+        .bind(stale_after_seconds)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find(
+        &self,
+        idempotency_key: &str,
+        scope: &str,
+    ) -> Result<Option<IdempotencyRecord>, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT idempotency_key, scope, fingerprint, status, response_status,
+                   response_content_type, response_body, created_at, expires_at
+            FROM idempotency_keys
+            WHERE idempotency_key = $1 AND scope = $2
+            "#,
+        )
+        .bind(idempotency_key)
+        .bind(scope)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(Self::map_row_to_record).transpose()
+    }
+
+    pub async fn complete(
+        &self,
+        idempotency_key: &str,
+        scope: &str,
+        response_status: i32,
+        response_content_type: Option<&str>,
+        response_body: &[u8],
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE idempotency_keys
+            SET status = $3, response_status = $4, response_content_type = $5, response_body = $6
+            WHERE idempotency_key = $1 AND scope = $2
+            "#,
+        )
+        .bind(idempotency_key)
+        .bind(scope)
+        .bind(IdempotencyStatus::Completed.as_str())
+        .bind(response_status)
+        .bind(response_content_type)
+        .bind(response_body)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets a key whose request did not complete, so a retry runs it again.
+    pub async fn release(&self, idempotency_key: &str, scope: &str) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_keys
+            WHERE idempotency_key = $1 AND scope = $2 AND status = $3
+            "#,
+        )
+        .bind(idempotency_key)
+        .bind(scope)
+        .bind(IdempotencyStatus::InProgress.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_expired(&self) -> Result<u64, Error> {
+        let result = sqlx::query("DELETE FROM idempotency_keys WHERE expires_at <= NOW()")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -1,16 +1,62 @@
-use crate::models::inventory::CreateTransactionDto;
+use super::BinRepository;
 use crate::models::inventory::{
-    CreateInventoryItemDto, CreateReservationDto, InventoryItem, InventoryReservation,
-    ReservationStatus, UpdateInventoryItemDto, UpdateReservationDto,
+    CreateInventoryItemDto, CreateReservationDto, InventoryItem, InventoryLevel,
+    InventoryReservation, InventoryTransaction, LedgerDiscrepancy, LedgerPosting,
+    ReservationStatus, StockReservation, TransactionType, UpdateInventoryItemDto,
+    UpdateReservationDto,
 };
+use crate::models::lot::{InventoryLot, LotQuantity, LotStatus, ReceiveLotDto};
 use crate::models::warehouse::Warehouse;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use num_traits::ToPrimitive;
 use rust_decimal;
-use sqlx::{types::time::OffsetDateTime, Error, PgPool, Row};
+use sqlx::{
+    error::{DatabaseError, ErrorKind},
+    types::time::{Date, Month, OffsetDateTime},
+    Error, PgPool, Postgres, Row, Transaction,
+};
+use std::fmt;
 use std::str::FromStr;
 use uuid::Uuid;
 
+/// A stock movement refused because it would take an item's on-hand quantity
+/// below zero. It is raised as a check violation, as a constraint on the item
+/// would be, and reaches callers as a conflict rather than a server error.
+#[derive(Debug)]
+pub struct NegativeStockError {
+    message: String,
+}
+
+impl fmt::Display for NegativeStockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for NegativeStockError {}
+
+impl DatabaseError for NegativeStockError {
+    fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::CheckViolation
+    }
+}
+
 pub struct InventoryRepository {
     pool: PgPool,
 }
@@ -216,14 +262,13 @@ impl InventoryRepository {
                 name = $2,
                 description = $3,   
                 warehouse_id = $4,
-                quantity = $5,
-                price = $6,
-                attributes = $7,
-                category = $8,
-                low_stock_threshold = $9,
-                overstock_threshold = $10,
+                price = $5,
+                attributes = $6,
+                category = $7,
+                low_stock_threshold = $8,
+                overstock_threshold = $9,
                 updated_at = NOW()
-            WHERE id = $11
+            WHERE id = $10
             RETURNING
                 id,
                 sku,
@@ -244,7 +289,6 @@ impl InventoryRepository {
         .bind(dto.name.unwrap_or(current_item.name))
         .bind(dto.description.or(current_item.description))
         .bind(dto.warehouse_id.unwrap_or(current_item.warehouse_id))
-        .bind(dto.quantity.unwrap_or(current_item.quantity))
         .bind(
             dto.price
                 .map(|p| p.to_f64().unwrap_or(0.0))
@@ -291,35 +335,6 @@ impl InventoryRepository {
         Ok(result.rows_affected() > 0)
     }
 
-    pub async fn adjust_quantity(
-        &self,
-        id: Uuid,
-        quantity_delta: i32,
-    ) -> Result<Option<InventoryItem>, Error> {
-        // Replace sqlx::query_as! with plain sqlx::query and custom mapper
-        let row_result = sqlx::query(
-            r#"
-            UPDATE inventory_items
-            SET
-                quantity = quantity + $1,
-                updated_at = NOW()
-            WHERE id = $2
-            RETURNING *
-            "#,
-        )
-        .bind(quantity_delta)
-        .bind(id)
-        .map(Self::map_row_to_inventory_item)
-        .fetch_optional(&self.pool)
-        .await?;
-
-        // Process optional result to handle nested Result
-        match row_result {
-            Some(row_result) => Ok(Some(row_result?)),
-            None => Ok(None),
-        }
-    }
-
     fn map_row_to_inventory_reservation(
         row: &sqlx::postgres::PgRow,
     ) -> Result<InventoryReservation, Error> {
@@ -422,6 +437,1590 @@ impl InventoryRepository {
         }
     }
 
+    fn map_row_to_inventory_level(row: sqlx::postgres::PgRow) -> Result<InventoryLevel, Error> {
+        let last_updated: OffsetDateTime = row.try_get("last_updated")?;
+
+        Ok(InventoryLevel {
+            item_id: row.try_get("item_id")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            sku: row.try_get("sku")?,
+            name: row.try_get("name")?,
+            quantity: row.try_get("quantity")?,
+            reserved: row.try_get("reserved")?,
+            available: row.try_get("available")?,
+            low_stock_threshold: row.try_get("low_stock_threshold")?,
+            last_updated: Self::convert_datetime(last_updated),
+        })
+    }
+
+    fn map_row_to_stock_reservation(row: sqlx::postgres::PgRow) -> Result<StockReservation, Error> {
+        let status: String = row.try_get("status")?;
+
+        Ok(StockReservation {
+            id: row.try_get("id")?,
+            order_id: row.try_get("order_id")?,
+            product_id: row.try_get("product_id")?,
+            sku: row.try_get("sku")?,
+            quantity: row.try_get("quantity")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            status: ReservationStatus::from(status),
+        })
+    }
+
+    /// Levels matching all of the given filters; empty filters match everything.
+    pub async fn find_levels(
+        &self,
+        item_ids: &[Uuid],
+        skus: &[String],
+        warehouse_id: Option<Uuid>,
+    ) -> Result<Vec<InventoryLevel>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                   l.available, i.low_stock_threshold, l.last_updated
+            FROM inventory_levels l
+            JOIN inventory_items i ON i.id = l.item_id
+            WHERE (cardinality($1::uuid[]) = 0 OR l.item_id = ANY($1))
+              AND (cardinality($2::text[]) = 0 OR i.sku = ANY($2))
+              AND ($3::uuid IS NULL OR l.warehouse_id = $3)
+            ORDER BY i.sku, l.warehouse_id
+            "#,
+        )
+        .bind(item_ids)
+        .bind(skus)
+        .bind(warehouse_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_level)
+            .collect()
+    }
+
+    /// Brings the item's level in its home warehouse in line with the item's
+    /// on-hand quantity after a manual change, keeping existing reservations
+    /// and leaving units of expired lots blocked. Units held in other
+    /// warehouses are not counted towards the home warehouse.
+    pub async fn sync_level(&self, item_id: Uuid) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO inventory_levels (item_id, warehouse_id, quantity, reserved, available, last_updated)
+            SELECT id, warehouse_id, home_quantity, 0, home_quantity, NOW()
+            FROM (
+                SELECT i.id, i.warehouse_id,
+                       (i.quantity - COALESCE((
+                            SELECT SUM(o.quantity) FROM inventory_levels o
+                            WHERE o.item_id = i.id AND o.warehouse_id <> i.warehouse_id
+                        ), 0))::INTEGER AS home_quantity
+                FROM inventory_items i
+                WHERE i.id = $1
+            ) home
+            ON CONFLICT (item_id, warehouse_id) DO UPDATE
+            SET quantity = EXCLUDED.quantity,
+                available = EXCLUDED.quantity - inventory_levels.reserved - COALESCE((
+                    SELECT SUM(lot.quantity - lot.reserved) FROM inventory_lots lot
+                    WHERE lot.item_id = inventory_levels.item_id
+                      AND lot.warehouse_id = inventory_levels.warehouse_id
+                      AND lot.status = $2
+                ), 0)::INTEGER,
+                last_updated = NOW()
+            "#,
+        )
+        .bind(item_id)
+        .bind(LotStatus::Expired.as_str())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Items created before levels were tracked get a level seeded from their
+    // on-hand quantity the first time stock is moved
+    async fn ensure_level_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO inventory_levels (item_id, warehouse_id, quantity, reserved, available, last_updated)
+            SELECT id, warehouse_id, quantity, 0, quantity, NOW()
+            FROM inventory_items
+            WHERE id = $1
+            ON CONFLICT (item_id, warehouse_id) DO NOTHING
+            "#,
+        )
+        .bind(item_id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Posts a movement to the inventory ledger: moves the item's on-hand
+    /// quantity by the movement's effect and appends an immutable transaction
+    /// stamped with the resulting balance. Every change to an item's on-hand
+    /// quantity goes through here, so its ledger entries always sum to it.
+    /// Returns `None` without posting when the item does not exist or the
+    /// movement would take its on-hand quantity below zero.
+    pub async fn post_transaction_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        posting: &LedgerPosting,
+    ) -> Result<Option<InventoryTransaction>, Error> {
+        // Locks the item, so its entries are appended one at a time and each
+        // balance follows from the one before
+        let item = sqlx::query(
+            r#"
+            UPDATE inventory_items
+            SET quantity = quantity + $2, updated_at = NOW()
+            WHERE id = $1 AND quantity + $2 >= 0
+            RETURNING quantity
+            "#,
+        )
+        .bind(posting.item_id)
+        .bind(posting.transaction_type.on_hand_delta(posting.quantity))
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let balance: i32 = match item {
+            Some(row) => row.try_get("quantity")?,
+            None => return Ok(None),
+        };
+
+        // Units leaving the warehouse are picked out of its bins
+        let delta = posting.transaction_type.on_hand_delta(posting.quantity);
+        if delta < 0 && posting.pick_from_bins {
+            BinRepository::pick_with_transaction(
+                tx,
+                posting.warehouse_id,
+                posting.item_id,
+                -delta,
+                &posting.reference,
+                Some(posting.user_id.as_str()),
+            )
+            .await?;
+        }
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO inventory_transactions
+                (id, item_id, warehouse_id, quantity, "type", reference, user_id, balance_after, timestamp)
+            VALUES (gen_random_uuid(), $1, $2, $3, $4, $5, $6, $7, NOW())
+            RETURNING
+                id, item_id, warehouse_id, quantity::BIGINT AS quantity, "type",
+                reference, user_id, balance_after, timestamp,
+                (SELECT name FROM inventory_items WHERE id = item_id) as item_name,
+                (SELECT sku FROM inventory_items WHERE id = item_id) as item_sku,
+                (SELECT name FROM warehouses WHERE id = warehouse_id) as warehouse_name
+            "#,
+        )
+        .bind(posting.item_id)
+        .bind(posting.warehouse_id)
+        .bind(posting.quantity)
+        .bind(posting.transaction_type.to_string())
+        .bind(&posting.reference)
+        .bind(&posting.user_id)
+        .bind(balance)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Self::map_row_to_inventory_transaction(row).map(Some)
+    }
+
+    // Stock movements are checked against the warehouse levels before they are
+    // posted, so a refused posting means the item and its levels disagree
+    async fn post_movement_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        posting: LedgerPosting,
+    ) -> Result<(), Error> {
+        match self.post_transaction_with_transaction(tx, &posting).await? {
+            Some(_) => Ok(()),
+            None => Err(Error::Database(Box::new(NegativeStockError {
+                message: format!(
+                    "Posting {} {} would take item {} below zero on hand",
+                    posting.transaction_type.to_string(),
+                    posting.quantity,
+                    posting.item_id
+                ),
+            }))),
+        }
+    }
+
+    /// Levels that reservations of the given items draw from: the item's home
+    /// warehouse, or `warehouse_id` when one is given.
+    pub async fn find_reservable_levels(
+        &self,
+        item_ids: &[Uuid],
+        warehouse_id: Option<Uuid>,
+    ) -> Result<Vec<InventoryLevel>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                   l.available, i.low_stock_threshold, l.last_updated
+            FROM inventory_levels l
+            JOIN inventory_items i ON i.id = l.item_id
+            WHERE l.item_id = ANY($1) AND l.warehouse_id = COALESCE($2, i.warehouse_id)
+            "#,
+        )
+        .bind(item_ids)
+        .bind(warehouse_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_level)
+            .collect()
+    }
+
+    /// The item's level in `warehouse_id`, or its home warehouse when `None`,
+    /// locked until the transaction ends so nothing else reserves from it in
+    /// between.
+    pub async fn lock_reservable_level_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Option<Uuid>,
+    ) -> Result<Option<InventoryLevel>, Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+        self.expire_lots_with_transaction(tx, &[item_id]).await?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                   l.available, i.low_stock_threshold, l.last_updated
+            FROM inventory_levels l
+            JOIN inventory_items i ON i.id = l.item_id
+            WHERE l.item_id = $1 AND l.warehouse_id = COALESCE($2, i.warehouse_id)
+            FOR UPDATE OF l
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        row.map(Self::map_row_to_inventory_level).transpose()
+    }
+
+    const STOCK_SITE_QUERY: &'static str = r#"
+        SELECT l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+               l.available, i.low_stock_threshold, l.last_updated
+        FROM inventory_levels l
+        JOIN inventory_items i ON i.id = l.item_id
+        JOIN warehouses w ON w.id = l.warehouse_id
+        WHERE l.item_id = ANY($1) AND w.active = true
+        ORDER BY l.item_id, l.warehouse_id
+        "#;
+
+    /// The items' levels in every active warehouse that stocks them.
+    pub async fn find_stock_sites(&self, item_ids: &[Uuid]) -> Result<Vec<InventoryLevel>, Error> {
+        let rows = sqlx::query(Self::STOCK_SITE_QUERY)
+            .bind(item_ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_level)
+            .collect()
+    }
+
+    /// Like `find_stock_sites`, with the levels locked until the transaction
+    /// ends. Rows are locked in a fixed order so concurrent orders for the same
+    /// items cannot deadlock.
+    pub async fn lock_stock_sites_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_ids: &[Uuid],
+    ) -> Result<Vec<InventoryLevel>, Error> {
+        for item_id in item_ids {
+            Self::ensure_level_with_transaction(tx, *item_id).await?;
+        }
+        self.expire_lots_with_transaction(tx, item_ids).await?;
+
+        let query = format!("{} FOR UPDATE OF l", Self::STOCK_SITE_QUERY);
+        let rows = sqlx::query(&query)
+            .bind(item_ids)
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_level)
+            .collect()
+    }
+
+    /// Moves `quantity` units of the item from available to reserved in
+    /// `warehouse_id`, or the item's home warehouse when `None`, and records the
+    /// reservation against the lots that expire first. Returns `None` without
+    /// changing anything when not enough units are available there.
+    pub async fn reserve_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+        warehouse_id: Option<Uuid>,
+        expires_at: DateTime<Utc>,
+        user_id: &str,
+    ) -> Result<Option<(StockReservation, InventoryLevel)>, Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+        self.expire_lots_with_transaction(tx, &[item_id]).await?;
+
+        let level = sqlx::query(
+            r#"
+            UPDATE inventory_levels l
+            SET reserved = l.reserved + $2, available = l.available - $2, last_updated = NOW()
+            FROM inventory_items i
+            WHERE l.item_id = $1 AND i.id = l.item_id
+              AND l.warehouse_id = COALESCE($3, i.warehouse_id)
+              AND l.available >= $2
+            RETURNING l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                      l.available, i.low_stock_threshold, l.last_updated
+            "#,
+        )
+        .bind(item_id)
+        .bind(quantity)
+        .bind(warehouse_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let level = match level {
+            Some(row) => Self::map_row_to_inventory_level(row)?,
+            None => return Ok(None),
+        };
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO inventory_reservations
+                (id, order_id, product_id, warehouse_id, sku, quantity, status, expires_at, created_at, updated_at)
+            VALUES (gen_random_uuid(), $1, $2, $3, $4, $5, $6, $7, NOW(), NOW())
+            RETURNING id, order_id, product_id, warehouse_id, sku, quantity, status
+            "#,
+        )
+        .bind(order_id)
+        .bind(item_id)
+        .bind(level.warehouse_id)
+        .bind(&level.sku)
+        .bind(quantity)
+        .bind(ReservationStatus::Pending.to_string())
+        .bind(
+            OffsetDateTime::from_unix_timestamp(expires_at.timestamp())
+                .unwrap_or_else(|_| OffsetDateTime::now_utc()),
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        let reservation = Self::map_row_to_stock_reservation(row)?;
+        Self::reserve_lots_with_transaction(tx, &reservation).await?;
+
+        self.post_movement_with_transaction(
+            tx,
+            LedgerPosting::new(
+                item_id,
+                level.warehouse_id,
+                quantity,
+                TransactionType::Allocate,
+                &format!("reservation:{}", reservation.id),
+                user_id,
+            ),
+        )
+        .await?;
+
+        Ok(Some((reservation, level)))
+    }
+
+    const STOCK_RESERVATION_COLUMNS: &'static str = r#"
+        SELECT r.id, r.order_id, r.product_id, COALESCE(r.warehouse_id, i.warehouse_id) AS warehouse_id,
+               r.sku, r.quantity, r.status
+        FROM inventory_reservations r
+        JOIN inventory_items i ON i.id = r.product_id
+        "#;
+
+    /// All reservations of an order, locked until the transaction ends.
+    pub async fn find_stock_reservations_for_order_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<Vec<StockReservation>, Error> {
+        let query = format!(
+            "{} WHERE r.order_id = $1 ORDER BY r.created_at FOR UPDATE OF r",
+            Self::STOCK_RESERVATION_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(order_id)
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_stock_reservation)
+            .collect()
+    }
+
+    pub async fn find_stock_reservation_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<Option<StockReservation>, Error> {
+        let query = format!(
+            "{} WHERE r.id = $1 FOR UPDATE OF r",
+            Self::STOCK_RESERVATION_COLUMNS
+        );
+
+        let row = sqlx::query(&query)
+            .bind(id)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        row.map(Self::map_row_to_stock_reservation).transpose()
+    }
+
+    /// Claims pending reservations that are past `expires_at`. The rows stay
+    /// locked until the transaction ends, so concurrent sweepers skip them.
+    pub async fn claim_expired_reservations_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        limit: i64,
+    ) -> Result<Vec<StockReservation>, Error> {
+        let query = format!(
+            "{} WHERE r.status = $1 AND r.expires_at <= NOW() ORDER BY r.expires_at LIMIT $2 FOR UPDATE OF r SKIP LOCKED",
+            Self::STOCK_RESERVATION_COLUMNS
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(ReservationStatus::Pending.to_string())
+            .bind(limit)
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_stock_reservation)
+            .collect()
+    }
+
+    async fn set_reservation_status_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        status: ReservationStatus,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE inventory_reservations
+            SET status = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status.to_string())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Ships the reserved units out of stock and marks the reservation
+    /// `Confirmed`. A reservation that lapsed before it was committed takes its
+    /// units from available stock instead; returns `false` when there are not
+    /// enough.
+    pub async fn commit_reservation_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &StockReservation,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<bool, Error> {
+        match reservation.status {
+            ReservationStatus::Confirmed => return Ok(true),
+            ReservationStatus::Pending => {
+                sqlx::query(
+                    r#"
+                    UPDATE inventory_levels
+                    SET reserved = GREATEST(reserved - $3, 0), quantity = quantity - $3, last_updated = NOW()
+                    WHERE item_id = $1 AND warehouse_id = $2
+                    "#,
+                )
+                .bind(reservation.product_id)
+                .bind(reservation.warehouse_id)
+                .bind(reservation.quantity)
+                .execute(&mut **tx)
+                .await?;
+                Self::commit_reservation_lots_with_transaction(tx, reservation.id).await?;
+
+                self.post_movement_with_transaction(
+                    tx,
+                    LedgerPosting::new(
+                        reservation.product_id,
+                        reservation.warehouse_id,
+                        reservation.quantity,
+                        TransactionType::Remove,
+                        reference,
+                        user_id,
+                    ),
+                )
+                .await?;
+            }
+            ReservationStatus::Released | ReservationStatus::Rejected => {
+                let deducted = self
+                    .deduct_stock_from_with_transaction(
+                        tx,
+                        reservation.product_id,
+                        Some(reservation.warehouse_id),
+                        reservation.quantity,
+                        reference,
+                        user_id,
+                    )
+                    .await?;
+                if deducted.is_none() {
+                    return Ok(false);
+                }
+            }
+        }
+
+        Self::set_reservation_status_with_transaction(
+            tx,
+            reservation.id,
+            ReservationStatus::Confirmed,
+        )
+        .await?;
+
+        Ok(true)
+    }
+
+    /// Gives the units back: a pending reservation moves them from reserved to
+    /// available on the lots it held, a confirmed one puts them back on hand in
+    /// its warehouse and on the lots it shipped from. Either way the
+    /// reservation ends up `Released`.
+    pub async fn release_reservation_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &StockReservation,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<(), Error> {
+        match reservation.status {
+            ReservationStatus::Pending => {
+                sqlx::query(
+                    r#"
+                    UPDATE inventory_levels
+                    SET reserved = GREATEST(reserved - $3, 0), available = available + $3, last_updated = NOW()
+                    WHERE item_id = $1 AND warehouse_id = $2
+                    "#,
+                )
+                .bind(reservation.product_id)
+                .bind(reservation.warehouse_id)
+                .bind(reservation.quantity)
+                .execute(&mut **tx)
+                .await?;
+                Self::release_reservation_lots_with_transaction(tx, reservation).await?;
+            }
+            ReservationStatus::Confirmed => {
+                self.return_stock_to_with_transaction(
+                    tx,
+                    reservation.product_id,
+                    Some(reservation.warehouse_id),
+                    reservation.quantity,
+                    reference,
+                    user_id,
+                )
+                .await?;
+                Self::restore_reservation_lots_with_transaction(tx, reservation).await?;
+            }
+            // Nothing is held for rejected or already released reservations
+            ReservationStatus::Rejected | ReservationStatus::Released => return Ok(()),
+        }
+
+        Self::set_reservation_status_with_transaction(
+            tx,
+            reservation.id,
+            ReservationStatus::Released,
+        )
+        .await?;
+
+        if reservation.status == ReservationStatus::Pending {
+            self.post_movement_with_transaction(
+                tx,
+                LedgerPosting::new(
+                    reservation.product_id,
+                    reservation.warehouse_id,
+                    reservation.quantity,
+                    TransactionType::Release,
+                    reference,
+                    user_id,
+                ),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves units between the order's committed reservation of the item in
+    /// the warehouse and stock: a positive `quantity` puts units the order no
+    /// longer holds back on hand, onto the lots they shipped from, a negative
+    /// one takes units from available stock for it again. The reservation's
+    /// quantity follows, so a later release returns only what it still holds.
+    /// Returns `false` without changing anything when the order has no such
+    /// reservation, or not enough units are held or available.
+    pub async fn adjust_committed_reservation_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<bool, Error> {
+        let query = format!(
+            r#"{} WHERE r.order_id = $1 AND r.product_id = $2
+                  AND COALESCE(r.warehouse_id, i.warehouse_id) = $3 AND r.status = $4
+                ORDER BY r.quantity DESC, r.created_at
+                LIMIT 1
+                FOR UPDATE OF r"#,
+            Self::STOCK_RESERVATION_COLUMNS
+        );
+        let reservation = match sqlx::query(&query)
+            .bind(order_id)
+            .bind(item_id)
+            .bind(warehouse_id)
+            .bind(ReservationStatus::Confirmed.to_string())
+            .fetch_optional(&mut **tx)
+            .await?
+        {
+            Some(row) => Self::map_row_to_stock_reservation(row)?,
+            None => return Ok(false),
+        };
+
+        if quantity > 0 {
+            if quantity > reservation.quantity {
+                return Ok(false);
+            }
+            self.return_stock_to_with_transaction(
+                tx,
+                item_id,
+                Some(warehouse_id),
+                quantity,
+                reference,
+                user_id,
+            )
+            .await?;
+            Self::restore_some_reservation_lots_with_transaction(tx, &reservation, quantity)
+                .await?;
+        } else if quantity < 0 {
+            let taken = self
+                .deduct_stock_from_with_transaction(
+                    tx,
+                    item_id,
+                    Some(warehouse_id),
+                    -quantity,
+                    reference,
+                    user_id,
+                )
+                .await?;
+            if taken.is_none() {
+                return Ok(false);
+            }
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE inventory_reservations
+            SET quantity = quantity - $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(reservation.id)
+        .bind(quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(true)
+    }
+
+    /// Takes units straight from available stock, out of the lots that expire
+    /// first. Returns `None` when not enough units are available.
+    pub async fn deduct_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<Option<InventoryLevel>, Error> {
+        self.deduct_stock_from_with_transaction(tx, item_id, None, quantity, reference, user_id)
+            .await
+    }
+
+    /// Like `deduct_stock_with_transaction`, but takes the units from
+    /// `warehouse_id` rather than the item's home warehouse when one is given.
+    pub async fn deduct_stock_from_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Option<Uuid>,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<Option<InventoryLevel>, Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+        self.expire_lots_with_transaction(tx, &[item_id]).await?;
+
+        let level = sqlx::query(
+            r#"
+            UPDATE inventory_levels l
+            SET quantity = l.quantity - $2, available = l.available - $2, last_updated = NOW()
+            FROM inventory_items i
+            WHERE l.item_id = $1 AND i.id = l.item_id
+              AND l.warehouse_id = COALESCE($3, i.warehouse_id)
+              AND l.available >= $2
+            RETURNING l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                      l.available, i.low_stock_threshold, l.last_updated
+            "#,
+        )
+        .bind(item_id)
+        .bind(quantity)
+        .bind(warehouse_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let level = match level {
+            Some(row) => Self::map_row_to_inventory_level(row)?,
+            None => return Ok(None),
+        };
+        self.consume_lots_with_transaction(tx, item_id, level.warehouse_id, quantity, false)
+            .await?;
+
+        self.post_movement_with_transaction(
+            tx,
+            LedgerPosting::new(
+                item_id,
+                level.warehouse_id,
+                quantity,
+                TransactionType::Remove,
+                reference,
+                user_id,
+            ),
+        )
+        .await?;
+
+        Ok(Some(level))
+    }
+
+    /// Puts units back on hand and makes them available again. Returns `None`
+    /// when the item no longer exists.
+    pub async fn return_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<Option<InventoryLevel>, Error> {
+        self.return_stock_to_with_transaction(tx, item_id, None, quantity, reference, user_id)
+            .await
+    }
+
+    /// Like `return_stock_with_transaction`, but puts the units into
+    /// `warehouse_id` rather than the item's home warehouse when one is given,
+    /// creating the level there if the item had none.
+    pub async fn return_stock_to_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Option<Uuid>,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<Option<InventoryLevel>, Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+
+        let level = sqlx::query(
+            r#"
+            WITH credited AS (
+                INSERT INTO inventory_levels (item_id, warehouse_id, quantity, reserved, available, last_updated)
+                SELECT id, COALESCE($3, warehouse_id), $2, 0, $2, NOW()
+                FROM inventory_items
+                WHERE id = $1
+                ON CONFLICT (item_id, warehouse_id) DO UPDATE
+                SET quantity = inventory_levels.quantity + EXCLUDED.quantity,
+                    available = inventory_levels.available + EXCLUDED.quantity,
+                    last_updated = NOW()
+                RETURNING item_id, warehouse_id, quantity, reserved, available, last_updated
+            )
+            SELECT l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                   l.available, i.low_stock_threshold, l.last_updated
+            FROM credited l
+            JOIN inventory_items i ON i.id = l.item_id
+            "#,
+        )
+        .bind(item_id)
+        .bind(quantity)
+        .bind(warehouse_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let level = match level {
+            Some(row) => Self::map_row_to_inventory_level(row)?,
+            None => return Ok(None),
+        };
+
+        self.post_movement_with_transaction(
+            tx,
+            LedgerPosting::new(
+                item_id,
+                level.warehouse_id,
+                quantity,
+                TransactionType::Add,
+                reference,
+                user_id,
+            ),
+        )
+        .await?;
+
+        Ok(Some(level))
+    }
+
+    /// Takes units of the item out of `warehouse_id` for a transfer, from the
+    /// lots that expire first, and posts the `transfer_out` leg. The units leave
+    /// the item's on-hand quantity until they are received. Returns the level
+    /// with the lots the units came from, or `None` without changing anything
+    /// when not enough units are available there.
+    pub async fn dispatch_transfer_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<Option<(InventoryLevel, Vec<LotQuantity>)>, Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+        self.expire_lots_with_transaction(tx, &[item_id]).await?;
+
+        let level = sqlx::query(
+            r#"
+            UPDATE inventory_levels l
+            SET quantity = l.quantity - $3, available = l.available - $3, last_updated = NOW()
+            FROM inventory_items i
+            WHERE l.item_id = $1 AND l.warehouse_id = $2 AND i.id = l.item_id
+              AND l.available >= $3
+            RETURNING l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                      l.available, i.low_stock_threshold, l.last_updated
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(quantity)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        let level = match level {
+            Some(row) => Self::map_row_to_inventory_level(row)?,
+            None => return Ok(None),
+        };
+        let lots = self
+            .consume_lots_with_transaction(tx, item_id, warehouse_id, quantity, false)
+            .await?;
+
+        self.post_movement_with_transaction(
+            tx,
+            LedgerPosting::new(
+                item_id,
+                warehouse_id,
+                quantity,
+                TransactionType::TransferOut,
+                reference,
+                user_id,
+            ),
+        )
+        .await?;
+
+        Ok(Some((level, lots)))
+    }
+
+    /// Puts the units that arrived on a transfer into `warehouse_id`, the ones
+    /// in `lots` back into the lots they were dispatched from. The
+    /// `transfer_in` leg always matches the `transfer_out` leg, so a shortage
+    /// or overage is posted as a separate `remove` or `add` at the destination.
+    pub async fn receive_transfer_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        shipped: i32,
+        received: i32,
+        lots: &[LotQuantity],
+        reference: &str,
+        user_id: &str,
+    ) -> Result<InventoryLevel, Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+
+        let row = sqlx::query(
+            r#"
+            WITH credited AS (
+                INSERT INTO inventory_levels (item_id, warehouse_id, quantity, reserved, available, last_updated)
+                VALUES ($1, $2, $3, 0, $3, NOW())
+                ON CONFLICT (item_id, warehouse_id) DO UPDATE
+                SET quantity = inventory_levels.quantity + EXCLUDED.quantity,
+                    available = inventory_levels.available + EXCLUDED.quantity,
+                    last_updated = NOW()
+                RETURNING item_id, warehouse_id, quantity, reserved, available, last_updated
+            )
+            SELECT l.item_id, l.warehouse_id, i.sku, i.name, l.quantity, l.reserved,
+                   l.available, i.low_stock_threshold, l.last_updated
+            FROM credited l
+            JOIN inventory_items i ON i.id = l.item_id
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(received)
+        .fetch_one(&mut **tx)
+        .await?;
+        let level = Self::map_row_to_inventory_level(row)?;
+
+        self.post_movement_with_transaction(
+            tx,
+            LedgerPosting::new(
+                item_id,
+                warehouse_id,
+                shipped,
+                TransactionType::TransferIn,
+                reference,
+                user_id,
+            ),
+        )
+        .await?;
+
+        let discrepancy = received - shipped;
+        if discrepancy != 0 {
+            let transaction_type = if discrepancy < 0 {
+                TransactionType::Remove
+            } else {
+                TransactionType::Add
+            };
+            // A shortfall comes off the units that were in transit, which
+            // were never put away in the destination's bins
+            self.post_movement_with_transaction(
+                tx,
+                LedgerPosting::new(
+                    item_id,
+                    warehouse_id,
+                    discrepancy.abs(),
+                    transaction_type,
+                    &format!("{}:discrepancy", reference),
+                    user_id,
+                )
+                .without_bin_pick(),
+            )
+            .await?;
+        }
+
+        for lot in lots {
+            Self::add_to_lot_with_transaction(tx, item_id, warehouse_id, lot).await?;
+        }
+        // Lots that expired in transit are blocked on arrival
+        self.expire_lots_with_transaction(tx, &[item_id]).await?;
+
+        Ok(level)
+    }
+
+    fn convert_date(date: Date) -> NaiveDate {
+        NaiveDate::from_ymd_opt(date.year(), date.month() as u32, date.day() as u32)
+            .unwrap_or_default()
+    }
+
+    fn to_sql_date(date: NaiveDate) -> Date {
+        Month::try_from(date.month() as u8)
+            .ok()
+            .and_then(|month| Date::from_calendar_date(date.year(), month, date.day() as u8).ok())
+            .unwrap_or(Date::MIN)
+    }
+
+    fn map_row_to_inventory_lot(row: sqlx::postgres::PgRow) -> Result<InventoryLot, Error> {
+        let status: String = row.try_get("status")?;
+        let status = LotStatus::from_str(&status).map_err(|e| Error::Decode(e.into()))?;
+        let manufactured_at: Option<Date> = row.try_get("manufactured_at")?;
+        let expires_at: Option<Date> = row.try_get("expires_at")?;
+        let quantity: i32 = row.try_get("quantity")?;
+        let reserved: i32 = row.try_get("reserved")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+        Ok(InventoryLot {
+            id: row.try_get("id")?,
+            item_id: row.try_get("item_id")?,
+            sku: row.try_get("sku")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            lot_number: row.try_get("lot_number")?,
+            manufactured_at: manufactured_at.map(Self::convert_date),
+            expires_at: expires_at.map(Self::convert_date),
+            quantity,
+            reserved,
+            available: match status {
+                LotStatus::Active => quantity - reserved,
+                LotStatus::Expired => 0,
+            },
+            status,
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+        })
+    }
+
+    const LOT_COLUMNS: &'static str = r#"
+        SELECT l.id, l.item_id, i.sku, l.warehouse_id, l.lot_number, l.manufactured_at,
+               l.expires_at, l.quantity, l.reserved, l.status, l.created_at, l.updated_at
+        FROM inventory_lots l
+        JOIN inventory_items i ON i.id = l.item_id
+        "#;
+
+    /// The item's lots that still hold stock, in the order they are picked.
+    pub async fn find_item_lots(&self, item_id: Uuid) -> Result<Vec<InventoryLot>, Error> {
+        let query = format!(
+            "{} WHERE l.item_id = $1 AND l.quantity > 0 ORDER BY l.warehouse_id, l.expires_at NULLS LAST, l.created_at",
+            Self::LOT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(item_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_lot)
+            .collect()
+    }
+
+    /// Active lots with stock left that expire within `days` days, soonest
+    /// first.
+    pub async fn find_expiring_lots(
+        &self,
+        days: i32,
+        warehouse_id: Option<Uuid>,
+    ) -> Result<Vec<InventoryLot>, Error> {
+        let query = format!(
+            r#"{}
+            WHERE l.status = $1 AND l.quantity > 0
+              AND l.expires_at <= CURRENT_DATE + $2::INTEGER
+              AND ($3::uuid IS NULL OR l.warehouse_id = $3)
+            ORDER BY l.expires_at, i.sku"#,
+            Self::LOT_COLUMNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(LotStatus::Active.as_str())
+            .bind(days)
+            .bind(warehouse_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_lot)
+            .collect()
+    }
+
+    /// Receives units of the item into `warehouse_id` and posts them to the
+    /// ledger.
+    pub async fn receive_stock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<(), Error> {
+        Self::ensure_level_with_transaction(tx, item_id).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO inventory_levels (item_id, warehouse_id, quantity, reserved, available, last_updated)
+            VALUES ($1, $2, $3, 0, $3, NOW())
+            ON CONFLICT (item_id, warehouse_id) DO UPDATE
+            SET quantity = inventory_levels.quantity + EXCLUDED.quantity,
+                available = inventory_levels.available + EXCLUDED.quantity,
+                last_updated = NOW()
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        self.post_movement_with_transaction(
+            tx,
+            LedgerPosting::new(
+                item_id,
+                warehouse_id,
+                quantity,
+                TransactionType::Add,
+                reference,
+                user_id,
+            ),
+        )
+        .await
+    }
+
+    /// Receives units of the item into a lot in `warehouse_id`, adding to the
+    /// lot when it was received there before, and posts them to the ledger.
+    pub async fn receive_lot_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        dto: &ReceiveLotDto,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<InventoryLot, Error> {
+        self.receive_stock_with_transaction(
+            tx,
+            item_id,
+            warehouse_id,
+            dto.quantity,
+            reference,
+            user_id,
+        )
+        .await?;
+
+        Self::add_to_lot_with_transaction(
+            tx,
+            item_id,
+            warehouse_id,
+            &LotQuantity {
+                lot_number: dto.lot_number.clone(),
+                manufactured_at: dto.manufactured_at,
+                expires_at: dto.expires_at,
+                quantity: dto.quantity,
+            },
+        )
+        .await
+    }
+
+    // Adds units to the lot in the warehouse, creating it when it was never
+    // received there
+    async fn add_to_lot_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        lot: &LotQuantity,
+    ) -> Result<InventoryLot, Error> {
+        let row = sqlx::query(
+            r#"
+            WITH received AS (
+                INSERT INTO inventory_lots
+                    (item_id, warehouse_id, lot_number, manufactured_at, expires_at, quantity, status)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (item_id, warehouse_id, lot_number) DO UPDATE
+                SET quantity = inventory_lots.quantity + EXCLUDED.quantity,
+                    manufactured_at = COALESCE(EXCLUDED.manufactured_at, inventory_lots.manufactured_at),
+                    expires_at = COALESCE(EXCLUDED.expires_at, inventory_lots.expires_at),
+                    updated_at = NOW()
+                RETURNING *
+            )
+            SELECT l.id, l.item_id, i.sku, l.warehouse_id, l.lot_number, l.manufactured_at,
+                   l.expires_at, l.quantity, l.reserved, l.status, l.created_at, l.updated_at
+            FROM received l
+            JOIN inventory_items i ON i.id = l.item_id
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(&lot.lot_number)
+        .bind(lot.manufactured_at.map(Self::to_sql_date))
+        .bind(lot.expires_at.map(Self::to_sql_date))
+        .bind(lot.quantity)
+        .bind(LotStatus::Active.as_str())
+        .fetch_one(&mut **tx)
+        .await?;
+        let received = Self::map_row_to_inventory_lot(row)?;
+
+        // Units received into a lot that has already expired are blocked
+        // straight away
+        if received.status == LotStatus::Expired {
+            Self::block_units_with_transaction(tx, item_id, warehouse_id, lot.quantity).await?;
+        }
+
+        Ok(received)
+    }
+
+    // Takes units out of the level's available stock without moving them
+    async fn block_units_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE inventory_levels
+            SET available = available - $3, last_updated = NOW()
+            WHERE item_id = $1 AND warehouse_id = $2
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Expires active lots that have reached their expiry date, for the given
+    /// items or every item when `item_ids` is empty. Their unreserved units are
+    /// taken out of available stock so they can no longer be allocated;
+    /// reservations already made on them are kept.
+    pub async fn expire_lots_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_ids: &[Uuid],
+    ) -> Result<Vec<InventoryLot>, Error> {
+        let rows = sqlx::query(
+            r#"
+            WITH expired AS (
+                UPDATE inventory_lots
+                SET status = $2, updated_at = NOW()
+                WHERE status = $3 AND expires_at <= CURRENT_DATE
+                  AND (cardinality($1::uuid[]) = 0 OR item_id = ANY($1))
+                RETURNING *
+            ),
+            blocked AS (
+                UPDATE inventory_levels l
+                SET available = l.available - b.units, last_updated = NOW()
+                FROM (
+                    SELECT item_id, warehouse_id, SUM(quantity - reserved)::INTEGER AS units
+                    FROM expired
+                    GROUP BY item_id, warehouse_id
+                ) b
+                WHERE l.item_id = b.item_id AND l.warehouse_id = b.warehouse_id
+            )
+            SELECT l.id, l.item_id, i.sku, l.warehouse_id, l.lot_number, l.manufactured_at,
+                   l.expires_at, l.quantity, l.reserved, l.status, l.created_at, l.updated_at
+            FROM expired l
+            JOIN inventory_items i ON i.id = l.item_id
+            ORDER BY i.sku, l.lot_number
+            "#,
+        )
+        .bind(item_ids)
+        .bind(LotStatus::Expired.as_str())
+        .bind(LotStatus::Active.as_str())
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_lot)
+            .collect()
+    }
+
+    // Picks the lots `quantity` units of the item come from in the warehouse,
+    // first expired first out, and locks them. Units reserved on a lot stay on
+    // it and expired lots are only drawn from when `include_expired`. Whatever
+    // the lots cannot cover comes from units held without a lot.
+    async fn pick_lots_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+        include_expired: bool,
+    ) -> Result<Vec<(Uuid, i32)>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, quantity - reserved AS free
+            FROM inventory_lots
+            WHERE item_id = $1 AND warehouse_id = $2 AND quantity > reserved
+              AND (status = $3 OR $4)
+            ORDER BY expires_at NULLS LAST, created_at, id
+            FOR UPDATE
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(LotStatus::Active.as_str())
+        .bind(include_expired)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let mut remaining = quantity;
+        let mut picks = Vec::new();
+        for row in rows {
+            if remaining == 0 {
+                break;
+            }
+            let free: i32 = row.try_get("free")?;
+            let take = free.min(remaining);
+            picks.push((row.try_get("id")?, take));
+            remaining -= take;
+        }
+
+        Ok(picks)
+    }
+
+    // Holds the reservation's units on the lots that expire first
+    async fn reserve_lots_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &StockReservation,
+    ) -> Result<(), Error> {
+        let picks = Self::pick_lots_with_transaction(
+            tx,
+            reservation.product_id,
+            reservation.warehouse_id,
+            reservation.quantity,
+            false,
+        )
+        .await?;
+
+        for (lot_id, quantity) in picks {
+            sqlx::query(
+                "UPDATE inventory_lots SET reserved = reserved + $2, updated_at = NOW() WHERE id = $1",
+            )
+            .bind(lot_id)
+            .bind(quantity)
+            .execute(&mut **tx)
+            .await?;
+
+            sqlx::query(
+                "INSERT INTO reservation_lots (reservation_id, lot_id, quantity) VALUES ($1, $2, $3)",
+            )
+            .bind(reservation.id)
+            .bind(lot_id)
+            .bind(quantity)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes units that are not reserved out of the item's lots in the
+    /// warehouse, first expired first out, and returns what was taken from
+    /// each lot in that order. Expired lots are only drawn from when
+    /// `include_expired`, as when stock is written off.
+    pub async fn consume_lots_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+        include_expired: bool,
+    ) -> Result<Vec<LotQuantity>, Error> {
+        let picks =
+            Self::pick_lots_with_transaction(tx, item_id, warehouse_id, quantity, include_expired)
+                .await?;
+
+        let mut consumed = Vec::with_capacity(picks.len());
+        for (lot_id, quantity) in picks {
+            let row = sqlx::query(
+                r#"
+                UPDATE inventory_lots SET quantity = quantity - $2, updated_at = NOW()
+                WHERE id = $1
+                RETURNING lot_number, manufactured_at, expires_at
+                "#,
+            )
+            .bind(lot_id)
+            .bind(quantity)
+            .fetch_one(&mut **tx)
+            .await?;
+
+            let manufactured_at: Option<Date> = row.try_get("manufactured_at")?;
+            let expires_at: Option<Date> = row.try_get("expires_at")?;
+            consumed.push(LotQuantity {
+                lot_number: row.try_get("lot_number")?,
+                manufactured_at: manufactured_at.map(Self::convert_date),
+                expires_at: expires_at.map(Self::convert_date),
+                quantity,
+            });
+        }
+
+        Ok(consumed)
+    }
+
+    // Ships the reservation's units out of the lots they were held on. The
+    // reservation keeps its lots so shipped orders can be traced back to them.
+    async fn commit_reservation_lots_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        reservation_id: Uuid,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE inventory_lots l
+            SET quantity = l.quantity - r.quantity, reserved = l.reserved - r.quantity, updated_at = NOW()
+            FROM reservation_lots r
+            WHERE r.reservation_id = $1 AND l.id = r.lot_id
+            "#,
+        )
+        .bind(reservation_id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    // Lets go of the lots a pending reservation held. Units handed back to a
+    // lot that expired in the meantime stay blocked.
+    async fn release_reservation_lots_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &StockReservation,
+    ) -> Result<(), Error> {
+        let blocked: i32 = sqlx::query(
+            r#"
+            WITH released AS (
+                UPDATE inventory_lots l
+                SET reserved = l.reserved - r.quantity, updated_at = NOW()
+                FROM reservation_lots r
+                WHERE r.reservation_id = $1 AND l.id = r.lot_id
+                RETURNING l.status, r.quantity
+            )
+            SELECT COALESCE(SUM(quantity) FILTER (WHERE status = $2), 0)::INTEGER AS blocked
+            FROM released
+            "#,
+        )
+        .bind(reservation.id)
+        .bind(LotStatus::Expired.as_str())
+        .fetch_one(&mut **tx)
+        .await?
+        .try_get("blocked")?;
+
+        if blocked > 0 {
+            Self::block_units_with_transaction(
+                tx,
+                reservation.product_id,
+                reservation.warehouse_id,
+                blocked,
+            )
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM reservation_lots WHERE reservation_id = $1")
+            .bind(reservation.id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    // Puts a confirmed reservation's units back on the lots they shipped from.
+    // Units returned to a lot that expired in the meantime stay blocked.
+    async fn restore_reservation_lots_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &StockReservation,
+    ) -> Result<(), Error> {
+        let blocked: i32 = sqlx::query(
+            r#"
+            WITH restored AS (
+                UPDATE inventory_lots l
+                SET quantity = l.quantity + r.quantity, updated_at = NOW()
+                FROM reservation_lots r
+                WHERE r.reservation_id = $1 AND l.id = r.lot_id
+                RETURNING l.status, r.quantity
+            )
+            SELECT COALESCE(SUM(quantity) FILTER (WHERE status = $2), 0)::INTEGER AS blocked
+            FROM restored
+            "#,
+        )
+        .bind(reservation.id)
+        .bind(LotStatus::Expired.as_str())
+        .fetch_one(&mut **tx)
+        .await?
+        .try_get("blocked")?;
+
+        if blocked > 0 {
+            Self::block_units_with_transaction(
+                tx,
+                reservation.product_id,
+                reservation.warehouse_id,
+                blocked,
+            )
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM reservation_lots WHERE reservation_id = $1")
+            .bind(reservation.id)
+            .execute(&mut **tx)
+            .await?;
+
+        Ok(())
+    }
+
+    // Puts some of a confirmed reservation's units back on the lots they
+    // shipped from, those expiring last first, and takes them off its lots
+    async fn restore_some_reservation_lots_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &StockReservation,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT r.lot_id, r.quantity, l.status
+            FROM reservation_lots r
+            JOIN inventory_lots l ON l.id = r.lot_id
+            WHERE r.reservation_id = $1
+            ORDER BY l.expires_at DESC NULLS FIRST, l.created_at DESC, l.id
+            FOR UPDATE OF r, l
+            "#,
+        )
+        .bind(reservation.id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let mut remaining = quantity;
+        let mut blocked = 0;
+        for row in &rows {
+            if remaining == 0 {
+                break;
+            }
+            let lot_id: Uuid = row.try_get("lot_id")?;
+            let held: i32 = row.try_get("quantity")?;
+            let status: String = row.try_get("status")?;
+            let take = held.min(remaining);
+
+            sqlx::query(
+                "UPDATE inventory_lots SET quantity = quantity + $2, updated_at = NOW() WHERE id = $1",
+            )
+            .bind(lot_id)
+            .bind(take)
+            .execute(&mut **tx)
+            .await?;
+
+            if take == held {
+                sqlx::query(
+                    "DELETE FROM reservation_lots WHERE reservation_id = $1 AND lot_id = $2",
+                )
+                .bind(reservation.id)
+                .bind(lot_id)
+                .execute(&mut **tx)
+                .await?;
+            } else {
+                sqlx::query(
+                    r#"
+                    UPDATE reservation_lots
+                    SET quantity = quantity - $3
+                    WHERE reservation_id = $1 AND lot_id = $2
+                    "#,
+                )
+                .bind(reservation.id)
+                .bind(lot_id)
+                .bind(take)
+                .execute(&mut **tx)
+                .await?;
+            }
+
+            if status == LotStatus::Expired.as_str() {
+                blocked += take;
+            }
+            remaining -= take;
+        }
+
+        if blocked > 0 {
+            Self::block_units_with_transaction(
+                tx,
+                reservation.product_id,
+                reservation.warehouse_id,
+                blocked,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
     pub async fn create_reservation(
         &self,
         dto: CreateReservationDto,
@@ -690,6 +2289,7 @@ impl InventoryRepository {
         let transaction_type: String = row.try_get("type")?;
         let reference: Option<String> = row.try_get("reference").ok();
         let user_id: Option<String> = row.try_get("user_id").ok();
+        let balance_after: Option<i32> = row.try_get("balance_after").ok();
         let timestamp: OffsetDateTime = row.try_get("timestamp")?;
 
         // Optional fields from join
@@ -708,6 +2308,7 @@ impl InventoryRepository {
             transaction_type,
             reference,
             user_id,
+            balance_after,
             timestamp: Self::convert_datetime(timestamp),
         })
     }
@@ -720,7 +2321,7 @@ impl InventoryRepository {
         let query = r#"
             SELECT 
                 t.id, t.item_id, t.warehouse_id, t.quantity, t."type", 
-                t.reference, t.user_id, t.timestamp,
+                t.reference, t.user_id, t.balance_after, t.timestamp,
                 i.name as item_name, i.sku as item_sku,
                 w.name as warehouse_name
             FROM 
@@ -758,7 +2359,7 @@ impl InventoryRepository {
         let query = r#"
             SELECT 
                 t.id, t.item_id, t.warehouse_id, t.quantity, t."type", 
-                t.reference, t.user_id, t.timestamp,
+                t.reference, t.user_id, t.balance_after, t.timestamp,
                 i.name as item_name, i.sku as item_sku,
                 w.name as warehouse_name
             FROM 
@@ -782,33 +2383,129 @@ impl InventoryRepository {
         }
     }
 
-    pub async fn create_transaction(
+    /// The item's ledger, latest entry first.
+    pub async fn find_item_transactions(
         &self,
-        dto: CreateTransactionDto,
-    ) -> Result<crate::models::inventory::InventoryTransaction, Error> {
-        let query = r#"
-            INSERT INTO inventory_transactions (id, item_id, warehouse_id, quantity, "type", reference, user_id, timestamp)
-            VALUES (gen_random_uuid(), $1, $2, $3, $4, $5, $6, NOW())
-            RETURNING 
-                id, item_id, warehouse_id, quantity, "type", 
-                reference, user_id, timestamp,
-                (SELECT name FROM inventory_items WHERE id = item_id) as item_name,
-                (SELECT sku FROM inventory_items WHERE id = item_id) as item_sku,
-                (SELECT name FROM warehouses WHERE id = warehouse_id) as warehouse_name
-        "#;
+        item_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<InventoryTransaction>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.id, t.item_id, t.warehouse_id, t.quantity::BIGINT AS quantity, t."type",
+                   t.reference, t.user_id, t.balance_after, t.timestamp,
+                   i.name AS item_name, i.sku AS item_sku, w.name AS warehouse_name
+            FROM inventory_transactions t
+            JOIN inventory_items i ON i.id = t.item_id
+            LEFT JOIN warehouses w ON w.id = t.warehouse_id
+            WHERE t.item_id = $1
+            ORDER BY t.entry_number DESC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(item_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
 
-        let row = sqlx::query(query)
-            .bind(&dto.item_id)
-            .bind(&dto.warehouse_id)
-            .bind(&dto.quantity)
-            .bind(&dto.transaction_type)
-            .bind(&dto.reference)
-            .bind(&dto.user_id)
-            .map(|row| Self::map_row_to_inventory_transaction(row))
+        rows.into_iter()
+            .map(Self::map_row_to_inventory_transaction)
+            .collect()
+    }
+
+    /// Compares every item's on-hand quantity with the sum of its ledger
+    /// entries. Returns how many items were checked and those that disagree,
+    /// largest difference first.
+    pub async fn find_ledger_discrepancies(&self) -> Result<(i64, Vec<LedgerDiscrepancy>), Error> {
+        // Transaction types that add to and take from on-hand stock
+        let types_moving = |direction: i32| {
+            TransactionType::ALL
+                .iter()
+                .filter(|transaction_type| transaction_type.on_hand_delta(1) == direction)
+                .map(|transaction_type| transaction_type.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let items_checked: i64 = sqlx::query("SELECT COUNT(*) AS count FROM inventory_items")
             .fetch_one(&self.pool)
-            .await?;
+            .await?
+            .try_get("count")?;
+
+        let rows = sqlx::query(
+            r#"
+            WITH balances AS (
+                SELECT i.id, i.sku, i.name, COALESCE(i.quantity, 0) AS on_hand,
+                       COALESCE(SUM(CASE
+                           WHEN LOWER(t."type") = ANY($1) THEN t.quantity
+                           WHEN LOWER(t."type") = ANY($2) THEN -t.quantity
+                           ELSE 0
+                       END), 0)::INTEGER AS ledger_balance,
+                       COUNT(t.id) AS entries,
+                       MAX(t.timestamp) AS last_posted_at
+                FROM inventory_items i
+                LEFT JOIN inventory_transactions t ON t.item_id = i.id
+                GROUP BY i.id, i.sku, i.name, i.quantity
+            )
+            SELECT id, sku, name, on_hand, ledger_balance, entries, last_posted_at
+            FROM balances
+            WHERE on_hand <> ledger_balance
+            ORDER BY ABS(on_hand - ledger_balance) DESC, sku
+            "#,
+        )
+        .bind(types_moving(1))
+        .bind(types_moving(-1))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let discrepancies = rows
+            .into_iter()
+            .map(|row| -> Result<LedgerDiscrepancy, Error> {
+                let on_hand: i32 = row.try_get("on_hand")?;
+                let ledger_balance: i32 = row.try_get("ledger_balance")?;
+                let last_posted_at: Option<OffsetDateTime> = row.try_get("last_posted_at")?;
 
-        Ok(row?)
+                Ok(LedgerDiscrepancy {
+                    item_id: row.try_get("id")?,
+                    sku: row.try_get("sku")?,
+                    name: row.try_get("name")?,
+                    on_hand,
+                    ledger_balance,
+                    difference: on_hand - ledger_balance,
+                    entries: row.try_get("entries")?,
+                    last_posted_at: last_posted_at.map(Self::convert_datetime),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        Ok((items_checked, discrepancies))
+    }
+
+    /// The warehouse most of the item's units left from for `reference`, e.g.
+    /// an order, or `None` when none left for it.
+    pub async fn find_shipped_from_warehouse_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        reference: &str,
+    ) -> Result<Option<Uuid>, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT warehouse_id
+            FROM inventory_transactions
+            WHERE item_id = $1 AND reference = $2 AND "type" = $3
+            GROUP BY warehouse_id
+            ORDER BY SUM(quantity) DESC, MIN(timestamp)
+            LIMIT 1
+            "#,
+        )
+        .bind(item_id)
+        .bind(reference)
+        .bind(TransactionType::Remove.to_string())
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        row.map(|row| row.try_get("warehouse_id")).transpose()
     }
 
     pub async fn find_warehouse_by_id(&self, id: Uuid) -> Result<Option<Warehouse>, Error> {
@@ -817,7 +2514,7 @@ impl InventoryRepository {
                 id, name, code,
                 address_line1, address_line2, city, state, postal_code, country,
                 contact_name, contact_email, contact_phone,
-                active,
+                latitude, longitude, fulfillment_cost, active,
                 created_at, updated_at
             FROM warehouses
             WHERE id = $1
@@ -826,6 +2523,8 @@ impl InventoryRepository {
         let row = sqlx::query(query)
             .bind(id)
             .map(|row: sqlx::postgres::PgRow| {
+                let fulfillment_cost: sqlx::types::BigDecimal = row.try_get("fulfillment_cost")?;
+
                 Ok(Warehouse {
                     id: row.try_get("id")?,
                     name: row.try_get("name")?,
@@ -836,9 +2535,15 @@ impl InventoryRepository {
                     state: row.try_get("state")?,
                     postal_code: row.try_get("postal_code")?,
                     country: row.try_get("country")?,
+                    latitude: row.try_get("latitude")?,
+                    longitude: row.try_get("longitude")?,
                     contact_name: row.try_get("contact_name").ok(),
                     contact_email: row.try_get("contact_email").ok(),
                     contact_phone: row.try_get("contact_phone").ok(),
+                    fulfillment_cost: rust_decimal::Decimal::from_str(
+                        &fulfillment_cost.to_string(),
+                    )
+                    .unwrap_or_default(),
                     active: row.try_get("active")?,
                     created_at: Self::convert_datetime(row.try_get("created_at")?),
                     updated_at: Self::convert_datetime(row.try_get("updated_at")?),
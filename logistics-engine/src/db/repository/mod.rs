@@ -1,16 +1,36 @@
+pub mod activity_repository;
 pub mod analytics_repository;
+pub mod backorder_repository;
+pub mod bin_repository;
+pub mod cancellation_repository;
 pub mod customer_repository;
+pub mod idempotency_repository;
 pub mod inventory_repository;
 pub mod order_item_repository;
 pub mod order_repository;
+pub mod outbox_repository;
 pub mod payment_repository;
+pub mod return_repository;
+pub mod serial_repository;
 pub mod shipping_repository;
+pub mod transfer_repository;
 pub mod warehouse_repository;
+pub mod wave_repository;
 
+pub use activity_repository::ActivityRepository;
+pub use backorder_repository::BackorderRepository;
+pub use bin_repository::BinRepository;
+pub use cancellation_repository::CancellationRepository;
 pub use customer_repository::CustomerRepository;
+pub use idempotency_repository::IdempotencyRepository;
 pub use inventory_repository::InventoryRepository;
 pub use order_item_repository::OrderItemRepository;
 pub use order_repository::OrderRepository;
+pub use outbox_repository::OutboxRepository;
 pub use payment_repository::PaymentRepository;
+pub use return_repository::ReturnRepository;
+pub use serial_repository::SerialRepository;
 pub use shipping_repository::ShippingRepository;
+pub use transfer_repository::TransferRepository;
 pub use warehouse_repository::WarehouseRepository;
+pub use wave_repository::WaveRepository;
@@ -113,6 +113,44 @@ impl OrderItemRepository {
         Ok(items)
     }
 
+    /// Items of any of the given orders, oldest first.
+    pub async fn find_by_order_ids(&self, order_ids: &[Uuid]) -> Result<Vec<OrderItem>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM order_items
+            WHERE order_id = ANY($1)
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(order_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(Self::map_row_to_order_item).collect()
+    }
+
+    pub async fn find_by_order_id_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<Vec<OrderItem>, Error> {
+        let row_results = sqlx::query(
+            r#"
+            SELECT * FROM order_items
+            WHERE order_id = $1
+            ORDER BY created_at ASC
+            "#,
+        )
+        .bind(order_id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        row_results
+            .into_iter()
+            .map(Self::map_row_to_order_item)
+            .collect()
+    }
+
     pub async fn find_by_product_id(&self, product_id: &str) -> Result<Vec<OrderItem>, Error> {
         let row_results = sqlx::query(
             r#"
@@ -1,8 +1,11 @@
 use crate::models::{
-    dto::order::{CreateOrderDto, UpdateOrderDto},
+    allocation::{AllocationPlan, OrderAllocation},
+    cancellation::RemoteReservation,
+    dto::order::{CreateOrderDto, OrderListFilter, UpdateOrderDto},
     entities::{
         self,
         order::{Order, OrderStatus},
+        order_status_history::OrderStatusHistory,
     },
 };
 use chrono::{DateTime, TimeZone, Utc};
@@ -232,7 +235,9 @@ impl OrderRepository {
         let status = dto.status.as_ref().map(|s| match s.as_str() {
             "pending" => OrderStatus::Pending,
             "processing" => OrderStatus::Processing,
+            "partially_shipped" => OrderStatus::PartiallyShipped,
             "shipped" => OrderStatus::Shipped,
+            "partially_delivered" => OrderStatus::PartiallyDelivered,
             "delivered" => OrderStatus::Delivered,
             "cancelled" => OrderStatus::Cancelled,
             "returned" => OrderStatus::Returned,
@@ -401,6 +406,102 @@ impl OrderRepository {
         })
     }
 
+    fn to_offset_datetime(dt: DateTime<Utc>) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(dt.timestamp())
+            .map(|odt| {
+                odt.replace_nanosecond(dt.timestamp_subsec_nanos())
+                    .unwrap_or(odt)
+            })
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    }
+
+    /// Orders matching `filter`, sorted by `sort_column` (which must come from
+    /// `OrderListFilter::sort_column`, as it is interpolated into the query).
+    pub async fn find_filtered(
+        &self,
+        filter: &OrderListFilter,
+        sort_column: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Order>, Error> {
+        let direction = if filter.sort_desc { "DESC" } else { "ASC" };
+        let query = format!(
+            r#"
+            SELECT
+                orders.id,
+                orders.customer_id,
+                customers.name as customer_name,
+                orders.total_amount,
+                orders.status,
+                orders.currency,
+                orders.tracking_number,
+                orders.notes,
+                orders.created_at,
+                orders.updated_at
+            FROM orders
+            LEFT JOIN customers ON orders.customer_id = customers.id
+            WHERE ($1::order_status IS NULL OR orders.status = $1)
+              AND ($2::uuid IS NULL OR orders.customer_id = $2)
+              AND ($3::timestamptz IS NULL OR orders.created_at >= $3)
+              AND ($4::timestamptz IS NULL OR orders.created_at < $4)
+            ORDER BY orders.{column} {direction}, orders.id {direction}
+            LIMIT $5
+            OFFSET $6
+            "#,
+            column = sort_column,
+            direction = direction
+        );
+
+        sqlx::query(&query)
+            .bind(filter.status)
+            .bind(filter.customer_id)
+            .bind(filter.created_from.map(Self::to_offset_datetime))
+            .bind(filter.created_to.map(Self::to_offset_datetime))
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .map(|rows| {
+                rows.into_iter()
+                    .map(|row| Order {
+                        id: row.get("id"),
+                        customer_id: row.get("customer_id"),
+                        customer_name: row.get("customer_name"),
+                        total_amount: Decimal::from_str(
+                            &row.get::<BigDecimal, _>("total_amount").to_string(),
+                        )
+                        .unwrap_or_default(),
+                        status: row.get("status"),
+                        currency: row.get("currency"),
+                        tracking_number: row.get("tracking_number"),
+                        notes: row.get("notes"),
+                        created_at: Self::convert_datetime(row.get("created_at")),
+                        updated_at: Self::convert_datetime(row.get("updated_at")),
+                    })
+                    .collect()
+            })
+    }
+
+    pub async fn count_filtered(&self, filter: &OrderListFilter) -> Result<i64, Error> {
+        sqlx::query(
+            r#"
+            SELECT COUNT(*) as count
+            FROM orders
+            WHERE ($1::order_status IS NULL OR status = $1)
+              AND ($2::uuid IS NULL OR customer_id = $2)
+              AND ($3::timestamptz IS NULL OR created_at >= $3)
+              AND ($4::timestamptz IS NULL OR created_at < $4)
+            "#,
+        )
+        .bind(filter.status)
+        .bind(filter.customer_id)
+        .bind(filter.created_from.map(Self::to_offset_datetime))
+        .bind(filter.created_to.map(Self::to_offset_datetime))
+        .fetch_one(&self.pool)
+        .await
+        .map(|row| row.get("count"))
+    }
+
     pub async fn create_with_transaction(
         &self,
         tx: &mut Transaction<'_, Postgres>,
@@ -463,4 +564,348 @@ impl OrderRepository {
             updated_at: Self::convert_datetime(row.get("updated_at")),
         })
     }
+
+    /// Sets the order's priority for pick waves. Returns `None` when there is
+    /// no such order.
+    pub async fn update_priority(&self, id: Uuid, priority: i32) -> Result<Option<i32>, Error> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET priority = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING priority
+            "#,
+        )
+        .bind(id)
+        .bind(priority)
+        .fetch_optional(&self.pool)
+        .await?
+        .map(|row| row.try_get("priority"))
+        .transpose()
+    }
+
+    pub async fn lock_status_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<Option<OrderStatus>, Error> {
+        sqlx::query(
+            r#"
+            SELECT status
+            FROM orders
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map(|opt| opt.map(|row| row.get("status")))
+    }
+
+    pub async fn update_status_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        status: OrderStatus,
+        notes: Option<String>,
+    ) -> Result<Order, Error> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET
+                status = $1,
+                notes = COALESCE($2, notes),
+                updated_at = NOW()
+            WHERE id = $3
+            RETURNING
+                id,
+                customer_id,
+                total_amount,
+                status,
+                currency,
+                tracking_number,
+                notes,
+                created_at,
+                updated_at
+            "#,
+        )
+        .bind(status)
+        .bind(notes)
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await
+        .map(|row| Order {
+            id: row.get("id"),
+            customer_id: row.get("customer_id"),
+            customer_name: None,
+            total_amount: Decimal::from_str(&row.get::<BigDecimal, _>("total_amount").to_string())
+                .unwrap_or_default(),
+            status: row.get("status"),
+            currency: row.get("currency"),
+            tracking_number: row.get("tracking_number"),
+            notes: row.get("notes"),
+            created_at: Self::convert_datetime(row.get("created_at")),
+            updated_at: Self::convert_datetime(row.get("updated_at")),
+        })
+    }
+
+    /// Applies the tracking number and notes of an order update. The status is
+    /// left alone; it only changes through a status transition.
+    pub async fn update_details_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        dto: &UpdateOrderDto,
+    ) -> Result<Option<Order>, Error> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET
+                tracking_number = COALESCE($1, tracking_number),
+                notes = COALESCE($2, notes),
+                updated_at = NOW()
+            WHERE id = $3
+            RETURNING
+                id,
+                customer_id,
+                total_amount,
+                status,
+                currency,
+                tracking_number,
+                notes,
+                created_at,
+                updated_at
+            "#,
+        )
+        .bind(&dto.tracking_number)
+        .bind(&dto.notes)
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map(|opt| {
+            opt.map(|row| Order {
+                id: row.get("id"),
+                customer_id: row.get("customer_id"),
+                customer_name: None,
+                total_amount: Decimal::from_str(
+                    &row.get::<BigDecimal, _>("total_amount").to_string(),
+                )
+                .unwrap_or_default(),
+                status: row.get("status"),
+                currency: row.get("currency"),
+                tracking_number: row.get("tracking_number"),
+                notes: row.get("notes"),
+                created_at: Self::convert_datetime(row.get("created_at")),
+                updated_at: Self::convert_datetime(row.get("updated_at")),
+            })
+        })
+    }
+
+    pub async fn create_status_history_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        history: &OrderStatusHistory,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO order_status_history (
+                id,
+                order_id,
+                previous_status,
+                new_status,
+                status_notes,
+                changed_by
+            )
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(history.id)
+        .bind(history.order_id)
+        .bind(history.previous_status)
+        .bind(history.new_status)
+        .bind(&history.status_notes)
+        .bind(&history.changed_by)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks an order as accepted without a stock check.
+    pub async fn create_pending_stock_check_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        reason: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO pending_stock_checks (order_id, reason)
+            VALUES ($1, $2)
+            ON CONFLICT (order_id) DO NOTHING
+            "#,
+        )
+        .bind(order_id)
+        .bind(reason)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Clears the order's pending stock check. Returns `false` when the order
+    /// did not have one.
+    pub async fn take_pending_stock_check_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM pending_stock_checks
+            WHERE order_id = $1
+            "#,
+        )
+        .bind(order_id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn count_pending_stock_checks(&self) -> Result<i64, Error> {
+        sqlx::query("SELECT COUNT(*) FROM pending_stock_checks")
+            .fetch_one(&self.pool)
+            .await
+            .map(|row| row.get::<i64, _>(0))
+    }
+
+    pub async fn create_remote_reservation_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        reservation: &RemoteReservation,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO order_remote_reservations
+                (order_id, reservation_id, remote_order_id, warehouse_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (order_id, remote_order_id) DO NOTHING
+            "#,
+        )
+        .bind(reservation.order_id)
+        .bind(&reservation.reservation_id)
+        .bind(&reservation.remote_order_id)
+        .bind(reservation.warehouse_id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_remote_reservations(
+        &self,
+        order_id: Uuid,
+    ) -> Result<Vec<RemoteReservation>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT order_id, reservation_id, remote_order_id, warehouse_id,
+                   committed_at IS NOT NULL AS committed
+            FROM order_remote_reservations
+            WHERE order_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(order_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(RemoteReservation {
+                    order_id: row.try_get("order_id")?,
+                    reservation_id: row.try_get("reservation_id")?,
+                    remote_order_id: row.try_get("remote_order_id")?,
+                    warehouse_id: row.try_get("warehouse_id")?,
+                    committed: row.try_get("committed")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn mark_remote_reservation_committed(
+        &self,
+        order_id: Uuid,
+        remote_order_id: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE order_remote_reservations
+            SET committed_at = NOW()
+            WHERE order_id = $1 AND remote_order_id = $2 AND committed_at IS NULL
+            "#,
+        )
+        .bind(order_id)
+        .bind(remote_order_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn create_allocation_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        plan: &AllocationPlan,
+    ) -> Result<(), Error> {
+        let lines =
+            serde_json::to_value(&plan.lines).map_err(|e| Error::Protocol(e.to_string()))?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO order_allocations (order_id, strategy, split, explanation, lines)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (order_id) DO NOTHING
+            "#,
+        )
+        .bind(order_id)
+        .bind(&plan.strategy)
+        .bind(plan.split)
+        .bind(&plan.explanation)
+        .bind(lines)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_allocation(&self, order_id: Uuid) -> Result<Option<OrderAllocation>, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT order_id, strategy, split, explanation, lines, created_at
+            FROM order_allocations
+            WHERE order_id = $1
+            "#,
+        )
+        .bind(order_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            let lines: serde_json::Value = row.try_get("lines")?;
+            let created_at: OffsetDateTime = row.try_get("created_at")?;
+
+            Ok(OrderAllocation {
+                order_id: row.try_get("order_id")?,
+                strategy: row.try_get("strategy")?,
+                split: row.try_get("split")?,
+                explanation: row.try_get("explanation")?,
+                lines: serde_json::from_value(lines).map_err(|e| Error::Decode(e.into()))?,
+                created_at: Self::convert_datetime(created_at),
+            })
+        })
+        .transpose()
+    }
 }
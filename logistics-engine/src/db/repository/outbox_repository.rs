@@ -0,0 +1,169 @@
+use crate::models::outbox::{NewOutboxEvent, OutboxEvent};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Postgres, Row, Transaction};
+
+pub struct OutboxRepository {
+    pool: PgPool,
+}
+
+impl OutboxRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn map_row_to_outbox_event(row: sqlx::postgres::PgRow) -> Result<OutboxEvent, Error> {
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let published_at: Option<OffsetDateTime> = row.try_get("published_at")?;
+        let parked_at: Option<OffsetDateTime> = row.try_get("parked_at")?;
+
+        Ok(OutboxEvent {
+            id: row.try_get("id")?,
+            aggregate_type: row.try_get("aggregate_type")?,
+            aggregate_id: row.try_get("aggregate_id")?,
+            event_type: row.try_get("event_type")?,
+            routing_key: row.try_get("routing_key")?,
+            payload: row.try_get("payload")?,
+            attempts: row.try_get("attempts")?,
+            last_error: row.try_get("last_error")?,
+            created_at: Self::convert_datetime(created_at),
+            published_at: published_at.map(Self::convert_datetime),
+            parked_at: parked_at.map(Self::convert_datetime),
+        })
+    }
+
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        event: &NewOutboxEvent,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO outbox_events
+                (id, aggregate_type, aggregate_id, event_type, routing_key, payload)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(event.id)
+        .bind(&event.aggregate_type)
+        .bind(event.aggregate_id)
+        .bind(&event.event_type)
+        .bind(&event.routing_key)
+        .bind(&event.payload)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Claims the oldest unpublished events that are not parked. The rows stay
+    /// locked until the transaction ends, so concurrent relays skip them
+    /// instead of publishing twice. An event is left out while an earlier event
+    /// of its aggregate is pending outside the claim, e.g. locked by another
+    /// relay, so each aggregate's events go out in order.
+    pub async fn claim_pending_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        limit: i64,
+    ) -> Result<Vec<OutboxEvent>, Error> {
+        let rows = sqlx::query(
+            r#"
+            WITH claimed AS (
+                SELECT id
+                FROM outbox_events
+                WHERE published_at IS NULL AND parked_at IS NULL
+                ORDER BY created_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            SELECT o.id, o.aggregate_type, o.aggregate_id, o.event_type, o.routing_key,
+                   o.payload, o.attempts, o.last_error, o.created_at, o.published_at,
+                   o.parked_at
+            FROM outbox_events o
+            JOIN claimed c ON c.id = o.id
+            WHERE NOT EXISTS (
+                SELECT 1 FROM outbox_events e
+                WHERE e.aggregate_id = o.aggregate_id
+                  AND e.published_at IS NULL
+                  AND e.parked_at IS NULL
+                  AND e.created_at < o.created_at
+                  AND e.id NOT IN (SELECT id FROM claimed)
+            )
+            ORDER BY o.created_at
+            "#,
+        )
+        .bind(limit)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(Self::map_row_to_outbox_event)
+            .collect()
+    }
+
+    pub async fn mark_published_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: uuid::Uuid,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE outbox_events
+            SET published_at = NOW(), attempts = attempts + 1, last_error = NULL
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Counts a failed attempt against the event and parks it once it has
+    /// failed `max_attempts` times. Returns whether the event is now parked.
+    pub async fn record_failure_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: uuid::Uuid,
+        error: &str,
+        max_attempts: i32,
+    ) -> Result<bool, Error> {
+        let row = sqlx::query(
+            r#"
+            UPDATE outbox_events
+            SET attempts = attempts + 1,
+                last_error = $2,
+                parked_at = CASE WHEN attempts + 1 >= $3 THEN NOW() END
+            WHERE id = $1
+            RETURNING parked_at IS NOT NULL AS parked
+            "#,
+        )
+        .bind(id)
+        .bind(error)
+        .bind(max_attempts)
+        .fetch_one(&mut **tx)
+        .await?;
+
+        row.try_get("parked")
+    }
+
+    pub async fn delete_published_older_than(&self, hours: i32) -> Result<u64, Error> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM outbox_events
+            WHERE published_at IS NOT NULL
+              AND published_at < NOW() - make_interval(hours => $1)
+            "#,
+        )
+        .bind(hours)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
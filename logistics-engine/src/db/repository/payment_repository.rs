@@ -4,7 +4,7 @@ use rust_decimal::{prelude::FromPrimitive, Decimal};
 use sqlx::{
     postgres::PgPool,
     types::{time::OffsetDateTime, BigDecimal},
-    Error, Postgres, Transaction,
+    Error, Postgres, Row, Transaction,
 };
 use std::str::FromStr;
 use uuid::Uuid;
@@ -156,6 +156,46 @@ impl PaymentRepository {
         })
     }
 
+    /// The latest payment of each of the given orders that has one.
+    pub async fn find_latest_by_order_ids(
+        &self,
+        order_ids: &[Uuid],
+    ) -> Result<Vec<PaymentInfo>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT DISTINCT ON (order_id)
+                id, order_id, payment_method, transaction_id, amount, currency, status,
+                payment_date, created_at, updated_at
+            FROM payment_info
+            WHERE order_id = ANY($1)
+            ORDER BY order_id, created_at DESC
+            "#,
+        )
+        .bind(order_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let amount: BigDecimal = row.try_get("amount")?;
+                let status: Option<String> = row.try_get("status")?;
+
+                Ok(PaymentInfo {
+                    id: row.try_get("id")?,
+                    order_id: row.try_get("order_id")?,
+                    payment_method: row.try_get("payment_method")?,
+                    transaction_id: row.try_get("transaction_id")?,
+                    amount: Decimal::from_str(&amount.to_string()).unwrap_or_default(),
+                    currency: row.try_get("currency")?,
+                    status: status.unwrap_or(PaymentStatus::Pending.to_string()),
+                    payment_date: Self::convert_optional_datetime(row.try_get("payment_date")?),
+                    created_at: Self::convert_datetime(row.try_get("created_at")?),
+                    updated_at: Self::convert_datetime(row.try_get("updated_at")?),
+                })
+            })
+            .collect()
+    }
+
     pub async fn find_by_customer_id(
         &self,
         customer_id: Uuid,
@@ -442,6 +482,95 @@ impl PaymentRepository {
             .unwrap_or(0.0))
     }
 
+    /// Adds `amount` to what has been refunded on a succeeded or partially
+    /// refunded payment, which becomes `refunded` once nothing is left.
+    /// Returns `None` when the payment cannot be refunded that much.
+    /// What is left to refund on the payment, or `None` when it does not exist.
+    pub async fn find_refundable_amount(&self, id: Uuid) -> Result<Option<Decimal>, Error> {
+        let row = sqlx::query(
+            "SELECT amount - refunded_amount AS refundable FROM payment_info WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| -> Result<Decimal, Error> {
+            let refundable: BigDecimal = row.try_get("refundable")?;
+            Decimal::from_str(&refundable.to_string()).map_err(|e| Error::Decode(e.into()))
+        })
+        .transpose()
+    }
+
+    // Adds the refund to the payment unless it is not refundable or the
+    // refund would exceed what is left of it
+    async fn apply_refund<'e, E>(
+        executor: E,
+        id: Uuid,
+        amount: Decimal,
+    ) -> Result<Option<PaymentInfo>, Error>
+    where
+        E: sqlx::Executor<'e, Database = Postgres>,
+    {
+        let amount_bd = BigDecimal::from_str(&amount.to_string()).unwrap_or_default();
+
+        let row = sqlx::query(
+            r#"
+            UPDATE payment_info
+            SET refunded_amount = refunded_amount + $2,
+                status = CASE WHEN refunded_amount + $2 >= amount THEN $3 ELSE $4 END,
+                updated_at = NOW()
+            WHERE id = $1
+              AND status IN ($5, $4)
+              AND refunded_amount + $2 <= amount
+            RETURNING id, order_id, payment_method, transaction_id, amount, currency, status,
+                      payment_date, created_at, updated_at
+            "#,
+        )
+        .bind(id)
+        .bind(amount_bd)
+        .bind(PaymentStatus::Refunded.as_str())
+        .bind(PaymentStatus::PartiallyRefunded.as_str())
+        .bind(PaymentStatus::Succeeded.as_str())
+        .fetch_optional(executor)
+        .await?;
+
+        row.map(|row| -> Result<PaymentInfo, Error> {
+            let amount: BigDecimal = row.try_get("amount")?;
+            let status: Option<String> = row.try_get("status")?;
+
+            Ok(PaymentInfo {
+                id: row.try_get("id")?,
+                order_id: row.try_get("order_id")?,
+                payment_method: row.try_get("payment_method")?,
+                transaction_id: row.try_get("transaction_id")?,
+                amount: Decimal::from_str(&amount.to_string()).unwrap_or_default(),
+                currency: row.try_get("currency")?,
+                status: status.unwrap_or(PaymentStatus::Pending.to_string()),
+                payment_date: Self::convert_optional_datetime(row.try_get("payment_date")?),
+                created_at: Self::convert_datetime(row.try_get("created_at")?),
+                updated_at: Self::convert_datetime(row.try_get("updated_at")?),
+            })
+        })
+        .transpose()
+    }
+
+    pub async fn record_refund(
+        &self,
+        id: Uuid,
+        amount: Decimal,
+    ) -> Result<Option<PaymentInfo>, Error> {
+        Self::apply_refund(&self.pool, id, amount).await
+    }
+
+    pub async fn record_refund_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        amount: Decimal,
+    ) -> Result<Option<PaymentInfo>, Error> {
+        Self::apply_refund(&mut **tx, id, amount).await
+    }
+
     pub async fn create_with_transaction(
         &self,
         tx: &mut Transaction<'_, Postgres>,
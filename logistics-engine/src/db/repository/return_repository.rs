@@ -0,0 +1,405 @@
+use crate::models::entities::order::OrderStatus;
+use crate::models::rma::{
+    Disposition, ItemCondition, ReturnAuthorization, ReturnItem, ReturnReason, ReturnStatus,
+};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::{
+    types::{time::OffsetDateTime, BigDecimal},
+    Error, PgPool, Postgres, Row, Transaction,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct ReturnRepository {
+    pool: PgPool,
+}
+
+impl ReturnRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn decode_error(message: String) -> Error {
+        Error::Decode(message.into())
+    }
+
+    fn to_big_decimal(amount: Decimal) -> BigDecimal {
+        BigDecimal::from_str(&amount.to_string()).unwrap_or_default()
+    }
+
+    fn to_decimal(amount: BigDecimal) -> Decimal {
+        Decimal::from_str(&amount.to_string()).unwrap_or_default()
+    }
+
+    fn map_row_to_item(row: &sqlx::postgres::PgRow) -> Result<(Uuid, ReturnItem), Error> {
+        let unit_price: BigDecimal = row.try_get("unit_price")?;
+        let reason_code: String = row.try_get("reason_code")?;
+        let condition: Option<String> = row.try_get("condition")?;
+        let disposition: Option<String> = row.try_get("disposition")?;
+
+        let item = ReturnItem {
+            id: row.try_get("id")?,
+            order_item_id: row.try_get("order_item_id")?,
+            product_id: row.try_get("product_id")?,
+            sku: row.try_get("sku")?,
+            quantity: row.try_get("quantity")?,
+            received_quantity: row.try_get("received_quantity")?,
+            unit_price: Self::to_decimal(unit_price),
+            reason_code: ReturnReason::from_str(&reason_code).map_err(Self::decode_error)?,
+            condition: condition
+                .as_deref()
+                .map(ItemCondition::from_str)
+                .transpose()
+                .map_err(Self::decode_error)?,
+            disposition: disposition
+                .as_deref()
+                .map(Disposition::from_str)
+                .transpose()
+                .map_err(Self::decode_error)?,
+        };
+
+        Ok((row.try_get("return_id")?, item))
+    }
+
+    fn map_row_to_return(
+        row: &sqlx::postgres::PgRow,
+        items: Vec<ReturnItem>,
+    ) -> Result<ReturnAuthorization, Error> {
+        let status: String = row.try_get("status")?;
+        let reason_code: String = row.try_get("reason_code")?;
+        let refund_amount: Option<BigDecimal> = row.try_get("refund_amount")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+        let received_at: Option<OffsetDateTime> = row.try_get("received_at")?;
+        let refunded_at: Option<OffsetDateTime> = row.try_get("refunded_at")?;
+
+        Ok(ReturnAuthorization {
+            id: row.try_get("id")?,
+            rma_number: row.try_get("rma_number")?,
+            order_id: row.try_get("order_id")?,
+            status: ReturnStatus::from_str(&status).map_err(Self::decode_error)?,
+            reason_code: ReturnReason::from_str(&reason_code).map_err(Self::decode_error)?,
+            notes: row.try_get("notes")?,
+            requested_by: row.try_get("requested_by")?,
+            refund_amount: refund_amount.map(Self::to_decimal),
+            payment_id: row.try_get("payment_id")?,
+            items,
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+            received_at: received_at.map(Self::convert_datetime),
+            refunded_at: refunded_at.map(Self::convert_datetime),
+        })
+    }
+
+    // Pairs each return row with its items
+    fn assemble(
+        rows: Vec<sqlx::postgres::PgRow>,
+        item_rows: Vec<sqlx::postgres::PgRow>,
+    ) -> Result<Vec<ReturnAuthorization>, Error> {
+        let mut items: HashMap<Uuid, Vec<ReturnItem>> = HashMap::new();
+        for row in &item_rows {
+            let (return_id, item) = Self::map_row_to_item(row)?;
+            items.entry(return_id).or_default().push(item);
+        }
+
+        rows.iter()
+            .map(|row| {
+                let id: Uuid = row.try_get("id")?;
+                Self::map_row_to_return(row, items.remove(&id).unwrap_or_default())
+            })
+            .collect()
+    }
+
+    const SELECT_RETURNS: &'static str = r#"
+        SELECT id, rma_number, order_id, status, reason_code, notes, requested_by,
+               refund_amount, payment_id, created_at, updated_at, received_at, refunded_at
+        FROM return_authorizations
+        "#;
+
+    const SELECT_ITEMS: &'static str = r#"
+        SELECT id, return_id, order_item_id, product_id, sku, quantity, received_quantity,
+               unit_price, reason_code, condition, disposition
+        FROM return_items
+        WHERE return_id = ANY($1)
+        ORDER BY created_at, sku
+        "#;
+
+    async fn load(
+        &self,
+        rows: Vec<sqlx::postgres::PgRow>,
+    ) -> Result<Vec<ReturnAuthorization>, Error> {
+        let ids = rows
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect::<Result<Vec<Uuid>, Error>>()?;
+
+        let item_rows = sqlx::query(Self::SELECT_ITEMS)
+            .bind(&ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Self::assemble(rows, item_rows)
+    }
+
+    /// Locks the order so concurrent returns cannot authorize the same units,
+    /// and returns its status.
+    pub async fn lock_order_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<Option<OrderStatus>, Error> {
+        let row = sqlx::query("SELECT status FROM orders WHERE id = $1 FOR UPDATE")
+            .bind(order_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        row.map(|row| row.try_get("status")).transpose()
+    }
+
+    /// Units of each of the order's lines covered by returns that were not
+    /// cancelled: what arrived for received returns, what was authorized for
+    /// the rest.
+    pub async fn returned_quantities_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<HashMap<Uuid, i64>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT ri.order_item_id,
+                   SUM(COALESCE(ri.received_quantity, ri.quantity))::BIGINT AS quantity
+            FROM return_items ri
+            JOIN return_authorizations r ON r.id = ri.return_id
+            WHERE r.order_id = $1 AND r.status <> $2
+            GROUP BY ri.order_item_id
+            "#,
+        )
+        .bind(order_id)
+        .bind(ReturnStatus::Cancelled.as_str())
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| -> Result<(Uuid, i64), Error> {
+                Ok((row.try_get("order_item_id")?, row.try_get("quantity")?))
+            })
+            .collect()
+    }
+
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        rma_number: &str,
+        order_id: Uuid,
+        reason_code: ReturnReason,
+        notes: Option<&str>,
+        requested_by: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO return_authorizations
+                (id, rma_number, order_id, status, reason_code, notes, requested_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(id)
+        .bind(rma_number)
+        .bind(order_id)
+        .bind(ReturnStatus::Authorized.as_str())
+        .bind(reason_code.as_str())
+        .bind(notes)
+        .bind(requested_by)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn add_item_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        return_id: Uuid,
+        order_item_id: Uuid,
+        product_id: Uuid,
+        sku: &str,
+        quantity: i32,
+        unit_price: Decimal,
+        reason_code: ReturnReason,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO return_items
+                (return_id, order_item_id, product_id, sku, quantity, unit_price, reason_code)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(return_id)
+        .bind(order_item_id)
+        .bind(product_id)
+        .bind(sku)
+        .bind(quantity)
+        .bind(Self::to_big_decimal(unit_price))
+        .bind(reason_code.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<ReturnAuthorization>, Error> {
+        let query = format!("{} WHERE id = $1", Self::SELECT_RETURNS);
+        let rows = sqlx::query(&query).bind(id).fetch_all(&self.pool).await?;
+
+        Ok(self.load(rows).await?.into_iter().next())
+    }
+
+    /// The return and its items, locked until the transaction ends.
+    pub async fn find_by_id_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<Option<ReturnAuthorization>, Error> {
+        let query = format!("{} WHERE id = $1 FOR UPDATE", Self::SELECT_RETURNS);
+        let rows = sqlx::query(&query).bind(id).fetch_all(&mut **tx).await?;
+
+        let item_rows = sqlx::query(Self::SELECT_ITEMS)
+            .bind(vec![id])
+            .fetch_all(&mut **tx)
+            .await?;
+
+        Ok(Self::assemble(rows, item_rows)?.into_iter().next())
+    }
+
+    pub async fn find_by_order_id(
+        &self,
+        order_id: Uuid,
+    ) -> Result<Vec<ReturnAuthorization>, Error> {
+        let query = format!(
+            "{} WHERE order_id = $1 ORDER BY created_at",
+            Self::SELECT_RETURNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(order_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.load(rows).await
+    }
+
+    pub async fn find_all(
+        &self,
+        status: Option<ReturnStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<ReturnAuthorization>, Error> {
+        let query = format!(
+            "{} WHERE ($1::text IS NULL OR status = $1) ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+            Self::SELECT_RETURNS
+        );
+        let rows = sqlx::query(&query)
+            .bind(status.map(|status| status.as_str()))
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.load(rows).await
+    }
+
+    pub async fn receive_item_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        received_quantity: i32,
+        condition: Option<ItemCondition>,
+        disposition: Option<Disposition>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE return_items
+            SET received_quantity = $2, condition = $3, disposition = $4
+            WHERE id = $1
+            "#,
+        )
+        .bind(item_id)
+        .bind(received_quantity)
+        .bind(condition.map(|condition| condition.as_str()))
+        .bind(disposition.map(|disposition| disposition.as_str()))
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn mark_received_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        refund_amount: Decimal,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE return_authorizations
+            SET status = $2, refund_amount = $3, received_at = NOW(), updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(ReturnStatus::Received.as_str())
+        .bind(Self::to_big_decimal(refund_amount))
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn mark_refunded_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        payment_id: Option<Uuid>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE return_authorizations
+            SET status = $2, payment_id = $3, refunded_at = NOW(), updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(ReturnStatus::Refunded.as_str())
+        .bind(payment_id)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn cancel_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE return_authorizations
+            SET status = $2, updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(ReturnStatus::Cancelled.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+}
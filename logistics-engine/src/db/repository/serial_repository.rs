@@ -0,0 +1,622 @@
+use crate::models::entities::order::OrderStatus;
+use crate::models::inventory::ReservationStatus;
+use crate::models::serial::{
+    SerialEvent, SerialHistory, SerialHistoryEntry, SerialNumber, SerialStatus, ShipmentSerialLine,
+    SERIAL_TRACKED_ATTRIBUTE,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgPool, Postgres, Row, Transaction};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct SerialRepository {
+    pool: PgPool,
+}
+
+impl SerialRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn map_row_to_serial(row: &sqlx::postgres::PgRow) -> Result<SerialNumber, Error> {
+        let status: String = row.try_get("status")?;
+        let received_at: OffsetDateTime = row.try_get("received_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+        Ok(SerialNumber {
+            id: row.try_get("id")?,
+            item_id: row.try_get("item_id")?,
+            sku: row.try_get("sku")?,
+            serial_number: row.try_get("serial_number")?,
+            status: SerialStatus::from_str(&status).map_err(|e| Error::Decode(e.into()))?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            order_id: row.try_get("order_id")?,
+            order_item_id: row.try_get("order_item_id")?,
+            shipping_id: row.try_get("shipping_id")?,
+            return_id: row.try_get("return_id")?,
+            received_at: Self::convert_datetime(received_at),
+            updated_at: Self::convert_datetime(updated_at),
+        })
+    }
+
+    fn map_row_to_history_entry(
+        row: &sqlx::postgres::PgRow,
+    ) -> Result<(Uuid, SerialHistoryEntry), Error> {
+        let event: String = row.try_get("event")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+
+        let entry = SerialHistoryEntry {
+            event: SerialEvent::from_str(&event).map_err(|e| Error::Decode(e.into()))?,
+            reference: row.try_get("reference")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            order_id: row.try_get("order_id")?,
+            order_item_id: row.try_get("order_item_id")?,
+            shipping_id: row.try_get("shipping_id")?,
+            tracking_number: row.try_get("tracking_number")?,
+            return_id: row.try_get("return_id")?,
+            rma_number: row.try_get("rma_number")?,
+            created_at: Self::convert_datetime(created_at),
+        };
+
+        Ok((row.try_get("serial_id")?, entry))
+    }
+
+    const SELECT_SERIALS: &'static str = r#"
+        SELECT s.id, s.item_id, i.sku, s.serial_number, s.status, s.warehouse_id,
+               oi.order_id, s.order_item_id, s.shipping_id, s.return_id, s.received_at,
+               s.updated_at
+        FROM serial_numbers s
+        JOIN inventory_items i ON i.id = s.item_id
+        LEFT JOIN order_items oi ON oi.id = s.order_item_id
+        "#;
+
+    // Snapshots where each serial is now as a step of its history
+    async fn record_event_with_transaction(
+        tx: &mut Transaction<'_, Postgres>,
+        serial_ids: &[Uuid],
+        event: SerialEvent,
+        reference: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO serial_number_events
+                (serial_id, event, reference, warehouse_id, order_item_id, shipping_id, return_id)
+            SELECT id, $2, $3, warehouse_id, order_item_id, shipping_id, return_id
+            FROM serial_numbers
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(serial_ids)
+        .bind(event.as_str())
+        .bind(reference)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Registers received serial numbers of the item as in stock in
+    /// `warehouse_id`. Returns the serial numbers the item already has instead,
+    /// without registering anything, when there are any.
+    pub async fn receive_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        serial_numbers: &[String],
+        reference: &str,
+    ) -> Result<Result<Vec<SerialNumber>, Vec<String>>, Error> {
+        let rows = sqlx::query(
+            "SELECT serial_number FROM serial_numbers WHERE item_id = $1 AND serial_number = ANY($2)",
+        )
+        .bind(item_id)
+        .bind(serial_numbers)
+        .fetch_all(&mut **tx)
+        .await?;
+        if !rows.is_empty() {
+            let existing = rows
+                .iter()
+                .map(|row| row.try_get("serial_number"))
+                .collect::<Result<Vec<String>, Error>>()?;
+            return Ok(Err(existing));
+        }
+
+        let ids = sqlx::query(
+            r#"
+            INSERT INTO serial_numbers (item_id, serial_number, warehouse_id, status)
+            SELECT $1, serial_number, $2, $3
+            FROM UNNEST($4::text[]) AS received(serial_number)
+            RETURNING id
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(SerialStatus::InStock.as_str())
+        .bind(serial_numbers)
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect::<Result<Vec<Uuid>, Error>>()?;
+
+        Self::record_event_with_transaction(tx, &ids, SerialEvent::Received, reference).await?;
+
+        let query = format!(
+            "{} WHERE s.id = ANY($1) ORDER BY s.serial_number",
+            Self::SELECT_SERIALS
+        );
+        let rows = sqlx::query(&query).bind(&ids).fetch_all(&mut **tx).await?;
+
+        rows.iter()
+            .map(Self::map_row_to_serial)
+            .collect::<Result<Vec<_>, Error>>()
+            .map(Ok)
+    }
+
+    /// The item's serial numbers, optionally only those with `status`.
+    pub async fn find_by_item(
+        &self,
+        item_id: Uuid,
+        status: Option<SerialStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<SerialNumber>, Error> {
+        let query = format!(
+            r#"{}
+            WHERE s.item_id = $1 AND ($2::text IS NULL OR s.status = $2)
+            ORDER BY s.serial_number
+            LIMIT $3 OFFSET $4"#,
+            Self::SELECT_SERIALS
+        );
+        let rows = sqlx::query(&query)
+            .bind(item_id)
+            .bind(status.map(|status| status.as_str()))
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(Self::map_row_to_serial).collect()
+    }
+
+    /// The item's units with the given serial numbers, locked until the
+    /// transaction ends.
+    pub async fn lock_by_numbers_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        serial_numbers: &[String],
+    ) -> Result<Vec<SerialNumber>, Error> {
+        let query = format!(
+            "{} WHERE s.item_id = $1 AND s.serial_number = ANY($2) ORDER BY s.id FOR UPDATE OF s",
+            Self::SELECT_SERIALS
+        );
+        let rows = sqlx::query(&query)
+            .bind(item_id)
+            .bind(serial_numbers)
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.iter().map(Self::map_row_to_serial).collect()
+    }
+
+    /// Locks the order the item belongs to so concurrent allocations cannot
+    /// overfill the line, and returns the order's status with the line's
+    /// product and quantity.
+    pub async fn lock_order_item_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        order_item_id: Uuid,
+    ) -> Result<Option<(OrderStatus, Uuid, i32)>, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT o.status, oi.product_id, oi.quantity
+            FROM order_items oi
+            JOIN orders o ON o.id = oi.order_id
+            WHERE oi.id = $2 AND oi.order_id = $1
+            FOR UPDATE OF o
+            "#,
+        )
+        .bind(order_id)
+        .bind(order_item_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        row.map(|row| {
+            Ok((
+                row.try_get("status")?,
+                row.try_get("product_id")?,
+                row.try_get("quantity")?,
+            ))
+        })
+        .transpose()
+    }
+
+    /// The warehouses the order takes the item from: those its pending or
+    /// confirmed reservations of the item hold stock in.
+    pub async fn find_order_warehouses_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        item_id: Uuid,
+    ) -> Result<Vec<Uuid>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT DISTINCT COALESCE(r.warehouse_id, i.warehouse_id) AS warehouse_id
+            FROM inventory_reservations r
+            JOIN inventory_items i ON i.id = r.product_id
+            WHERE r.order_id = $1 AND r.product_id = $2 AND r.status IN ($3, $4)
+            "#,
+        )
+        .bind(order_id)
+        .bind(item_id)
+        .bind(ReservationStatus::Pending.to_string())
+        .bind(ReservationStatus::Confirmed.to_string())
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.iter().map(|row| row.try_get("warehouse_id")).collect()
+    }
+
+    /// Units with serial numbers allocated to or shipped on the order item.
+    pub async fn count_for_order_item_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_item_id: Uuid,
+    ) -> Result<i64, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS count
+            FROM serial_numbers
+            WHERE order_item_id = $1 AND status IN ($2, $3)
+            "#,
+        )
+        .bind(order_item_id)
+        .bind(SerialStatus::Allocated.as_str())
+        .bind(SerialStatus::Shipped.as_str())
+        .fetch_one(&mut **tx)
+        .await?;
+
+        row.try_get("count")
+    }
+
+    /// Sets the units aside for the order item.
+    pub async fn allocate_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        serial_ids: &[Uuid],
+        order_item_id: Uuid,
+        reference: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE serial_numbers
+            SET status = $3, order_item_id = $2, updated_at = NOW()
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(serial_ids)
+        .bind(order_item_id)
+        .bind(SerialStatus::Allocated.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Self::record_event_with_transaction(tx, serial_ids, SerialEvent::Allocated, reference).await
+    }
+
+    /// Puts units allocated to the order, or only to one of its items, back in
+    /// stock. Units that have shipped are not touched. Returns how many were
+    /// released.
+    pub async fn release_for_order_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        order_item_id: Option<Uuid>,
+        reference: &str,
+    ) -> Result<u64, Error> {
+        let result = sqlx::query(
+            r#"
+            WITH released AS (
+                UPDATE serial_numbers s
+                SET status = $3, order_item_id = NULL, updated_at = NOW()
+                FROM order_items oi
+                WHERE s.order_item_id = oi.id AND oi.order_id = $1
+                  AND ($2::uuid IS NULL OR oi.id = $2)
+                  AND s.status = $4
+                RETURNING s.id, s.warehouse_id, oi.id AS order_item_id
+            )
+            INSERT INTO serial_number_events (serial_id, event, reference, warehouse_id, order_item_id)
+            SELECT id, $5, $6, warehouse_id, order_item_id
+            FROM released
+            "#,
+        )
+        .bind(order_id)
+        .bind(order_item_id)
+        .bind(SerialStatus::InStock.as_str())
+        .bind(SerialStatus::Allocated.as_str())
+        .bind(SerialEvent::Released.as_str())
+        .bind(reference)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// The shipment's lines of serial-tracked items.
+    pub async fn find_shipment_lines_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        shipping_id: Uuid,
+    ) -> Result<Vec<ShipmentSerialLine>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT si.order_item_id, i.sku, si.quantity,
+                   (SELECT COUNT(*) FROM serial_numbers s
+                    WHERE s.order_item_id = si.order_item_id AND s.shipping_id = si.shipping_id
+                   )::INTEGER AS recorded,
+                   (SELECT COUNT(*) FROM serial_numbers s
+                    WHERE s.order_item_id = si.order_item_id AND s.status = $2
+                   )::INTEGER AS allocated
+            FROM shipment_items si
+            JOIN order_items oi ON oi.id = si.order_item_id
+            JOIN inventory_items i ON i.id = oi.product_id
+            WHERE si.shipping_id = $1 AND i.attributes->>$3 = 'true'
+            ORDER BY si.order_item_id
+            "#,
+        )
+        .bind(shipping_id)
+        .bind(SerialStatus::Allocated.as_str())
+        .bind(SERIAL_TRACKED_ATTRIBUTE)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(ShipmentSerialLine {
+                    order_item_id: row.try_get("order_item_id")?,
+                    sku: row.try_get("sku")?,
+                    quantity: row.try_get("quantity")?,
+                    recorded: row.try_get("recorded")?,
+                    allocated: row.try_get("allocated")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Records `quantity` of the units allocated to the order item as shipped
+    /// on the shipment, the longest allocated first.
+    pub async fn ship_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        shipping_id: Uuid,
+        order_item_id: Uuid,
+        quantity: i32,
+        reference: &str,
+    ) -> Result<(), Error> {
+        let ids = sqlx::query(
+            r#"
+            WITH picked AS (
+                SELECT id
+                FROM serial_numbers
+                WHERE order_item_id = $2 AND status = $4
+                ORDER BY updated_at, serial_number
+                LIMIT $3
+                FOR UPDATE
+            )
+            UPDATE serial_numbers s
+            SET status = $5, shipping_id = $1, updated_at = NOW()
+            FROM picked
+            WHERE s.id = picked.id
+            RETURNING s.id
+            "#,
+        )
+        .bind(shipping_id)
+        .bind(order_item_id)
+        .bind(quantity as i64)
+        .bind(SerialStatus::Allocated.as_str())
+        .bind(SerialStatus::Shipped.as_str())
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect::<Result<Vec<Uuid>, Error>>()?;
+
+        Self::record_event_with_transaction(tx, &ids, SerialEvent::Shipped, reference).await
+    }
+
+    /// Takes units back on a return. Restocked units go back in stock in
+    /// `restock_warehouse_id`; the others stay `Returned` against the return.
+    pub async fn return_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        serial_ids: &[Uuid],
+        return_id: Uuid,
+        restock_warehouse_id: Option<Uuid>,
+        reference: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE serial_numbers
+            SET status = $3, return_id = $2, updated_at = NOW()
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(serial_ids)
+        .bind(return_id)
+        .bind(SerialStatus::Returned.as_str())
+        .execute(&mut **tx)
+        .await?;
+        Self::record_event_with_transaction(tx, serial_ids, SerialEvent::Returned, reference)
+            .await?;
+
+        if let Some(warehouse_id) = restock_warehouse_id {
+            sqlx::query(
+                r#"
+                UPDATE serial_numbers
+                SET status = $3, warehouse_id = $2, order_item_id = NULL, shipping_id = NULL,
+                    return_id = NULL, updated_at = NOW()
+                WHERE id = ANY($1)
+                "#,
+            )
+            .bind(serial_ids)
+            .bind(warehouse_id)
+            .bind(SerialStatus::InStock.as_str())
+            .execute(&mut **tx)
+            .await?;
+            Self::record_event_with_transaction(tx, serial_ids, SerialEvent::Restocked, reference)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends up to `quantity` of the item's in-stock units in the warehouse, the
+    /// longest held first, on the transfer line. They are in no warehouse until
+    /// the line is received. Returns how many were dispatched.
+    pub async fn dispatch_for_transfer_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        transfer_line_id: Uuid,
+        quantity: i32,
+        reference: &str,
+    ) -> Result<usize, Error> {
+        let ids = sqlx::query(
+            r#"
+            WITH picked AS (
+                SELECT id
+                FROM serial_numbers
+                WHERE item_id = $1 AND warehouse_id = $2 AND status = $5
+                ORDER BY received_at, serial_number
+                LIMIT $4
+                FOR UPDATE
+            )
+            UPDATE serial_numbers s
+            SET status = $6, warehouse_id = NULL, transfer_line_id = $3, updated_at = NOW()
+            FROM picked
+            WHERE s.id = picked.id
+            RETURNING s.id
+            "#,
+        )
+        .bind(item_id)
+        .bind(warehouse_id)
+        .bind(transfer_line_id)
+        .bind(quantity as i64)
+        .bind(SerialStatus::InStock.as_str())
+        .bind(SerialStatus::InTransit.as_str())
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect::<Result<Vec<Uuid>, Error>>()?;
+
+        Self::record_event_with_transaction(tx, &ids, SerialEvent::Dispatched, reference).await?;
+
+        Ok(ids.len())
+    }
+
+    /// The units still in transit on the transfer line, locked until the
+    /// transaction ends.
+    pub async fn lock_in_transit_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        transfer_line_id: Uuid,
+    ) -> Result<Vec<SerialNumber>, Error> {
+        let query = format!(
+            r#"{}
+            WHERE s.transfer_line_id = $1 AND s.status = $2
+            ORDER BY s.serial_number
+            FOR UPDATE OF s"#,
+            Self::SELECT_SERIALS
+        );
+        let rows = sqlx::query(&query)
+            .bind(transfer_line_id)
+            .bind(SerialStatus::InTransit.as_str())
+            .fetch_all(&mut **tx)
+            .await?;
+
+        rows.iter().map(Self::map_row_to_serial).collect()
+    }
+
+    /// Puts units that arrived on a transfer in stock in `warehouse_id`.
+    pub async fn receive_transfer_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        serial_ids: &[Uuid],
+        warehouse_id: Uuid,
+        reference: &str,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE serial_numbers
+            SET status = $3, warehouse_id = $2, transfer_line_id = NULL, updated_at = NOW()
+            WHERE id = ANY($1)
+            "#,
+        )
+        .bind(serial_ids)
+        .bind(warehouse_id)
+        .bind(SerialStatus::InStock.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Self::record_event_with_transaction(tx, serial_ids, SerialEvent::Transferred, reference)
+            .await
+    }
+
+    /// Every unit with the serial number, across items, with its history from
+    /// receipt onwards.
+    pub async fn find_history(&self, serial_number: &str) -> Result<Vec<SerialHistory>, Error> {
+        let query = format!(
+            "{} WHERE s.serial_number = $1 ORDER BY i.sku",
+            Self::SELECT_SERIALS
+        );
+        let rows = sqlx::query(&query)
+            .bind(serial_number)
+            .fetch_all(&self.pool)
+            .await?;
+        let serials = rows
+            .iter()
+            .map(Self::map_row_to_serial)
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let ids = serials.iter().map(|serial| serial.id).collect::<Vec<_>>();
+        let event_rows = sqlx::query(
+            r#"
+            SELECT e.serial_id, e.event, e.reference, e.warehouse_id, oi.order_id,
+                   e.order_item_id, e.shipping_id, sh.tracking_number, e.return_id,
+                   ra.rma_number, e.created_at
+            FROM serial_number_events e
+            LEFT JOIN order_items oi ON oi.id = e.order_item_id
+            LEFT JOIN shipping_info sh ON sh.id = e.shipping_id
+            LEFT JOIN return_authorizations ra ON ra.id = e.return_id
+            WHERE e.serial_id = ANY($1)
+            ORDER BY e.created_at, e.id
+            "#,
+        )
+        .bind(&ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut history: HashMap<Uuid, Vec<SerialHistoryEntry>> = HashMap::new();
+        for row in &event_rows {
+            let (serial_id, entry) = Self::map_row_to_history_entry(row)?;
+            history.entry(serial_id).or_default().push(entry);
+        }
+
+        Ok(serials
+            .into_iter()
+            .map(|serial| SerialHistory {
+                history: history.remove(&serial.id).unwrap_or_default(),
+                serial,
+            })
+            .collect())
+    }
+}
@@ -9,6 +9,7 @@ use uuid::Uuid;
 
 use crate::models::{
     dto::shipping::{CreateShippingInfoDto, UpdateShippingInfoDto},
+    entities::shipment_item::{FulfillmentProgress, ShipmentItem},
     entities::shipping_info::ShippingInfo,
     shipping::ShippingStatus,
 };
@@ -122,7 +123,8 @@ impl ShippingRepository {
         })
     }
 
-    pub async fn find_by_order_id(&self, order_id: Uuid) -> Result<Option<ShippingInfo>, Error> {
+    /// The order's shipments, oldest first.
+    pub async fn find_by_order_id(&self, order_id: Uuid) -> Result<Vec<ShippingInfo>, Error> {
         sqlx::query!(
             r#"
             SELECT
@@ -137,33 +139,97 @@ impl ShippingRepository {
             "#,
             order_id
         )
-        .fetch_optional(&self.pool)
+        .fetch_all(&self.pool)
         .await
-        .map(|opt| {
-            opt.map(|row| ShippingInfo {
-                id: row.id,
-                order_id: row.order_id,
-                address_line1: row.address_line1,
-                address_line2: row.address_line2,
-                city: row.city,
-                state: row.state,
-                postal_code: row.postal_code,
-                country: row.country,
-                recipient_name: row.recipient_name,
-                recipient_phone: row.recipient_phone,
-                shipping_method: row.shipping_method,
-                shipping_cost: Decimal::from_str(&row.shipping_cost.to_string()).unwrap_or_default(),
-                tracking_number: Some(row.tracking_number.unwrap_or_default()),
-                carrier: Some(row.carrier.unwrap_or_default()),
-                status: row.status.unwrap_or_else(|| ShippingStatus::Pending.as_str().to_string()),
-                expected_delivery: Self::convert_optional_datetime(row.expected_delivery),
-                actual_delivery: Self::convert_optional_datetime(row.actual_delivery),
-                created_at: Self::convert_datetime(row.created_at),
-                updated_at: Self::convert_datetime(row.updated_at),
-            })
+        .map(|rows| {
+            let mut shipments = rows
+                .into_iter()
+                .map(|row| ShippingInfo {
+                    id: row.id,
+                    order_id: row.order_id,
+                    address_line1: row.address_line1,
+                    address_line2: row.address_line2,
+                    city: row.city,
+                    state: row.state,
+                    postal_code: row.postal_code,
+                    country: row.country,
+                    recipient_name: row.recipient_name,
+                    recipient_phone: row.recipient_phone,
+                    shipping_method: row.shipping_method,
+                    shipping_cost: Decimal::from_str(&row.shipping_cost.to_string()).unwrap_or_default(),
+                    tracking_number: Some(row.tracking_number.unwrap_or_default()),
+                    carrier: Some(row.carrier.unwrap_or_default()),
+                    status: row.status.unwrap_or_else(|| ShippingStatus::Pending.as_str().to_string()),
+                    expected_delivery: Self::convert_optional_datetime(row.expected_delivery),
+                    actual_delivery: Self::convert_optional_datetime(row.actual_delivery),
+                    created_at: Self::convert_datetime(row.created_at),
+                    updated_at: Self::convert_datetime(row.updated_at),
+                })
+                .collect::<Vec<_>>();
+            shipments.sort_by_key(|shipping| shipping.created_at);
+            shipments
         })
     }
 
+    /// Shipments of any of the given orders, oldest first.
+    pub async fn find_by_order_ids(&self, order_ids: &[Uuid]) -> Result<Vec<ShippingInfo>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id, order_id, address_line1, address_line2, city, state,
+                postal_code, country, recipient_name, recipient_phone,
+                shipping_method, shipping_cost, tracking_number, carrier,
+                status::text AS status,
+                expected_delivery, actual_delivery,
+                created_at, updated_at
+            FROM shipping_info
+            WHERE order_id = ANY($1)
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(order_ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let shipping_cost: BigDecimal = row.try_get("shipping_cost")?;
+                let tracking_number: Option<String> = row.try_get("tracking_number")?;
+                let carrier: Option<String> = row.try_get("carrier")?;
+                let status: Option<String> = row.try_get("status")?;
+                let created_at: OffsetDateTime = row.try_get("created_at")?;
+                let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+                Ok(ShippingInfo {
+                    id: row.try_get("id")?,
+                    order_id: row.try_get("order_id")?,
+                    address_line1: row.try_get("address_line1")?,
+                    address_line2: row.try_get("address_line2")?,
+                    city: row.try_get("city")?,
+                    state: row.try_get("state")?,
+                    postal_code: row.try_get("postal_code")?,
+                    country: row.try_get("country")?,
+                    recipient_name: row.try_get("recipient_name")?,
+                    recipient_phone: row.try_get("recipient_phone")?,
+                    shipping_method: row.try_get("shipping_method")?,
+                    shipping_cost: Decimal::from_str(&shipping_cost.to_string())
+                        .unwrap_or_default(),
+                    tracking_number: Some(tracking_number.unwrap_or_default()),
+                    carrier: Some(carrier.unwrap_or_default()),
+                    status: status.unwrap_or_else(|| ShippingStatus::Pending.as_str().to_string()),
+                    expected_delivery: Self::convert_optional_datetime(
+                        row.try_get("expected_delivery")?,
+                    ),
+                    actual_delivery: Self::convert_optional_datetime(
+                        row.try_get("actual_delivery")?,
+                    ),
+                    created_at: Self::convert_datetime(created_at),
+                    updated_at: Self::convert_datetime(updated_at),
+                })
+            })
+            .collect()
+    }
+
     pub async fn find_by_tracking_number(
         &self,
         tracking_number: &str,
@@ -806,4 +872,180 @@ impl ShippingRepository {
 
         Ok(row)
     }
+
+    fn map_row_to_item(row: sqlx::postgres::PgRow) -> Result<ShipmentItem, Error> {
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+
+        Ok(ShipmentItem {
+            id: row.try_get("id")?,
+            shipping_id: row.try_get("shipping_id")?,
+            order_item_id: row.try_get("order_item_id")?,
+            product_id: row.try_get("product_id")?,
+            sku: row.try_get("sku")?,
+            name: row.try_get("name")?,
+            quantity: row.try_get("quantity")?,
+            created_at: Self::convert_datetime(created_at),
+        })
+    }
+
+    /// Items carried by any of the given shipments.
+    pub async fn find_items(&self, shipping_ids: &[Uuid]) -> Result<Vec<ShipmentItem>, Error> {
+        sqlx::query(
+            r#"
+            SELECT si.id, si.shipping_id, si.order_item_id, oi.product_id, oi.sku, oi.name,
+                   si.quantity, si.created_at
+            FROM shipment_items si
+            JOIN order_items oi ON oi.id = si.order_item_id
+            WHERE si.shipping_id = ANY($1)
+            ORDER BY si.created_at, oi.sku
+            "#,
+        )
+        .bind(shipping_ids)
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(Self::map_row_to_item)
+        .collect()
+    }
+
+    /// Locks the shipment and returns its order and status.
+    pub async fn lock_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<Option<(Uuid, ShippingStatus)>, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT order_id, status::text AS status
+            FROM shipping_info
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        row.map(|row| -> Result<(Uuid, ShippingStatus), Error> {
+            let status: String = row.try_get("status")?;
+            Ok((
+                row.try_get("order_id")?,
+                ShippingStatus::from_str(&status).unwrap_or_default(),
+            ))
+        })
+        .transpose()
+    }
+
+    /// Units of each of the order's lines that no shipment carries yet,
+    /// leaving out `exclude_shipping_id` and cancelled shipments. Locks the
+    /// order so concurrent allocations see each other.
+    pub async fn find_unallocated_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        exclude_shipping_id: Option<Uuid>,
+    ) -> Result<Vec<(Uuid, i32)>, Error> {
+        sqlx::query("SELECT id FROM orders WHERE id = $1 FOR UPDATE")
+            .bind(order_id)
+            .execute(&mut **tx)
+            .await?;
+
+        let rows = sqlx::query(
+            r#"
+            SELECT oi.id,
+                   (oi.quantity - COALESCE(SUM(si.quantity) FILTER (
+                       WHERE s.status <> 'cancelled'
+                         AND ($2::uuid IS NULL OR s.id <> $2)
+                   ), 0))::int AS remaining
+            FROM order_items oi
+            LEFT JOIN shipment_items si ON si.order_item_id = oi.id
+            LEFT JOIN shipping_info s ON s.id = si.shipping_id
+            WHERE oi.order_id = $1
+            GROUP BY oi.id, oi.quantity
+            "#,
+        )
+        .bind(order_id)
+        .bind(exclude_shipping_id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| -> Result<(Uuid, i32), Error> {
+                Ok((row.try_get("id")?, row.try_get("remaining")?))
+            })
+            .collect()
+    }
+
+    /// Sets the items the shipment carries, replacing any it had.
+    pub async fn replace_items_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        shipping_id: Uuid,
+        items: &[(Uuid, i32)],
+    ) -> Result<(), Error> {
+        sqlx::query("DELETE FROM shipment_items WHERE shipping_id = $1")
+            .bind(shipping_id)
+            .execute(&mut **tx)
+            .await?;
+
+        let (order_item_ids, quantities): (Vec<Uuid>, Vec<i32>) = items.iter().copied().unzip();
+
+        sqlx::query(
+            r#"
+            INSERT INTO shipment_items (shipping_id, order_item_id, quantity)
+            SELECT $1, order_item_id, quantity
+            FROM UNNEST($2::uuid[], $3::int[]) AS i(order_item_id, quantity)
+            "#,
+        )
+        .bind(shipping_id)
+        .bind(&order_item_ids)
+        .bind(&quantities)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// How many of the order's units have shipped and been delivered.
+    pub async fn fulfillment_progress_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<FulfillmentProgress, Error> {
+        let shipped_statuses = [
+            ShippingStatus::Shipped,
+            ShippingStatus::InTransit,
+            ShippingStatus::OutForDelivery,
+            ShippingStatus::Delivered,
+        ]
+        .iter()
+        .map(|status| status.as_str().to_string())
+        .collect::<Vec<_>>();
+
+        let row = sqlx::query(
+            r#"
+            SELECT
+                (SELECT COALESCE(SUM(quantity), 0) FROM order_items WHERE order_id = $1)::bigint
+                    AS ordered,
+                COALESCE(SUM(si.quantity) FILTER (WHERE s.status::text = ANY($2)), 0)::bigint
+                    AS shipped,
+                COALESCE(SUM(si.quantity) FILTER (WHERE s.status::text = $3), 0)::bigint
+                    AS delivered
+            FROM shipping_info s
+            JOIN shipment_items si ON si.shipping_id = s.id
+            WHERE s.order_id = $1
+            "#,
+        )
+        .bind(order_id)
+        .bind(&shipped_statuses)
+        .bind(ShippingStatus::Delivered.as_str())
+        .fetch_one(&mut **tx)
+        .await?;
+
+        Ok(FulfillmentProgress {
+            ordered: row.try_get("ordered")?,
+            shipped: row.try_get("shipped")?,
+            delivered: row.try_get("delivered")?,
+        })
+    }
 }
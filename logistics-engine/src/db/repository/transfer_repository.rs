@@ -0,0 +1,368 @@
+use crate::models::lot::LotQuantity;
+use crate::models::transfer::{StockTransfer, StockTransferLine, TransferStatus};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use sqlx::{
+    types::time::{Date, Month, OffsetDateTime},
+    Error, PgPool, Postgres, Row, Transaction,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct TransferRepository {
+    pool: PgPool,
+}
+
+impl TransferRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn convert_date(date: Date) -> NaiveDate {
+        NaiveDate::from_ymd_opt(date.year(), date.month() as u32, date.day() as u32)
+            .unwrap_or_default()
+    }
+
+    fn to_sql_date(date: NaiveDate) -> Date {
+        Month::try_from(date.month() as u8)
+            .ok()
+            .and_then(|month| Date::from_calendar_date(date.year(), month, date.day() as u8).ok())
+            .unwrap_or(Date::MIN)
+    }
+
+    fn map_row_to_lot(row: &sqlx::postgres::PgRow) -> Result<(Uuid, LotQuantity), Error> {
+        let manufactured_at: Option<Date> = row.try_get("manufactured_at")?;
+        let expires_at: Option<Date> = row.try_get("expires_at")?;
+
+        let lot = LotQuantity {
+            lot_number: row.try_get("lot_number")?,
+            manufactured_at: manufactured_at.map(Self::convert_date),
+            expires_at: expires_at.map(Self::convert_date),
+            quantity: row.try_get("quantity")?,
+        };
+
+        Ok((row.try_get("transfer_line_id")?, lot))
+    }
+
+    fn map_row_to_line(
+        row: &sqlx::postgres::PgRow,
+        lots: Vec<LotQuantity>,
+    ) -> Result<(Uuid, StockTransferLine), Error> {
+        let shipped_quantity: Option<i32> = row.try_get("shipped_quantity")?;
+        let received_quantity: Option<i32> = row.try_get("received_quantity")?;
+
+        let line = StockTransferLine {
+            id: row.try_get("id")?,
+            item_id: row.try_get("item_id")?,
+            sku: row.try_get("sku")?,
+            quantity: row.try_get("quantity")?,
+            shipped_quantity,
+            received_quantity,
+            in_transit: match received_quantity {
+                Some(_) => 0,
+                None => shipped_quantity.unwrap_or(0),
+            },
+            discrepancy_reason: row.try_get("discrepancy_reason")?,
+            lots,
+        };
+
+        Ok((row.try_get("transfer_id")?, line))
+    }
+
+    fn map_row_to_transfer(
+        row: &sqlx::postgres::PgRow,
+        lines: Vec<StockTransferLine>,
+    ) -> Result<StockTransfer, Error> {
+        let status: String = row.try_get("status")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+        let dispatched_at: Option<OffsetDateTime> = row.try_get("dispatched_at")?;
+        let received_at: Option<OffsetDateTime> = row.try_get("received_at")?;
+
+        Ok(StockTransfer {
+            id: row.try_get("id")?,
+            transfer_number: row.try_get("transfer_number")?,
+            source_warehouse_id: row.try_get("source_warehouse_id")?,
+            destination_warehouse_id: row.try_get("destination_warehouse_id")?,
+            status: TransferStatus::from_str(&status).map_err(|e| Error::Decode(e.into()))?,
+            notes: row.try_get("notes")?,
+            requested_by: row.try_get("requested_by")?,
+            lines,
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+            dispatched_at: dispatched_at.map(Self::convert_datetime),
+            received_at: received_at.map(Self::convert_datetime),
+        })
+    }
+
+    // Pairs each transfer row with its lines, and each line with its lots
+    fn assemble(
+        rows: Vec<sqlx::postgres::PgRow>,
+        line_rows: Vec<sqlx::postgres::PgRow>,
+        lot_rows: Vec<sqlx::postgres::PgRow>,
+    ) -> Result<Vec<StockTransfer>, Error> {
+        let mut lots: HashMap<Uuid, Vec<LotQuantity>> = HashMap::new();
+        for row in &lot_rows {
+            let (line_id, lot) = Self::map_row_to_lot(row)?;
+            lots.entry(line_id).or_default().push(lot);
+        }
+
+        let mut lines: HashMap<Uuid, Vec<StockTransferLine>> = HashMap::new();
+        for row in &line_rows {
+            let line_id: Uuid = row.try_get("id")?;
+            let (transfer_id, line) =
+                Self::map_row_to_line(row, lots.remove(&line_id).unwrap_or_default())?;
+            lines.entry(transfer_id).or_default().push(line);
+        }
+
+        rows.iter()
+            .map(|row| {
+                let id: Uuid = row.try_get("id")?;
+                Self::map_row_to_transfer(row, lines.remove(&id).unwrap_or_default())
+            })
+            .collect()
+    }
+
+    const SELECT_TRANSFERS: &'static str = r#"
+        SELECT id, transfer_number, source_warehouse_id, destination_warehouse_id, status,
+               notes, requested_by, created_at, updated_at, dispatched_at, received_at
+        FROM stock_transfers
+        "#;
+
+    // Ordered by item so stock levels are always locked in the same order
+    const SELECT_LINES: &'static str = r#"
+        SELECT id, transfer_id, item_id, sku, quantity, shipped_quantity, received_quantity,
+               discrepancy_reason
+        FROM stock_transfer_lines
+        WHERE transfer_id = ANY($1)
+        ORDER BY item_id
+        "#;
+
+    // In the order the lots were dispatched, soonest expiry first
+    const SELECT_LOTS: &'static str = r#"
+        SELECT t.transfer_line_id, t.lot_number, t.manufactured_at, t.expires_at, t.quantity
+        FROM stock_transfer_lots t
+        JOIN stock_transfer_lines l ON l.id = t.transfer_line_id
+        WHERE l.transfer_id = ANY($1)
+        ORDER BY t.expires_at NULLS LAST, t.lot_number
+        "#;
+
+    async fn load(&self, rows: Vec<sqlx::postgres::PgRow>) -> Result<Vec<StockTransfer>, Error> {
+        let ids = rows
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect::<Result<Vec<Uuid>, Error>>()?;
+
+        let line_rows = sqlx::query(Self::SELECT_LINES)
+            .bind(&ids)
+            .fetch_all(&self.pool)
+            .await?;
+        let lot_rows = sqlx::query(Self::SELECT_LOTS)
+            .bind(&ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Self::assemble(rows, line_rows, lot_rows)
+    }
+
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        transfer_number: &str,
+        source_warehouse_id: Uuid,
+        destination_warehouse_id: Uuid,
+        notes: Option<&str>,
+        requested_by: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO stock_transfers
+                (id, transfer_number, source_warehouse_id, destination_warehouse_id, status,
+                 notes, requested_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(id)
+        .bind(transfer_number)
+        .bind(source_warehouse_id)
+        .bind(destination_warehouse_id)
+        .bind(TransferStatus::Requested.as_str())
+        .bind(notes)
+        .bind(requested_by)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn add_line_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        transfer_id: Uuid,
+        item_id: Uuid,
+        sku: &str,
+        quantity: i32,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO stock_transfer_lines (transfer_id, item_id, sku, quantity)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(transfer_id)
+        .bind(item_id)
+        .bind(sku)
+        .bind(quantity)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<StockTransfer>, Error> {
+        let query = format!("{} WHERE id = $1", Self::SELECT_TRANSFERS);
+        let rows = sqlx::query(&query).bind(id).fetch_all(&self.pool).await?;
+
+        Ok(self.load(rows).await?.into_iter().next())
+    }
+
+    /// The transfer and its lines, locked until the transaction ends.
+    pub async fn find_by_id_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+    ) -> Result<Option<StockTransfer>, Error> {
+        let query = format!("{} WHERE id = $1 FOR UPDATE", Self::SELECT_TRANSFERS);
+        let rows = sqlx::query(&query).bind(id).fetch_all(&mut **tx).await?;
+
+        let line_rows = sqlx::query(Self::SELECT_LINES)
+            .bind(vec![id])
+            .fetch_all(&mut **tx)
+            .await?;
+        let lot_rows = sqlx::query(Self::SELECT_LOTS)
+            .bind(vec![id])
+            .fetch_all(&mut **tx)
+            .await?;
+
+        Ok(Self::assemble(rows, line_rows, lot_rows)?
+            .into_iter()
+            .next())
+    }
+
+    /// Transfers matching the filters, newest first. `warehouse_id` matches
+    /// either end of a transfer.
+    pub async fn find_all(
+        &self,
+        status: Option<TransferStatus>,
+        warehouse_id: Option<Uuid>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<StockTransfer>, Error> {
+        let query = format!(
+            r#"{}
+            WHERE ($1::text IS NULL OR status = $1)
+              AND ($2::uuid IS NULL OR source_warehouse_id = $2 OR destination_warehouse_id = $2)
+            ORDER BY created_at DESC
+            LIMIT $3 OFFSET $4"#,
+            Self::SELECT_TRANSFERS
+        );
+        let rows = sqlx::query(&query)
+            .bind(status.map(|status| status.as_str()))
+            .bind(warehouse_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        self.load(rows).await
+    }
+
+    /// Records what left the source for the line and the lots it came from.
+    pub async fn ship_line_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        line_id: Uuid,
+        shipped_quantity: i32,
+        lots: &[LotQuantity],
+    ) -> Result<(), Error> {
+        sqlx::query("UPDATE stock_transfer_lines SET shipped_quantity = $2 WHERE id = $1")
+            .bind(line_id)
+            .bind(shipped_quantity)
+            .execute(&mut **tx)
+            .await?;
+
+        for lot in lots {
+            sqlx::query(
+                r#"
+                INSERT INTO stock_transfer_lots
+                    (transfer_line_id, lot_number, manufactured_at, expires_at, quantity)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+            )
+            .bind(line_id)
+            .bind(&lot.lot_number)
+            .bind(lot.manufactured_at.map(Self::to_sql_date))
+            .bind(lot.expires_at.map(Self::to_sql_date))
+            .bind(lot.quantity)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn receive_line_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        line_id: Uuid,
+        received_quantity: i32,
+        discrepancy_reason: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE stock_transfer_lines
+            SET received_quantity = $2, discrepancy_reason = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(line_id)
+        .bind(received_quantity)
+        .bind(discrepancy_reason)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Moves the transfer to `status`, stamping when it was dispatched or
+    /// received.
+    pub async fn update_status_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        status: TransferStatus,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE stock_transfers
+            SET status = $2::text,
+                dispatched_at = CASE WHEN $2::text = 'in_transit' THEN NOW() ELSE dispatched_at END,
+                received_at = CASE WHEN $2::text = 'received' THEN NOW() ELSE received_at END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,8 +1,15 @@
 use chrono::{DateTime, TimeZone, Utc};
-use sqlx::{types::time::OffsetDateTime, Error, PgPool, Row};
+use rust_decimal::Decimal;
+use sqlx::{
+    types::{time::OffsetDateTime, BigDecimal},
+    Error, PgPool, Row,
+};
+use std::str::FromStr;
 use uuid::Uuid;
 
-use crate::models::warehouse::{CreateWarehouseDto, UpdateWarehouseDto, Warehouse};
+use crate::models::warehouse::{
+    CreateWarehouseDto, NearbyWarehouse, PostalCodeLocation, UpdateWarehouseDto, Warehouse,
+};
 
 pub struct WarehouseRepository {
     pool: PgPool,
@@ -27,6 +34,8 @@ impl WarehouseRepository {
     }
 
     fn map_row_to_warehouse(row: sqlx::postgres::PgRow) -> Result<Warehouse, Error> {
+        let fulfillment_cost: BigDecimal = row.get("fulfillment_cost");
+
         Ok(Warehouse {
             id: row.get("id"),
             code: row.get("code"),
@@ -37,9 +46,12 @@ impl WarehouseRepository {
             state: row.get("state"),
             postal_code: row.get("postal_code"),
             country: row.get("country"),
+            latitude: row.get("latitude"),
+            longitude: row.get("longitude"),
             contact_name: row.get("contact_name"),
             contact_phone: row.get("contact_phone"),
             contact_email: row.get("contact_email"),
+            fulfillment_cost: Decimal::from_str(&fulfillment_cost.to_string()).unwrap_or_default(),
             active: row.get("active"),
             created_at: Self::convert_datetime(row.get("created_at")),
             updated_at: Self::convert_datetime(row.get("updated_at")),
@@ -54,7 +66,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             FROM warehouses
             ORDER BY name ASC
             LIMIT $1 OFFSET $2
@@ -80,7 +92,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             FROM warehouses
             WHERE active = true
             ORDER BY name ASC
@@ -107,7 +119,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             FROM warehouses
             WHERE id = $1
             "#,
@@ -122,6 +134,31 @@ impl WarehouseRepository {
         }
     }
 
+    pub async fn find_by_ids(&self, ids: &[Uuid]) -> Result<Vec<Warehouse>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id, code, name,
+                address_line1, address_line2,
+                city, state, postal_code, country,
+                contact_name, contact_phone, contact_email,
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
+            FROM warehouses
+            WHERE id = ANY($1)
+            ORDER BY code ASC
+            "#,
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut warehouses = Vec::with_capacity(rows.len());
+        for row in rows {
+            warehouses.push(Self::map_row_to_warehouse(row)?);
+        }
+        Ok(warehouses)
+    }
+
     pub async fn find_by_code(&self, code: &str) -> Result<Option<Warehouse>, Error> {
         let row = sqlx::query(
             r#"
@@ -130,7 +167,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             FROM warehouses
             WHERE code = $1
             "#,
@@ -158,7 +195,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             FROM warehouses
             WHERE city ILIKE $1
             ORDER BY name ASC
@@ -191,7 +228,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             FROM warehouses
             WHERE country = $1
             ORDER BY name ASC
@@ -211,18 +248,96 @@ impl WarehouseRepository {
         Ok(warehouses)
     }
 
+    /// Active warehouses with coordinates, closest to the point first.
+    pub async fn find_nearest(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        limit: i64,
+    ) -> Result<Vec<NearbyWarehouse>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                id, code, name,
+                address_line1, address_line2,
+                city, state, postal_code, country,
+                contact_name, contact_phone, contact_email,
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at,
+                6371 * 2 * ASIN(SQRT(LEAST(1,
+                    POWER(SIN(RADIANS(latitude - $1) / 2), 2)
+                    + COS(RADIANS($1)) * COS(RADIANS(latitude))
+                      * POWER(SIN(RADIANS(longitude - $2) / 2), 2)
+                ))) AS distance_km
+            FROM warehouses
+            WHERE active = true AND latitude IS NOT NULL AND longitude IS NOT NULL
+            ORDER BY distance_km ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(latitude)
+        .bind(longitude)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut warehouses = Vec::with_capacity(rows.len());
+        for row in rows {
+            let distance_km: f64 = row.get("distance_km");
+            warehouses.push(NearbyWarehouse {
+                warehouse: Self::map_row_to_warehouse(row)?,
+                distance_km,
+            });
+        }
+        Ok(warehouses)
+    }
+
+    /// Looks a postal code up in the offline geocoding table. Without a
+    /// country, the first match is returned.
+    pub async fn find_postal_code_location(
+        &self,
+        postal_code: &str,
+        country: Option<&str>,
+    ) -> Result<Option<PostalCodeLocation>, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT postal_code, country, latitude, longitude, city, state
+            FROM postal_code_locations
+            WHERE UPPER(postal_code) = UPPER($1)
+              AND ($2::text IS NULL OR UPPER(country) = UPPER($2))
+            ORDER BY country
+            LIMIT 1
+            "#,
+        )
+        .bind(postal_code.trim())
+        .bind(country.map(str::trim))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|row| {
+            Ok(PostalCodeLocation {
+                postal_code: row.try_get("postal_code")?,
+                country: row.try_get("country")?,
+                latitude: row.try_get("latitude")?,
+                longitude: row.try_get("longitude")?,
+                city: row.try_get("city")?,
+                state: row.try_get("state")?,
+            })
+        })
+        .transpose()
+    }
+
     pub async fn create(&self, dto: CreateWarehouseDto) -> Result<Warehouse, Error> {
         let row = sqlx::query(
             r#"
             INSERT INTO warehouses
-            (code, name, address_line1, address_line2, city, state, postal_code, country, contact_name, contact_phone, contact_email, active)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            (code, name, address_line1, address_line2, city, state, postal_code, country, contact_name, contact_phone, contact_email, active, fulfillment_cost, latitude, longitude)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
             RETURNING
                 id, code, name,
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             "#,
         )
         .bind(dto.code)
@@ -237,6 +352,12 @@ impl WarehouseRepository {
         .bind(dto.contact_phone)
         .bind(dto.contact_email)
         .bind(dto.active.unwrap_or(true))
+        .bind(
+            BigDecimal::from_str(&dto.fulfillment_cost.unwrap_or_default().to_string())
+                .unwrap_or_default(),
+        )
+        .bind(dto.latitude)
+        .bind(dto.longitude)
         .fetch_one(&self.pool)
         .await?;
 
@@ -269,14 +390,17 @@ impl WarehouseRepository {
                 contact_phone = $10,
                 contact_email = $11,
                 active = $12,
+                fulfillment_cost = $13,
+                latitude = $14,
+                longitude = $15,
                 updated_at = NOW()
-            WHERE id = $13
+            WHERE id = $16
             RETURNING
                 id, code, name,
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             "#,
         )
         .bind(dto.name.unwrap_or(current.name))
@@ -303,6 +427,16 @@ impl WarehouseRepository {
                 .unwrap_or_else(|| current.contact_email.clone().unwrap_or_default()),
         )
         .bind(dto.active.unwrap_or(current.active))
+        .bind(
+            BigDecimal::from_str(
+                &dto.fulfillment_cost
+                    .unwrap_or(current.fulfillment_cost)
+                    .to_string(),
+            )
+            .unwrap_or_default(),
+        )
+        .bind(dto.latitude.or(current.latitude))
+        .bind(dto.longitude.or(current.longitude))
         .bind(id)
         .fetch_one(&self.pool)
         .await?;
@@ -321,7 +455,7 @@ impl WarehouseRepository {
                 address_line1, address_line2,
                 city, state, postal_code, country,
                 contact_name, contact_phone, contact_email,
-                active, created_at, updated_at
+                latitude, longitude, fulfillment_cost, active, created_at, updated_at
             "#,
         )
         .bind(active)
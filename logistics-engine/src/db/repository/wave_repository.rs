@@ -0,0 +1,696 @@
+use crate::models::entities::order::OrderStatus;
+use crate::models::wave::{
+    CarrierCutoff, CreateWaveDto, NewPickLine, PickLineOrder, PickLineStatus, PickWave,
+    PickWaveLine, PickWaveSummary, WaveCandidate, WaveDemand, WaveOrder, WaveOrderStatus,
+    WaveStatus,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{types::time::OffsetDateTime, Error, PgConnection, PgPool, Postgres, Row, Transaction};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub struct WaveRepository {
+    pool: PgPool,
+}
+
+impl WaveRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    fn convert_datetime(offset_dt: OffsetDateTime) -> DateTime<Utc> {
+        DateTime::<Utc>::from_timestamp(offset_dt.unix_timestamp(), offset_dt.nanosecond())
+            .unwrap_or_else(Utc::now)
+    }
+
+    fn to_offset_datetime(dt: DateTime<Utc>) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp_nanos(dt.timestamp_nanos_opt().unwrap_or(0) as i128)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+    }
+
+    fn convert_optional_datetime(offset_dt: Option<OffsetDateTime>) -> Option<DateTime<Utc>> {
+        offset_dt.map(Self::convert_datetime)
+    }
+
+    fn map_status(row: &sqlx::postgres::PgRow) -> Result<WaveStatus, Error> {
+        let status: String = row.try_get("status")?;
+        WaveStatus::from_str(&status).map_err(|e| Error::Decode(e.into()))
+    }
+
+    const SELECT_WAVES: &'static str = r#"
+        SELECT id, warehouse_id, status, carrier, zone, min_priority, cutoff_at, created_by,
+               completed_at, created_at, updated_at
+        FROM pick_waves
+        "#;
+
+    // Loads the wave with its orders and pick list, settled
+    async fn load(
+        conn: &mut PgConnection,
+        warehouse_id: Uuid,
+        id: Uuid,
+        lock: bool,
+    ) -> Result<Option<PickWave>, Error> {
+        let query = format!(
+            "{} WHERE id = $1 AND warehouse_id = $2{}",
+            Self::SELECT_WAVES,
+            if lock { " FOR UPDATE" } else { "" }
+        );
+        let row = match sqlx::query(&query)
+            .bind(id)
+            .bind(warehouse_id)
+            .fetch_optional(&mut *conn)
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok(None),
+        };
+
+        let order_rows = sqlx::query(
+            r#"
+            SELECT wo.order_id, wo.sequence, o.priority, o.status AS order_status,
+                   wo.packed_at, wo.packed_by
+            FROM pick_wave_orders wo
+            JOIN orders o ON o.id = wo.order_id
+            WHERE wo.wave_id = $1
+            ORDER BY wo.sequence
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let line_rows = sqlx::query(
+            r#"
+            SELECT l.id, l.line_number, l.bin_id, l.bin_code, l.item_id, i.sku, i.name,
+                   l.quantity, l.picked_quantity, l.confirmed_at, l.confirmed_by
+            FROM pick_wave_lines l
+            JOIN inventory_items i ON i.id = l.item_id
+            WHERE l.wave_id = $1
+            ORDER BY l.line_number
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let share_rows = sqlx::query(
+            r#"
+            SELECT lo.line_id, lo.order_id, lo.quantity
+            FROM pick_wave_line_orders lo
+            JOIN pick_wave_lines l ON l.id = lo.line_id
+            WHERE l.wave_id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        let mut shares: HashMap<Uuid, Vec<PickLineOrder>> = HashMap::new();
+        for share in &share_rows {
+            shares
+                .entry(share.try_get("line_id")?)
+                .or_default()
+                .push(PickLineOrder {
+                    order_id: share.try_get("order_id")?,
+                    quantity: share.try_get("quantity")?,
+                    picked: 0,
+                });
+        }
+
+        let orders = order_rows
+            .iter()
+            .map(|order| {
+                let packed_at: Option<OffsetDateTime> = order.try_get("packed_at")?;
+                Ok(WaveOrder {
+                    order_id: order.try_get("order_id")?,
+                    sequence: order.try_get("sequence")?,
+                    priority: order.try_get("priority")?,
+                    order_status: order.try_get::<OrderStatus, _>("order_status")?,
+                    status: WaveOrderStatus::Picking,
+                    units: 0,
+                    picked: 0,
+                    short: 0,
+                    packed_at: Self::convert_optional_datetime(packed_at),
+                    packed_by: order.try_get("packed_by")?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let lines = line_rows
+            .iter()
+            .map(|line| {
+                let id: Uuid = line.try_get("id")?;
+                let quantity: i32 = line.try_get("quantity")?;
+                let picked_quantity: Option<i32> = line.try_get("picked_quantity")?;
+                let confirmed_at: Option<OffsetDateTime> = line.try_get("confirmed_at")?;
+                Ok(PickWaveLine {
+                    id,
+                    line_number: line.try_get("line_number")?,
+                    bin_id: line.try_get("bin_id")?,
+                    code: line.try_get("bin_code")?,
+                    item_id: line.try_get("item_id")?,
+                    sku: line.try_get("sku")?,
+                    name: line.try_get("name")?,
+                    quantity,
+                    picked_quantity,
+                    status: PickLineStatus::of(quantity, picked_quantity),
+                    confirmed_at: Self::convert_optional_datetime(confirmed_at),
+                    confirmed_by: line.try_get("confirmed_by")?,
+                    orders: shares.remove(&id).unwrap_or_default(),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let cutoff_at: Option<OffsetDateTime> = row.try_get("cutoff_at")?;
+        let completed_at: Option<OffsetDateTime> = row.try_get("completed_at")?;
+        let created_at: OffsetDateTime = row.try_get("created_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+        let mut wave = PickWave {
+            id: row.try_get("id")?,
+            warehouse_id: row.try_get("warehouse_id")?,
+            status: Self::map_status(&row)?,
+            carrier: row.try_get("carrier")?,
+            zone: row.try_get("zone")?,
+            min_priority: row.try_get("min_priority")?,
+            cutoff_at: Self::convert_optional_datetime(cutoff_at),
+            created_by: row.try_get("created_by")?,
+            completed_at: Self::convert_optional_datetime(completed_at),
+            orders,
+            lines,
+            created_at: Self::convert_datetime(created_at),
+            updated_at: Self::convert_datetime(updated_at),
+        };
+        wave.settle();
+
+        Ok(Some(wave))
+    }
+
+    pub async fn find_by_id(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<PickWave>, Error> {
+        let mut conn = self.pool.acquire().await?;
+        Self::load(&mut conn, warehouse_id, id, false).await
+    }
+
+    /// The wave, its orders and its pick list, with the wave locked until the
+    /// transaction ends.
+    pub async fn find_by_id_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        id: Uuid,
+    ) -> Result<Option<PickWave>, Error> {
+        Self::load(&mut **tx, warehouse_id, id, true).await
+    }
+
+    /// The warehouse's waves, those still being picked first by cutoff, then
+    /// the newest.
+    pub async fn find_by_warehouse(
+        &self,
+        warehouse_id: Uuid,
+        status: Option<WaveStatus>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PickWaveSummary>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT w.id, w.warehouse_id, w.status, w.carrier, w.zone, w.cutoff_at, w.created_at,
+                   (SELECT COUNT(*) FROM pick_wave_orders wo WHERE wo.wave_id = w.id) AS orders,
+                   (SELECT COUNT(*) FROM pick_wave_orders wo
+                    WHERE wo.wave_id = w.id AND wo.packed_at IS NOT NULL) AS packed_orders,
+                   (SELECT COUNT(*) FROM pick_wave_lines l WHERE l.wave_id = w.id) AS lines,
+                   (SELECT COUNT(*) FROM pick_wave_lines l
+                    WHERE l.wave_id = w.id AND l.picked_quantity IS NULL) AS pending_lines,
+                   (SELECT COUNT(*) FROM pick_wave_lines l
+                    WHERE l.wave_id = w.id AND l.picked_quantity < l.quantity) AS short_lines
+            FROM pick_waves w
+            WHERE w.warehouse_id = $1
+              AND ($2::text IS NULL OR w.status = $2)
+            ORDER BY w.status <> 'picking', w.cutoff_at NULLS LAST, w.created_at DESC
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(status.map(|status| status.as_str()))
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let cutoff_at: Option<OffsetDateTime> = row.try_get("cutoff_at")?;
+                let created_at: OffsetDateTime = row.try_get("created_at")?;
+                Ok(PickWaveSummary {
+                    id: row.try_get("id")?,
+                    warehouse_id: row.try_get("warehouse_id")?,
+                    status: Self::map_status(row)?,
+                    carrier: row.try_get("carrier")?,
+                    zone: row.try_get("zone")?,
+                    cutoff_at: Self::convert_optional_datetime(cutoff_at),
+                    orders: row.try_get("orders")?,
+                    packed_orders: row.try_get("packed_orders")?,
+                    lines: row.try_get("lines")?,
+                    pending_lines: row.try_get("pending_lines")?,
+                    short_lines: row.try_get("short_lines")?,
+                    created_at: Self::convert_datetime(created_at),
+                })
+            })
+            .collect()
+    }
+
+    /// Locks the warehouse so only one wave is built from its orders at a
+    /// time. Returns `false` when there is no such warehouse.
+    pub async fn lock_warehouse_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+    ) -> Result<bool, Error> {
+        let row = sqlx::query("SELECT id FROM warehouses WHERE id = $1 FOR UPDATE")
+            .bind(warehouse_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// `Processing` orders that took units out of the warehouse and are not in
+    /// any of its waves yet, those their carrier collects next first, then
+    /// highest priority first, then oldest first. With `cutoff_at` only orders
+    /// whose carrier collects by then are returned.
+    pub async fn find_candidates_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        carrier: Option<&str>,
+        min_priority: Option<i32>,
+        cutoff_at: Option<DateTime<Utc>>,
+    ) -> Result<Vec<WaveCandidate>, Error> {
+        let rows = sqlx::query(
+            r#"
+            SELECT o.id, c.cutoff_at
+            FROM orders o
+            LEFT JOIN shipping_info s ON s.order_id = o.id
+            LEFT JOIN LATERAL (
+                SELECT ((date_trunc('day', NOW() AT TIME ZONE 'UTC') + co.cutoff_time)
+                        AT TIME ZONE 'UTC')
+                       + CASE WHEN (NOW() AT TIME ZONE 'UTC')::time > co.cutoff_time
+                              THEN INTERVAL '1 day' ELSE INTERVAL '0 days' END AS cutoff_at
+                FROM carrier_cutoffs co
+                WHERE co.warehouse_id = $1 AND LOWER(co.carrier) = LOWER(s.carrier)
+            ) c ON TRUE
+            WHERE o.status = $2
+              AND EXISTS (
+                  SELECT 1 FROM inventory_transactions t
+                  WHERE t.warehouse_id = $1 AND t.reference = 'order:' || o.id::text
+                    AND t."type"::text = 'remove'
+              )
+              AND NOT EXISTS (
+                  SELECT 1 FROM pick_wave_orders wo
+                  JOIN pick_waves w ON w.id = wo.wave_id
+                  WHERE wo.order_id = o.id AND w.warehouse_id = $1 AND w.status <> 'cancelled'
+              )
+              AND ($3::text IS NULL OR LOWER(s.carrier) = LOWER($3))
+              AND ($4::integer IS NULL OR o.priority >= $4)
+              AND ($5::timestamptz IS NULL OR c.cutoff_at <= $5)
+            ORDER BY c.cutoff_at NULLS LAST, o.priority DESC, o.created_at, o.id
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(OrderStatus::Processing)
+        .bind(carrier)
+        .bind(min_priority)
+        .bind(cutoff_at.map(Self::to_offset_datetime))
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let cutoff_at: Option<OffsetDateTime> = row.try_get("cutoff_at")?;
+                Ok(WaveCandidate {
+                    order_id: row.try_get("id")?,
+                    cutoff_at: Self::convert_optional_datetime(cutoff_at),
+                })
+            })
+            .collect()
+    }
+
+    // What the orders took out of stock when they went to `Processing`: the
+    // units picked from each bin, and the rest as units without a bin. Every
+    // warehouse counts when `warehouse_id` is `None`.
+    async fn load_demand(
+        conn: &mut PgConnection,
+        warehouse_id: Option<Uuid>,
+        order_ids: &[Uuid],
+    ) -> Result<Vec<WaveDemand>, Error> {
+        let rows = sqlx::query(
+            r#"
+            WITH taken AS (
+                SELECT o.id AS order_id, t.warehouse_id, t.item_id,
+                       SUM(CASE t."type"::text
+                               WHEN 'remove' THEN t.quantity
+                               WHEN 'add' THEN -t.quantity
+                               ELSE 0
+                           END)::INTEGER AS quantity
+                FROM UNNEST($2::uuid[]) AS o(id)
+                JOIN inventory_transactions t ON t.reference = 'order:' || o.id::text
+                WHERE $1::uuid IS NULL OR t.warehouse_id = $1
+                GROUP BY o.id, t.warehouse_id, t.item_id
+            ),
+            picked AS (
+                SELECT o.id AS order_id, m.warehouse_id, m.item_id, m.from_bin_id AS bin_id,
+                       SUM(m.quantity)::INTEGER AS quantity
+                FROM UNNEST($2::uuid[]) AS o(id)
+                JOIN bin_movements m ON m.reference = 'order:' || o.id::text
+                WHERE ($1::uuid IS NULL OR m.warehouse_id = $1) AND m.movement_type = 'pick'
+                  AND m.from_bin_id IS NOT NULL
+                GROUP BY o.id, m.warehouse_id, m.item_id, m.from_bin_id
+            ),
+            demand AS (
+                SELECT order_id, warehouse_id, item_id, bin_id, quantity FROM picked
+                UNION ALL
+                SELECT t.order_id, t.warehouse_id, t.item_id, NULL::uuid,
+                       (t.quantity - COALESCE(SUM(p.quantity), 0))::INTEGER
+                FROM taken t
+                LEFT JOIN picked p ON p.order_id = t.order_id
+                    AND p.warehouse_id = t.warehouse_id AND p.item_id = t.item_id
+                GROUP BY t.order_id, t.warehouse_id, t.item_id, t.quantity
+            )
+            SELECT d.order_id, d.warehouse_id, d.item_id, i.sku, i.name, d.bin_id, b.code,
+                   b.zone, w.walk_order, d.quantity
+            FROM demand d
+            JOIN inventory_items i ON i.id = d.item_id
+            LEFT JOIN warehouse_bins b ON b.id = d.bin_id
+            LEFT JOIN warehouse_bin_walk w ON w.id = d.bin_id
+            WHERE d.quantity > 0
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(order_ids)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(WaveDemand {
+                    order_id: row.try_get("order_id")?,
+                    warehouse_id: row.try_get("warehouse_id")?,
+                    item_id: row.try_get("item_id")?,
+                    sku: row.try_get("sku")?,
+                    name: row.try_get("name")?,
+                    bin_id: row.try_get("bin_id")?,
+                    code: row.try_get("code")?,
+                    zone: row.try_get("zone")?,
+                    walk_order: row.try_get("walk_order")?,
+                    quantity: row.try_get("quantity")?,
+                })
+            })
+            .collect()
+    }
+
+    /// What the orders took out of the warehouse when they went to
+    /// `Processing`: the units picked from each bin, and the rest as units
+    /// without a bin.
+    pub async fn find_demand_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        order_ids: &[Uuid],
+    ) -> Result<Vec<WaveDemand>, Error> {
+        Self::load_demand(&mut **tx, Some(warehouse_id), order_ids).await
+    }
+
+    /// What the order took out of each warehouse, as picked for a wave.
+    pub async fn find_order_demand(&self, order_id: Uuid) -> Result<Vec<WaveDemand>, Error> {
+        let mut conn = self.pool.acquire().await?;
+        Self::load_demand(&mut conn, None, &[order_id]).await
+    }
+
+    /// Creates the wave with its orders, in the order given, and its pick
+    /// list. Returns the wave's id.
+    pub async fn create_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        warehouse_id: Uuid,
+        dto: &CreateWaveDto,
+        order_ids: &[Uuid],
+        lines: &[NewPickLine],
+    ) -> Result<Uuid, Error> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO pick_waves
+                (warehouse_id, status, carrier, zone, min_priority, cutoff_at, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(WaveStatus::Picking.as_str())
+        .bind(&dto.carrier)
+        .bind(&dto.zone)
+        .bind(dto.min_priority)
+        .bind(dto.cutoff_at.map(Self::to_offset_datetime))
+        .bind(&dto.user_id)
+        .fetch_one(&mut **tx)
+        .await?;
+        let id: Uuid = row.try_get("id")?;
+
+        for (sequence, order_id) in order_ids.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO pick_wave_orders (wave_id, order_id, sequence) VALUES ($1, $2, $3)",
+            )
+            .bind(id)
+            .bind(order_id)
+            .bind(sequence as i32 + 1)
+            .execute(&mut **tx)
+            .await?;
+        }
+
+        for line in lines {
+            let row = sqlx::query(
+                r#"
+                INSERT INTO pick_wave_lines (wave_id, line_number, bin_id, bin_code, item_id, quantity)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING id
+                "#,
+            )
+            .bind(id)
+            .bind(line.line_number)
+            .bind(line.bin_id)
+            .bind(&line.code)
+            .bind(line.item_id)
+            .bind(line.quantity)
+            .fetch_one(&mut **tx)
+            .await?;
+            let line_id: Uuid = row.try_get("id")?;
+
+            for (order_id, quantity) in &line.orders {
+                sqlx::query(
+                    r#"
+                    INSERT INTO pick_wave_line_orders (line_id, order_id, quantity)
+                    VALUES ($1, $2, $3)
+                    "#,
+                )
+                .bind(line_id)
+                .bind(order_id)
+                .bind(quantity)
+                .execute(&mut **tx)
+                .await?;
+            }
+        }
+
+        Ok(id)
+    }
+
+    pub async fn confirm_line_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        line_id: Uuid,
+        picked_quantity: i32,
+        confirmed_by: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE pick_wave_lines
+            SET picked_quantity = $2, confirmed_at = NOW(), confirmed_by = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(line_id)
+        .bind(picked_quantity)
+        .bind(confirmed_by)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn pack_order_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        wave_id: Uuid,
+        order_id: Uuid,
+        packed_by: Option<&str>,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE pick_wave_orders
+            SET packed_at = NOW(), packed_by = $3
+            WHERE wave_id = $1 AND order_id = $2
+            "#,
+        )
+        .bind(wave_id)
+        .bind(order_id)
+        .bind(packed_by)
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_status_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        status: WaveStatus,
+    ) -> Result<(), Error> {
+        sqlx::query(
+            r#"
+            UPDATE pick_waves
+            SET status = $2,
+                completed_at = CASE WHEN $2 = 'completed' THEN NOW() ELSE completed_at END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    const SELECT_CARRIER_CUTOFFS: &'static str = r#"
+        SELECT warehouse_id, carrier, to_char(cutoff_time, 'HH24:MI') AS cutoff_time,
+               ((date_trunc('day', NOW() AT TIME ZONE 'UTC') + cutoff_time) AT TIME ZONE 'UTC')
+               + CASE WHEN (NOW() AT TIME ZONE 'UTC')::time > cutoff_time
+                      THEN INTERVAL '1 day' ELSE INTERVAL '0 days' END AS next_cutoff_at,
+               updated_by, updated_at
+        FROM carrier_cutoffs
+        "#;
+
+    fn map_row_to_carrier_cutoff(row: &sqlx::postgres::PgRow) -> Result<CarrierCutoff, Error> {
+        let next_cutoff_at: OffsetDateTime = row.try_get("next_cutoff_at")?;
+        let updated_at: OffsetDateTime = row.try_get("updated_at")?;
+
+        Ok(CarrierCutoff {
+            warehouse_id: row.try_get("warehouse_id")?,
+            carrier: row.try_get("carrier")?,
+            cutoff_time: row.try_get("cutoff_time")?,
+            next_cutoff_at: Self::convert_datetime(next_cutoff_at),
+            updated_by: row.try_get("updated_by")?,
+            updated_at: Self::convert_datetime(updated_at),
+        })
+    }
+
+    /// The warehouse's carrier cutoffs, the one collecting next first.
+    pub async fn find_carrier_cutoffs(
+        &self,
+        warehouse_id: Uuid,
+    ) -> Result<Vec<CarrierCutoff>, Error> {
+        let query = format!(
+            "{} WHERE warehouse_id = $1 ORDER BY next_cutoff_at, carrier",
+            Self::SELECT_CARRIER_CUTOFFS
+        );
+        let rows = sqlx::query(&query)
+            .bind(warehouse_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(Self::map_row_to_carrier_cutoff).collect()
+    }
+
+    /// Sets when the carrier collects from the warehouse. `cutoff_time` is
+    /// `HH:MM:SS` in UTC.
+    pub async fn upsert_carrier_cutoff(
+        &self,
+        warehouse_id: Uuid,
+        carrier: &str,
+        cutoff_time: &str,
+        updated_by: Option<&str>,
+    ) -> Result<CarrierCutoff, Error> {
+        sqlx::query(
+            r#"
+            INSERT INTO carrier_cutoffs (warehouse_id, carrier, cutoff_time, updated_by)
+            VALUES ($1, $2, $3::time, $4)
+            ON CONFLICT (warehouse_id, LOWER(carrier)) DO UPDATE
+            SET carrier = EXCLUDED.carrier, cutoff_time = EXCLUDED.cutoff_time,
+                updated_by = EXCLUDED.updated_by, updated_at = NOW()
+            "#,
+        )
+        .bind(warehouse_id)
+        .bind(carrier)
+        .bind(cutoff_time)
+        .bind(updated_by)
+        .execute(&self.pool)
+        .await?;
+
+        let query = format!(
+            "{} WHERE warehouse_id = $1 AND LOWER(carrier) = LOWER($2)",
+            Self::SELECT_CARRIER_CUTOFFS
+        );
+        let row = sqlx::query(&query)
+            .bind(warehouse_id)
+            .bind(carrier)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Self::map_row_to_carrier_cutoff(&row)
+    }
+
+    /// Returns `false` when the carrier had no cutoff in the warehouse.
+    pub async fn delete_carrier_cutoff(
+        &self,
+        warehouse_id: Uuid,
+        carrier: &str,
+    ) -> Result<bool, Error> {
+        let result = sqlx::query(
+            "DELETE FROM carrier_cutoffs WHERE warehouse_id = $1 AND LOWER(carrier) = LOWER($2)",
+        )
+        .bind(warehouse_id)
+        .bind(carrier)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether the order is in a wave still being picked and has not been
+    /// packed yet.
+    pub async fn is_awaiting_packing_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+    ) -> Result<bool, Error> {
+        let row = sqlx::query(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM pick_wave_orders wo
+                JOIN pick_waves w ON w.id = wo.wave_id
+                WHERE wo.order_id = $1 AND w.status = $2 AND wo.packed_at IS NULL
+            ) AS awaiting
+            "#,
+        )
+        .bind(order_id)
+        .bind(WaveStatus::Picking.as_str())
+        .fetch_one(&mut **tx)
+        .await?;
+
+        row.try_get("awaiting")
+    }
+}
@@ -1,5 +1,8 @@
 use std::fmt;
 
+use crate::db::repository::inventory_repository::NegativeStockError;
+use crate::models::entities::order::OrderStatus;
+
 #[derive(Debug)]
 pub enum LogisticsError {
     DatabaseError(sqlx::Error),
@@ -7,6 +10,10 @@ pub enum LogisticsError {
     ValidationError(String),
     InternalError(String),
     BadRequest(String),
+    InvalidStatusTransition(OrderStatus, OrderStatus),
+    ServiceUnavailable(String),
+    Conflict(String),
+    UnprocessableEntity(String),
 }
 
 impl fmt::Display for LogisticsError {
@@ -19,6 +26,15 @@ impl fmt::Display for LogisticsError {
             LogisticsError::ValidationError(msg) => write!(f, "Validation error: {}", msg),
             LogisticsError::InternalError(msg) => write!(f, "Internal error: {}", msg),
             LogisticsError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            LogisticsError::InvalidStatusTransition(from, to) => write!(
+                f,
+                "Invalid status transition from {} to {}",
+                from.to_string(),
+                to.to_string()
+            ),
+            LogisticsError::ServiceUnavailable(msg) => write!(f, "Service unavailable: {}", msg),
+            LogisticsError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            LogisticsError::UnprocessableEntity(msg) => write!(f, "Unprocessable entity: {}", msg),
         }
     }
 }
@@ -27,8 +43,48 @@ impl std::error::Error for LogisticsError {}
 
 impl From<sqlx::Error> for LogisticsError {
     fn from(err: sqlx::Error) -> Self {
+        // Stock the ledger refuses to take is the caller's conflict, not ours
+        if let sqlx::Error::Database(db_err) = &err {
+            if let Some(refused) = db_err.try_downcast_ref::<NegativeStockError>() {
+                return LogisticsError::Conflict(refused.to_string());
+            }
+        }
+
         LogisticsError::DatabaseError(err)
     }
 }
 
+impl From<crate::error::AppError> for LogisticsError {
+    fn from(err: crate::error::AppError) -> Self {
+        use crate::error::AppError;
+
+        match err {
+            AppError::DatabaseError(err) => LogisticsError::DatabaseError(err),
+            AppError::BadRequest(msg) => LogisticsError::BadRequest(msg),
+            AppError::ValidationError(msg) => LogisticsError::ValidationError(msg),
+            other => LogisticsError::InternalError(other.to_string()),
+        }
+    }
+}
+
+impl From<LogisticsError> for tonic::Status {
+    fn from(err: LogisticsError) -> Self {
+        match err {
+            LogisticsError::NotFound(..) => tonic::Status::not_found(err.to_string()),
+            LogisticsError::ValidationError(msg) | LogisticsError::BadRequest(msg) => {
+                tonic::Status::invalid_argument(msg)
+            }
+            LogisticsError::InvalidStatusTransition(..) => {
+                tonic::Status::failed_precondition(err.to_string())
+            }
+            LogisticsError::DatabaseError(_) | LogisticsError::InternalError(_) => {
+                tonic::Status::internal(err.to_string())
+            }
+            LogisticsError::ServiceUnavailable(msg) => tonic::Status::unavailable(msg),
+            LogisticsError::Conflict(msg) => tonic::Status::aborted(msg),
+            LogisticsError::UnprocessableEntity(msg) => tonic::Status::invalid_argument(msg),
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, LogisticsError>;
@@ -0,0 +1,267 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through
+    Closed,
+    /// Calls fail fast until the open period is over
+    Open,
+    /// The open period is over and a single trial call decides what happens next
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitBreakerSnapshot {
+    pub name: &'static str,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub failure_threshold: u32,
+    pub opened_at: Option<DateTime<Utc>>,
+    pub rejected_calls: u64,
+}
+
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    opened_at_utc: Option<DateTime<Utc>>,
+    trial_started_at: Option<Instant>,
+    rejected_calls: u64,
+}
+
+/// Stops calling a dependency after `failure_threshold` consecutive failures
+/// and lets a single trial call through once `open_duration` has passed.
+pub struct CircuitBreaker {
+    name: &'static str,
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            name,
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            state: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                opened_at_utc: None,
+                trial_started_at: None,
+                rejected_calls: 0,
+            }),
+        }
+    }
+
+    /// Whether a call may be made now. Every allowed call must be followed by
+    /// `record_success` or `record_failure`.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let allowed = match state.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => {
+                let elapsed = state
+                    .opened_at
+                    .map_or(true, |opened_at| opened_at.elapsed() >= self.open_duration);
+                if elapsed {
+                    state.state = CircuitState::HalfOpen;
+                    state.trial_started_at = Some(Instant::now());
+                }
+                elapsed
+            }
+            // A trial whose caller went away never reports back, so it only
+            // holds the slot for one open period
+            CircuitState::HalfOpen => {
+                let stale = state
+                    .trial_started_at
+                    .map_or(true, |started| started.elapsed() >= self.open_duration);
+                if stale {
+                    state.trial_started_at = Some(Instant::now());
+                }
+                stale
+            }
+        };
+
+        if !allowed {
+            state.rejected_calls += 1;
+        }
+        allowed
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.state != CircuitState::Closed {
+            info!("Circuit breaker {} closed", self.name);
+        }
+        state.state = CircuitState::Closed;
+        state.consecutive_failures = 0;
+        state.opened_at = None;
+        state.opened_at_utc = None;
+        state.trial_started_at = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+
+        let trip = state.state == CircuitState::HalfOpen
+            || state.consecutive_failures >= self.failure_threshold;
+        if trip {
+            if state.state != CircuitState::Open {
+                warn!(
+                    "Circuit breaker {} opened after {} consecutive failures",
+                    self.name, state.consecutive_failures
+                );
+            }
+            state.state = CircuitState::Open;
+            state.opened_at = Some(Instant::now());
+            state.opened_at_utc = Some(Utc::now());
+            state.trial_started_at = None;
+        }
+    }
+
+    pub fn snapshot(&self) -> CircuitBreakerSnapshot {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        // An open breaker whose period is over admits the next call
+        let current = match (state.state, state.opened_at) {
+            (CircuitState::Open, Some(opened_at)) if opened_at.elapsed() >= self.open_duration => {
+                CircuitState::HalfOpen
+            }
+            (current, _) => current,
+        };
+
+        CircuitBreakerSnapshot {
+            name: self.name,
+            state: current,
+            consecutive_failures: state.consecutive_failures,
+            failure_threshold: self.failure_threshold,
+            opened_at: state.opened_at_utc,
+            rejected_calls: state.rejected_calls,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONG: Duration = Duration::from_secs(3600);
+    const SHORT: Duration = Duration::from_millis(20);
+
+    fn wait_out(period: Duration) {
+        std::thread::sleep(period + Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_breaker_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new("test", 3, LONG);
+
+        for _ in 0..2 {
+            assert!(breaker.try_acquire());
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.snapshot().state, CircuitState::Closed);
+
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert_eq!(snapshot.consecutive_failures, 3);
+        assert!(snapshot.opened_at.is_some());
+    }
+
+    #[test]
+    fn test_success_resets_the_failure_count() {
+        let breaker = CircuitBreaker::new("test", 2, LONG);
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!(snapshot.consecutive_failures, 1);
+    }
+
+    #[test]
+    fn test_open_breaker_rejects_calls_until_the_period_is_over() {
+        let breaker = CircuitBreaker::new("test", 1, LONG);
+        breaker.record_failure();
+
+        assert!(!breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert_eq!(snapshot.rejected_calls, 2);
+    }
+
+    #[test]
+    fn test_half_open_breaker_admits_a_single_trial() {
+        let breaker = CircuitBreaker::new("test", 1, Duration::ZERO);
+        breaker.record_failure();
+        assert_eq!(breaker.snapshot().state, CircuitState::HalfOpen);
+
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.snapshot().state, CircuitState::HalfOpen);
+
+        breaker.record_success();
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!(snapshot.consecutive_failures, 0);
+        assert!(snapshot.opened_at.is_none());
+    }
+
+    #[test]
+    fn test_failed_trial_reopens_the_breaker() {
+        let breaker = CircuitBreaker::new("test", 3, SHORT);
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        wait_out(SHORT);
+
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+        breaker.record_failure();
+
+        // A failed trial opens the breaker for a full period again
+        assert!(!breaker.try_acquire());
+        let snapshot = breaker.snapshot();
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert_eq!(snapshot.rejected_calls, 2);
+    }
+
+    #[test]
+    fn test_abandoned_trial_frees_the_slot_after_the_open_period() {
+        let breaker = CircuitBreaker::new("test", 1, SHORT);
+        breaker.record_failure();
+        wait_out(SHORT);
+
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        wait_out(SHORT);
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn test_threshold_is_at_least_one() {
+        let breaker = CircuitBreaker::new("test", 0, LONG);
+        assert_eq!(breaker.snapshot().failure_threshold, 1);
+
+        breaker.record_failure();
+        assert_eq!(breaker.snapshot().state, CircuitState::Open);
+    }
+}
@@ -1,7 +1,12 @@
 use crate::config::get as get_config;
+use crate::grpc::circuit_breaker::{CircuitBreaker, CircuitBreakerSnapshot};
+use std::future::Future;
 use std::str::FromStr;
 use std::time::Duration;
+use tokio::time;
 use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Response, Status};
+use tracing::warn;
 
 // Import the proto-generated code
 use crate::proto::inventory::{
@@ -10,22 +15,126 @@ use crate::proto::inventory::{
     ReleaseStockRequest, ReleaseStockResponse, StockReservationRequest, StockReservationResponse,
 };
 
+/// Whether the call failed because the inventory service could not be reached
+/// or did not answer in time, as opposed to the service rejecting the request.
+pub fn is_unavailable(status: &Status) -> bool {
+    matches!(
+        status.code(),
+        Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted
+    )
+}
+
+/// Client for the inventory service. Every call has a deadline and goes through
+/// a circuit breaker; idempotent calls are retried with exponential backoff when
+/// the service is unreachable.
 pub struct InventoryClient {
     client: InventoryServiceClient<Channel>,
+    breaker: CircuitBreaker,
+    call_timeout: Duration,
+    retry_attempts: u32,
+    retry_base_delay: Duration,
 }
 
 impl InventoryClient {
-    pub async fn new() -> Result<Self, tonic::transport::Error> {
+    /// Builds the client without connecting; the channel connects on first use
+    /// and reconnects on its own, so a service that is down at startup does
+    /// not keep the engine from booting.
+    pub fn new() -> Result<Self, tonic::transport::Error> {
         let config = get_config();
+        let settings = &config.inventory_client;
+
+        let call_timeout = Duration::from_millis(settings.call_timeout_ms.max(1));
         let endpoint = Endpoint::from_str(&config.grpc.inventory_url)?
-            .timeout(Duration::from_secs(10))
-            .connect_timeout(Duration::from_secs(5))
+            .timeout(call_timeout)
+            .connect_timeout(Duration::from_millis(settings.connect_timeout_ms.max(1)))
             .tcp_keepalive(Some(Duration::from_secs(30)));
 
-        let client = InventoryServiceClient::connect(endpoint).await?;
-        Ok(Self { client })
+        Ok(Self {
+            client: InventoryServiceClient::new(endpoint.connect_lazy()),
+            breaker: CircuitBreaker::new(
+                "inventory-service",
+                settings.breaker_failure_threshold,
+                Duration::from_secs(settings.breaker_open_seconds),
+            ),
+            call_timeout,
+            retry_attempts: settings.retry_attempts,
+            retry_base_delay: Duration::from_millis(settings.retry_base_delay_ms),
+        })
+    }
+
+    pub fn circuit_breaker(&self) -> CircuitBreakerSnapshot {
+        self.breaker.snapshot()
+    }
+
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request.set_timeout(self.call_timeout);
+        request
+    }
+
+    /// Runs `rpc` through the circuit breaker. Only failures that mean the
+    /// service is unreachable count against the breaker or are retried, and
+    /// retries only happen for `idempotent` calls.
+    async fn call<T, F, Fut>(&self, name: &str, idempotent: bool, mut rpc: F) -> Result<T, Status>
+    where
+        F: FnMut(InventoryServiceClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<Response<T>, Status>>,
+    {
+        let max_attempts = if idempotent {
+            self.retry_attempts + 1
+        } else {
+            1
+        };
+        let mut attempt = 1;
+
+        loop {
+            if !self.breaker.try_acquire() {
+                return Err(Status::unavailable(format!(
+                    "Inventory service circuit is open, {} not attempted",
+                    name
+                )));
+            }
+
+            let result = match time::timeout(self.call_timeout, rpc(self.client.clone())).await {
+                Ok(result) => result.map(Response::into_inner),
+                Err(_) => Err(Status::deadline_exceeded(format!(
+                    "{} did not complete within {:?}",
+                    name, self.call_timeout
+                ))),
+            };
+
+            match result {
+                Err(status) if is_unavailable(&status) => {
+                    self.breaker.record_failure();
+
+                    if attempt >= max_attempts {
+                        return Err(status);
+                    }
+
+                    let delay = self
+                        .retry_base_delay
+                        .saturating_mul(2u32.saturating_pow(attempt - 1));
+                    warn!(
+                        "Inventory {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        name,
+                        attempt,
+                        max_attempts,
+                        delay,
+                        status.message()
+                    );
+                    time::sleep(delay).await;
+                    attempt += 1;
+                }
+                // The service answered, even if it turned the request down
+                other => {
+                    self.breaker.record_success();
+                    return other;
+                }
+            }
+        }
     }
 
+    /// Not retried: a retry after a lost response could reserve the stock twice.
     pub async fn check_and_reserve_stock(
         &self,
         order_id: String,
@@ -38,9 +147,11 @@ impl InventoryClient {
             warehouse_id,
         };
 
-        let mut client = self.client.clone();
-        let response = client.check_and_reserve_stock(request).await?;
-        Ok(response.into_inner())
+        self.call("CheckAndReserveStock", false, |mut client| {
+            let request = self.request(request.clone());
+            async move { client.check_and_reserve_stock(request).await }
+        })
+        .await
     }
 
     pub async fn release_reserved_stock(
@@ -55,9 +166,11 @@ impl InventoryClient {
             reason,
         };
 
-        let mut client = self.client.clone();
-        let response = client.release_reserved_stock(request).await?;
-        Ok(response.into_inner())
+        self.call("ReleaseReservedStock", true, |mut client| {
+            let request = self.request(request.clone());
+            async move { client.release_reserved_stock(request).await }
+        })
+        .await
     }
 
     pub async fn commit_reservation(
@@ -70,9 +183,11 @@ impl InventoryClient {
             order_id,
         };
 
-        let mut client = self.client.clone();
-        let response = client.commit_reservation(request).await?;
-        Ok(response.into_inner())
+        self.call("CommitReservation", true, |mut client| {
+            let request = self.request(request.clone());
+            async move { client.commit_reservation(request).await }
+        })
+        .await
     }
 
     pub async fn get_inventory_levels(
@@ -87,8 +202,10 @@ impl InventoryClient {
             warehouse_id,
         };
 
-        let mut client = self.client.clone();
-        let response = client.get_inventory_levels(request).await?;
-        Ok(response.into_inner())
+        self.call("GetInventoryLevels", true, |mut client| {
+            let request = self.request(request.clone());
+            async move { client.get_inventory_levels(request).await }
+        })
+        .await
     }
 }
@@ -3,3 +3,4 @@ pub mod server;
 
 // Re-export for convenient access
 pub use client::InventoryClient;
+pub use server::InventoryGrpcService;
@@ -1,2 +1,264 @@
-// This file will contain the gRPC server implementation for the Inventory Service
-// It will be implemented when the inventory service is ready to expose gRPC endpoints
+use crate::errors::LogisticsError;
+use crate::models::inventory::{InventoryLevel, StockAvailability, StockLine};
+use crate::services::{BinService, InventoryService};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tracing::info;
+use uuid::Uuid;
+
+// Import the proto-generated code
+use crate::proto::inventory::{
+    inventory_service_server::{InventoryService as GrpcInventoryService, InventoryServiceServer},
+    CommitReservationRequest, CommitReservationResponse, InventoryItem as GrpcInventoryItem,
+    InventoryLevelsRequest, InventoryLevelsResponse, ProductAvailability, ReleaseStockRequest,
+    ReleaseStockResponse, StockReservationRequest, StockReservationResponse,
+};
+
+const RESERVATION_ID_PREFIX: &str = "reservation-";
+
+pub struct InventoryGrpcService {
+    inventory_service: Arc<InventoryService>,
+    bin_service: Arc<BinService>,
+}
+
+impl InventoryGrpcService {
+    pub fn new(inventory_service: Arc<InventoryService>, bin_service: Arc<BinService>) -> Self {
+        Self {
+            inventory_service,
+            bin_service,
+        }
+    }
+
+    pub fn into_service(self) -> InventoryServiceServer<Self> {
+        InventoryServiceServer::new(self)
+    }
+
+    fn parse_uuid(value: &str, field: &str) -> Result<Uuid, Status> {
+        Uuid::parse_str(value)
+            .map_err(|e| Status::invalid_argument(format!("Invalid {}: {}", field, e)))
+    }
+
+    // An empty warehouse ID means each item's home warehouse
+    fn parse_warehouse_id(value: &str) -> Result<Option<Uuid>, Status> {
+        if value.is_empty() {
+            return Ok(None);
+        }
+        Self::parse_uuid(value, "warehouse ID").map(Some)
+    }
+
+    // A reservation covers all lines of one order, so its ID is derived from
+    // the order ID
+    fn reservation_id(order_id: Uuid) -> String {
+        format!("{}{}", RESERVATION_ID_PREFIX, order_id)
+    }
+
+    /// Resolves the order a release or commit refers to. The order ID wins when
+    /// both are given.
+    fn resolve_order_id(order_id: &str, reservation_id: &str) -> Result<Uuid, Status> {
+        if !order_id.is_empty() {
+            return Self::parse_uuid(order_id, "order ID");
+        }
+
+        match reservation_id.strip_prefix(RESERVATION_ID_PREFIX) {
+            Some(order_id) => Self::parse_uuid(order_id, "reservation ID"),
+            None if reservation_id.is_empty() => Err(Status::invalid_argument(
+                "Either order_id or reservation_id is required",
+            )),
+            None => Err(Status::invalid_argument(format!(
+                "Invalid reservation ID: {}",
+                reservation_id
+            ))),
+        }
+    }
+
+    fn to_product_availability(line: StockAvailability) -> ProductAvailability {
+        ProductAvailability {
+            product_id: line.product_id.to_string(),
+            sku: line.sku,
+            in_stock: line.in_stock,
+            available_quantity: line.available,
+            error_message: line.error.unwrap_or_default(),
+        }
+    }
+
+    // `location` lists the bins holding the item in the warehouse, in walking
+    // order
+    fn to_grpc_item(
+        level: InventoryLevel,
+        locations: &HashMap<(Uuid, Uuid), Vec<String>>,
+    ) -> GrpcInventoryItem {
+        let location = locations
+            .get(&(level.item_id, level.warehouse_id))
+            .map(|codes| codes.join(","))
+            .unwrap_or_default();
+
+        GrpcInventoryItem {
+            product_id: level.item_id.to_string(),
+            sku: level.sku,
+            quantity: level.quantity,
+            reserved_quantity: level.reserved,
+            available_quantity: level.available,
+            warehouse_id: level.warehouse_id.to_string(),
+            location,
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl GrpcInventoryService for InventoryGrpcService {
+    async fn check_and_reserve_stock(
+        &self,
+        request: Request<StockReservationRequest>,
+    ) -> Result<Response<StockReservationResponse>, Status> {
+        let req = request.into_inner();
+        let order_id = Self::parse_uuid(&req.order_id, "order ID")?;
+        let warehouse_id = Self::parse_warehouse_id(&req.warehouse_id)?;
+
+        let lines = req
+            .items
+            .iter()
+            .map(|item| {
+                Ok(StockLine {
+                    product_id: Self::parse_uuid(&item.product_id, "product ID")?,
+                    quantity: item.quantity,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        let outcome = self
+            .inventory_service
+            .reserve_order_stock(order_id, &lines, warehouse_id)
+            .await?;
+
+        let message = if outcome.reserved {
+            info!(
+                "Reserved stock for order {} ({} reservations)",
+                order_id,
+                outcome.reservations.len()
+            );
+            "Stock reserved".to_string()
+        } else {
+            let short = outcome.lines.iter().filter(|line| !line.in_stock).count();
+            format!("Insufficient stock for {} of {} items", short, lines.len())
+        };
+
+        Ok(Response::new(StockReservationResponse {
+            success: outcome.reserved,
+            reservation_id: if outcome.reserved {
+                Self::reservation_id(order_id)
+            } else {
+                String::new()
+            },
+            items: outcome
+                .lines
+                .into_iter()
+                .map(Self::to_product_availability)
+                .collect(),
+            message,
+        }))
+    }
+
+    async fn release_reserved_stock(
+        &self,
+        request: Request<ReleaseStockRequest>,
+    ) -> Result<Response<ReleaseStockResponse>, Status> {
+        let req = request.into_inner();
+        let order_id = Self::resolve_order_id(&req.order_id, &req.reservation_id)?;
+
+        let reason = if req.reason.is_empty() {
+            "Released on request"
+        } else {
+            req.reason.as_str()
+        };
+
+        let released = self
+            .inventory_service
+            .release_order_stock(order_id, reason)
+            .await?;
+
+        let response = if released.is_empty() {
+            ReleaseStockResponse {
+                success: false,
+                message: format!("No pending reservations for order {}", order_id),
+            }
+        } else {
+            info!(
+                "Released {} reservations for order {}: {}",
+                released.len(),
+                order_id,
+                reason
+            );
+            ReleaseStockResponse {
+                success: true,
+                message: format!("Released {} reservations", released.len()),
+            }
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn commit_reservation(
+        &self,
+        request: Request<CommitReservationRequest>,
+    ) -> Result<Response<CommitReservationResponse>, Status> {
+        let req = request.into_inner();
+        let order_id = Self::resolve_order_id(&req.order_id, &req.reservation_id)?;
+
+        let response = match self.inventory_service.commit_order_stock(order_id).await {
+            Ok(committed) => CommitReservationResponse {
+                success: true,
+                message: format!("Committed {} reservations", committed.len()),
+            },
+            // Nothing to commit or the stock is gone: a business outcome the
+            // caller acts on rather than a failed call
+            Err(err @ LogisticsError::NotFound(..)) => CommitReservationResponse {
+                success: false,
+                message: err.to_string(),
+            },
+            Err(LogisticsError::ValidationError(message)) => CommitReservationResponse {
+                success: false,
+                message,
+            },
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_inventory_levels(
+        &self,
+        request: Request<InventoryLevelsRequest>,
+    ) -> Result<Response<InventoryLevelsResponse>, Status> {
+        let req = request.into_inner();
+        let warehouse_id = Self::parse_warehouse_id(&req.warehouse_id)?;
+
+        let item_ids = req
+            .product_ids
+            .iter()
+            .map(|id| Self::parse_uuid(id, "product ID"))
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        let levels = self
+            .inventory_service
+            .get_inventory_levels(&item_ids, &req.skus, warehouse_id)
+            .await?;
+
+        let item_ids = levels.iter().map(|level| level.item_id).collect::<Vec<_>>();
+        let warehouse_ids = levels
+            .iter()
+            .map(|level| level.warehouse_id)
+            .collect::<Vec<_>>();
+        let locations = self
+            .bin_service
+            .get_item_locations(&item_ids, &warehouse_ids)
+            .await?;
+
+        Ok(Response::new(InventoryLevelsResponse {
+            items: levels
+                .into_iter()
+                .map(|level| Self::to_grpc_item(level, &locations))
+                .collect(),
+        }))
+    }
+}
@@ -1,18 +1,18 @@
+pub mod circuit_breaker;
 pub mod inventory;
 pub mod order;
 
 use crate::error::AppError;
+use circuit_breaker::CircuitBreakerSnapshot;
 use std::sync::Arc;
 use tokio::sync::OnceCell;
 
 static INVENTORY_CLIENT: OnceCell<Arc<inventory::client::InventoryClient>> = OnceCell::const_new();
 
 pub async fn init_grpc_clients() -> Result<(), AppError> {
-    let inventory_client = inventory::client::InventoryClient::new()
-        .await
-        .map_err(|e| {
-            AppError::GrpcError(format!("Failed to initialize inventory client: {}", e))
-        })?;
+    let inventory_client = inventory::client::InventoryClient::new().map_err(|e| {
+        AppError::GrpcError(format!("Failed to initialize inventory client: {}", e))
+    })?;
 
     INVENTORY_CLIENT
         .set(Arc::new(inventory_client))
@@ -28,11 +28,9 @@ pub async fn get_inventory_client() -> Result<Arc<inventory::client::InventoryCl
         return Ok(client.clone());
     }
 
-    let client = inventory::client::InventoryClient::new()
-        .await
-        .map_err(|e| {
-            AppError::GrpcError(format!("Failed to initialize inventory client: {}", e))
-        })?;
+    let client = inventory::client::InventoryClient::new().map_err(|e| {
+        AppError::GrpcError(format!("Failed to initialize inventory client: {}", e))
+    })?;
 
     let client = Arc::new(client);
 
@@ -41,3 +39,10 @@ pub async fn get_inventory_client() -> Result<Arc<inventory::client::InventoryCl
 
     Ok(client)
 }
+
+/// Breaker state of the inventory client, `None` before it is initialized.
+pub fn inventory_circuit_breaker() -> Option<CircuitBreakerSnapshot> {
+    INVENTORY_CLIENT
+        .get()
+        .map(|client| client.circuit_breaker())
+}
@@ -1,21 +1,32 @@
-use crate::models::entities::OrderStatus;
-use crate::models::UpdateOrderDto;
+use crate::config::get as get_config;
+use crate::models::dto::order::{CreateOrderDto, OrderListFilter};
+use crate::models::dto::order_item::CreateOrderItemDto;
+use crate::models::dto::payment::CreatePaymentInfoDto;
+use crate::models::dto::shipping::CreateShippingInfoDto;
+use crate::models::entities::{OrderDetails, OrderStatus, ShippingInfo as ShippingEntity};
+use crate::realtime::order_updates::{self, OrderUpdate, OrderUpdateFilter, OrderUpdateMessage};
 use crate::services::OrderService;
 use chrono::{DateTime, Utc};
-use futures_util::StreamExt;
 use prost_types::Timestamp;
 use std::sync::Arc;
+use tokio::sync::mpsc;
 use tonic::{Request, Response, Status};
+use tracing::warn;
 use uuid::Uuid;
+use validator::Validate;
 
 // Import the proto-generated code
 use crate::proto::order::{
     order_service_server::{OrderService as GrpcOrderService, OrderServiceServer},
-    CreateOrderRequest, GetOrderRequest, ListOrdersRequest, ListOrdersResponse, OrderResponse,
-    OrderStatus as GrpcOrderStatus, OrderStatusEvent, PaymentInfo, ShippingInfo,
+    CreateOrderRequest, GetOrderRequest, ListOrdersRequest, ListOrdersResponse,
+    OrderItem as GrpcOrderItem, OrderResponse, OrderStatusEvent, PaymentInfo,
+    Shipment as GrpcShipment, ShipmentItem as GrpcShipmentItem, ShippingInfo,
     StreamOrderUpdatesRequest, UpdateOrderStatusRequest,
 };
 
+const DEFAULT_PAGE_SIZE: i32 = 20;
+const MAX_PAGE_SIZE: i32 = 100;
+
 pub struct OrderGrpcService {
     order_service: Arc<OrderService>,
 }
@@ -38,6 +49,8 @@ impl OrderGrpcService {
             OrderStatus::Cancelled => 5,
             OrderStatus::Returned => 6,
             OrderStatus::OutOfStock => 7,
+            OrderStatus::PartiallyShipped => 8,
+            OrderStatus::PartiallyDelivered => 9,
         }
     }
 
@@ -50,6 +63,8 @@ impl OrderGrpcService {
             5 => OrderStatus::Cancelled,
             6 => OrderStatus::Returned,
             7 => OrderStatus::OutOfStock,
+            8 => OrderStatus::PartiallyShipped,
+            9 => OrderStatus::PartiallyDelivered,
             _ => OrderStatus::Pending, // Default to pending for unknown status
         }
     }
@@ -61,6 +76,225 @@ impl OrderGrpcService {
             nanos: datetime.timestamp_subsec_nanos() as i32,
         }
     }
+
+    fn from_timestamp(timestamp: &Timestamp) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp(timestamp.seconds, timestamp.nanos.max(0) as u32)
+    }
+
+    fn parse_uuid(value: &str, field: &str) -> Result<Uuid, Status> {
+        Uuid::parse_str(value)
+            .map_err(|e| Status::invalid_argument(format!("Invalid {}: {}", field, e)))
+    }
+
+    fn non_empty(value: String) -> Option<String> {
+        Some(value).filter(|value| !value.is_empty())
+    }
+
+    fn to_f64<T: ToString>(value: &T) -> f64 {
+        value.to_string().parse::<f64>().unwrap_or(0.0)
+    }
+
+    fn to_create_order_dto(req: CreateOrderRequest) -> Result<CreateOrderDto, Status> {
+        let customer_id = Self::parse_uuid(&req.customer_id, "customer ID")?;
+
+        let items = req
+            .items
+            .into_iter()
+            .map(|item| {
+                if item.product_id.trim().is_empty() {
+                    return Err(Status::invalid_argument(format!(
+                        "Product ID is required for item {}",
+                        item.sku
+                    )));
+                }
+                let product_id = Self::parse_uuid(&item.product_id, "product ID")?;
+
+                Ok(CreateOrderItemDto {
+                    product_id,
+                    sku: item.sku,
+                    name: item.name,
+                    quantity: item.quantity,
+                    unit_price: item.unit_price,
+                })
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        let shipping = req
+            .shipping_info
+            .ok_or_else(|| Status::invalid_argument("Shipping info is required"))?;
+        let payment = req
+            .payment_info
+            .ok_or_else(|| Status::invalid_argument("Payment info is required"))?;
+
+        let currency = if payment.currency.is_empty() {
+            "USD".to_string()
+        } else {
+            payment.currency.clone()
+        };
+
+        let dto = CreateOrderDto {
+            customer_id: customer_id.to_string(),
+            items,
+            shipping_info: CreateShippingInfoDto {
+                order_id: Uuid::nil(),
+                address_line1: shipping.address_line1,
+                address_line2: Self::non_empty(shipping.address_line2),
+                city: shipping.city,
+                state: shipping.state,
+                postal_code: shipping.postal_code,
+                country: shipping.country,
+                recipient_name: shipping.recipient_name,
+                recipient_phone: Self::non_empty(shipping.recipient_phone),
+                shipping_method: shipping.shipping_method,
+                shipping_cost: shipping.shipping_cost,
+                items: Vec::new(),
+            },
+            payment_info: CreatePaymentInfoDto {
+                order_id: Uuid::nil(),
+                payment_method: payment.payment_method,
+                transaction_id: Self::non_empty(payment.transaction_id),
+                amount: payment.amount,
+                currency: currency.clone(),
+                payment_date: payment.payment_date.as_ref().and_then(Self::from_timestamp),
+            },
+            notes: Self::non_empty(req.notes),
+            currency,
+        };
+
+        dto.validate()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        Ok(dto)
+    }
+
+    fn to_shipping_info(shipping: &ShippingEntity) -> ShippingInfo {
+        ShippingInfo {
+            address_line1: shipping.address_line1.clone(),
+            address_line2: shipping.address_line2.clone().unwrap_or_default(),
+            city: shipping.city.clone(),
+            state: shipping.state.clone(),
+            postal_code: shipping.postal_code.clone(),
+            country: shipping.country.clone(),
+            recipient_name: shipping.recipient_name.clone(),
+            recipient_phone: shipping.recipient_phone.clone().unwrap_or_default(),
+            shipping_method: shipping.shipping_method.clone(),
+            shipping_cost: Self::to_f64(&shipping.shipping_cost),
+        }
+    }
+
+    fn to_order_response(details: OrderDetails) -> OrderResponse {
+        let OrderDetails {
+            order,
+            items,
+            shipments,
+            payment_info,
+        } = details;
+
+        OrderResponse {
+            id: order.id.to_string(),
+            customer_id: order.customer_id.to_string(),
+            status: Self::to_grpc_status(&order.status),
+            total_amount: Self::to_f64(&order.total_amount),
+            items: items
+                .into_iter()
+                .map(|item| GrpcOrderItem {
+                    product_id: item.product_id.to_string(),
+                    name: item.name,
+                    quantity: item.quantity,
+                    unit_price: Self::to_f64(&item.unit_price),
+                    total_price: Self::to_f64(&item.total_price),
+                    sku: item.sku,
+                })
+                .collect(),
+            shipping_info: shipments
+                .first()
+                .map(|shipment| Self::to_shipping_info(&shipment.shipping_info)),
+            shipments: shipments
+                .into_iter()
+                .map(|shipment| GrpcShipment {
+                    shipping_info: Some(Self::to_shipping_info(&shipment.shipping_info)),
+                    id: shipment.shipping_info.id.to_string(),
+                    status: shipment.shipping_info.status,
+                    carrier: shipment.shipping_info.carrier.unwrap_or_default(),
+                    tracking_number: shipment.shipping_info.tracking_number.unwrap_or_default(),
+                    expected_delivery: shipment
+                        .shipping_info
+                        .expected_delivery
+                        .map(Self::to_timestamp),
+                    actual_delivery: shipment
+                        .shipping_info
+                        .actual_delivery
+                        .map(Self::to_timestamp),
+                    items: shipment
+                        .items
+                        .into_iter()
+                        .map(|item| GrpcShipmentItem {
+                            order_item_id: item.order_item_id.to_string(),
+                            product_id: item.product_id.to_string(),
+                            sku: item.sku,
+                            name: item.name,
+                            quantity: item.quantity,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            payment_info: payment_info.map(|payment| PaymentInfo {
+                is_paid: payment.is_paid(),
+                payment_method: payment.payment_method,
+                transaction_id: payment.transaction_id.unwrap_or_default(),
+                amount: Self::to_f64(&payment.amount),
+                currency: payment.currency,
+                payment_date: payment.payment_date.map(Self::to_timestamp),
+            }),
+            created_at: Some(Self::to_timestamp(order.created_at)),
+            updated_at: Some(Self::to_timestamp(order.updated_at)),
+            tracking_number: order.tracking_number.unwrap_or_default(),
+            notes: order.notes.unwrap_or_default(),
+        }
+    }
+
+    fn to_status_event(update: OrderUpdate) -> OrderStatusEvent {
+        OrderStatusEvent {
+            order_id: update.order_id.to_string(),
+            previous_status: update
+                .previous_status
+                .as_ref()
+                .map_or(0, Self::to_grpc_status),
+            new_status: Self::to_grpc_status(&update.new_status),
+            timestamp: Some(Self::to_timestamp(update.timestamp)),
+            customer_id: update.customer_id.to_string(),
+            notes: update.notes.unwrap_or_default(),
+            missed_events: 0,
+        }
+    }
+
+    fn to_list_filter(req: &ListOrdersRequest) -> Result<OrderListFilter, Status> {
+        let status = match req.status_filter {
+            0 => None,
+            1..=9 => Some(Self::from_grpc_status(req.status_filter)),
+            other => {
+                return Err(Status::invalid_argument(format!(
+                    "Invalid status filter: {}",
+                    other
+                )))
+            }
+        };
+
+        let customer_id = if req.customer_id.is_empty() {
+            None
+        } else {
+            Some(Self::parse_uuid(&req.customer_id, "customer ID")?)
+        };
+
+        Ok(OrderListFilter {
+            status,
+            customer_id,
+            created_from: req.start_date.as_ref().and_then(Self::from_timestamp),
+            created_to: req.end_date.as_ref().and_then(Self::from_timestamp),
+            sort_by: Self::non_empty(req.sort_by.clone()),
+            sort_desc: req.sort_desc,
+        })
+    }
 }
 
 #[tonic::async_trait]
@@ -69,12 +303,21 @@ impl GrpcOrderService for OrderGrpcService {
         &self,
         request: Request<CreateOrderRequest>,
     ) -> Result<Response<OrderResponse>, Status> {
-        let req = request.into_inner();
+        let dto = Self::to_create_order_dto(request.into_inner())?;
+
+        let order = self
+            .order_service
+            .create_order(dto, None)
+            .await
+            .map_err(Status::from)?;
 
-        // TODO: Implement full conversion from gRPC CreateOrderRequest to internal CreateOrderDto
-        // This is a simplified placeholder implementation
+        let details = self
+            .order_service
+            .load_order_details(order)
+            .await
+            .map_err(Status::from)?;
 
-        Err(Status::unimplemented("create_order not yet implemented"))
+        Ok(Response::new(Self::to_order_response(details)))
     }
 
     async fn get_order(
@@ -82,28 +325,26 @@ impl GrpcOrderService for OrderGrpcService {
         request: Request<GetOrderRequest>,
     ) -> Result<Response<OrderResponse>, Status> {
         let req = request.into_inner();
+        let order_id = Self::parse_uuid(&req.order_id, "order ID")?;
 
-        let order_id = match Uuid::parse_str(&req.order_id) {
-            Ok(id) => id,
-            Err(e) => return Err(Status::invalid_argument(format!("Invalid order ID: {}", e))),
-        };
-
-        let order = self
+        let details = self
             .order_service
-            .get_order_by_id(order_id)
+            .get_order_details(order_id)
             .await
-            .map_err(|e| Status::internal(format!("Failed to get order: {}", e)))?;
+            .map_err(Status::from)?;
 
-        // TODO: Convert order to OrderResponse
-        // This is a simplified placeholder implementation
-
-        Err(Status::unimplemented("get_order not yet implemented"))
+        Ok(Response::new(Self::to_order_response(details)))
     }
 
     async fn update_order_status(
         &self,
         request: Request<UpdateOrderStatusRequest>,
     ) -> Result<Response<OrderResponse>, Status> {
+        let changed_by = request
+            .metadata()
+            .get("x-user-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
         let req = request.into_inner();
 
         let order_id = match Uuid::parse_str(&req.order_id) {
@@ -112,37 +353,59 @@ impl GrpcOrderService for OrderGrpcService {
         };
 
         let status = Self::from_grpc_status(req.new_status);
-        let notes = Some(req.status_notes);
+        let notes = Some(req.status_notes).filter(|notes| !notes.is_empty());
 
         let order = self
             .order_service
-            .update_order_status(order_id, status, notes)
+            .update_order_status(order_id, status, notes, changed_by)
             .await
-            .map_err(|e| Status::internal(format!("Failed to update order status: {}", e)))?;
+            .map_err(Status::from)?;
 
-        let response = OrderResponse {
-            id: order.id.to_string(),
-            customer_id: order.customer_id.to_string(),
-            status: Self::to_grpc_status(&order.status),
-            total_amount: order.total_amount.to_string().parse::<f64>().unwrap_or(0.0),
-            items: vec![],
-            shipping_info: None,
-            payment_info: None,
-            created_at: Some(Self::to_timestamp(order.created_at)),
-            updated_at: Some(Self::to_timestamp(order.updated_at)),
-            tracking_number: order.tracking_number.clone().unwrap_or_default(),
-            notes: order.notes.clone().unwrap_or_default(),
-        };
+        let details = self
+            .order_service
+            .load_order_details(order)
+            .await
+            .map_err(Status::from)?;
 
-        Ok(Response::new(response))
+        Ok(Response::new(Self::to_order_response(details)))
     }
 
     async fn list_orders(
         &self,
         request: Request<ListOrdersRequest>,
     ) -> Result<Response<ListOrdersResponse>, Status> {
-        // TODO: Implement this method
-        Err(Status::unimplemented("list_orders not yet implemented"))
+        let req = request.into_inner();
+        let filter = Self::to_list_filter(&req)?;
+
+        let page = req.page.max(1);
+        let page_size = if req.page_size <= 0 {
+            DEFAULT_PAGE_SIZE
+        } else {
+            req.page_size.min(MAX_PAGE_SIZE)
+        };
+
+        let (orders, total_count) = self
+            .order_service
+            .list_orders(&filter, page as u32, page_size as u32)
+            .await
+            .map_err(Status::from)?;
+
+        let details = self
+            .order_service
+            .load_orders_details(orders)
+            .await
+            .map_err(Status::from)?;
+
+        let total_count = total_count as i32;
+        let total_pages = (total_count + page_size - 1) / page_size;
+
+        Ok(Response::new(ListOrdersResponse {
+            orders: details.into_iter().map(Self::to_order_response).collect(),
+            total_count,
+            page,
+            page_size,
+            total_pages,
+        }))
     }
 
     type StreamOrderUpdatesStream =
@@ -155,10 +418,53 @@ impl GrpcOrderService for OrderGrpcService {
         Response<tokio_stream::wrappers::ReceiverStream<Result<OrderStatusEvent, Status>>>,
         Status,
     > {
-        // TODO: Implement real-time order updates streaming
-        // Will be implemented in a future phase
-        Err(Status::unimplemented(
-            "stream_order_updates not yet implemented",
-        ))
+        let req = request.into_inner();
+
+        let filter = OrderUpdateFilter {
+            order_id: match req.order_id.as_str() {
+                "" => None,
+                id => Some(Self::parse_uuid(id, "order ID")?),
+            },
+            customer_id: match req.customer_id.as_str() {
+                "" => None,
+                id => Some(Self::parse_uuid(id, "customer ID")?),
+            },
+        };
+
+        let mut subscription = order_updates::subscribe(filter);
+        let (tx, rx) = mpsc::channel(get_config().realtime.subscriber_buffer.max(1));
+
+        // The bounded channel applies backpressure to this task; while it waits,
+        // updates queue up in the hub and overflow is reported as a lag marker
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    message = subscription.recv() => {
+                        let event = match message {
+                            Some(OrderUpdateMessage::Update(update)) => {
+                                Self::to_status_event(update)
+                            }
+                            Some(OrderUpdateMessage::Lagged(missed)) => {
+                                warn!("Order update stream lagged, {} events missed", missed);
+                                OrderStatusEvent {
+                                    missed_events: missed,
+                                    ..Default::default()
+                                }
+                            }
+                            None => break,
+                        };
+
+                        if tx.send(Ok(event)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(
+            rx,
+        )))
     }
 }
@@ -7,4 +7,5 @@ pub mod grpc;
 pub mod models;
 pub mod mq;
 pub mod proto;
+pub mod realtime;
 pub mod services;
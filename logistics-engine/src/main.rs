@@ -2,7 +2,7 @@ use sqlx::postgres::PgPoolOptions;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::signal;
-use tracing::{error, info, Level};
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
 mod api;
@@ -13,6 +13,7 @@ mod errors;
 mod grpc;
 mod models;
 mod mq;
+mod realtime;
 mod services;
 
 // Define the proto module here for the binary
@@ -28,9 +29,14 @@ pub mod proto {
 
 use config::get as get_config;
 use services::order_producer_service::OrderProducerConfig;
+use services::outbox_relay_service::OutboxRelayConfig;
+use services::reservation_sweeper_service::ReservationSweeperConfig;
 use services::{
-    AnalyticsService, CustomerService, InventoryService, OrderProducerService, OrderService,
-    PaymentService, ShippingService, WarehouseService,
+    ActivityService, AllocationService, AnalyticsService, BinService, CustomerService,
+    IdempotencyService, InventoryService,
+    OrderProducerService, OrderService, OutboxRelayService, PaymentService,
+    ReservationSweeperService, ReturnService, SerialService, ShippingService, TransferService,
+    WarehouseService, WaveService,
 };
 
 #[tokio::main]
@@ -84,23 +90,106 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let order_item_repo = Arc::new(db::repository::OrderItemRepository::new(pool.clone()));
     let payment_repo = Arc::new(db::repository::PaymentRepository::new(pool.clone()));
     let shipping_repo = Arc::new(db::repository::ShippingRepository::new(pool.clone()));
+    let outbox_repo = Arc::new(db::repository::OutboxRepository::new(pool.clone()));
+    let activity_repo = Arc::new(db::repository::ActivityRepository::new(pool.clone()));
+    let cancellation_repo = Arc::new(db::repository::CancellationRepository::new(pool.clone()));
+    let backorder_repo = Arc::new(db::repository::BackorderRepository::new(pool.clone()));
+    let return_repo = Arc::new(db::repository::ReturnRepository::new(pool.clone()));
+    let transfer_repo = Arc::new(db::repository::TransferRepository::new(pool.clone()));
+    let serial_repo = Arc::new(db::repository::SerialRepository::new(pool.clone()));
+    let bin_repo = Arc::new(db::repository::BinRepository::new(pool.clone()));
+    let wave_repo = Arc::new(db::repository::WaveRepository::new(pool.clone()));
+    let idempotency_repo = Arc::new(db::repository::IdempotencyRepository::new(pool.clone()));
     let analytics_repo =
         Arc::new(db::repository::analytics_repository::AnalyticsRepository::new(pool.clone()));
 
     // Initialize services
     let customer_service = Arc::new(CustomerService::new(customer_repo.clone()));
     let warehouse_service = Arc::new(WarehouseService::new(warehouse_repo.clone()));
-    let inventory_service = Arc::new(InventoryService::new(inventory_repo.clone()));
+    let activity_service = Arc::new(ActivityService::new(activity_repo.clone()));
+    let inventory_service = Arc::new(InventoryService::new(
+        inventory_repo.clone(),
+        backorder_repo.clone(),
+        activity_service.clone(),
+        pool.clone(),
+    ));
+    let allocation_service = Arc::new(AllocationService::with_kind(config.allocation.strategy));
+    info!("Allocating orders with the {} strategy", allocation_service.strategy());
+    let payment_service = Arc::new(PaymentService::new(
+        payment_repo.clone(),
+        activity_service.clone(),
+    ));
     let order_service = Arc::new(OrderService::new(
         Arc::clone(&order_repo),
         Arc::clone(&order_item_repo),
         Arc::clone(&payment_repo),
         Arc::clone(&shipping_repo),
+        Arc::clone(&inventory_repo),
+        Arc::clone(&outbox_repo),
+        Arc::clone(&activity_repo),
+        Arc::clone(&cancellation_repo),
+        Arc::clone(&backorder_repo),
+        Arc::clone(&warehouse_repo),
+        Arc::clone(&serial_repo),
+        Arc::clone(&wave_repo),
+        allocation_service,
+        payment_service.clone(),
+        pool.clone(),
+    ));
+    let shipping_service = Arc::new(ShippingService::new(
+        shipping_repo.clone(),
+        serial_repo.clone(),
+        activity_service.clone(),
+        order_service.clone(),
+        pool.clone(),
+    ));
+    let return_service = Arc::new(ReturnService::new(
+        return_repo.clone(),
+        order_item_repo.clone(),
+        inventory_repo.clone(),
+        payment_repo.clone(),
+        outbox_repo.clone(),
+        serial_repo.clone(),
+        order_service.clone(),
+        inventory_service.clone(),
+        payment_service.clone(),
+        activity_service.clone(),
+        pool.clone(),
+    ));
+    let transfer_service = Arc::new(TransferService::new(
+        transfer_repo.clone(),
+        inventory_repo.clone(),
+        warehouse_repo.clone(),
+        serial_repo.clone(),
+        inventory_service.clone(),
+        activity_service.clone(),
+        pool.clone(),
+    ));
+    let serial_service = Arc::new(SerialService::new(
+        serial_repo.clone(),
+        inventory_repo.clone(),
+        inventory_service.clone(),
+        activity_service.clone(),
+        pool.clone(),
+    ));
+    let bin_service = Arc::new(BinService::new(
+        bin_repo.clone(),
+        warehouse_repo.clone(),
+        order_repo.clone(),
+        wave_repo.clone(),
+        activity_service.clone(),
+        pool.clone(),
+    ));
+    let wave_service = Arc::new(WaveService::new(
+        wave_repo.clone(),
+        warehouse_repo.clone(),
+        inventory_repo.clone(),
+        order_service.clone(),
+        activity_service.clone(),
         pool.clone(),
     ));
-    let payment_service = Arc::new(PaymentService::new(payment_repo.clone()));
-    let shipping_service = Arc::new(ShippingService::new(shipping_repo.clone()));
     let analytics_service = Arc::new(AnalyticsService::new(analytics_repo.clone()));
+    let idempotency_service = Arc::new(IdempotencyService::new(idempotency_repo.clone()));
 
     let order_producer_service = if config.order_producer.enabled {
         info!("Order producer service is enabled");
@@ -123,15 +212,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None
     };
 
+    // Started once RabbitMQ is up; built here so its metrics can be shared
+    let mut reservation_sweeper = ReservationSweeperService::new(
+        ReservationSweeperConfig::from(config.reservation_sweeper.clone()),
+        inventory_repo.clone(),
+        outbox_repo.clone(),
+        activity_repo.clone(),
+        pool.clone(),
+    );
+
     // Create shared application state
     let app_state = api::AppState {
         customer_service,
         warehouse_service,
-        inventory_service,
+        inventory_service: inventory_service.clone(),
         order_service,
         payment_service,
         shipping_service,
+        return_service,
+        transfer_service,
+        serial_service,
+        bin_service,
+        wave_service,
         analytics_service,
+        activity_service,
+        idempotency_service: idempotency_service.clone(),
+        reservation_sweeper_metrics: reservation_sweeper.metrics(),
     };
 
     // Initialize gRPC clients
@@ -140,6 +246,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize RabbitMQ connection
     mq::init_rabbitmq().await?;
 
+    // Start relaying outbox events to RabbitMQ
+    let mut outbox_relay = OutboxRelayService::new(
+        OutboxRelayConfig::from(config.outbox.clone()),
+        outbox_repo.clone(),
+        pool.clone(),
+    );
+    if let Err(e) = outbox_relay.start().await {
+        error!("Failed to start outbox relay: {}", e);
+    }
+
+    // Release inventory held by expired reservations
+    if config.reservation_sweeper.enabled {
+        if let Err(e) = reservation_sweeper.start().await {
+            error!("Failed to start reservation sweeper: {}", e);
+        }
+    } else {
+        info!("Reservation sweeper is disabled");
+    }
+
+    // Drop idempotency keys that retries can no longer use
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match idempotency_service.purge_expired().await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Purged {} expired idempotency keys", deleted),
+                Err(e) => warn!("Failed to purge expired idempotency keys: {}", e),
+            }
+        }
+    });
+
+    // Block lots that reached their expiry date from allocation
+    let lot_expiry_inventory_service = inventory_service.clone();
+    let lot_expiry_interval_seconds = config.lots.expiry_sweep_interval_seconds;
+    tokio::spawn(async move {
+        let mut interval =
+            tokio::time::interval(std::time::Duration::from_secs(lot_expiry_interval_seconds));
+        loop {
+            interval.tick().await;
+            match lot_expiry_inventory_service.expire_lots().await {
+                Ok(expired) if expired.is_empty() => {}
+                Ok(expired) => info!("Expired {} inventory lots", expired.len()),
+                Err(e) => warn!("Failed to expire inventory lots: {}", e),
+            }
+        }
+    });
+
+    // Compare each item's on-hand quantity with what its ledger adds up to
+    if config.ledger.reconciliation_enabled {
+        let interval_seconds = config.ledger.reconciliation_interval_seconds;
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(std::time::Duration::from_secs(interval_seconds));
+            loop {
+                interval.tick().await;
+                match inventory_service.reconcile_ledger().await {
+                    Ok(reconciliation) if reconciliation.discrepancies.is_empty() => {}
+                    Ok(reconciliation) => warn!(
+                        "Inventory ledger drift on {} of {} items",
+                        reconciliation.discrepancies.len(),
+                        reconciliation.items_checked
+                    ),
+                    Err(e) => warn!("Failed to reconcile the inventory ledger: {}", e),
+                }
+            }
+        });
+    } else {
+        info!("Inventory ledger reconciliation is disabled");
+    }
+
     // Setup API router
     let app = api::create_router(Arc::new(app_state.clone())).await;
 
@@ -157,7 +334,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
     axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal(order_producer_service))
+        .with_graceful_shutdown(shutdown_signal(
+            order_producer_service,
+            outbox_relay,
+            reservation_sweeper,
+        ))
         .await?;
 
     info!("Server shutdown complete");
@@ -171,18 +352,27 @@ async fn start_grpc_server(
     let addr = format!("{}:{}", config.grpc.host, config.grpc.port).parse()?;
 
     let order_grpc_service = grpc::order::OrderGrpcService::new(app_state.order_service.clone());
+    let inventory_grpc_service = grpc::inventory::InventoryGrpcService::new(
+        app_state.inventory_service.clone(),
+        app_state.bin_service.clone(),
+    );
 
     info!("Starting gRPC server on {}", addr);
 
     tonic::transport::Server::builder()
         .add_service(order_grpc_service.into_service())
+        .add_service(inventory_grpc_service.into_service())
         .serve(addr)
         .await?;
 
     Ok(())
 }
 
-async fn shutdown_signal(mut order_producer_service: Option<OrderProducerService>) {
+async fn shutdown_signal(
+    order_producer_service: Option<OrderProducerService>,
+    mut outbox_relay: OutboxRelayService,
+    mut reservation_sweeper: ReservationSweeperService,
+) {
     let ctrl_c = async {
         signal::ctrl_c()
             .await
@@ -215,4 +405,14 @@ async fn shutdown_signal(mut order_producer_service: Option<OrderProducerService
             error!("Error shutting down order producer service: {}", e);
         }
     }
+
+    info!("Shutting down reservation sweeper");
+    if let Err(e) = reservation_sweeper.stop().await {
+        error!("Error shutting down reservation sweeper: {}", e);
+    }
+
+    info!("Shutting down outbox relay");
+    if let Err(e) = outbox_relay.stop().await {
+        error!("Error shutting down outbox relay: {}", e);
+    }
 }
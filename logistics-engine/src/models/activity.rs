@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivitySeverity {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl ActivitySeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivitySeverity::Info => "info",
+            ActivitySeverity::Success => "success",
+            ActivitySeverity::Warning => "warning",
+            ActivitySeverity::Error => "error",
+        }
+    }
+}
+
+impl FromStr for ActivitySeverity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(ActivitySeverity::Info),
+            "success" => Ok(ActivitySeverity::Success),
+            "warning" => Ok(ActivitySeverity::Warning),
+            "error" => Ok(ActivitySeverity::Error),
+            _ => Err(format!(
+                "Invalid severity: {}. Must be one of: info, success, warning, error",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActivityEntry {
+    pub id: Uuid,
+    pub entity_type: String,
+    pub entity_id: Option<Uuid>,
+    pub action: String,
+    pub severity: String,
+    pub message: String,
+    pub actor: Option<String>,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewActivity {
+    pub entity_type: String,
+    pub entity_id: Option<Uuid>,
+    pub action: String,
+    pub severity: ActivitySeverity,
+    pub message: String,
+    pub actor: Option<String>,
+    pub metadata: serde_json::Value,
+}
+
+impl NewActivity {
+    pub fn new(
+        entity_type: &str,
+        entity_id: Uuid,
+        action: &str,
+        severity: ActivitySeverity,
+        message: String,
+    ) -> Self {
+        Self {
+            entity_type: entity_type.to_string(),
+            entity_id: Some(entity_id),
+            action: action.to_string(),
+            severity,
+            message,
+            actor: None,
+            metadata: serde_json::json!({}),
+        }
+    }
+
+    pub fn with_actor(mut self, actor: Option<String>) -> Self {
+        self.actor = actor;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ActivityFilter {
+    pub entity_type: Option<String>,
+    pub severity: Option<ActivitySeverity>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Position in the feed, newest first. Encoded as `<created_at micros>_<id>` so
+/// it stays valid while new activities are added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActivityCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl ActivityCursor {
+    pub fn from_entry(entry: &ActivityEntry) -> Self {
+        Self {
+            created_at: entry.created_at,
+            id: entry.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!("{}_{}", self.created_at.timestamp_micros(), self.id)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid cursor: {}", cursor);
+
+        let (micros, id) = cursor.split_once('_').ok_or_else(invalid)?;
+        let micros = micros.parse::<i64>().map_err(|_| invalid())?;
+
+        Ok(Self {
+            created_at: DateTime::from_timestamp_micros(micros).ok_or_else(invalid)?,
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
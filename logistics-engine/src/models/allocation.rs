@@ -0,0 +1,193 @@
+use crate::models::dto::shipping::CreateShippingInfoDto;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Where an order is going, as far as picking a warehouse is concerned.
+#[derive(Debug, Clone)]
+pub struct ShippingDestination {
+    pub city: String,
+    pub state: String,
+    pub postal_code: String,
+    pub country: String,
+    /// `(latitude, longitude)` when the postal code is geocoded
+    pub coordinates: Option<(f64, f64)>,
+}
+
+impl From<&CreateShippingInfoDto> for ShippingDestination {
+    fn from(dto: &CreateShippingInfoDto) -> Self {
+        Self {
+            city: dto.city.clone(),
+            state: dto.state.clone(),
+            postal_code: dto.postal_code.clone(),
+            country: dto.country.clone(),
+            coordinates: None,
+        }
+    }
+}
+
+/// An order line to allocate.
+#[derive(Debug, Clone)]
+pub struct AllocationLine {
+    /// `None` until the order item exists
+    pub order_item_id: Option<Uuid>,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+}
+
+/// Units of a line taken from one warehouse.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllocationSource {
+    pub warehouse_id: Uuid,
+    pub warehouse_code: String,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllocatedLine {
+    pub order_item_id: Option<Uuid>,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub requested: i32,
+    pub sources: Vec<AllocationSource>,
+    /// Units no warehouse could supply
+    pub backordered: i32,
+}
+
+/// The warehouses a strategy picked for an order and why.
+#[derive(Debug, Clone, Serialize)]
+pub struct AllocationPlan {
+    pub strategy: String,
+    /// Whether the order is fulfilled from more than one warehouse
+    pub split: bool,
+    pub explanation: String,
+    pub lines: Vec<AllocatedLine>,
+}
+
+impl AllocationPlan {
+    /// The units the plan takes from each warehouse, one line per product, in
+    /// the order the warehouses first appear in the plan.
+    pub fn by_warehouse(&self) -> Vec<(Uuid, Vec<AllocationLine>)> {
+        let mut warehouses: Vec<(Uuid, Vec<AllocationLine>)> = Vec::new();
+
+        for line in &self.lines {
+            for source in &line.sources {
+                let index = match warehouses
+                    .iter()
+                    .position(|(warehouse_id, _)| *warehouse_id == source.warehouse_id)
+                {
+                    Some(index) => index,
+                    None => {
+                        warehouses.push((source.warehouse_id, Vec::new()));
+                        warehouses.len() - 1
+                    }
+                };
+
+                let lines = &mut warehouses[index].1;
+                match lines
+                    .iter_mut()
+                    .find(|taken| taken.product_id == line.product_id)
+                {
+                    Some(taken) => taken.quantity += source.quantity,
+                    None => lines.push(AllocationLine {
+                        order_item_id: None,
+                        product_id: line.product_id,
+                        sku: line.sku.clone(),
+                        quantity: source.quantity,
+                    }),
+                }
+            }
+        }
+
+        warehouses
+    }
+}
+
+/// The allocation decision recorded for an order.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderAllocation {
+    pub order_id: Uuid,
+    pub strategy: String,
+    pub split: bool,
+    pub explanation: String,
+    pub lines: Vec<AllocatedLine>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(warehouse_id: Uuid, quantity: i32) -> AllocationSource {
+        AllocationSource {
+            warehouse_id,
+            warehouse_code: warehouse_id.to_string(),
+            quantity,
+        }
+    }
+
+    #[test]
+    fn test_by_warehouse_groups_units_per_site_and_product() {
+        let (east, west) = (Uuid::new_v4(), Uuid::new_v4());
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let line = |product_id: Uuid, sku: &str, sources: Vec<AllocationSource>| AllocatedLine {
+            order_item_id: Some(Uuid::new_v4()),
+            product_id,
+            sku: sku.to_string(),
+            requested: sources.iter().map(|source| source.quantity).sum(),
+            sources,
+            backordered: 0,
+        };
+        let plan = AllocationPlan {
+            strategy: "lowest_cost".to_string(),
+            split: true,
+            explanation: String::new(),
+            lines: vec![
+                line(first, "A", vec![source(east, 3), source(west, 3)]),
+                line(second, "B", vec![source(west, 2)]),
+                // A second line for the same product is merged into the first
+                line(first, "A", vec![source(east, 1)]),
+            ],
+        };
+
+        let grouped = plan
+            .by_warehouse()
+            .into_iter()
+            .map(|(warehouse_id, lines)| {
+                let lines = lines
+                    .into_iter()
+                    .map(|line| (line.product_id, line.quantity, line.order_item_id))
+                    .collect::<Vec<_>>();
+                (warehouse_id, lines)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            grouped,
+            vec![
+                (east, vec![(first, 4, None)]),
+                (west, vec![(first, 3, None), (second, 2, None)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_by_warehouse_skips_backordered_units() {
+        let plan = AllocationPlan {
+            strategy: "nearest".to_string(),
+            split: false,
+            explanation: String::new(),
+            lines: vec![AllocatedLine {
+                order_item_id: None,
+                product_id: Uuid::new_v4(),
+                sku: "A".to_string(),
+                requested: 4,
+                sources: Vec::new(),
+                backordered: 4,
+            }],
+        };
+
+        assert!(plan.by_warehouse().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackorderStatus {
+    /// Waiting for some or all of its units
+    Pending,
+    Fulfilled,
+    Cancelled,
+}
+
+impl BackorderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackorderStatus::Pending => "pending",
+            BackorderStatus::Fulfilled => "fulfilled",
+            BackorderStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for BackorderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(BackorderStatus::Pending),
+            "fulfilled" => Ok(BackorderStatus::Fulfilled),
+            "cancelled" => Ok(BackorderStatus::Cancelled),
+            _ => Err(format!("Invalid backorder status: {}", s)),
+        }
+    }
+}
+
+/// The part of an order line that was not in stock when the order was placed.
+/// `allocated` counts the units reserved for it since.
+#[derive(Debug, Clone, Serialize)]
+pub struct Backorder {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub order_item_id: Uuid,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+    pub allocated: i32,
+    pub status: BackorderStatus,
+    pub expected_restock_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub fulfilled_at: Option<DateTime<Utc>>,
+}
+
+impl Backorder {
+    /// Units still to allocate.
+    pub fn outstanding(&self) -> i32 {
+        (self.quantity - self.allocated).max(0)
+    }
+}
+
+/// A pending backorder with what purchasing needs to prioritise it.
+#[derive(Debug, Clone, Serialize)]
+pub struct BacklogEntry {
+    #[serde(flatten)]
+    pub backorder: Backorder,
+    pub outstanding: i32,
+    pub product_name: String,
+    pub customer_id: Uuid,
+    pub order_created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateBackorderDto {
+    pub expected_restock_at: Option<DateTime<Utc>>,
+}
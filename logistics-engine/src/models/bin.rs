@@ -0,0 +1,253 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// How units moved between a warehouse's bins: put away from unbinned stock,
+/// moved from one bin to another, or picked out of the warehouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinMovementType {
+    Putaway,
+    Move,
+    Pick,
+}
+
+impl BinMovementType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinMovementType::Putaway => "putaway",
+            BinMovementType::Move => "move",
+            BinMovementType::Pick => "pick",
+        }
+    }
+}
+
+impl FromStr for BinMovementType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "putaway" => Ok(BinMovementType::Putaway),
+            "move" => Ok(BinMovementType::Move),
+            "pick" => Ok(BinMovementType::Pick),
+            _ => Err(format!("Invalid bin movement type: {}", s)),
+        }
+    }
+}
+
+/// The code a bin is labelled with, e.g. `A-03-12`.
+pub fn bin_code(zone: &str, aisle: &str, bin: &str) -> String {
+    format!("{}-{}-{}", zone, aisle, bin)
+}
+
+/// Units of one item stored in a bin.
+#[derive(Debug, Clone, Serialize)]
+pub struct BinStock {
+    pub item_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WarehouseBin {
+    pub id: Uuid,
+    pub warehouse_id: Uuid,
+    pub zone: String,
+    pub aisle: String,
+    pub bin: String,
+    pub code: String,
+    pub pick_sequence: Option<i32>,
+    pub capacity: Option<i32>,
+    pub active: bool,
+    /// Position on the warehouse's walking path, starting at 1
+    pub walk_order: i64,
+    /// Units of all items in the bin
+    pub stored: i32,
+    pub stock: Vec<BinStock>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WarehouseBin {
+    /// Units the bin can still take; `None` when it has no capacity limit.
+    pub fn room(&self) -> Option<i32> {
+        self.capacity
+            .map(|capacity| (capacity - self.stored).max(0))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateBinDto {
+    #[validate(length(min = 1, max = 50, message = "Zone must be 1-50 characters"))]
+    pub zone: String,
+
+    #[validate(length(min = 1, max = 50, message = "Aisle must be 1-50 characters"))]
+    pub aisle: String,
+
+    #[validate(length(min = 1, max = 50, message = "Bin must be 1-50 characters"))]
+    pub bin: String,
+
+    pub pick_sequence: Option<i32>,
+
+    #[validate(range(min = 1, message = "Capacity must be positive"))]
+    pub capacity: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct UpdateBinDto {
+    pub pick_sequence: Option<i32>,
+
+    #[validate(range(min = 1, message = "Capacity must be positive"))]
+    pub capacity: Option<i32>,
+
+    /// Inactive bins are still picked from but take no new stock
+    pub active: Option<bool>,
+}
+
+/// A bin to put received units away into and how many of them it takes.
+#[derive(Debug, Clone, Serialize)]
+pub struct PutawaySuggestion {
+    pub bin_id: Uuid,
+    pub code: String,
+    pub quantity: i32,
+    /// Whether the bin already holds the item
+    pub consolidates: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PutawayPlan {
+    pub item_id: Uuid,
+    pub warehouse_id: Uuid,
+    /// Units on hand in the warehouse that are not in any bin
+    pub unbinned: i32,
+    pub suggestions: Vec<PutawaySuggestion>,
+    /// Units no bin has room for
+    pub unplaced: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct PutawayDto {
+    pub item_id: Uuid,
+    pub bin_id: Uuid,
+
+    #[validate(range(min = 1, message = "Quantity must be positive"))]
+    pub quantity: i32,
+
+    pub reference: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct MoveBinStockDto {
+    pub item_id: Uuid,
+    pub from_bin_id: Uuid,
+    pub to_bin_id: Uuid,
+
+    #[validate(range(min = 1, message = "Quantity must be positive"))]
+    pub quantity: i32,
+
+    pub reference: Option<String>,
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BinMovement {
+    pub id: Uuid,
+    pub warehouse_id: Uuid,
+    pub item_id: Uuid,
+    pub sku: String,
+    pub from_bin_id: Option<Uuid>,
+    pub from_code: Option<String>,
+    pub to_bin_id: Option<Uuid>,
+    pub to_code: Option<String>,
+    pub quantity: i32,
+    pub movement_type: BinMovementType,
+    pub reference: Option<String>,
+    pub user_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Units of an item held in a bin, with the bin's place on the walking path.
+#[derive(Debug, Clone)]
+pub struct BinLocation {
+    pub bin_id: Uuid,
+    pub warehouse_id: Uuid,
+    pub code: String,
+    pub item_id: Uuid,
+    pub quantity: i32,
+    pub walk_order: i64,
+}
+
+/// One stop on a pick list. Units that are not in any bin are listed last,
+/// without a bin.
+#[derive(Debug, Clone, Serialize)]
+pub struct PickListLine {
+    pub warehouse_id: Uuid,
+    pub bin_id: Option<Uuid>,
+    pub code: Option<String>,
+    pub item_id: Uuid,
+    pub sku: String,
+    pub name: String,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PickList {
+    pub order_id: Uuid,
+    pub lines: Vec<PickListLine>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bin(capacity: Option<i32>, stored: i32) -> WarehouseBin {
+        WarehouseBin {
+            id: Uuid::new_v4(),
+            warehouse_id: Uuid::new_v4(),
+            zone: "A".to_string(),
+            aisle: "03".to_string(),
+            bin: "12".to_string(),
+            code: bin_code("A", "03", "12"),
+            pick_sequence: None,
+            capacity,
+            active: true,
+            walk_order: 1,
+            stored,
+            stock: Vec::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_bin_code_joins_zone_aisle_and_bin() {
+        assert_eq!(bin_code("A", "03", "12"), "A-03-12");
+    }
+
+    #[test]
+    fn test_room_is_what_the_capacity_leaves() {
+        assert_eq!(bin(Some(10), 4).room(), Some(6));
+        assert_eq!(bin(Some(10), 10).room(), Some(0));
+        // A bin lowered below what it holds has no room rather than negative room
+        assert_eq!(bin(Some(5), 8).room(), Some(0));
+        assert_eq!(bin(None, 500).room(), None);
+    }
+
+    #[test]
+    fn test_bin_movement_type_round_trips() {
+        for movement_type in [
+            BinMovementType::Putaway,
+            BinMovementType::Move,
+            BinMovementType::Pick,
+        ] {
+            assert_eq!(
+                BinMovementType::from_str(movement_type.as_str()),
+                Ok(movement_type)
+            );
+        }
+        assert!(BinMovementType::from_str("transfer").is_err());
+    }
+}
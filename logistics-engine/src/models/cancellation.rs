@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancellationStatus {
+    InProgress,
+    Completed,
+    Failed,
+}
+
+impl CancellationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CancellationStatus::InProgress => "in_progress",
+            CancellationStatus::Completed => "completed",
+            CancellationStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for CancellationStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "in_progress" => Ok(CancellationStatus::InProgress),
+            "completed" => Ok(CancellationStatus::Completed),
+            "failed" => Ok(CancellationStatus::Failed),
+            _ => Err(format!("Invalid cancellation status: {}", s)),
+        }
+    }
+}
+
+/// The compensating steps of a cancellation, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CancellationStep {
+    /// Give reserved or committed stock back in `inventory_levels`
+    ReleaseInventory,
+    /// Release the reservation held by the inventory service
+    ReleaseRemoteReservation,
+    /// Refund a settled payment or cancel one that has not settled
+    RefundPayment,
+    /// Cancel the shipment before it leaves the warehouse
+    CancelShipment,
+    /// Publish `OrderCancelledEvent` through the outbox
+    PublishEvent,
+}
+
+impl CancellationStep {
+    pub const ALL: [CancellationStep; 5] = [
+        CancellationStep::ReleaseInventory,
+        CancellationStep::ReleaseRemoteReservation,
+        CancellationStep::RefundPayment,
+        CancellationStep::CancelShipment,
+        CancellationStep::PublishEvent,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CancellationStep::ReleaseInventory => "release_inventory",
+            CancellationStep::ReleaseRemoteReservation => "release_remote_reservation",
+            CancellationStep::RefundPayment => "refund_payment",
+            CancellationStep::CancelShipment => "cancel_shipment",
+            CancellationStep::PublishEvent => "publish_event",
+        }
+    }
+}
+
+impl FromStr for CancellationStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CancellationStep::ALL
+            .into_iter()
+            .find(|step| step.as_str() == s)
+            .ok_or_else(|| format!("Invalid cancellation step: {}", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pending,
+    Completed,
+    /// Nothing to compensate, e.g. an order without a payment
+    Skipped,
+    Failed,
+}
+
+impl StepStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StepStatus::Pending => "pending",
+            StepStatus::Completed => "completed",
+            StepStatus::Skipped => "skipped",
+            StepStatus::Failed => "failed",
+        }
+    }
+
+    /// Whether the step no longer needs to run.
+    pub fn is_done(&self) -> bool {
+        matches!(self, StepStatus::Completed | StepStatus::Skipped)
+    }
+}
+
+impl FromStr for StepStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(StepStatus::Pending),
+            "completed" => Ok(StepStatus::Completed),
+            "skipped" => Ok(StepStatus::Skipped),
+            "failed" => Ok(StepStatus::Failed),
+            _ => Err(format!("Invalid step status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CancellationStepState {
+    pub step: CancellationStep,
+    pub status: StepStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub detail: Option<serde_json::Value>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A cancellation and the state of each of its steps. Steps that are not
+/// done yet run again when the cancellation is resumed.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderCancellation {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub status: CancellationStatus,
+    pub reason: String,
+    pub requested_by: Option<String>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub steps: Vec<CancellationStepState>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl OrderCancellation {
+    /// Steps still to run, in order.
+    pub fn remaining_steps(&self) -> Vec<CancellationStep> {
+        CancellationStep::ALL
+            .into_iter()
+            .filter(|step| {
+                self.steps
+                    .iter()
+                    .find(|state| state.step == *step)
+                    .map_or(true, |state| !state.status.is_done())
+            })
+            .collect()
+    }
+}
+
+/// A reservation the inventory service holds for an order, one for each
+/// warehouse the order draws on.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteReservation {
+    pub order_id: Uuid,
+    pub reservation_id: String,
+    /// The ID the inventory service knows the order by
+    pub remote_order_id: String,
+    /// `None` for reservations made before they were tied to a warehouse
+    pub warehouse_id: Option<Uuid>,
+    /// Whether the order took the stock, so the reservation is not held any more
+    pub committed: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cancellation(steps: &[(CancellationStep, StepStatus)]) -> OrderCancellation {
+        OrderCancellation {
+            id: Uuid::new_v4(),
+            order_id: Uuid::new_v4(),
+            status: CancellationStatus::InProgress,
+            reason: "Customer request".to_string(),
+            requested_by: None,
+            attempts: 1,
+            last_error: None,
+            steps: steps
+                .iter()
+                .map(|&(step, status)| CancellationStepState {
+                    step,
+                    status,
+                    attempts: 1,
+                    last_error: None,
+                    detail: None,
+                    updated_at: Utc::now(),
+                })
+                .collect(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            completed_at: None,
+        }
+    }
+
+    #[test]
+    fn test_remaining_steps_resume_after_the_done_ones_in_order() {
+        let cancellation = cancellation(&[
+            (CancellationStep::ReleaseInventory, StepStatus::Completed),
+            (
+                CancellationStep::ReleaseRemoteReservation,
+                StepStatus::Skipped,
+            ),
+            (CancellationStep::RefundPayment, StepStatus::Failed),
+        ]);
+
+        assert_eq!(
+            cancellation.remaining_steps(),
+            vec![
+                CancellationStep::RefundPayment,
+                CancellationStep::CancelShipment,
+                CancellationStep::PublishEvent,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remaining_steps_of_a_new_or_finished_cancellation() {
+        assert_eq!(
+            cancellation(&[]).remaining_steps(),
+            CancellationStep::ALL.to_vec()
+        );
+
+        let finished = CancellationStep::ALL.map(|step| (step, StepStatus::Completed));
+        assert!(cancellation(&finished).remaining_steps().is_empty());
+    }
+
+    #[test]
+    fn test_cancellation_step_round_trips() {
+        for step in CancellationStep::ALL {
+            assert_eq!(CancellationStep::from_str(step.as_str()), Ok(step));
+        }
+        assert!(CancellationStep::from_str("notify_customer").is_err());
+    }
+}
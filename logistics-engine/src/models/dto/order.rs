@@ -3,9 +3,11 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::models::entities::order::OrderStatus;
+
 use super::{
     CreateOrderItemDto, CreatePaymentInfoDto, CreateShippingInfoDto, OrderItemDto, PaymentInfoDto,
-    ShippingInfoDto,
+    ShipmentDto, ShippingInfoDto,
 };
 
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
@@ -51,11 +53,40 @@ pub struct OrderResponseDto {
     pub notes: Option<String>,
     pub items: Vec<OrderItemDto>,
     pub shipping_info: Option<ShippingInfoDto>,
+    pub shipments: Vec<ShipmentDto>,
     pub payment_info: Option<PaymentInfoDto>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Filters for paginated order listings. Unset fields match every order.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct OrderListFilter {
+    pub status: Option<OrderStatus>,
+    pub customer_id: Option<Uuid>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub sort_by: Option<String>,
+    #[serde(default)]
+    pub sort_desc: bool,
+}
+
+impl OrderListFilter {
+    /// Column to sort by. Defaults to `created_at`.
+    pub fn sort_column(&self) -> Result<&'static str, String> {
+        match self.sort_by.as_deref() {
+            None | Some("") | Some("created_at") => Ok("created_at"),
+            Some("updated_at") => Ok("updated_at"),
+            Some("total_amount") => Ok("total_amount"),
+            Some("status") => Ok("status"),
+            Some(other) => Err(format!(
+                "Invalid sort field: {}. Must be one of: created_at, updated_at, total_amount, status",
+                other
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OrdersResponseDto {
     pub orders: Vec<OrderResponseDto>,
@@ -73,6 +104,25 @@ pub struct UpdateOrderStatusDto {
     pub changed_by: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderTransitionsDto {
+    pub order_id: Uuid,
+    pub current_status: String,
+    pub allowed_transitions: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateOrderPriorityDto {
+    /// Orders with a higher priority join pick waves first
+    pub priority: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrderPriorityDto {
+    pub order_id: Uuid,
+    pub priority: i32,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OrderStatusEventDto {
     pub order_id: Uuid,
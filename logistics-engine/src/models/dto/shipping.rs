@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::models::entities::shipping_info::{Shipment, ShippingInfo as ShippingEntity};
+use crate::models::entities::ShipmentItem;
 use crate::models::shipping::{ShippingInfo, ShippingStatus};
 
 #[derive(Debug, Serialize, Deserialize, Validate, Clone)]
@@ -64,6 +66,28 @@ pub struct CreateShippingInfoDto {
 
     #[validate(range(min = 0.0, message = "Shipping cost must be non-negative"))]
     pub shipping_cost: f64,
+
+    /// Order lines the shipment carries. Empty means everything the order's
+    /// other shipments do not carry yet, which is all of it for the shipment
+    /// created with the order.
+    #[serde(default)]
+    #[validate(nested)]
+    pub items: Vec<ShipmentItemAllocationDto>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate, Clone)]
+pub struct ShipmentItemAllocationDto {
+    pub order_item_id: Uuid,
+
+    #[validate(range(min = 1, message = "Shipment item quantity must be positive"))]
+    pub quantity: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateShipmentItemsDto {
+    #[validate(length(min = 1, message = "A shipment needs at least one item"))]
+    #[validate(nested)]
+    pub items: Vec<ShipmentItemAllocationDto>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -132,3 +156,62 @@ impl From<ShippingInfo> for ShippingInfoDto {
         }
     }
 }
+
+/// A shipment with the order lines it carries.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShipmentDto {
+    #[serde(flatten)]
+    pub shipping_info: ShippingInfoDto,
+    pub items: Vec<ShipmentItem>,
+}
+
+impl From<Shipment> for ShipmentDto {
+    fn from(shipment: Shipment) -> Self {
+        let ShippingEntity {
+            id,
+            order_id,
+            address_line1,
+            address_line2,
+            city,
+            state,
+            postal_code,
+            country,
+            recipient_name,
+            recipient_phone,
+            shipping_method,
+            shipping_cost,
+            tracking_number,
+            carrier,
+            status,
+            expected_delivery,
+            actual_delivery,
+            created_at,
+            updated_at,
+        } = shipment.shipping_info;
+
+        Self {
+            shipping_info: ShippingInfoDto {
+                id,
+                order_id,
+                address_line1,
+                address_line2,
+                city,
+                state,
+                postal_code,
+                country,
+                recipient_name,
+                recipient_phone,
+                shipping_method,
+                shipping_cost: shipping_cost.to_string(),
+                status,
+                carrier,
+                tracking_number,
+                expected_delivery,
+                actual_delivery,
+                created_at,
+                updated_at,
+            },
+            items: shipment.items,
+        }
+    }
+}
@@ -4,6 +4,7 @@ pub mod order;
 pub mod order_item;
 pub mod order_status_history;
 pub mod payment_info;
+pub mod shipment_item;
 pub mod shipping_info;
 
 pub use customer::*;
@@ -12,6 +13,7 @@ pub use order::*;
 pub use order_item::*;
 pub use order_status_history::*;
 pub use payment_info::*;
+pub use shipment_item::*;
 pub use shipping_info::*;
 
 pub use sqlx::postgres::PgRow;
@@ -1,26 +1,82 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use std::str::FromStr;
 use uuid::Uuid;
 
+use super::{payment_info::PaymentInfo, shipping_info::Shipment};
+use crate::models::order_item::OrderItem;
+
 #[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Eq)]
 #[sqlx(type_name = "order_status", rename_all = "lowercase")]
 pub enum OrderStatus {
     Pending,
     Processing,
+    /// Some of the order's items have shipped
+    #[sqlx(rename = "partially_shipped")]
+    PartiallyShipped,
     Shipped,
+    /// Some of the order's items have been delivered
+    #[sqlx(rename = "partially_delivered")]
+    PartiallyDelivered,
     Delivered,
     Cancelled,
     Returned,
     OutOfStock,
 }
 
+impl OrderStatus {
+    /// Statuses an order may move to from its current status. Cancelled and
+    /// Returned are terminal.
+    pub fn allowed_transitions(&self) -> &'static [OrderStatus] {
+        match self {
+            OrderStatus::Pending => &[
+                OrderStatus::Processing,
+                OrderStatus::Cancelled,
+                OrderStatus::OutOfStock,
+            ],
+            OrderStatus::OutOfStock => &[
+                OrderStatus::Pending,
+                OrderStatus::Processing,
+                OrderStatus::Cancelled,
+            ],
+            OrderStatus::Processing => &[
+                OrderStatus::PartiallyShipped,
+                OrderStatus::Shipped,
+                OrderStatus::Cancelled,
+                OrderStatus::OutOfStock,
+            ],
+            OrderStatus::PartiallyShipped => {
+                &[OrderStatus::Shipped, OrderStatus::PartiallyDelivered]
+            }
+            OrderStatus::Shipped => &[
+                OrderStatus::PartiallyDelivered,
+                OrderStatus::Delivered,
+                OrderStatus::Returned,
+            ],
+            OrderStatus::PartiallyDelivered => &[OrderStatus::Delivered, OrderStatus::Returned],
+            OrderStatus::Delivered => &[OrderStatus::Returned],
+            OrderStatus::Cancelled | OrderStatus::Returned => &[],
+        }
+    }
+
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        self.allowed_transitions().contains(&next)
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        self.allowed_transitions().is_empty()
+    }
+}
+
 impl ToString for OrderStatus {
     fn to_string(&self) -> String {
         match self {
             OrderStatus::Pending => "pending".to_string(),
             OrderStatus::Processing => "processing".to_string(),
+            OrderStatus::PartiallyShipped => "partially_shipped".to_string(),
             OrderStatus::Shipped => "shipped".to_string(),
+            OrderStatus::PartiallyDelivered => "partially_delivered".to_string(),
             OrderStatus::Delivered => "delivered".to_string(),
             OrderStatus::Cancelled => "cancelled".to_string(),
             OrderStatus::Returned => "returned".to_string(),
@@ -34,7 +90,9 @@ impl From<String> for OrderStatus {
         match s.to_lowercase().as_str() {
             "pending" => OrderStatus::Pending,
             "processing" => OrderStatus::Processing,
+            "partially_shipped" => OrderStatus::PartiallyShipped,
             "shipped" => OrderStatus::Shipped,
+            "partially_delivered" => OrderStatus::PartiallyDelivered,
             "delivered" => OrderStatus::Delivered,
             "cancelled" => OrderStatus::Cancelled,
             "returned" => OrderStatus::Returned,
@@ -44,6 +102,28 @@ impl From<String> for OrderStatus {
     }
 }
 
+impl FromStr for OrderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pending" => Ok(OrderStatus::Pending),
+            "processing" => Ok(OrderStatus::Processing),
+            "partially_shipped" => Ok(OrderStatus::PartiallyShipped),
+            "shipped" => Ok(OrderStatus::Shipped),
+            "partially_delivered" => Ok(OrderStatus::PartiallyDelivered),
+            "delivered" => Ok(OrderStatus::Delivered),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "returned" => Ok(OrderStatus::Returned),
+            "out_of_stock" => Ok(OrderStatus::OutOfStock),
+            _ => Err(format!(
+                "Invalid status: {}. Must be one of: pending, processing, partially_shipped, shipped, partially_delivered, delivered, cancelled, returned, out_of_stock",
+                s
+            )),
+        }
+    }
+}
+
 impl From<i32> for OrderStatus {
     fn from(value: i32) -> Self {
         match value {
@@ -54,6 +134,8 @@ impl From<i32> for OrderStatus {
             5 => OrderStatus::Cancelled,
             6 => OrderStatus::Returned,
             7 => OrderStatus::OutOfStock,
+            8 => OrderStatus::PartiallyShipped,
+            9 => OrderStatus::PartiallyDelivered,
             _ => OrderStatus::Pending,
         }
     }
@@ -69,6 +151,8 @@ impl From<OrderStatus> for i32 {
             OrderStatus::Cancelled => 5,
             OrderStatus::Returned => 6,
             OrderStatus::OutOfStock => 7,
+            OrderStatus::PartiallyShipped => 8,
+            OrderStatus::PartiallyDelivered => 9,
         }
     }
 }
@@ -119,3 +203,99 @@ impl Order {
         self.updated_at = Utc::now();
     }
 }
+
+/// An order together with its line items, shipments and most recent payment.
+#[derive(Debug, Clone)]
+pub struct OrderDetails {
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+    /// Oldest first, so the first one carries the address the order was
+    /// placed with
+    pub shipments: Vec<Shipment>,
+    pub payment_info: Option<PaymentInfo>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderStatus;
+
+    const ALL: [OrderStatus; 9] = [
+        OrderStatus::Pending,
+        OrderStatus::Processing,
+        OrderStatus::PartiallyShipped,
+        OrderStatus::Shipped,
+        OrderStatus::PartiallyDelivered,
+        OrderStatus::Delivered,
+        OrderStatus::Cancelled,
+        OrderStatus::Returned,
+        OrderStatus::OutOfStock,
+    ];
+
+    #[test]
+    fn test_orders_move_forward_through_fulfillment() {
+        assert!(OrderStatus::Pending.can_transition_to(OrderStatus::Processing));
+        assert!(OrderStatus::Processing.can_transition_to(OrderStatus::PartiallyShipped));
+        assert!(OrderStatus::Processing.can_transition_to(OrderStatus::Shipped));
+        assert!(OrderStatus::PartiallyShipped.can_transition_to(OrderStatus::Shipped));
+        assert!(OrderStatus::Shipped.can_transition_to(OrderStatus::PartiallyDelivered));
+        assert!(OrderStatus::Shipped.can_transition_to(OrderStatus::Delivered));
+        assert!(OrderStatus::PartiallyDelivered.can_transition_to(OrderStatus::Delivered));
+        assert!(OrderStatus::Delivered.can_transition_to(OrderStatus::Returned));
+    }
+
+    #[test]
+    fn test_orders_cannot_skip_or_go_back() {
+        assert!(!OrderStatus::Pending.can_transition_to(OrderStatus::Shipped));
+        assert!(!OrderStatus::Pending.can_transition_to(OrderStatus::Delivered));
+        assert!(!OrderStatus::Processing.can_transition_to(OrderStatus::Pending));
+        assert!(!OrderStatus::Shipped.can_transition_to(OrderStatus::Processing));
+        assert!(!OrderStatus::Delivered.can_transition_to(OrderStatus::Shipped));
+        assert!(!OrderStatus::PartiallyShipped.can_transition_to(OrderStatus::PartiallyShipped));
+    }
+
+    #[test]
+    fn test_only_unshipped_orders_can_be_cancelled() {
+        for status in ALL {
+            let unshipped = matches!(
+                status,
+                OrderStatus::Pending | OrderStatus::Processing | OrderStatus::OutOfStock
+            );
+            assert_eq!(
+                status.can_transition_to(OrderStatus::Cancelled),
+                unshipped,
+                "{:?} -> Cancelled",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn test_out_of_stock_orders_can_resume() {
+        assert!(OrderStatus::Pending.can_transition_to(OrderStatus::OutOfStock));
+        assert!(OrderStatus::Processing.can_transition_to(OrderStatus::OutOfStock));
+        assert!(OrderStatus::OutOfStock.can_transition_to(OrderStatus::Pending));
+        assert!(OrderStatus::OutOfStock.can_transition_to(OrderStatus::Processing));
+        assert!(!OrderStatus::OutOfStock.can_transition_to(OrderStatus::Shipped));
+    }
+
+    #[test]
+    fn test_cancelled_and_returned_orders_are_terminal() {
+        for terminal in [OrderStatus::Cancelled, OrderStatus::Returned] {
+            assert!(terminal.is_terminal());
+            for status in ALL {
+                assert!(
+                    !terminal.can_transition_to(status),
+                    "{:?} -> {:?}",
+                    terminal,
+                    status
+                );
+            }
+        }
+
+        for status in ALL {
+            if !matches!(status, OrderStatus::Cancelled | OrderStatus::Returned) {
+                assert!(!status.is_terminal(), "{:?} is terminal", status);
+            }
+        }
+    }
+}
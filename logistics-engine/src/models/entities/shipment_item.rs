@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::order::OrderStatus;
+
+/// Units of one order line carried by a shipment.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShipmentItem {
+    pub id: Uuid,
+    pub shipping_id: Uuid,
+    pub order_item_id: Uuid,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub name: String,
+    pub quantity: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Units of an order by how far its shipments have got.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FulfillmentProgress {
+    pub ordered: i64,
+    /// Units in shipments that left the warehouse, delivered ones included
+    pub shipped: i64,
+    pub delivered: i64,
+}
+
+impl FulfillmentProgress {
+    /// The order status the shipments amount to, or `None` before anything
+    /// has shipped.
+    pub fn order_status(&self) -> Option<OrderStatus> {
+        if self.ordered <= 0 {
+            return None;
+        }
+
+        if self.delivered >= self.ordered {
+            Some(OrderStatus::Delivered)
+        } else if self.delivered > 0 {
+            Some(OrderStatus::PartiallyDelivered)
+        } else if self.shipped >= self.ordered {
+            Some(OrderStatus::Shipped)
+        } else if self.shipped > 0 {
+            Some(OrderStatus::PartiallyShipped)
+        } else {
+            None
+        }
+    }
+}
@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+use super::shipment_item::ShipmentItem;
 use crate::models::shipping::ShippingStatus;
 
 #[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
@@ -103,3 +104,10 @@ impl ShippingInfo {
         self.set_status(ShippingStatus::Delivered);
     }
 }
+
+/// A shipment together with the order lines it carries.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Shipment {
+    pub shipping_info: ShippingInfo,
+    pub items: Vec<ShipmentItem>,
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdempotencyStatus {
+    /// The first request with the key is still running
+    InProgress,
+    /// The response is stored and replayed for retries
+    Completed,
+}
+
+impl IdempotencyStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdempotencyStatus::InProgress => "in_progress",
+            IdempotencyStatus::Completed => "completed",
+        }
+    }
+}
+
+impl FromStr for IdempotencyStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "in_progress" => Ok(IdempotencyStatus::InProgress),
+            "completed" => Ok(IdempotencyStatus::Completed),
+            _ => Err(format!("Invalid idempotency status: {}", s)),
+        }
+    }
+}
+
+/// A request made with an `Idempotency-Key`. The key is scoped to the method
+/// and path it was first used with.
+#[derive(Debug, Clone)]
+pub struct IdempotencyRecord {
+    pub idempotency_key: String,
+    pub scope: String,
+    /// SHA-256 of the scope and request body, hex encoded
+    pub fingerprint: String,
+    pub status: IdempotencyStatus,
+    pub response_status: Option<i32>,
+    pub response_content_type: Option<String>,
+    pub response_body: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The response stored for a completed request.
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
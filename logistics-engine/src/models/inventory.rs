@@ -55,6 +55,10 @@ pub enum TransactionType {
     Remove,
     Allocate,
     Release,
+    /// Stock leaving a warehouse on a transfer
+    TransferOut,
+    /// Stock arriving at a warehouse on a transfer
+    TransferIn,
 }
 
 impl ToString for TransactionType {
@@ -64,6 +68,8 @@ impl ToString for TransactionType {
             TransactionType::Remove => "remove".to_string(),
             TransactionType::Allocate => "allocate".to_string(),
             TransactionType::Release => "release".to_string(),
+            TransactionType::TransferOut => "transfer_out".to_string(),
+            TransactionType::TransferIn => "transfer_in".to_string(),
         }
     }
 }
@@ -75,11 +81,34 @@ impl From<String> for TransactionType {
             "remove" => TransactionType::Remove,
             "allocate" => TransactionType::Allocate,
             "release" => TransactionType::Release,
+            "transfer_out" => TransactionType::TransferOut,
+            "transfer_in" => TransactionType::TransferIn,
             _ => TransactionType::Add,
         }
     }
 }
 
+impl TransactionType {
+    pub const ALL: [TransactionType; 6] = [
+        TransactionType::Add,
+        TransactionType::Remove,
+        TransactionType::Allocate,
+        TransactionType::Release,
+        TransactionType::TransferOut,
+        TransactionType::TransferIn,
+    ];
+
+    /// How a transaction of `quantity` units moves the item's on-hand quantity.
+    /// Allocations and releases only move units between available and reserved.
+    pub fn on_hand_delta(&self, quantity: i32) -> i32 {
+        match self {
+            TransactionType::Add | TransactionType::TransferIn => quantity,
+            TransactionType::Remove | TransactionType::TransferOut => -quantity,
+            TransactionType::Allocate | TransactionType::Release => 0,
+        }
+    }
+}
+
 // Implement PgHasArrayType to use this enum in arrays with PostgreSQL
 impl PgHasArrayType for TransactionType {
     fn array_type_info() -> sqlx::postgres::PgTypeInfo {
@@ -107,6 +136,65 @@ impl InventoryReservation {
     }
 }
 
+/// A reservation as seen by stock accounting: which warehouse level holds the
+/// units and whether they are still reserved (`Pending`) or already shipped
+/// out of stock (`Confirmed`).
+#[derive(Debug, Clone, Serialize)]
+pub struct StockReservation {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+    pub warehouse_id: Uuid,
+    pub status: ReservationStatus,
+}
+
+/// One line of a multi-item reservation request.
+#[derive(Debug, Clone)]
+pub struct StockLine {
+    pub product_id: Uuid,
+    pub quantity: i32,
+}
+
+/// Whether a requested line could be covered and how many units were available
+/// to it when the reservation was attempted.
+#[derive(Debug, Clone, Serialize)]
+pub struct StockAvailability {
+    pub product_id: Uuid,
+    pub sku: String,
+    pub requested: i32,
+    pub available: i32,
+    pub in_stock: bool,
+    pub error: Option<String>,
+}
+
+/// Outcome of reserving every line of an order at once. When `reserved` is
+/// false nothing was held and `lines` tells which products fell short.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderStockReservation {
+    pub order_id: Uuid,
+    pub reserved: bool,
+    pub reservations: Vec<StockReservation>,
+    pub lines: Vec<StockAvailability>,
+}
+
+/// Stock of one item in one warehouse. `quantity` is what is on hand,
+/// `reserved` is held for orders that have not been committed yet and
+/// `available` is what can still be reserved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InventoryLevel {
+    pub item_id: Uuid,
+    pub warehouse_id: Uuid,
+    pub sku: String,
+    pub name: String,
+    pub quantity: i32,
+    pub reserved: i32,
+    pub available: i32,
+    pub low_stock_threshold: Option<i32>,
+    pub last_updated: DateTime<Utc>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct InventoryTransaction {
     pub id: Uuid,
@@ -119,6 +207,9 @@ pub struct InventoryTransaction {
     pub transaction_type: String,
     pub reference: Option<String>,
     pub user_id: Option<String>,
+    /// The item's on-hand quantity once the transaction was posted; `None` for
+    /// transactions recorded before balances were tracked
+    pub balance_after: Option<i32>,
     pub timestamp: DateTime<Utc>,
 }
 
@@ -128,6 +219,84 @@ impl InventoryTransaction {
     }
 }
 
+/// Actor recorded for changes made through the API without an authenticated
+/// user.
+pub const ANONYMOUS_ACTOR: &str = "anonymous";
+/// Actor recorded for stock allocated to backorders as it arrives.
+pub const BACKORDER_ALLOCATOR_ACTOR: &str = "backorder-allocator";
+/// Actor recorded for reservations made by other services over gRPC.
+pub const INVENTORY_API_ACTOR: &str = "inventory-api";
+/// Actor recorded for orders placed or moved on without a user, e.g. from the
+/// order queue.
+pub const ORDER_SERVICE_ACTOR: &str = "order-service";
+/// Actor recorded for reservations released because they expired.
+pub const RESERVATION_SWEEPER_ACTOR: &str = "reservation-sweeper";
+
+/// A stock movement to post to the inventory ledger.
+#[derive(Debug, Clone)]
+pub struct LedgerPosting {
+    pub item_id: Uuid,
+    pub warehouse_id: Uuid,
+    /// Always positive; the transaction type gives the direction
+    pub quantity: i32,
+    pub transaction_type: TransactionType,
+    /// The document behind the movement, e.g. `order:<id>` or `transfer:<number>`
+    pub reference: String,
+    /// The user who made the change, or the system actor that made it for them
+    pub user_id: String,
+    /// Whether units leaving the warehouse are picked out of its bins; off for
+    /// units that were never put away, such as a transfer's missing units
+    pub pick_from_bins: bool,
+}
+
+impl LedgerPosting {
+    pub fn new(
+        item_id: Uuid,
+        warehouse_id: Uuid,
+        quantity: i32,
+        transaction_type: TransactionType,
+        reference: impl Into<String>,
+        user_id: impl Into<String>,
+    ) -> Self {
+        Self {
+            item_id,
+            warehouse_id,
+            quantity,
+            transaction_type,
+            reference: reference.into(),
+            user_id: user_id.into(),
+            pick_from_bins: true,
+        }
+    }
+
+    /// Posts the movement without picking its units out of bins.
+    pub fn without_bin_pick(mut self) -> Self {
+        self.pick_from_bins = false;
+        self
+    }
+}
+
+/// An item whose on-hand quantity disagrees with the sum of its ledger entries.
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerDiscrepancy {
+    pub item_id: Uuid,
+    pub sku: String,
+    pub name: String,
+    pub on_hand: i32,
+    pub ledger_balance: i32,
+    /// `on_hand - ledger_balance`
+    pub difference: i32,
+    pub entries: i64,
+    pub last_posted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerReconciliation {
+    pub checked_at: DateTime<Utc>,
+    pub items_checked: i64,
+    pub discrepancies: Vec<LedgerDiscrepancy>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateReservationDto {
     pub order_id: Uuid,
@@ -235,3 +404,47 @@ pub struct CreateTransactionDto {
     pub reference: Option<String>,
     pub user_id: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_hand_delta_by_transaction_type() {
+        assert_eq!(TransactionType::Add.on_hand_delta(5), 5);
+        assert_eq!(TransactionType::TransferIn.on_hand_delta(5), 5);
+        assert_eq!(TransactionType::Remove.on_hand_delta(5), -5);
+        assert_eq!(TransactionType::TransferOut.on_hand_delta(5), -5);
+        // Allocations only move units between available and reserved
+        assert_eq!(TransactionType::Allocate.on_hand_delta(5), 0);
+        assert_eq!(TransactionType::Release.on_hand_delta(5), 0);
+    }
+
+    #[test]
+    fn test_transaction_type_round_trips() {
+        for transaction_type in TransactionType::ALL {
+            assert_eq!(
+                TransactionType::from(transaction_type.to_string()),
+                transaction_type
+            );
+        }
+        assert_eq!(
+            TransactionType::from("TRANSFER_OUT".to_string()),
+            TransactionType::TransferOut
+        );
+    }
+
+    #[test]
+    fn test_ledger_posting_picks_from_bins_unless_told_not_to() {
+        let posting = LedgerPosting::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            2,
+            TransactionType::Remove,
+            "transfer:TRF-1:discrepancy",
+            ANONYMOUS_ACTOR,
+        );
+        assert!(posting.pick_from_bins);
+        assert!(!posting.without_bin_pick().pick_from_bins);
+    }
+}
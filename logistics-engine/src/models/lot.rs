@@ -0,0 +1,100 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// A lot is `active` until its expiry date, after which its unreserved units
+/// are blocked from allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LotStatus {
+    Active,
+    Expired,
+}
+
+impl LotStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LotStatus::Active => "active",
+            LotStatus::Expired => "expired",
+        }
+    }
+}
+
+impl FromStr for LotStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "active" => Ok(LotStatus::Active),
+            "expired" => Ok(LotStatus::Expired),
+            _ => Err(format!("Invalid lot status: {}", s)),
+        }
+    }
+}
+
+/// Units of an item that share a lot number in one warehouse. A warehouse's
+/// level can also hold units that were received without a lot.
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryLot {
+    pub id: Uuid,
+    pub item_id: Uuid,
+    pub sku: String,
+    pub warehouse_id: Uuid,
+    pub lot_number: String,
+    pub manufactured_at: Option<NaiveDate>,
+    pub expires_at: Option<NaiveDate>,
+    pub quantity: i32,
+    pub reserved: i32,
+    /// Units that can still be reserved; always 0 once the lot has expired
+    pub available: i32,
+    pub status: LotStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Units taken out of a lot, with what it takes to put them into the same lot
+/// in another warehouse.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LotQuantity {
+    pub lot_number: String,
+    pub manufactured_at: Option<NaiveDate>,
+    pub expires_at: Option<NaiveDate>,
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ReceiveLotDto {
+    /// Defaults to the item's home warehouse
+    pub warehouse_id: Option<Uuid>,
+
+    #[validate(length(min = 1, max = 100, message = "Lot number must be 1-100 characters"))]
+    pub lot_number: String,
+
+    pub manufactured_at: Option<NaiveDate>,
+    pub expires_at: Option<NaiveDate>,
+
+    #[validate(range(min = 1, message = "Received quantity must be positive"))]
+    pub quantity: i32,
+
+    /// Source document of the receipt, e.g. a purchase order number
+    pub reference: Option<String>,
+
+    /// Defaults to the user making the request
+    pub received_by: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lot_status_round_trips() {
+        for status in [LotStatus::Active, LotStatus::Expired] {
+            assert_eq!(LotStatus::from_str(status.as_str()), Ok(status));
+        }
+        assert_eq!(LotStatus::from_str("EXPIRED"), Ok(LotStatus::Expired));
+        assert!(LotStatus::from_str("quarantined").is_err());
+    }
+}
@@ -1,13 +1,25 @@
+pub mod activity;
+pub mod allocation;
 pub mod analytics;
+pub mod backorder;
+pub mod bin;
+pub mod cancellation;
 pub mod customer;
 pub mod dto;
 pub mod entities;
+pub mod idempotency;
 pub mod inventory;
+pub mod lot;
 pub mod order;
 pub mod order_item;
+pub mod outbox;
 pub mod payment;
+pub mod rma;
+pub mod serial;
 pub mod shipping;
+pub mod transfer;
 pub mod warehouse;
+pub mod wave;
 
 // Re-export commonly used types
 pub use customer::{CreateCustomerDto, Customer, UpdateCustomerDto};
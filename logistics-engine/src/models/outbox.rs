@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::mq::events::Event;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    pub id: Uuid,
+    pub aggregate_type: String,
+    pub aggregate_id: Uuid,
+    pub event_type: String,
+    pub routing_key: String,
+    pub payload: serde_json::Value,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub published_at: Option<DateTime<Utc>>,
+    /// Set once the event failed too often to be retried
+    pub parked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewOutboxEvent {
+    pub id: Uuid,
+    pub aggregate_type: String,
+    pub aggregate_id: Uuid,
+    pub event_type: String,
+    pub routing_key: String,
+    pub payload: serde_json::Value,
+}
+
+impl NewOutboxEvent {
+    /// Wraps an event envelope for the outbox. The envelope id doubles as the
+    /// outbox id and the AMQP message id, so consumers can deduplicate on it.
+    pub fn from_event<T: Serialize>(
+        aggregate_type: &str,
+        aggregate_id: Uuid,
+        routing_key: &str,
+        event: Event<T>,
+    ) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            id: event.id,
+            aggregate_type: aggregate_type.to_string(),
+            aggregate_id,
+            event_type: format!("{:?}", event.event_type),
+            routing_key: routing_key.to_string(),
+            payload: serde_json::to_value(&event)?,
+        })
+    }
+}
@@ -0,0 +1,307 @@
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Lifecycle of a return authorization: it is authorized against a delivered
+/// order, the goods are received and inspected, then the customer is refunded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnStatus {
+    Authorized,
+    Received,
+    Refunded,
+    Cancelled,
+}
+
+impl ReturnStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnStatus::Authorized => "authorized",
+            ReturnStatus::Received => "received",
+            ReturnStatus::Refunded => "refunded",
+            ReturnStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for ReturnStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "authorized" => Ok(ReturnStatus::Authorized),
+            "received" => Ok(ReturnStatus::Received),
+            "refunded" => Ok(ReturnStatus::Refunded),
+            "cancelled" => Ok(ReturnStatus::Cancelled),
+            _ => Err(format!("Invalid return status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReturnReason {
+    Defective,
+    DamagedInTransit,
+    WrongItem,
+    NotAsDescribed,
+    NoLongerNeeded,
+    Other,
+}
+
+impl ReturnReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReturnReason::Defective => "defective",
+            ReturnReason::DamagedInTransit => "damaged_in_transit",
+            ReturnReason::WrongItem => "wrong_item",
+            ReturnReason::NotAsDescribed => "not_as_described",
+            ReturnReason::NoLongerNeeded => "no_longer_needed",
+            ReturnReason::Other => "other",
+        }
+    }
+}
+
+impl FromStr for ReturnReason {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "defective" => Ok(ReturnReason::Defective),
+            "damaged_in_transit" => Ok(ReturnReason::DamagedInTransit),
+            "wrong_item" => Ok(ReturnReason::WrongItem),
+            "not_as_described" => Ok(ReturnReason::NotAsDescribed),
+            "no_longer_needed" => Ok(ReturnReason::NoLongerNeeded),
+            "other" => Ok(ReturnReason::Other),
+            _ => Err(format!("Invalid return reason: {}", s)),
+        }
+    }
+}
+
+/// State of a returned item when it is inspected on receipt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemCondition {
+    New,
+    OpenBox,
+    Used,
+    Damaged,
+}
+
+impl ItemCondition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ItemCondition::New => "new",
+            ItemCondition::OpenBox => "open_box",
+            ItemCondition::Used => "used",
+            ItemCondition::Damaged => "damaged",
+        }
+    }
+}
+
+impl FromStr for ItemCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "new" => Ok(ItemCondition::New),
+            "open_box" => Ok(ItemCondition::OpenBox),
+            "used" => Ok(ItemCondition::Used),
+            "damaged" => Ok(ItemCondition::Damaged),
+            _ => Err(format!("Invalid item condition: {}", s)),
+        }
+    }
+}
+
+/// What happens to a returned item once it has been inspected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Disposition {
+    /// Back into sellable stock
+    Restock,
+    /// Sent for repair; it is not stocked until it comes back
+    Refurbish,
+    Scrap,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Disposition::Restock => "restock",
+            Disposition::Refurbish => "refurbish",
+            Disposition::Scrap => "scrap",
+        }
+    }
+}
+
+impl FromStr for Disposition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "restock" => Ok(Disposition::Restock),
+            "refurbish" => Ok(Disposition::Refurbish),
+            "scrap" => Ok(Disposition::Scrap),
+            _ => Err(format!("Invalid disposition: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReturnItem {
+    pub id: Uuid,
+    pub order_item_id: Uuid,
+    pub product_id: Uuid,
+    pub sku: String,
+    /// Units authorized for return
+    pub quantity: i32,
+    /// Units that actually arrived; `None` until the return is received
+    pub received_quantity: Option<i32>,
+    pub unit_price: Decimal,
+    pub reason_code: ReturnReason,
+    pub condition: Option<ItemCondition>,
+    pub disposition: Option<Disposition>,
+}
+
+impl ReturnItem {
+    /// What the customer gets back for `received` units of the line, at the
+    /// price they were sold for, whatever their condition or disposition.
+    pub fn refund_for(&self, received: i32) -> Decimal {
+        self.unit_price * Decimal::from(received.max(0))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReturnAuthorization {
+    pub id: Uuid,
+    pub rma_number: String,
+    pub order_id: Uuid,
+    pub status: ReturnStatus,
+    pub reason_code: ReturnReason,
+    pub notes: Option<String>,
+    pub requested_by: Option<String>,
+    /// Set once the return is received, from the units that arrived
+    pub refund_amount: Option<Decimal>,
+    pub payment_id: Option<Uuid>,
+    pub items: Vec<ReturnItem>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub received_at: Option<DateTime<Utc>>,
+    pub refunded_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateReturnDto {
+    pub reason_code: ReturnReason,
+    pub notes: Option<String>,
+    pub requested_by: Option<String>,
+
+    #[validate(length(min = 1, message = "A return needs at least one item"))]
+    #[validate(nested)]
+    pub items: Vec<CreateReturnItemDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateReturnItemDto {
+    pub order_item_id: Uuid,
+
+    #[validate(range(min = 1, message = "Return quantity must be positive"))]
+    pub quantity: i32,
+
+    /// Defaults to the return's reason code
+    pub reason_code: Option<ReturnReason>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ReceiveReturnDto {
+    /// Items left out did not arrive
+    #[validate(length(min = 1, message = "At least one received item is required"))]
+    #[validate(nested)]
+    pub items: Vec<ReceiveReturnItemDto>,
+
+    /// Warehouse the parcel arrived at; restocked units go back to the one
+    /// each item shipped from when left out
+    pub warehouse_id: Option<Uuid>,
+
+    /// Defaults to the user making the request
+    pub received_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ReceiveReturnItemDto {
+    pub return_item_id: Uuid,
+
+    /// Defaults to the authorized quantity
+    #[validate(range(min = 0, message = "Received quantity cannot be negative"))]
+    pub quantity: Option<i32>,
+
+    pub condition: ItemCondition,
+    pub disposition: Disposition,
+
+    /// Serial numbers of the units that arrived; required, one per unit, for
+    /// serial-tracked items
+    #[serde(default)]
+    pub serial_numbers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CancelReturnDto {
+    pub reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(unit_price: &str, disposition: Disposition) -> ReturnItem {
+        ReturnItem {
+            id: Uuid::new_v4(),
+            order_item_id: Uuid::new_v4(),
+            product_id: Uuid::new_v4(),
+            sku: "SKU-1".to_string(),
+            quantity: 3,
+            received_quantity: None,
+            unit_price: Decimal::from_str(unit_price).unwrap(),
+            reason_code: ReturnReason::Defective,
+            condition: Some(ItemCondition::Damaged),
+            disposition: Some(disposition),
+        }
+    }
+
+    #[test]
+    fn test_refund_is_the_sale_price_of_the_units_received() {
+        let restocked = item("19.99", Disposition::Restock);
+        let scrapped = item("5.50", Disposition::Scrap);
+
+        assert_eq!(restocked.refund_for(2), Decimal::from_str("39.98").unwrap());
+        // Scrapped units are refunded all the same
+        assert_eq!(scrapped.refund_for(3), Decimal::from_str("16.50").unwrap());
+        assert_eq!(
+            restocked.refund_for(2) + scrapped.refund_for(1),
+            Decimal::from_str("45.48").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_nothing_received_refunds_nothing() {
+        let item = item("19.99", Disposition::Restock);
+
+        assert_eq!(item.refund_for(0), Decimal::ZERO);
+        assert_eq!(item.refund_for(-1), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_disposition_round_trips() {
+        for disposition in [
+            Disposition::Restock,
+            Disposition::Refurbish,
+            Disposition::Scrap,
+        ] {
+            assert_eq!(Disposition::from_str(disposition.as_str()), Ok(disposition));
+        }
+        assert!(Disposition::from_str("resell").is_err());
+    }
+}
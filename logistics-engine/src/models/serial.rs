@@ -0,0 +1,196 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+/// The attribute that turns on serial tracking for an item.
+pub const SERIAL_TRACKED_ATTRIBUTE: &str = "serial_tracked";
+
+/// Whether units of an item with these attributes carry serial numbers.
+pub fn is_serial_tracked(attributes: Option<&serde_json::Value>) -> bool {
+    attributes
+        .and_then(|attributes| attributes.get(SERIAL_TRACKED_ATTRIBUTE))
+        .and_then(|flag| flag.as_bool())
+        .unwrap_or(false)
+}
+
+/// Trims serial numbers as entered and rejects blanks, overlong ones and
+/// repeats.
+pub fn normalize_serial_numbers(serial_numbers: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(serial_numbers.len());
+    for serial_number in serial_numbers {
+        let serial_number = serial_number.trim();
+        if serial_number.is_empty() || serial_number.len() > 100 {
+            return Err("Serial numbers must be 1-100 characters".to_string());
+        }
+        if normalized.iter().any(|seen| seen == serial_number) {
+            return Err(format!(
+                "Serial number {} is listed more than once",
+                serial_number
+            ));
+        }
+        normalized.push(serial_number.to_string());
+    }
+
+    Ok(normalized)
+}
+
+/// Where a serialized unit is: in a warehouse, on a transfer between
+/// warehouses, set aside for an order line, on its way to the customer, or
+/// back from a return without being restocked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialStatus {
+    InStock,
+    InTransit,
+    Allocated,
+    Shipped,
+    Returned,
+}
+
+impl SerialStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SerialStatus::InStock => "in_stock",
+            SerialStatus::InTransit => "in_transit",
+            SerialStatus::Allocated => "allocated",
+            SerialStatus::Shipped => "shipped",
+            SerialStatus::Returned => "returned",
+        }
+    }
+}
+
+impl FromStr for SerialStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "in_stock" => Ok(SerialStatus::InStock),
+            "in_transit" => Ok(SerialStatus::InTransit),
+            "allocated" => Ok(SerialStatus::Allocated),
+            "shipped" => Ok(SerialStatus::Shipped),
+            "returned" => Ok(SerialStatus::Returned),
+            _ => Err(format!("Invalid serial status: {}", s)),
+        }
+    }
+}
+
+/// A step in the life of a serialized unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialEvent {
+    Received,
+    Allocated,
+    Released,
+    Shipped,
+    Returned,
+    Restocked,
+    Dispatched,
+    Transferred,
+}
+
+impl SerialEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SerialEvent::Received => "received",
+            SerialEvent::Allocated => "allocated",
+            SerialEvent::Released => "released",
+            SerialEvent::Shipped => "shipped",
+            SerialEvent::Returned => "returned",
+            SerialEvent::Restocked => "restocked",
+            SerialEvent::Dispatched => "dispatched",
+            SerialEvent::Transferred => "transferred",
+        }
+    }
+}
+
+impl FromStr for SerialEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "received" => Ok(SerialEvent::Received),
+            "allocated" => Ok(SerialEvent::Allocated),
+            "released" => Ok(SerialEvent::Released),
+            "shipped" => Ok(SerialEvent::Shipped),
+            "returned" => Ok(SerialEvent::Returned),
+            "restocked" => Ok(SerialEvent::Restocked),
+            "dispatched" => Ok(SerialEvent::Dispatched),
+            "transferred" => Ok(SerialEvent::Transferred),
+            _ => Err(format!("Invalid serial event: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerialNumber {
+    pub id: Uuid,
+    pub item_id: Uuid,
+    pub sku: String,
+    pub serial_number: String,
+    pub status: SerialStatus,
+    /// The warehouse that holds the unit, or last held it; none while it is on
+    /// a transfer
+    pub warehouse_id: Option<Uuid>,
+    pub order_id: Option<Uuid>,
+    pub order_item_id: Option<Uuid>,
+    pub shipping_id: Option<Uuid>,
+    pub return_id: Option<Uuid>,
+    pub received_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One step of a serial's history with where the unit was at the time.
+#[derive(Debug, Clone, Serialize)]
+pub struct SerialHistoryEntry {
+    pub event: SerialEvent,
+    pub reference: Option<String>,
+    pub warehouse_id: Option<Uuid>,
+    pub order_id: Option<Uuid>,
+    pub order_item_id: Option<Uuid>,
+    pub shipping_id: Option<Uuid>,
+    pub tracking_number: Option<String>,
+    pub return_id: Option<Uuid>,
+    pub rma_number: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SerialHistory {
+    pub serial: SerialNumber,
+    pub history: Vec<SerialHistoryEntry>,
+}
+
+/// A serial-tracked line of a shipment and how many of its units have serial
+/// numbers recorded on the shipment or allocated and waiting to ship.
+#[derive(Debug, Clone)]
+pub struct ShipmentSerialLine {
+    pub order_item_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+    pub recorded: i32,
+    pub allocated: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ReceiveSerialsDto {
+    /// Defaults to the item's home warehouse
+    pub warehouse_id: Option<Uuid>,
+
+    /// One serial number per unit received
+    #[validate(length(min = 1, message = "At least one serial number is required"))]
+    pub serial_numbers: Vec<String>,
+
+    /// Source document of the receipt, e.g. a purchase order number
+    pub reference: Option<String>,
+
+    /// Defaults to the user making the request
+    pub received_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct AllocateSerialsDto {
+    #[validate(length(min = 1, message = "At least one serial number is required"))]
+    pub serial_numbers: Vec<String>,
+}
@@ -0,0 +1,247 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::lot::LotQuantity;
+
+/// Lifecycle of a stock transfer: it is requested, dispatched from the source
+/// warehouse and then received at the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferStatus {
+    Requested,
+    InTransit,
+    Received,
+    Cancelled,
+}
+
+impl TransferStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransferStatus::Requested => "requested",
+            TransferStatus::InTransit => "in_transit",
+            TransferStatus::Received => "received",
+            TransferStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for TransferStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "requested" => Ok(TransferStatus::Requested),
+            "in_transit" => Ok(TransferStatus::InTransit),
+            "received" => Ok(TransferStatus::Received),
+            "cancelled" => Ok(TransferStatus::Cancelled),
+            _ => Err(format!("Invalid transfer status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StockTransferLine {
+    pub id: Uuid,
+    pub item_id: Uuid,
+    pub sku: String,
+    /// Units requested
+    pub quantity: i32,
+    /// Units that left the source; `None` until the transfer is dispatched
+    pub shipped_quantity: Option<i32>,
+    /// Units that arrived; `None` until the transfer is received
+    pub received_quantity: Option<i32>,
+    /// Units shipped but not received yet
+    pub in_transit: i32,
+    pub discrepancy_reason: Option<String>,
+    /// Lots the shipped units were taken from, soonest expiry first
+    pub lots: Vec<LotQuantity>,
+}
+
+impl StockTransferLine {
+    /// The lots `received` units go back into at the destination. They fill
+    /// the shipped lots in order, so a shortfall comes off the lots that
+    /// expire last; units beyond those shipped in lots are held without one.
+    pub fn received_lots(&self, received: i32) -> Vec<LotQuantity> {
+        let mut remaining = received.max(0);
+        self.lots
+            .iter()
+            .map_while(|lot| {
+                let quantity = lot.quantity.min(remaining);
+                remaining -= quantity;
+                (quantity > 0).then(|| LotQuantity {
+                    quantity,
+                    ..lot.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Units received beyond (positive) or short of (negative) what was shipped.
+    pub fn discrepancy(&self) -> i32 {
+        match (self.shipped_quantity, self.received_quantity) {
+            (Some(shipped), Some(received)) => received - shipped,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StockTransfer {
+    pub id: Uuid,
+    pub transfer_number: String,
+    pub source_warehouse_id: Uuid,
+    pub destination_warehouse_id: Uuid,
+    pub status: TransferStatus,
+    pub notes: Option<String>,
+    pub requested_by: Option<String>,
+    pub lines: Vec<StockTransferLine>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub dispatched_at: Option<DateTime<Utc>>,
+    pub received_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateTransferDto {
+    pub source_warehouse_id: Uuid,
+    pub destination_warehouse_id: Uuid,
+    pub notes: Option<String>,
+    pub requested_by: Option<String>,
+
+    #[validate(length(min = 1, message = "A transfer needs at least one line"))]
+    #[validate(nested)]
+    pub lines: Vec<CreateTransferLineDto>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateTransferLineDto {
+    pub item_id: Uuid,
+
+    #[validate(range(min = 1, message = "Transfer quantity must be positive"))]
+    pub quantity: i32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct ReceiveTransferDto {
+    /// Lines left out arrived exactly as shipped
+    #[serde(default)]
+    #[validate(nested)]
+    pub lines: Vec<ReceiveTransferLineDto>,
+
+    /// Defaults to the user making the request
+    pub received_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ReceiveTransferLineDto {
+    pub line_id: Uuid,
+
+    #[validate(range(min = 0, message = "Received quantity cannot be negative"))]
+    pub quantity: i32,
+
+    /// Why the count differs from what was shipped
+    pub reason: Option<String>,
+
+    /// Serial numbers of the units that arrived, required for a serial-tracked
+    /// line counted short; units not listed stay in transit
+    pub serial_numbers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CancelTransferDto {
+    pub reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(
+        shipped: Option<i32>,
+        received: Option<i32>,
+        lots: Vec<LotQuantity>,
+    ) -> StockTransferLine {
+        StockTransferLine {
+            id: Uuid::new_v4(),
+            item_id: Uuid::new_v4(),
+            sku: "SKU-1".to_string(),
+            quantity: 10,
+            shipped_quantity: shipped,
+            received_quantity: received,
+            in_transit: 0,
+            discrepancy_reason: None,
+            lots,
+        }
+    }
+
+    #[test]
+    fn test_discrepancy_compares_received_with_shipped() {
+        assert_eq!(line(Some(10), Some(8), Vec::new()).discrepancy(), -2);
+        assert_eq!(line(Some(10), Some(11), Vec::new()).discrepancy(), 1);
+        assert_eq!(line(Some(10), Some(10), Vec::new()).discrepancy(), 0);
+        // Nothing to compare until the line is both shipped and received
+        assert_eq!(line(Some(10), None, Vec::new()).discrepancy(), 0);
+        assert_eq!(line(None, None, Vec::new()).discrepancy(), 0);
+    }
+
+    fn lot(lot_number: &str, expires_at: Option<(i32, u32, u32)>, quantity: i32) -> LotQuantity {
+        LotQuantity {
+            lot_number: lot_number.to_string(),
+            manufactured_at: None,
+            expires_at: expires_at.and_then(|(y, m, d)| chrono::NaiveDate::from_ymd_opt(y, m, d)),
+            quantity,
+        }
+    }
+
+    #[test]
+    fn test_received_lots_fill_the_shipped_lots_in_order() {
+        let shipped = vec![
+            lot("L-1", Some((2024, 5, 1)), 4),
+            lot("L-2", Some((2024, 6, 1)), 3),
+            lot("L-3", None, 2),
+        ];
+        let line = line(Some(10), None, shipped.clone());
+
+        assert_eq!(line.received_lots(9), shipped);
+        // A short count comes off the lots that expire last
+        assert_eq!(
+            line.received_lots(5),
+            vec![
+                lot("L-1", Some((2024, 5, 1)), 4),
+                lot("L-2", Some((2024, 6, 1)), 1)
+            ]
+        );
+        assert_eq!(
+            line.received_lots(4),
+            vec![lot("L-1", Some((2024, 5, 1)), 4)]
+        );
+    }
+
+    #[test]
+    fn test_received_lots_hold_extra_units_without_a_lot() {
+        let shipped = vec![lot("L-1", Some((2024, 5, 1)), 4)];
+        let with_lots = line(Some(6), None, shipped.clone());
+
+        // Units beyond those shipped in lots, or counted over, stay out of lots
+        assert_eq!(with_lots.received_lots(7), shipped);
+        assert!(with_lots.received_lots(0).is_empty());
+        assert!(with_lots.received_lots(-1).is_empty());
+        assert!(line(Some(3), None, Vec::new()).received_lots(3).is_empty());
+    }
+
+    #[test]
+    fn test_transfer_status_round_trips() {
+        for status in [
+            TransferStatus::Requested,
+            TransferStatus::InTransit,
+            TransferStatus::Received,
+            TransferStatus::Cancelled,
+        ] {
+            assert_eq!(TransferStatus::from_str(status.as_str()), Ok(status));
+        }
+        assert!(TransferStatus::from_str("lost").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -13,9 +14,13 @@ pub struct Warehouse {
     pub state: String,
     pub postal_code: String,
     pub country: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
     pub contact_name: Option<String>,
     pub contact_email: Option<String>,
     pub contact_phone: Option<String>,
+    /// Cost of picking, packing and handing over one unit from this site
+    pub fulfillment_cost: Decimal,
     pub active: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
@@ -31,9 +36,13 @@ pub struct CreateWarehouseDto {
     pub state: String,
     pub postal_code: String,
     pub country: String,
+    /// Looked up from the postal code when left out
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
     pub contact_name: Option<String>,
     pub contact_email: Option<String>,
     pub contact_phone: Option<String>,
+    pub fulfillment_cost: Option<Decimal>,
     pub active: Option<bool>,
 }
 
@@ -47,8 +56,47 @@ pub struct UpdateWarehouseDto {
     pub state: Option<String>,
     pub postal_code: Option<String>,
     pub country: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
     pub contact_name: Option<String>,
     pub contact_email: Option<String>,
     pub contact_phone: Option<String>,
+    pub fulfillment_cost: Option<Decimal>,
     pub active: Option<bool>,
 }
+
+impl Warehouse {
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        self.latitude.zip(self.longitude)
+    }
+}
+
+/// A postal code from the offline geocoding table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostalCodeLocation {
+    pub postal_code: String,
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub city: Option<String>,
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NearbyWarehouse {
+    #[serde(flatten)]
+    pub warehouse: Warehouse,
+    pub distance_km: f64,
+}
+
+/// Great-circle distance between two `(latitude, longitude)` points.
+pub fn distance_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    const EARTH_RADIUS_KM: f64 = 6371.0;
+
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
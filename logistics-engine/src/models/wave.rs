@@ -0,0 +1,381 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::entities::order::OrderStatus;
+
+/// Lifecycle of a pick wave: it is picked and packed, then completed once
+/// every order in it is packed, or cancelled to hand its orders back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveStatus {
+    Picking,
+    Completed,
+    Cancelled,
+}
+
+impl WaveStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WaveStatus::Picking => "picking",
+            WaveStatus::Completed => "completed",
+            WaveStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for WaveStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "picking" => Ok(WaveStatus::Picking),
+            "completed" => Ok(WaveStatus::Completed),
+            "cancelled" => Ok(WaveStatus::Cancelled),
+            _ => Err(format!("Invalid wave status: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PickLineStatus {
+    Pending,
+    Picked,
+    /// Confirmed with fewer units than the line asks for
+    Short,
+}
+
+impl PickLineStatus {
+    pub fn of(quantity: i32, picked_quantity: Option<i32>) -> Self {
+        match picked_quantity {
+            None => PickLineStatus::Pending,
+            Some(picked) if picked < quantity => PickLineStatus::Short,
+            Some(_) => PickLineStatus::Picked,
+        }
+    }
+}
+
+/// Where an order stands in its wave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveOrderStatus {
+    /// Some of its lines are still to be picked
+    Picking,
+    /// Every line is confirmed but some of its units were short
+    Short,
+    /// Every unit is picked and the order is ready to pack
+    Picked,
+    Packed,
+    /// The order left `Processing` before it was packed, e.g. it was cancelled
+    Withdrawn,
+}
+
+/// Units of a pick line meant for one order, and how many of them were
+/// picked. Picked units go to the orders earliest in the wave first.
+#[derive(Debug, Clone, Serialize)]
+pub struct PickLineOrder {
+    pub order_id: Uuid,
+    pub quantity: i32,
+    pub picked: i32,
+}
+
+/// One stop on a wave's pick list: the units of an item to take from a bin
+/// for all of the wave's orders. Units that are not in any bin are listed
+/// last, without a bin.
+#[derive(Debug, Clone, Serialize)]
+pub struct PickWaveLine {
+    pub id: Uuid,
+    pub line_number: i32,
+    pub bin_id: Option<Uuid>,
+    pub code: Option<String>,
+    pub item_id: Uuid,
+    pub sku: String,
+    pub name: String,
+    pub quantity: i32,
+    /// `None` until the line is confirmed
+    pub picked_quantity: Option<i32>,
+    pub status: PickLineStatus,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub confirmed_by: Option<String>,
+    pub orders: Vec<PickLineOrder>,
+}
+
+impl PickWaveLine {
+    /// Units each of the line's orders would be short if `picked` units were
+    /// confirmed, taken from the orders last in the wave first. Expects the
+    /// orders in wave order, as a settled wave has them.
+    pub fn shortfall(&self, picked: i32) -> Vec<(Uuid, i32)> {
+        let mut left = picked;
+        self.orders
+            .iter()
+            .map(|share| {
+                let taken = share.quantity.min(left.max(0));
+                left -= taken;
+                (share.order_id, share.quantity - taken)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WaveOrder {
+    pub order_id: Uuid,
+    pub sequence: i32,
+    pub priority: i32,
+    pub order_status: OrderStatus,
+    pub status: WaveOrderStatus,
+    /// Units the wave picks for the order
+    pub units: i32,
+    pub picked: i32,
+    pub short: i32,
+    pub packed_at: Option<DateTime<Utc>>,
+    pub packed_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PickWave {
+    pub id: Uuid,
+    pub warehouse_id: Uuid,
+    pub status: WaveStatus,
+    pub carrier: Option<String>,
+    pub zone: Option<String>,
+    pub min_priority: Option<i32>,
+    pub cutoff_at: Option<DateTime<Utc>>,
+    pub created_by: Option<String>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub orders: Vec<WaveOrder>,
+    pub lines: Vec<PickWaveLine>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PickWave {
+    /// Shares each line's picked units out over its orders in wave order and
+    /// works out where each order stands.
+    pub fn settle(&mut self) {
+        let sequence = self
+            .orders
+            .iter()
+            .map(|order| (order.order_id, order.sequence))
+            .collect::<HashMap<_, _>>();
+
+        // Units, picked units and whether anything is left to pick, per order
+        let mut tally: HashMap<Uuid, (i32, i32, bool)> = HashMap::new();
+        for line in &mut self.lines {
+            line.orders
+                .sort_by_key(|share| sequence.get(&share.order_id).copied());
+
+            let mut left = line.picked_quantity.unwrap_or(0);
+            for share in &mut line.orders {
+                share.picked = share.quantity.min(left);
+                left -= share.picked;
+
+                let entry = tally.entry(share.order_id).or_default();
+                entry.0 += share.quantity;
+                entry.1 += share.picked;
+                entry.2 |= line.status == PickLineStatus::Pending;
+            }
+        }
+
+        for order in &mut self.orders {
+            let (units, picked, pending) = tally.get(&order.order_id).copied().unwrap_or_default();
+            order.units = units;
+            order.picked = picked;
+            order.short = if pending { 0 } else { units - picked };
+            order.status = if order.packed_at.is_some() {
+                WaveOrderStatus::Packed
+            } else if order.order_status != OrderStatus::Processing {
+                WaveOrderStatus::Withdrawn
+            } else if pending {
+                WaveOrderStatus::Picking
+            } else if picked < units {
+                WaveOrderStatus::Short
+            } else {
+                WaveOrderStatus::Picked
+            };
+        }
+    }
+
+    /// Whether every order is packed or no longer waiting to be.
+    pub fn is_done(&self) -> bool {
+        self.orders.iter().all(|order| {
+            matches!(
+                order.status,
+                WaveOrderStatus::Packed | WaveOrderStatus::Withdrawn
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PickWaveSummary {
+    pub id: Uuid,
+    pub warehouse_id: Uuid,
+    pub status: WaveStatus,
+    pub carrier: Option<String>,
+    pub zone: Option<String>,
+    pub cutoff_at: Option<DateTime<Utc>>,
+    pub orders: i64,
+    pub packed_orders: i64,
+    pub lines: i64,
+    pub pending_lines: i64,
+    pub short_lines: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Which `Processing` orders a new wave takes. Orders are taken by their
+/// carrier's next collection, then highest priority first, then oldest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct CreateWaveDto {
+    /// Only orders shipping with the carrier
+    #[validate(length(min = 1, max = 100, message = "Carrier must be 1-100 characters"))]
+    pub carrier: Option<String>,
+
+    /// Only orders their carrier collects by then. Without it the wave takes
+    /// the orders of the next collection.
+    pub cutoff_at: Option<DateTime<Utc>>,
+
+    /// Only orders of at least this priority
+    pub min_priority: Option<i32>,
+
+    /// Only orders picked entirely from bins in the zone
+    #[validate(length(min = 1, max = 50, message = "Zone must be 1-50 characters"))]
+    pub zone: Option<String>,
+
+    #[validate(range(min = 1, max = 500, message = "Max orders must be between 1 and 500"))]
+    pub max_orders: Option<usize>,
+
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct ConfirmPickDto {
+    /// Units actually picked; defaults to the whole line. Fewer records a
+    /// short pick.
+    #[validate(range(min = 0, message = "Picked quantity cannot be negative"))]
+    pub picked_quantity: Option<i32>,
+
+    pub user_id: Option<String>,
+}
+
+/// Who packs an order of a wave or cancels the wave.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WaveActionDto {
+    pub user_id: Option<String>,
+}
+
+/// The time of day, in UTC, a carrier collects from a warehouse.
+#[derive(Debug, Clone, Serialize)]
+pub struct CarrierCutoff {
+    pub warehouse_id: Uuid,
+    pub carrier: String,
+    /// `HH:MM`
+    pub cutoff_time: String,
+    /// The carrier's next collection
+    pub next_cutoff_at: DateTime<Utc>,
+    pub updated_by: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetCarrierCutoffDto {
+    /// Time of day in UTC, `HH:MM`
+    pub cutoff_time: String,
+
+    pub user_id: Option<String>,
+}
+
+/// A `Processing` order waiting for a wave, and when its carrier collects
+/// next; `None` when its carrier has no cutoff in the warehouse.
+#[derive(Debug, Clone)]
+pub struct WaveCandidate {
+    pub order_id: Uuid,
+    pub cutoff_at: Option<DateTime<Utc>>,
+}
+
+/// Units an order took out of the warehouse, by item and the bin they were
+/// picked from.
+#[derive(Debug, Clone)]
+pub struct WaveDemand {
+    pub order_id: Uuid,
+    pub warehouse_id: Uuid,
+    pub item_id: Uuid,
+    pub sku: String,
+    pub name: String,
+    pub bin_id: Option<Uuid>,
+    pub code: Option<String>,
+    pub zone: Option<String>,
+    pub walk_order: Option<i64>,
+    pub quantity: i32,
+}
+
+/// A pick line of a wave about to be created.
+#[derive(Debug, Clone)]
+pub struct NewPickLine {
+    pub line_number: i32,
+    pub bin_id: Option<Uuid>,
+    pub code: Option<String>,
+    pub item_id: Uuid,
+    pub quantity: i32,
+    pub orders: Vec<(Uuid, i32)>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(orders: &[(Uuid, i32)]) -> PickWaveLine {
+        PickWaveLine {
+            id: Uuid::new_v4(),
+            line_number: 1,
+            bin_id: None,
+            code: None,
+            item_id: Uuid::new_v4(),
+            sku: "SKU-1".to_string(),
+            name: "Widget".to_string(),
+            quantity: orders.iter().map(|(_, quantity)| quantity).sum(),
+            picked_quantity: None,
+            status: PickLineStatus::Pending,
+            confirmed_at: None,
+            confirmed_by: None,
+            orders: orders
+                .iter()
+                .map(|&(order_id, quantity)| PickLineOrder {
+                    order_id,
+                    quantity,
+                    picked: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_shortfall_comes_off_the_orders_last_in_the_wave() {
+        let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let line = line(&[(first, 2), (second, 3), (third, 1)]);
+
+        assert_eq!(line.shortfall(4), vec![(first, 0), (second, 1), (third, 1)]);
+        assert_eq!(line.shortfall(6), vec![(first, 0), (second, 0), (third, 0)]);
+    }
+
+    #[test]
+    fn test_shortfall_of_nothing_picked_is_the_whole_line() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let line = line(&[(first, 2), (second, 3)]);
+
+        assert_eq!(line.shortfall(0), vec![(first, 2), (second, 3)]);
+        assert_eq!(line.shortfall(-1), vec![(first, 2), (second, 3)]);
+    }
+
+    #[test]
+    fn test_pick_line_status_of_confirmed_quantity() {
+        assert_eq!(PickLineStatus::of(5, None), PickLineStatus::Pending);
+        assert_eq!(PickLineStatus::of(5, Some(3)), PickLineStatus::Short);
+        assert_eq!(PickLineStatus::of(5, Some(5)), PickLineStatus::Picked);
+        assert_eq!(PickLineStatus::of(5, Some(0)), PickLineStatus::Short);
+    }
+}
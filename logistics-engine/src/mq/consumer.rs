@@ -1,15 +1,18 @@
 use crate::config::get as get_config;
 use crate::error::AppError;
+use crate::mq::dlq::{self, setup_dead_letter_queue};
 use crate::mq::events::{Event, EventType};
+use crate::mq::retry::{self, RetryPolicy};
 use deadpool_lapin::Pool;
 use futures_lite::StreamExt;
 use lapin::{
+    message::Delivery,
     options::{
-        BasicAckOptions, BasicConsumeOptions, ExchangeDeclareOptions, QueueBindOptions,
-        QueueDeclareOptions,
+        BasicAckOptions, BasicConsumeOptions, ConfirmSelectOptions, ExchangeDeclareOptions,
+        QueueBindOptions,
     },
     types::FieldTable,
-    ExchangeKind,
+    Channel, ExchangeKind,
 };
 use serde::de::DeserializeOwned;
 use std::sync::Arc;
@@ -17,6 +20,12 @@ use tokio::task::JoinHandle;
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// Consumes queues bound to an exchange, retrying failed deliveries and
+/// dead-lettering the ones that keep failing. The engine itself only publishes,
+/// through the outbox relay, and registers no handler; this is the consumer
+/// for services built on the crate, e.g. one reading
+/// `RabbitMQConfig::order_queue` off the order exchange. The DLQ admin
+/// endpoints manage the `.dlq` queues it declares.
 pub struct RabbitMQConsumer {
     pool: Arc<Pool>,
     handlers: Vec<JoinHandle<()>>,
@@ -55,6 +64,13 @@ impl RabbitMQConsumer {
             .await
             .map_err(|e| AppError::RabbitMQError(format!("Failed to create channel: {}", e)))?;
 
+        // Retries and dead letters are only acked off the queue once the
+        // broker confirms their republish
+        channel
+            .confirm_select(ConfirmSelectOptions::default())
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to enable confirms: {}", e)))?;
+
         info!(
             "Setting up RabbitMQ consumer for queue {} with routing key {}",
             queue_name, routing_key
@@ -74,18 +90,12 @@ impl RabbitMQConsumer {
             .await
             .map_err(|e| AppError::RabbitMQError(format!("Failed to declare exchange: {}", e)))?;
 
-        // Declare queue
-        channel
-            .queue_declare(
-                queue_name,
-                QueueDeclareOptions {
-                    durable: true,
-                    ..Default::default()
-                },
-                Default::default(),
-            )
-            .await
-            .map_err(|e| AppError::RabbitMQError(format!("Failed to declare queue: {}", e)))?;
+        // Declare the queue with its dead letter queue, plus the delay queues
+        // used for retries
+        setup_dead_letter_queue(&conn, queue_name).await?;
+
+        let retry_policy = RetryPolicy::from_config();
+        retry::setup_retry_queues(&channel, queue_name, &retry_policy).await?;
 
         // Bind queue to exchange
         channel
@@ -132,19 +142,29 @@ impl RabbitMQConsumer {
                                 }
                                 Err(e) => {
                                     error!("Error processing message: {}", e);
-                                    // TODO: Implement dead-letter queue or retry mechanism
-                                    // For now, acknowledge the message to avoid blocking the queue
-                                    if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
-                                        error!("Failed to acknowledge message after processing error: {}", e);
-                                    }
+                                    Self::handle_failure(
+                                        &channel,
+                                        &queue_name_clone,
+                                        &retry_policy,
+                                        &delivery,
+                                        &e.to_string(),
+                                    )
+                                    .await;
                                 }
                             }
                         }
                         Err(e) => {
                             error!("Error deserializing message: {}", e);
-                            // Acknowledge malformed messages to avoid blocking the queue
-                            if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
-                                error!("Failed to acknowledge malformed message: {}", e);
+                            // Malformed messages will never succeed, so skip the retries
+                            if let Err(e) = dlq::dead_letter(
+                                &channel,
+                                &queue_name_clone,
+                                &delivery,
+                                &format!("Failed to deserialize message: {}", e),
+                            )
+                            .await
+                            {
+                                error!("Failed to dead-letter malformed message: {}", e);
                             }
                         }
                     }
@@ -163,6 +183,47 @@ impl RabbitMQConsumer {
         Ok(())
     }
 
+    /// Sends a failed delivery to the next delay queue, or to the dead letter
+    /// queue once the configured retry attempts are used up.
+    async fn handle_failure(
+        channel: &Channel,
+        queue_name: &str,
+        retry_policy: &RetryPolicy,
+        delivery: &Delivery,
+        reason: &str,
+    ) {
+        let attempt = retry::retry_count(delivery) + 1;
+
+        if attempt <= retry_policy.max_attempts {
+            match retry::schedule_retry(channel, queue_name, delivery, attempt, reason).await {
+                Ok(()) => {
+                    warn!(
+                        "Retrying message from queue {} in {:?} (attempt {}/{})",
+                        queue_name,
+                        retry_policy.delay_for_attempt(attempt),
+                        attempt,
+                        retry_policy.max_attempts
+                    );
+                    if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                        error!(
+                            "Failed to acknowledge message after scheduling retry: {}",
+                            e
+                        );
+                    }
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to schedule retry, dead-lettering instead: {}", e);
+                }
+            }
+        }
+
+        let reason = format!("{} (after {} attempts)", reason, attempt);
+        if let Err(e) = dlq::dead_letter(channel, queue_name, delivery, &reason).await {
+            error!("Failed to dead-letter message: {}", e);
+        }
+    }
+
     pub async fn shutdown(self) {
         info!("Shutting down RabbitMQ consumers");
         for handle in self.handlers {
@@ -1,36 +1,46 @@
 use crate::error::AppError;
+use crate::mq::publisher;
+use crate::mq::retry::{
+    self, LAST_ERROR_HEADER, ORIGINAL_EXCHANGE_HEADER, ORIGINAL_ROUTING_KEY_HEADER,
+    RETRY_COUNT_HEADER,
+};
+use chrono::Utc;
 use lapin::{
-    options::{ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions},
-    types::{AMQPValue, FieldTable},
-    ExchangeKind,
+    message::Delivery,
+    options::{
+        BasicAckOptions, BasicGetOptions, BasicNackOptions, BasicPublishOptions,
+        QueueDeclareOptions, QueuePurgeOptions,
+    },
+    protocol::{AMQPErrorKind, AMQPSoftError},
+    types::{AMQPValue, FieldTable, ShortString},
 };
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+pub const FAILURE_REASON_HEADER: &str = "x-failure-reason";
+pub const FAILED_AT_HEADER: &str = "x-failed-at";
+pub const FAILED_QUEUE_HEADER: &str = "x-failed-queue";
 
+/// Declares the `.dlq` queue for `queue_name` and the queue itself with
+/// dead-letter arguments that route rejected messages straight to it through
+/// the default exchange, so each queue's failures land in its own DLQ whatever
+/// their routing key. Runs on a channel of its own: a queue declared before it
+/// had dead-lettering cannot be redeclared with the arguments, and the broker
+/// closes the channel that tries. Such a queue is left as it is with a warning;
+/// `dead_letter` still moves failed deliveries to the DLQ, but the arguments,
+/// or a policy setting them, are needed for the fallback when that republish
+/// fails.
 pub async fn setup_dead_letter_queue(
-    channel: &lapin::Channel,
+    conn: &lapin::Connection,
     queue_name: &str,
-    exchange_name: &str,
-    routing_key: &str,
 ) -> Result<(), AppError> {
-    // Declare the DLX (Dead Letter Exchange)
-    let dlx_name = format!("{}.dlx", exchange_name);
-    info!("Declaring dead letter exchange: {}", dlx_name);
-
-    channel
-        .exchange_declare(
-            &dlx_name,
-            ExchangeKind::Topic,
-            ExchangeDeclareOptions {
-                durable: true,
-                ..Default::default()
-            },
-            Default::default(),
-        )
+    let channel = conn
+        .create_channel()
         .await
-        .map_err(|e| AppError::RabbitMQError(format!("Failed to declare DLX: {}", e)))?;
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to create channel: {}", e)))?;
 
     // Declare the DLQ (Dead Letter Queue)
-    let dlq_name = format!("{}.dlq", queue_name);
+    let dlq_name = dead_letter_queue_name(queue_name);
     info!("Declaring dead letter queue: {}", dlq_name);
 
     channel
@@ -45,33 +55,23 @@ pub async fn setup_dead_letter_queue(
         .await
         .map_err(|e| AppError::RabbitMQError(format!("Failed to declare DLQ: {}", e)))?;
 
-    // Bind the DLQ to the DLX
-    info!("Binding DLQ to DLX with routing key: {}", routing_key);
-    channel
-        .queue_bind(
-            &dlq_name,
-            &dlx_name,
-            routing_key,
-            QueueBindOptions::default(),
-            FieldTable::default(),
-        )
-        .await
-        .map_err(|e| AppError::RabbitMQError(format!("Failed to bind DLQ: {}", e)))?;
-
-    // Create arguments for the main queue pointing to the DLX
+    // Create arguments for the main queue pointing at its DLQ
     let mut args = FieldTable::default();
     args.insert(
         "x-dead-letter-exchange".into(),
-        AMQPValue::LongString(dlx_name.into()),
+        AMQPValue::LongString("".into()),
     );
     args.insert(
         "x-dead-letter-routing-key".into(),
-        AMQPValue::LongString(routing_key.into()),
+        AMQPValue::LongString(dlq_name.as_str().into()),
     );
 
-    // Declare the main queue with DLX configuration
-    info!("Redeclaring main queue with DLX config: {}", queue_name);
-    channel
+    // Declare the main queue with dead-letter configuration
+    info!(
+        "Declaring main queue with dead-letter config: {}",
+        queue_name
+    );
+    match channel
         .queue_declare(
             queue_name,
             QueueDeclareOptions {
@@ -81,11 +81,439 @@ pub async fn setup_dead_letter_queue(
             args,
         )
         .await
-        .map_err(|e| {
-            AppError::RabbitMQError(format!("Failed to declare main queue with DLX: {}", e))
-        })?;
+    {
+        Ok(_) => {}
+        Err(lapin::Error::ProtocolError(e))
+            if matches!(
+                e.kind(),
+                AMQPErrorKind::Soft(AMQPSoftError::PRECONDITIONFAILED)
+            ) =>
+        {
+            warn!(
+                "Queue {} already exists with different dead-letter arguments; delete it or \
+                 apply a policy with an empty dead-letter-exchange and dead-letter-routing-key \
+                 {} so rejected messages reach {}",
+                queue_name, dlq_name, dlq_name
+            );
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(AppError::RabbitMQError(format!(
+                "Failed to declare main queue with DLX: {}",
+                e
+            )))
+        }
+    }
+
+    let _ = channel.close(200, "OK").await;
 
     info!("Dead letter queue setup complete for queue: {}", queue_name);
 
     Ok(())
 }
+
+/// Moves a delivery that cannot be processed into the dead letter queue, with
+/// the failure reason in its headers. The message is republished to the DLQ
+/// through the default exchange, keeping its original routing key in a header,
+/// and the original delivery is acked once the broker confirms it; if the republish
+/// fails or is rejected the delivery is nacked so the queue's own dead-letter
+/// routing still takes it out of circulation. `channel` must be in confirm
+/// mode.
+pub async fn dead_letter(
+    channel: &lapin::Channel,
+    queue_name: &str,
+    delivery: &Delivery,
+    reason: &str,
+) -> Result<(), AppError> {
+    let dlq_name = dead_letter_queue_name(queue_name);
+
+    let mut headers = retry::carry_over_headers(delivery);
+    headers.insert(
+        FAILURE_REASON_HEADER.into(),
+        AMQPValue::LongString(reason.into()),
+    );
+    headers.insert(
+        FAILED_AT_HEADER.into(),
+        AMQPValue::LongString(Utc::now().to_rfc3339().into()),
+    );
+    headers.insert(
+        FAILED_QUEUE_HEADER.into(),
+        AMQPValue::LongString(queue_name.into()),
+    );
+    headers.insert(
+        RETRY_COUNT_HEADER.into(),
+        AMQPValue::LongLongInt(retry::retry_count(delivery) as i64),
+    );
+
+    let properties = delivery.properties.clone().with_headers(headers);
+
+    let published = match channel
+        .basic_publish(
+            "",
+            &dlq_name,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            properties,
+        )
+        .await
+    {
+        Ok(confirm) => match confirm.await {
+            Ok(confirmation) if confirmation.is_ack() => true,
+            Ok(_) => {
+                error!("Broker rejected message published to {}", dlq_name);
+                false
+            }
+            Err(e) => {
+                error!("Failed to confirm message published to {}: {}", dlq_name, e);
+                false
+            }
+        },
+        Err(e) => {
+            error!("Failed to publish message to {}: {}", dlq_name, e);
+            false
+        }
+    };
+
+    if published {
+        warn!(
+            "Message from queue {} dead-lettered to {}: {}",
+            queue_name, dlq_name, reason
+        );
+        delivery
+            .ack(Default::default())
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to ack message: {}", e)))?;
+    } else {
+        delivery
+            .nack(BasicNackOptions {
+                requeue: false,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to nack message: {}", e)))?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeadLetterMessage {
+    pub message_id: Option<String>,
+    pub original_exchange: Option<String>,
+    pub routing_key: String,
+    pub failure_reason: Option<String>,
+    pub failed_at: Option<String>,
+    pub retry_count: u32,
+    pub headers: serde_json::Map<String, serde_json::Value>,
+    pub payload: serde_json::Value,
+}
+
+/// Which dead-lettered messages to replay. With neither field set, every
+/// message in the queue is replayed.
+#[derive(Debug, Default, Deserialize)]
+pub struct ReplaySelection {
+    pub message_ids: Option<Vec<String>>,
+    pub routing_key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplaySummary {
+    pub queue: String,
+    pub replayed: u32,
+    pub remaining: u32,
+}
+
+/// Accepts either the work queue name or the `.dlq` name itself.
+pub fn dead_letter_queue_name(queue_name: &str) -> String {
+    if queue_name.ends_with(".dlq") {
+        queue_name.to_string()
+    } else {
+        format!("{}.dlq", queue_name)
+    }
+}
+
+fn amqp_value_to_json(value: &AMQPValue) -> serde_json::Value {
+    match value {
+        AMQPValue::Boolean(b) => serde_json::Value::Bool(*b),
+        AMQPValue::ShortShortInt(n) => serde_json::json!(n),
+        AMQPValue::ShortShortUInt(n) => serde_json::json!(n),
+        AMQPValue::ShortInt(n) => serde_json::json!(n),
+        AMQPValue::ShortUInt(n) => serde_json::json!(n),
+        AMQPValue::LongInt(n) => serde_json::json!(n),
+        AMQPValue::LongUInt(n) => serde_json::json!(n),
+        AMQPValue::LongLongInt(n) => serde_json::json!(n),
+        AMQPValue::Timestamp(n) => serde_json::json!(n),
+        AMQPValue::ShortString(s) => serde_json::Value::String(s.to_string()),
+        AMQPValue::LongString(s) => serde_json::Value::String(s.to_string()),
+        AMQPValue::FieldTable(table) => serde_json::Value::Object(field_table_to_json(table)),
+        AMQPValue::FieldArray(array) => {
+            serde_json::Value::Array(array.as_slice().iter().map(amqp_value_to_json).collect())
+        }
+        other => serde_json::Value::String(format!("{:?}", other)),
+    }
+}
+
+fn field_table_to_json(table: &FieldTable) -> serde_json::Map<String, serde_json::Value> {
+    table
+        .inner()
+        .iter()
+        .map(|(key, value)| (key.to_string(), amqp_value_to_json(value)))
+        .collect()
+}
+
+fn to_dead_letter_message(delivery: &Delivery) -> DeadLetterMessage {
+    let headers = delivery
+        .properties
+        .headers()
+        .as_ref()
+        .map(field_table_to_json)
+        .unwrap_or_default();
+
+    let payload = serde_json::from_slice(&delivery.data).unwrap_or_else(|_| {
+        serde_json::Value::String(String::from_utf8_lossy(&delivery.data).to_string())
+    });
+
+    DeadLetterMessage {
+        message_id: delivery
+            .properties
+            .message_id()
+            .as_ref()
+            .map(|id| id.to_string()),
+        original_exchange: retry::header_string(delivery, ORIGINAL_EXCHANGE_HEADER),
+        routing_key: original_routing_key(delivery),
+        failure_reason: retry::header_string(delivery, FAILURE_REASON_HEADER),
+        failed_at: retry::header_string(delivery, FAILED_AT_HEADER),
+        retry_count: retry::retry_count(delivery),
+        headers,
+        payload,
+    }
+}
+
+fn original_routing_key(delivery: &Delivery) -> String {
+    retry::header_string(delivery, ORIGINAL_ROUTING_KEY_HEADER)
+        .unwrap_or_else(|| delivery.routing_key.to_string())
+}
+
+/// AMQP topic matching: `*` matches exactly one word, `#` zero or more.
+fn routing_key_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], key: &[&str]) -> bool {
+        match (pattern.first(), key.first()) {
+            (None, None) => true,
+            (Some(&"#"), _) => {
+                matches(&pattern[1..], key) || (!key.is_empty() && matches(pattern, &key[1..]))
+            }
+            (Some(&"*"), Some(_)) => matches(&pattern[1..], &key[1..]),
+            (Some(p), Some(k)) if p == k => matches(&pattern[1..], &key[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern = pattern.split('.').collect::<Vec<_>>();
+    let key = routing_key.split('.').collect::<Vec<_>>();
+    matches(&pattern, &key)
+}
+
+impl ReplaySelection {
+    fn matches(&self, message: &DeadLetterMessage) -> bool {
+        let id_matches = match &self.message_ids {
+            Some(ids) => message
+                .message_id
+                .as_ref()
+                .map_or(false, |id| ids.contains(id)),
+            None => true,
+        };
+        let routing_key_matches = match &self.routing_key {
+            Some(pattern) => routing_key_matches(pattern, &message.routing_key),
+            None => true,
+        };
+
+        id_matches && routing_key_matches
+    }
+}
+
+async fn message_count(channel: &lapin::Channel, dlq_name: &str) -> Result<u32, AppError> {
+    let queue = channel
+        .queue_declare(
+            dlq_name,
+            QueueDeclareOptions {
+                passive: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .map_err(|_| AppError::NotFound(dlq_name.to_string()))?;
+
+    Ok(queue.message_count())
+}
+
+/// Fetches up to `limit` messages without removing them from the queue. The
+/// messages are held unacked while reading so none is returned twice, then
+/// requeued.
+pub async fn list_dead_letters(
+    queue_name: &str,
+    limit: u32,
+) -> Result<Vec<DeadLetterMessage>, AppError> {
+    let dlq_name = dead_letter_queue_name(queue_name);
+    let channel = publisher::create_confirm_channel().await?;
+    let available = message_count(&channel, &dlq_name).await?;
+
+    let mut deliveries = Vec::new();
+    for _ in 0..available.min(limit) {
+        match channel
+            .basic_get(&dlq_name, BasicGetOptions { no_ack: false })
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to read DLQ: {}", e)))?
+        {
+            Some(message) => deliveries.push(message.delivery),
+            None => break,
+        }
+    }
+
+    let messages = deliveries.iter().map(to_dead_letter_message).collect();
+
+    for delivery in &deliveries {
+        delivery
+            .nack(BasicNackOptions {
+                requeue: true,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to requeue message: {}", e)))?;
+    }
+
+    let _ = channel.close(200, "OK").await;
+
+    Ok(messages)
+}
+
+/// Republishes matching messages straight to the queue that dead-lettered them,
+/// through the default exchange, so other queues bound to the original exchange
+/// do not see them a second time. The failure headers are stripped and the
+/// retry count reset.
+/// A message is removed from the DLQ only after the broker confirms the replay
+/// and has routed it to the queue.
+pub async fn replay_dead_letters(
+    queue_name: &str,
+    selection: &ReplaySelection,
+) -> Result<ReplaySummary, AppError> {
+    let dlq_name = dead_letter_queue_name(queue_name);
+    let channel = publisher::create_confirm_channel().await?;
+    let available = message_count(&channel, &dlq_name).await?;
+    let work_queue = dlq_name.trim_end_matches(".dlq").to_string();
+
+    let mut replayed = 0;
+    let mut kept = Vec::new();
+
+    for _ in 0..available {
+        let delivery = match channel
+            .basic_get(&dlq_name, BasicGetOptions { no_ack: false })
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to read DLQ: {}", e)))?
+        {
+            Some(message) => message.delivery,
+            None => break,
+        };
+
+        let message = to_dead_letter_message(&delivery);
+        if !selection.matches(&message) {
+            kept.push(delivery);
+            continue;
+        }
+
+        let target_queue = retry::header_string(&delivery, FAILED_QUEUE_HEADER)
+            .filter(|queue| !queue.is_empty())
+            .unwrap_or_else(|| work_queue.clone());
+
+        let mut headers = FieldTable::default();
+        if let Some(original) = delivery.properties.headers() {
+            for (key, value) in original.inner() {
+                if !matches!(
+                    key.as_str(),
+                    FAILURE_REASON_HEADER
+                        | FAILED_AT_HEADER
+                        | FAILED_QUEUE_HEADER
+                        | RETRY_COUNT_HEADER
+                        | LAST_ERROR_HEADER
+                ) {
+                    headers.insert(ShortString::from(key.as_str()), value.clone());
+                }
+            }
+        }
+
+        let confirmation = channel
+            .basic_publish(
+                "",
+                &target_queue,
+                // Unroutable when the queue is gone; it comes back instead of
+                // being dropped
+                BasicPublishOptions {
+                    mandatory: true,
+                    ..Default::default()
+                },
+                &delivery.data,
+                delivery.properties.clone().with_headers(headers),
+            )
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to replay message: {}", e)))?
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to confirm replay: {}", e)))?;
+
+        let rejected = confirmation.is_nack();
+        if rejected || confirmation.take_message().is_some() {
+            warn!(
+                "Broker could not replay message {:?} to {}",
+                message.message_id, target_queue
+            );
+            kept.push(delivery);
+            continue;
+        }
+
+        delivery
+            .ack(BasicAckOptions::default())
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to ack message: {}", e)))?;
+        replayed += 1;
+    }
+
+    let remaining = kept.len() as u32;
+    for delivery in kept {
+        delivery
+            .nack(BasicNackOptions {
+                requeue: true,
+                ..Default::default()
+            })
+            .await
+            .map_err(|e| AppError::RabbitMQError(format!("Failed to requeue message: {}", e)))?;
+    }
+
+    let _ = channel.close(200, "OK").await;
+
+    info!(
+        "Replayed {} messages from {} ({} left)",
+        replayed, dlq_name, remaining
+    );
+
+    Ok(ReplaySummary {
+        queue: dlq_name,
+        replayed,
+        remaining,
+    })
+}
+
+pub async fn purge_dead_letters(queue_name: &str) -> Result<u32, AppError> {
+    let dlq_name = dead_letter_queue_name(queue_name);
+    let channel = publisher::create_confirm_channel().await?;
+    message_count(&channel, &dlq_name).await?;
+
+    let purged = channel
+        .queue_purge(&dlq_name, QueuePurgeOptions::default())
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to purge DLQ: {}", e)))?;
+
+    let _ = channel.close(200, "OK").await;
+
+    warn!("Purged {} messages from {}", purged, dlq_name);
+
+    Ok(purged)
+}
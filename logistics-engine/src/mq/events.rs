@@ -14,6 +14,10 @@ pub enum EventType {
     ShipmentStatusChanged,
     PaymentProcessed,
     PaymentFailed,
+    ReturnAuthorized,
+    ReturnReceived,
+    ReturnRefunded,
+    ReturnCancelled,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -130,3 +134,51 @@ pub struct PaymentFailedEvent {
     pub error_code: String,
     pub error_message: String,
 }
+
+// Return Events
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnAuthorizedEvent {
+    pub return_id: Uuid,
+    pub rma_number: String,
+    pub order_id: Uuid,
+    pub reason_code: String,
+    pub items: Vec<ReturnedItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnedItem {
+    pub order_item_id: Uuid,
+    pub product_id: Uuid,
+    pub sku: String,
+    pub quantity: i32,
+    pub condition: Option<String>,
+    pub disposition: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnReceivedEvent {
+    pub return_id: Uuid,
+    pub rma_number: String,
+    pub order_id: Uuid,
+    pub items: Vec<ReturnedItem>,
+    pub refund_amount: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnRefundedEvent {
+    pub return_id: Uuid,
+    pub rma_number: String,
+    pub order_id: Uuid,
+    pub payment_id: Uuid,
+    pub amount: String,
+    pub currency: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReturnCancelledEvent {
+    pub return_id: Uuid,
+    pub rma_number: String,
+    pub order_id: Uuid,
+    pub reason: Option<String>,
+}
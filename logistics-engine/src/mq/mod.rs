@@ -3,6 +3,7 @@ pub mod consumer;
 pub mod dlq;
 pub mod events;
 pub mod publisher;
+pub mod retry;
 
 use crate::error::AppError;
 
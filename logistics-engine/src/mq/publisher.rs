@@ -4,8 +4,8 @@ use crate::mq::events::{Event, EventType};
 use chrono::Utc;
 use deadpool_lapin::{Manager, Pool, PoolError};
 use lapin::{
-    options::{BasicPublishOptions, ExchangeDeclareOptions},
-    BasicProperties, ConnectionProperties, ExchangeKind,
+    options::{BasicPublishOptions, ConfirmSelectOptions, ExchangeDeclareOptions},
+    BasicProperties, Channel, ConnectionProperties, ExchangeKind,
 };
 use serde::Serialize;
 use std::sync::OnceLock;
@@ -53,7 +53,7 @@ pub async fn init_rabbitmq_pool() -> Result<(), AppError> {
     Ok(())
 }
 
-async fn get_rabbitmq_connection() -> Result<deadpool_lapin::Object, AppError> {
+pub(crate) async fn get_rabbitmq_connection() -> Result<deadpool_lapin::Object, AppError> {
     let pool = match RABBITMQ_POOL.get() {
         Some(pool) => pool,
         None => {
@@ -70,18 +70,22 @@ async fn get_rabbitmq_connection() -> Result<deadpool_lapin::Object, AppError> {
         .map_err(|e: PoolError| AppError::RabbitMQError(format!("Failed to get connection: {}", e)))
 }
 
-pub async fn publish_event<T: Serialize>(
-    event_type: EventType,
-    routing_key: &str,
-    data: T,
-) -> Result<(), AppError> {
-    let event = Event {
+pub fn build_event<T: Serialize>(event_type: EventType, data: T) -> Event<T> {
+    Event {
         id: Uuid::new_v4(),
         event_type,
         timestamp: Utc::now(),
         version: "1.0".to_string(),
         data,
-    };
+    }
+}
+
+pub async fn publish_event<T: Serialize>(
+    event_type: EventType,
+    routing_key: &str,
+    data: T,
+) -> Result<(), AppError> {
+    let event = build_event(event_type, data);
 
     let json = serde_json::to_string(&event)
         .map_err(|e| AppError::InternalServerError(format!("JSON serialization error: {}", e)))?;
@@ -107,10 +111,63 @@ pub async fn publish_event<T: Serialize>(
             json.as_bytes(),
             BasicProperties::default()
                 .with_delivery_mode(2) // persistent
-                .with_content_type("application/json".into()),
+                .with_content_type("application/json".into())
+                .with_message_id(event.id.to_string().into()),
         )
         .await
         .map_err(|e| AppError::RabbitMQError(format!("Failed to publish message: {}", e)))?;
 
     Ok(())
 }
+
+/// Opens a channel in confirm mode so every publish is acknowledged by the broker.
+pub async fn create_confirm_channel() -> Result<Channel, AppError> {
+    let conn = get_rabbitmq_connection().await?;
+    let channel = conn
+        .create_channel()
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to create channel: {}", e)))?;
+
+    channel
+        .confirm_select(ConfirmSelectOptions::default())
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to enable confirms: {}", e)))?;
+
+    Ok(channel)
+}
+
+/// Publishes an already serialized event on a confirm channel and waits for the
+/// broker ack. The message id lets consumers drop redeliveries of the same event.
+pub async fn publish_confirmed(
+    channel: &Channel,
+    routing_key: &str,
+    message_id: &str,
+    payload: &[u8],
+) -> Result<(), AppError> {
+    let config = get_config();
+
+    let confirmation = channel
+        .basic_publish(
+            &config.rabbitmq.order_exchange,
+            routing_key,
+            BasicPublishOptions::default(),
+            payload,
+            BasicProperties::default()
+                .with_delivery_mode(2) // persistent
+                .with_content_type("application/json".into())
+                .with_message_id(message_id.into()),
+        )
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to publish message: {}", e)))?
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to confirm message: {}", e)))?;
+
+    if confirmation.is_nack() {
+        return Err(AppError::RabbitMQError(format!(
+            "Broker rejected message {} with routing key {}",
+            message_id, routing_key
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use crate::config::get as get_config;
+use crate::error::AppError;
+use lapin::{
+    message::Delivery,
+    options::{BasicPublishOptions, QueueDeclareOptions},
+    types::{AMQPValue, FieldTable, ShortString},
+    Channel,
+};
+use std::time::Duration;
+use tracing::info;
+
+pub const RETRY_COUNT_HEADER: &str = "x-retry-count";
+pub const LAST_ERROR_HEADER: &str = "x-last-error";
+pub const ORIGINAL_EXCHANGE_HEADER: &str = "x-original-exchange";
+pub const ORIGINAL_ROUTING_KEY_HEADER: &str = "x-original-routing-key";
+
+/// Exponential backoff for failed deliveries. Each attempt has its own delay
+/// queue whose TTL dead-letters the message back onto the work queue.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl RetryPolicy {
+    pub fn from_config() -> Self {
+        let config = get_config();
+        Self {
+            max_attempts: config.rabbitmq.retry_attempts,
+            base_delay_ms: config.rabbitmq.retry_base_delay_ms,
+            max_delay_ms: config.rabbitmq.retry_max_delay_ms,
+        }
+    }
+
+    /// Delay before the given attempt (1-based): base, 2x base, 4x base, ...
+    pub fn delay_for_attempt(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        let delay_ms = self
+            .base_delay_ms
+            .saturating_mul(factor)
+            .min(self.max_delay_ms);
+        Duration::from_millis(delay_ms)
+    }
+
+    pub fn retry_queue_name(queue_name: &str, attempt: u32) -> String {
+        format!("{}.retry.{}", queue_name, attempt)
+    }
+}
+
+/// Declares one delay queue per attempt. Expired messages are routed through the
+/// default exchange straight back to `queue_name`.
+pub async fn setup_retry_queues(
+    channel: &Channel,
+    queue_name: &str,
+    policy: &RetryPolicy,
+) -> Result<(), AppError> {
+    for attempt in 1..=policy.max_attempts {
+        let retry_queue = RetryPolicy::retry_queue_name(queue_name, attempt);
+        let delay = policy.delay_for_attempt(attempt);
+
+        let mut args = FieldTable::default();
+        args.insert(
+            "x-message-ttl".into(),
+            AMQPValue::LongLongInt(delay.as_millis() as i64),
+        );
+        args.insert(
+            "x-dead-letter-exchange".into(),
+            AMQPValue::LongString("".into()),
+        );
+        args.insert(
+            "x-dead-letter-routing-key".into(),
+            AMQPValue::LongString(queue_name.into()),
+        );
+
+        info!(
+            "Declaring retry queue {} with delay {:?}",
+            retry_queue, delay
+        );
+
+        channel
+            .queue_declare(
+                &retry_queue,
+                QueueDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                args,
+            )
+            .await
+            .map_err(|e| {
+                AppError::RabbitMQError(format!("Failed to declare retry queue: {}", e))
+            })?;
+    }
+
+    Ok(())
+}
+
+/// Number of retries a delivery has already been through.
+pub fn retry_count(delivery: &Delivery) -> u32 {
+    delivery
+        .properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(&ShortString::from(RETRY_COUNT_HEADER)))
+        .and_then(|value| match value {
+            AMQPValue::LongLongInt(n) => Some(*n as u32),
+            AMQPValue::LongInt(n) => Some(*n as u32),
+            AMQPValue::LongUInt(n) => Some(*n),
+            AMQPValue::ShortInt(n) => Some(*n as u32),
+            AMQPValue::ShortShortInt(n) => Some(*n as u32),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+pub fn header_string(delivery: &Delivery, key: &str) -> Option<String> {
+    delivery
+        .properties
+        .headers()
+        .as_ref()
+        .and_then(|headers| headers.inner().get(&ShortString::from(key)))
+        .and_then(|value| match value {
+            AMQPValue::LongString(s) => Some(s.to_string()),
+            AMQPValue::ShortString(s) => Some(s.to_string()),
+            _ => None,
+        })
+}
+
+/// Copies the delivery headers and records where the message was originally
+/// published, so retries and dead-lettering keep the original routing.
+pub fn carry_over_headers(delivery: &Delivery) -> FieldTable {
+    let mut headers = delivery.properties.headers().clone().unwrap_or_default();
+
+    if header_string(delivery, ORIGINAL_EXCHANGE_HEADER).is_none() {
+        headers.insert(
+            ORIGINAL_EXCHANGE_HEADER.into(),
+            AMQPValue::LongString(delivery.exchange.as_str().into()),
+        );
+    }
+    if header_string(delivery, ORIGINAL_ROUTING_KEY_HEADER).is_none() {
+        headers.insert(
+            ORIGINAL_ROUTING_KEY_HEADER.into(),
+            AMQPValue::LongString(delivery.routing_key.as_str().into()),
+        );
+    }
+
+    headers
+}
+
+/// Parks the delivery in the delay queue for `attempt`.
+pub async fn schedule_retry(
+    channel: &Channel,
+    queue_name: &str,
+    delivery: &Delivery,
+    attempt: u32,
+    error: &str,
+) -> Result<(), AppError> {
+    let mut headers = carry_over_headers(delivery);
+    headers.insert(
+        RETRY_COUNT_HEADER.into(),
+        AMQPValue::LongLongInt(attempt as i64),
+    );
+    headers.insert(
+        LAST_ERROR_HEADER.into(),
+        AMQPValue::LongString(error.into()),
+    );
+
+    let properties = delivery.properties.clone().with_headers(headers);
+
+    let retry_queue = RetryPolicy::retry_queue_name(queue_name, attempt);
+    let confirmation = channel
+        .basic_publish(
+            "",
+            &retry_queue,
+            BasicPublishOptions::default(),
+            &delivery.data,
+            properties,
+        )
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to schedule retry: {}", e)))?
+        .await
+        .map_err(|e| AppError::RabbitMQError(format!("Failed to confirm retry: {}", e)))?;
+
+    if !confirmation.is_ack() {
+        return Err(AppError::RabbitMQError(format!(
+            "Broker rejected retry published to {}",
+            retry_queue
+        )));
+    }
+
+    Ok(())
+}
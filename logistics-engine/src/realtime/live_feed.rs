@@ -0,0 +1,378 @@
+use crate::config::get as get_config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::broadcast;
+use tracing::warn;
+
+static LIVE_FEED: OnceLock<LiveFeed> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LiveTopic {
+    Orders,
+    Inventory,
+    Shipments,
+    Payments,
+}
+
+impl LiveTopic {
+    pub const ALL: [LiveTopic; 4] = [
+        LiveTopic::Orders,
+        LiveTopic::Inventory,
+        LiveTopic::Shipments,
+        LiveTopic::Payments,
+    ];
+}
+
+impl FromStr for LiveTopic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "orders" | "order" => Ok(LiveTopic::Orders),
+            "inventory" => Ok(LiveTopic::Inventory),
+            "shipments" | "shipment" | "shipping" => Ok(LiveTopic::Shipments),
+            "payments" | "payment" => Ok(LiveTopic::Payments),
+            _ => Err(format!(
+                "Invalid topic: {}. Must be one of: orders, inventory, shipments, payments",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveEvent {
+    pub id: u64,
+    pub topic: LiveTopic,
+    pub event_type: String,
+    pub entity_id: Option<String>,
+    pub data: serde_json::Value,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub enum LiveMessage {
+    Event(Arc<LiveEvent>),
+    /// Events were lost between the client's last event and now; the client
+    /// should reload its state instead of relying on the feed.
+    Reset {
+        missed: u64,
+    },
+}
+
+struct FeedState {
+    next_id: u64,
+    history: VecDeque<Arc<LiveEvent>>,
+}
+
+struct LiveFeed {
+    state: Mutex<FeedState>,
+    sender: broadcast::Sender<Arc<LiveEvent>>,
+    history_size: usize,
+}
+
+impl LiveFeed {
+    fn new(history_size: usize, channel_capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(channel_capacity.max(1));
+
+        LiveFeed {
+            state: Mutex::new(FeedState {
+                // Ids start from the boot time so they keep increasing across
+                // restarts and a stale Last-Event-ID is detected as a gap
+                next_id: Utc::now().timestamp_millis().max(1) as u64,
+                history: VecDeque::with_capacity(history_size),
+            }),
+            sender,
+            history_size: history_size.max(1),
+        }
+    }
+
+    fn publish(
+        &self,
+        topic: LiveTopic,
+        event_type: &str,
+        entity_id: Option<String>,
+        data: serde_json::Value,
+    ) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        let event = Arc::new(LiveEvent {
+            id: state.next_id,
+            topic,
+            event_type: event_type.to_string(),
+            entity_id,
+            data,
+            timestamp: Utc::now(),
+        });
+        state.next_id += 1;
+
+        state.history.push_back(event.clone());
+        while state.history.len() > self.history_size {
+            state.history.pop_front();
+        }
+
+        // Sent under the lock so ids reach subscribers in order
+        let _ = self.sender.send(event);
+    }
+
+    fn subscribe(
+        &self,
+        topics: HashSet<LiveTopic>,
+        last_event_id: Option<u64>,
+    ) -> LiveSubscription {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Subscribe while holding the lock so nothing falls between the backlog
+        // and the live stream
+        let receiver = self.sender.subscribe();
+        let newest_id = state.next_id - 1;
+
+        let mut backlog = VecDeque::new();
+        let mut pending_reset = None;
+        let mut last_id = newest_id;
+
+        if let Some(last_event_id) = last_event_id {
+            let oldest_id = state
+                .history
+                .front()
+                .map_or(state.next_id, |event| event.id);
+
+            if last_event_id > newest_id || last_event_id + 1 < oldest_id {
+                pending_reset = Some(newest_id.saturating_sub(last_event_id));
+            } else {
+                backlog = state
+                    .history
+                    .iter()
+                    .filter(|event| event.id > last_event_id)
+                    .cloned()
+                    .collect();
+                last_id = last_event_id;
+            }
+        }
+
+        let topics = if topics.is_empty() {
+            LiveTopic::ALL.into_iter().collect()
+        } else {
+            topics
+        };
+
+        LiveSubscription {
+            topics,
+            backlog,
+            receiver,
+            last_id,
+            pending_reset,
+        }
+    }
+}
+
+fn feed() -> &'static LiveFeed {
+    LIVE_FEED.get_or_init(|| {
+        let config = &get_config().realtime;
+        LiveFeed::new(config.history_size, config.channel_capacity)
+    })
+}
+
+/// Appends an event to the feed and pushes it to every connected client.
+pub fn publish<T: Serialize>(
+    topic: LiveTopic,
+    event_type: &str,
+    entity_id: Option<String>,
+    data: &T,
+) {
+    let data = match serde_json::to_value(data) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("Failed to serialize live event {}: {}", event_type, e);
+            return;
+        }
+    };
+
+    feed().publish(topic, event_type, entity_id, data);
+}
+
+pub struct LiveSubscription {
+    topics: HashSet<LiveTopic>,
+    backlog: VecDeque<Arc<LiveEvent>>,
+    receiver: broadcast::Receiver<Arc<LiveEvent>>,
+    last_id: u64,
+    pending_reset: Option<u64>,
+}
+
+/// Subscribes to `topics` (all topics when empty). With `last_event_id`, the
+/// buffered events after it are replayed first; if it is older than the buffer
+/// the subscription starts with a reset.
+pub fn subscribe(topics: HashSet<LiveTopic>, last_event_id: Option<u64>) -> LiveSubscription {
+    feed().subscribe(topics, last_event_id)
+}
+
+impl LiveSubscription {
+    pub fn topics(&self) -> &HashSet<LiveTopic> {
+        &self.topics
+    }
+
+    pub fn topics_mut(&mut self) -> &mut HashSet<LiveTopic> {
+        &mut self.topics
+    }
+
+    fn wants(&self, event: &LiveEvent) -> bool {
+        self.topics.contains(&event.topic)
+    }
+
+    /// Waits for the next event on a subscribed topic. Returns `None` once the
+    /// feed has shut down.
+    pub async fn recv(&mut self) -> Option<LiveMessage> {
+        if let Some(missed) = self.pending_reset.take() {
+            return Some(LiveMessage::Reset { missed });
+        }
+
+        while let Some(event) = self.backlog.pop_front() {
+            self.last_id = event.id;
+            if self.wants(&event) {
+                return Some(LiveMessage::Event(event));
+            }
+        }
+
+        loop {
+            match self.receiver.recv().await {
+                // Already delivered from the backlog
+                Ok(event) if event.id <= self.last_id => continue,
+                Ok(event) => {
+                    self.last_id = event.id;
+                    if self.wants(&event) {
+                        return Some(LiveMessage::Event(event));
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    return Some(LiveMessage::Reset { missed })
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish_to(feed: &LiveFeed, topic: LiveTopic, event_type: &str) -> u64 {
+        feed.publish(topic, event_type, None, serde_json::Value::Null);
+        feed.state.lock().unwrap().next_id - 1
+    }
+
+    async fn next_event(subscription: &mut LiveSubscription) -> (u64, String) {
+        match subscription.recv().await {
+            Some(LiveMessage::Event(event)) => (event.id, event.event_type.clone()),
+            other => panic!("expected an event, got {:?}", other),
+        }
+    }
+
+    async fn next_reset(subscription: &mut LiveSubscription) -> u64 {
+        match subscription.recv().await {
+            Some(LiveMessage::Reset { missed }) => missed,
+            other => panic!("expected a reset, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_subscription_only_sees_new_events() {
+        let feed = LiveFeed::new(10, 10);
+        publish_to(&feed, LiveTopic::Orders, "order.created");
+
+        let mut subscription = feed.subscribe(HashSet::new(), None);
+        let id = publish_to(&feed, LiveTopic::Orders, "order.updated");
+
+        assert_eq!(
+            next_event(&mut subscription).await,
+            (id, "order.updated".to_string())
+        );
+        assert_eq!(subscription.topics().len(), LiveTopic::ALL.len());
+    }
+
+    #[tokio::test]
+    async fn test_resume_replays_events_after_the_last_event_id() {
+        let feed = LiveFeed::new(10, 10);
+        let first = publish_to(&feed, LiveTopic::Orders, "order.created");
+        let second = publish_to(&feed, LiveTopic::Inventory, "inventory.adjusted");
+        let third = publish_to(&feed, LiveTopic::Orders, "order.updated");
+
+        let mut subscription = feed.subscribe(HashSet::new(), Some(first));
+        let fourth = publish_to(&feed, LiveTopic::Orders, "order.shipped");
+
+        assert_eq!(next_event(&mut subscription).await.0, second);
+        assert_eq!(next_event(&mut subscription).await.0, third);
+        // The live stream picks up after the backlog without repeating it
+        assert_eq!(next_event(&mut subscription).await.0, fourth);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_the_newest_event_replays_nothing() {
+        let feed = LiveFeed::new(10, 10);
+        let newest = publish_to(&feed, LiveTopic::Orders, "order.created");
+
+        let mut subscription = feed.subscribe(HashSet::new(), Some(newest));
+        let next = publish_to(&feed, LiveTopic::Orders, "order.updated");
+
+        assert_eq!(next_event(&mut subscription).await.0, next);
+    }
+
+    #[tokio::test]
+    async fn test_resume_skips_unsubscribed_topics() {
+        let feed = LiveFeed::new(10, 10);
+        let first = publish_to(&feed, LiveTopic::Orders, "order.created");
+        publish_to(&feed, LiveTopic::Payments, "payment.captured");
+        let shipment = publish_to(&feed, LiveTopic::Shipments, "shipment.created");
+
+        let topics = HashSet::from([LiveTopic::Shipments]);
+        let mut subscription = feed.subscribe(topics, Some(first));
+        publish_to(&feed, LiveTopic::Orders, "order.updated");
+        let live = publish_to(&feed, LiveTopic::Shipments, "shipment.delivered");
+
+        assert_eq!(next_event(&mut subscription).await.0, shipment);
+        assert_eq!(next_event(&mut subscription).await.0, live);
+    }
+
+    #[tokio::test]
+    async fn test_resume_older_than_the_history_starts_with_a_reset() {
+        let feed = LiveFeed::new(2, 10);
+        let first = publish_to(&feed, LiveTopic::Orders, "order.created");
+        for _ in 0..3 {
+            publish_to(&feed, LiveTopic::Orders, "order.updated");
+        }
+
+        let mut subscription = feed.subscribe(HashSet::new(), Some(first));
+        let live = publish_to(&feed, LiveTopic::Orders, "order.shipped");
+
+        assert_eq!(next_reset(&mut subscription).await, 3);
+        assert_eq!(next_event(&mut subscription).await.0, live);
+    }
+
+    #[tokio::test]
+    async fn test_resume_from_an_unknown_future_id_starts_with_a_reset() {
+        let feed = LiveFeed::new(10, 10);
+        let newest = publish_to(&feed, LiveTopic::Orders, "order.created");
+
+        let mut subscription = feed.subscribe(HashSet::new(), Some(newest + 100));
+
+        assert_eq!(next_reset(&mut subscription).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_gets_a_reset() {
+        let feed = LiveFeed::new(10, 1);
+        let mut subscription = feed.subscribe(HashSet::new(), None);
+
+        for _ in 0..2 {
+            publish_to(&feed, LiveTopic::Orders, "order.updated");
+        }
+        let newest = publish_to(&feed, LiveTopic::Orders, "order.shipped");
+
+        assert_eq!(next_reset(&mut subscription).await, 2);
+        assert_eq!(next_event(&mut subscription).await.0, newest);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod live_feed;
+pub mod order_updates;
@@ -0,0 +1,93 @@
+use crate::config::get as get_config;
+use crate::models::entities::order::OrderStatus;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::OnceLock;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+static ORDER_UPDATES: OnceLock<broadcast::Sender<OrderUpdate>> = OnceLock::new();
+
+/// A committed order status change.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderUpdate {
+    pub order_id: Uuid,
+    pub customer_id: Uuid,
+    pub previous_status: Option<OrderStatus>,
+    pub new_status: OrderStatus,
+    pub notes: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Restricts a subscription to one order and/or one customer. An empty filter
+/// receives every update.
+#[derive(Debug, Clone, Default)]
+pub struct OrderUpdateFilter {
+    pub order_id: Option<Uuid>,
+    pub customer_id: Option<Uuid>,
+}
+
+impl OrderUpdateFilter {
+    pub fn matches(&self, update: &OrderUpdate) -> bool {
+        self.order_id.map_or(true, |id| id == update.order_id)
+            && self.customer_id.map_or(true, |id| id == update.customer_id)
+    }
+}
+
+/// What a subscriber receives: either an update, or a marker saying how many
+/// updates it missed because it fell behind. After a lag marker the subscriber
+/// should re-read the current state of the orders it cares about.
+#[derive(Debug, Clone)]
+pub enum OrderUpdateMessage {
+    Update(OrderUpdate),
+    Lagged(u64),
+}
+
+fn sender() -> &'static broadcast::Sender<OrderUpdate> {
+    ORDER_UPDATES.get_or_init(|| {
+        let (tx, _) = broadcast::channel(get_config().realtime.channel_capacity.max(1));
+        tx
+    })
+}
+
+/// Fans a status change out to every subscriber. Call this only after the
+/// change has been committed.
+pub fn publish(update: OrderUpdate) {
+    // No subscribers is not an error
+    let _ = sender().send(update);
+}
+
+pub fn subscriber_count() -> usize {
+    sender().receiver_count()
+}
+
+pub struct OrderUpdateSubscription {
+    receiver: broadcast::Receiver<OrderUpdate>,
+    filter: OrderUpdateFilter,
+}
+
+pub fn subscribe(filter: OrderUpdateFilter) -> OrderUpdateSubscription {
+    OrderUpdateSubscription {
+        receiver: sender().subscribe(),
+        filter,
+    }
+}
+
+impl OrderUpdateSubscription {
+    /// Waits for the next update matching the filter. Returns `None` once the
+    /// hub has shut down.
+    pub async fn recv(&mut self) -> Option<OrderUpdateMessage> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(update) if self.filter.matches(&update) => {
+                    return Some(OrderUpdateMessage::Update(update))
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    return Some(OrderUpdateMessage::Lagged(missed))
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
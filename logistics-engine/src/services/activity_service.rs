@@ -0,0 +1,60 @@
+use crate::db::repository::ActivityRepository;
+use crate::errors::{LogisticsError, Result};
+use crate::models::activity::{ActivityCursor, ActivityEntry, ActivityFilter, NewActivity};
+use std::sync::Arc;
+use tracing::warn;
+
+pub struct ActivityService {
+    activity_repository: Arc<ActivityRepository>,
+}
+
+impl ActivityService {
+    pub fn new(activity_repository: Arc<ActivityRepository>) -> Self {
+        Self {
+            activity_repository,
+        }
+    }
+
+    /// Records an activity after the change it describes has been committed.
+    /// Failures are logged rather than returned so the audit trail never undoes
+    /// a completed operation.
+    pub async fn record(&self, activity: NewActivity) {
+        if let Err(e) = self.activity_repository.create(&activity).await {
+            warn!(
+                "Failed to record {} activity for {} {:?}: {}",
+                activity.action, activity.entity_type, activity.entity_id, e
+            );
+        }
+    }
+
+    /// Returns up to `limit` activities, newest first, and the cursor for the
+    /// next page when there is one.
+    pub async fn list_activities(
+        &self,
+        filter: &ActivityFilter,
+        cursor: Option<&str>,
+        limit: u32,
+    ) -> Result<(Vec<ActivityEntry>, Option<String>)> {
+        let cursor = cursor
+            .map(ActivityCursor::decode)
+            .transpose()
+            .map_err(LogisticsError::ValidationError)?;
+
+        let limit = limit as usize;
+        let mut activities = self
+            .activity_repository
+            .find_page(filter, cursor, limit as i64 + 1)
+            .await?;
+
+        let next_cursor = if activities.len() > limit {
+            activities.truncate(limit);
+            activities
+                .last()
+                .map(|entry| ActivityCursor::from_entry(entry).encode())
+        } else {
+            None
+        };
+
+        Ok((activities, next_cursor))
+    }
+}
@@ -0,0 +1,606 @@
+use crate::config::AllocationStrategyKind;
+use crate::models::allocation::{
+    AllocatedLine, AllocationLine, AllocationPlan, AllocationSource, ShippingDestination,
+};
+use crate::models::inventory::InventoryLevel;
+use crate::models::warehouse::{distance_km, Warehouse};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// What a strategy sees when ranking warehouses for an order.
+pub struct AllocationRequest<'a> {
+    pub lines: &'a [AllocationLine],
+    pub destination: &'a ShippingDestination,
+    pub warehouses: &'a [Warehouse],
+    /// The order's products in every warehouse that stocks them
+    pub levels: &'a [InventoryLevel],
+}
+
+impl AllocationRequest<'_> {
+    fn available(&self, product_id: Uuid, warehouse_id: Uuid) -> i32 {
+        self.levels
+            .iter()
+            .filter(|level| level.item_id == product_id && level.warehouse_id == warehouse_id)
+            .map(|level| level.available.max(0))
+            .sum()
+    }
+
+    /// Units the order asks for.
+    pub fn units(&self) -> i32 {
+        self.lines.iter().map(|line| line.quantity).sum()
+    }
+
+    /// Units of the order the warehouse could supply on its own.
+    pub fn supply(&self, warehouse_id: Uuid) -> i32 {
+        let mut requested: HashMap<Uuid, i32> = HashMap::new();
+        for line in self.lines {
+            *requested.entry(line.product_id).or_default() += line.quantity;
+        }
+
+        requested
+            .into_iter()
+            .map(|(product_id, quantity)| quantity.min(self.available(product_id, warehouse_id)))
+            .sum()
+    }
+}
+
+/// Decides which warehouses are tried first for an order. The whole order goes
+/// to the best-ranked warehouse that can fill it; when none can, each line is
+/// split across warehouses in rank order.
+pub trait AllocationStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Lower ranks are tried first. Ties go to the warehouse that can supply
+    /// more of the order.
+    fn rank(&self, warehouse: &Warehouse, request: &AllocationRequest<'_>) -> Decimal;
+
+    /// Why the warehouse ranks where it does, completing "the warehouse ...".
+    fn describe(&self, warehouse: &Warehouse, request: &AllocationRequest<'_>) -> String;
+}
+
+/// Prefers the warehouse that can supply the most of the order, so it ships
+/// in as few parcels as possible.
+pub struct SingleWarehouseStrategy;
+
+impl AllocationStrategy for SingleWarehouseStrategy {
+    fn name(&self) -> &'static str {
+        AllocationStrategyKind::SingleWarehouse.as_str()
+    }
+
+    fn rank(&self, warehouse: &Warehouse, request: &AllocationRequest<'_>) -> Decimal {
+        Decimal::from(-request.supply(warehouse.id))
+    }
+
+    fn describe(&self, warehouse: &Warehouse, request: &AllocationRequest<'_>) -> String {
+        format!(
+            "has {} of the order's {} unit(s)",
+            request.supply(warehouse.id),
+            request.units()
+        )
+    }
+}
+
+/// Prefers the warehouse closest to the shipping address. When both ends are
+/// geocoded the distance decides; warehouses that are not rank after those
+/// that are, by how much of the address they share.
+pub struct NearestWarehouseStrategy;
+
+impl NearestWarehouseStrategy {
+    // Further than any two points on Earth are apart
+    const UNLOCATED_RANK: i64 = 100_000;
+
+    fn distance(warehouse: &Warehouse, destination: &ShippingDestination) -> Option<f64> {
+        warehouse
+            .coordinates()
+            .zip(destination.coordinates)
+            .map(|(from, to)| distance_km(from, to))
+    }
+
+    fn proximity(warehouse: &Warehouse, destination: &ShippingDestination) -> (i64, &'static str) {
+        let same = |a: &str, b: &str| a.trim().eq_ignore_ascii_case(b.trim());
+
+        if !same(&warehouse.country, &destination.country) {
+            (4, "is in another country")
+        } else if same(&warehouse.postal_code, &destination.postal_code) {
+            (0, "shares the shipping address's postal code")
+        } else if same(&warehouse.state, &destination.state)
+            && same(&warehouse.city, &destination.city)
+        {
+            (1, "is in the same city as the shipping address")
+        } else if same(&warehouse.state, &destination.state) {
+            (2, "is in the same state as the shipping address")
+        } else {
+            (3, "is in the same country as the shipping address")
+        }
+    }
+}
+
+impl AllocationStrategy for NearestWarehouseStrategy {
+    fn name(&self) -> &'static str {
+        AllocationStrategyKind::Nearest.as_str()
+    }
+
+    fn rank(&self, warehouse: &Warehouse, request: &AllocationRequest<'_>) -> Decimal {
+        match Self::distance(warehouse, request.destination) {
+            Some(distance) => Decimal::from_f64(distance).unwrap_or(Decimal::MAX),
+            None => Decimal::from(
+                Self::UNLOCATED_RANK + Self::proximity(warehouse, request.destination).0,
+            ),
+        }
+    }
+
+    fn describe(&self, warehouse: &Warehouse, request: &AllocationRequest<'_>) -> String {
+        match Self::distance(warehouse, request.destination) {
+            Some(distance) => format!("is {:.1} km from the shipping address", distance),
+            None => Self::proximity(warehouse, request.destination)
+                .1
+                .to_string(),
+        }
+    }
+}
+
+/// Prefers the warehouse that is cheapest to fulfil from.
+pub struct LowestCostStrategy;
+
+impl AllocationStrategy for LowestCostStrategy {
+    fn name(&self) -> &'static str {
+        AllocationStrategyKind::LowestCost.as_str()
+    }
+
+    fn rank(&self, warehouse: &Warehouse, _request: &AllocationRequest<'_>) -> Decimal {
+        warehouse.fulfillment_cost
+    }
+
+    fn describe(&self, warehouse: &Warehouse, _request: &AllocationRequest<'_>) -> String {
+        format!(
+            "costs {} per unit to fulfil from",
+            warehouse.fulfillment_cost
+        )
+    }
+}
+
+pub struct AllocationService {
+    strategy: Box<dyn AllocationStrategy>,
+}
+
+impl AllocationService {
+    pub fn new(strategy: Box<dyn AllocationStrategy>) -> Self {
+        Self { strategy }
+    }
+
+    /// Uses one of the built-in strategies.
+    pub fn with_kind(kind: AllocationStrategyKind) -> Self {
+        let strategy: Box<dyn AllocationStrategy> = match kind {
+            AllocationStrategyKind::SingleWarehouse => Box::new(SingleWarehouseStrategy),
+            AllocationStrategyKind::Nearest => Box::new(NearestWarehouseStrategy),
+            AllocationStrategyKind::LowestCost => Box::new(LowestCostStrategy),
+        };
+
+        Self::new(strategy)
+    }
+
+    pub fn strategy(&self) -> &'static str {
+        self.strategy.name()
+    }
+
+    /// Picks the warehouse(s) that fulfil each line. Units no active
+    /// warehouse has are left for the caller to backorder.
+    pub fn allocate(&self, request: &AllocationRequest<'_>) -> AllocationPlan {
+        let units = request.units();
+        let mut ranked = request
+            .warehouses
+            .iter()
+            .filter(|warehouse| warehouse.active)
+            .map(|warehouse| {
+                (
+                    warehouse,
+                    self.strategy.rank(warehouse, request),
+                    request.supply(warehouse.id),
+                )
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then(b.2.cmp(&a.2))
+                .then_with(|| a.0.code.cmp(&b.0.code))
+        });
+
+        if let Some((warehouse, _, _)) = ranked
+            .iter()
+            .find(|(_, _, supply)| units > 0 && *supply >= units)
+        {
+            let lines = request
+                .lines
+                .iter()
+                .map(|line| AllocatedLine {
+                    order_item_id: line.order_item_id,
+                    product_id: line.product_id,
+                    sku: line.sku.clone(),
+                    requested: line.quantity,
+                    sources: vec![AllocationSource {
+                        warehouse_id: warehouse.id,
+                        warehouse_code: warehouse.code.clone(),
+                        quantity: line.quantity,
+                    }],
+                    backordered: 0,
+                })
+                .collect();
+
+            return AllocationPlan {
+                strategy: self.strategy.name().to_string(),
+                split: false,
+                explanation: format!(
+                    "{} can fill the whole order and {}",
+                    warehouse.code,
+                    self.strategy.describe(warehouse, request)
+                ),
+                lines,
+            };
+        }
+
+        // No single site can fill the order: take each line from as many
+        // warehouses as it needs, best-ranked first
+        let mut remaining: HashMap<(Uuid, Uuid), i32> = HashMap::new();
+        for level in request.levels {
+            *remaining
+                .entry((level.item_id, level.warehouse_id))
+                .or_default() += level.available.max(0);
+        }
+
+        let mut used: Vec<&Warehouse> = Vec::new();
+        let mut lines = Vec::with_capacity(request.lines.len());
+        for line in request.lines {
+            let mut outstanding = line.quantity;
+            let mut sources = Vec::new();
+
+            for (warehouse, _, _) in &ranked {
+                if outstanding <= 0 {
+                    break;
+                }
+
+                let left = remaining
+                    .entry((line.product_id, warehouse.id))
+                    .or_default();
+                let quantity = outstanding.min(*left);
+                if quantity <= 0 {
+                    continue;
+                }
+
+                *left -= quantity;
+                outstanding -= quantity;
+                sources.push(AllocationSource {
+                    warehouse_id: warehouse.id,
+                    warehouse_code: warehouse.code.clone(),
+                    quantity,
+                });
+                if !used.iter().any(|used| used.id == warehouse.id) {
+                    used.push(warehouse);
+                }
+            }
+
+            lines.push(AllocatedLine {
+                order_item_id: line.order_item_id,
+                product_id: line.product_id,
+                sku: line.sku.clone(),
+                requested: line.quantity,
+                sources,
+                backordered: outstanding.max(0),
+            });
+        }
+
+        let mut explanation = match used.as_slice() {
+            [] => "No warehouse has stock for the order".to_string(),
+            [warehouse] => format!(
+                "No warehouse can fill the whole order; {} supplies what is in stock and {}",
+                warehouse.code,
+                self.strategy.describe(warehouse, request)
+            ),
+            used => format!(
+                "No warehouse can fill the whole order; split across {}",
+                used.iter()
+                    .map(|warehouse| format!(
+                        "{}, which {}",
+                        warehouse.code,
+                        self.strategy.describe(warehouse, request)
+                    ))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        };
+        let backordered: i32 = lines.iter().map(|line| line.backordered).sum();
+        if backordered > 0 {
+            explanation.push_str(&format!("; {} unit(s) backordered", backordered));
+        }
+
+        AllocationPlan {
+            strategy: self.strategy.name().to_string(),
+            split: used.len() > 1,
+            explanation,
+            lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn warehouse(code: &str, postal_code: &str, coordinates: Option<(f64, f64)>) -> Warehouse {
+        Warehouse {
+            id: Uuid::new_v4(),
+            name: format!("{} warehouse", code),
+            code: code.to_string(),
+            address_line1: "1 Dock Road".to_string(),
+            address_line2: None,
+            city: "Springfield".to_string(),
+            state: "IL".to_string(),
+            postal_code: postal_code.to_string(),
+            country: "US".to_string(),
+            latitude: coordinates.map(|(latitude, _)| latitude),
+            longitude: coordinates.map(|(_, longitude)| longitude),
+            contact_name: None,
+            contact_email: None,
+            contact_phone: None,
+            fulfillment_cost: Decimal::ONE,
+            active: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn level(product_id: Uuid, warehouse: &Warehouse, available: i32) -> InventoryLevel {
+        InventoryLevel {
+            item_id: product_id,
+            warehouse_id: warehouse.id,
+            sku: "SKU".to_string(),
+            name: "Item".to_string(),
+            quantity: available,
+            reserved: 0,
+            available,
+            low_stock_threshold: None,
+            last_updated: Utc::now(),
+        }
+    }
+
+    fn line(product_id: Uuid, sku: &str, quantity: i32) -> AllocationLine {
+        AllocationLine {
+            order_item_id: None,
+            product_id,
+            sku: sku.to_string(),
+            quantity,
+        }
+    }
+
+    fn destination(postal_code: &str, coordinates: Option<(f64, f64)>) -> ShippingDestination {
+        ShippingDestination {
+            city: "Springfield".to_string(),
+            state: "IL".to_string(),
+            postal_code: postal_code.to_string(),
+            country: "US".to_string(),
+            coordinates,
+        }
+    }
+
+    fn sources(plan: &AllocationPlan) -> Vec<Vec<(String, i32)>> {
+        plan.lines
+            .iter()
+            .map(|line| {
+                line.sources
+                    .iter()
+                    .map(|source| (source.warehouse_code.clone(), source.quantity))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_single_warehouse_prefers_the_site_with_the_most_stock() {
+        let product = Uuid::new_v4();
+        let small = warehouse("SMALL", "62701", None);
+        let large = warehouse("LARGE", "62702", None);
+        let warehouses = vec![small, large];
+        let [small, large] = [&warehouses[0], &warehouses[1]];
+        let levels = vec![level(product, small, 5), level(product, large, 20)];
+        let lines = vec![line(product, "A", 4)];
+        let to = destination("62701", None);
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan = AllocationService::with_kind(AllocationStrategyKind::SingleWarehouse)
+            .allocate(&request);
+
+        assert_eq!(plan.strategy, "single_warehouse");
+        assert!(!plan.split);
+        assert_eq!(sources(&plan), vec![vec![("LARGE".to_string(), 4)]]);
+        assert_eq!(plan.lines[0].backordered, 0);
+        assert!(plan
+            .explanation
+            .starts_with("LARGE can fill the whole order"));
+    }
+
+    #[test]
+    fn test_nearest_prefers_the_closest_site_that_can_fill_the_order() {
+        let product = Uuid::new_v4();
+        // Chicago and St. Louis, shipping to Springfield, IL
+        let far = warehouse("CHI", "60601", Some((41.88, -87.63)));
+        let near = warehouse("STL", "63101", Some((38.63, -90.20)));
+        let warehouses = vec![far, near];
+        let [far, near] = [&warehouses[0], &warehouses[1]];
+        let levels = vec![level(product, far, 10), level(product, near, 10)];
+        let lines = vec![line(product, "A", 3)];
+        let to = destination("62701", Some((39.80, -89.64)));
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan = AllocationService::with_kind(AllocationStrategyKind::Nearest).allocate(&request);
+
+        assert_eq!(plan.strategy, "nearest");
+        assert!(!plan.split);
+        assert_eq!(sources(&plan), vec![vec![("STL".to_string(), 3)]]);
+        assert!(plan.explanation.contains("km from the shipping address"));
+    }
+
+    #[test]
+    fn test_nearest_falls_back_to_the_address_without_coordinates() {
+        let product = Uuid::new_v4();
+        let same_city = warehouse("AAA", "62901", None);
+        let same_postal_code = warehouse("BBB", "62701", None);
+        let warehouses = vec![same_city, same_postal_code];
+        let [same_city, same_postal_code] = [&warehouses[0], &warehouses[1]];
+        let levels = vec![
+            level(product, same_city, 10),
+            level(product, same_postal_code, 10),
+        ];
+        let lines = vec![line(product, "A", 1)];
+        let to = destination("62701", None);
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan = AllocationService::with_kind(AllocationStrategyKind::Nearest).allocate(&request);
+
+        assert_eq!(sources(&plan), vec![vec![("BBB".to_string(), 1)]]);
+        assert!(plan
+            .explanation
+            .contains("shares the shipping address's postal code"));
+    }
+
+    #[test]
+    fn test_lowest_cost_prefers_the_cheapest_site_and_skips_inactive_ones() {
+        let product = Uuid::new_v4();
+        let mut dear = warehouse("DEAR", "62701", None);
+        dear.fulfillment_cost = Decimal::new(300, 2);
+        let mut cheapest = warehouse("CLOSED", "62701", None);
+        cheapest.fulfillment_cost = Decimal::new(50, 2);
+        cheapest.active = false;
+        let mut cheap = warehouse("CHEAP", "62701", None);
+        cheap.fulfillment_cost = Decimal::new(150, 2);
+        let warehouses = vec![dear, cheapest, cheap];
+        let [dear, cheapest, cheap] = [&warehouses[0], &warehouses[1], &warehouses[2]];
+        let levels = vec![
+            level(product, dear, 10),
+            level(product, cheapest, 10),
+            level(product, cheap, 10),
+        ];
+        let lines = vec![line(product, "A", 2)];
+        let to = destination("62701", None);
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan =
+            AllocationService::with_kind(AllocationStrategyKind::LowestCost).allocate(&request);
+
+        assert_eq!(plan.strategy, "lowest_cost");
+        assert_eq!(sources(&plan), vec![vec![("CHEAP".to_string(), 2)]]);
+        assert!(plan.explanation.contains("costs 1.50 per unit"));
+    }
+
+    #[test]
+    fn test_lines_are_split_when_no_site_can_fill_the_order() {
+        let first = Uuid::new_v4();
+        let second = Uuid::new_v4();
+        let mut west = warehouse("WEST", "62701", None);
+        west.fulfillment_cost = Decimal::TWO;
+        let mut east = warehouse("EAST", "62701", None);
+        east.fulfillment_cost = Decimal::ONE;
+        let warehouses = vec![west, east];
+        let [west, east] = [&warehouses[0], &warehouses[1]];
+        let levels = vec![
+            level(first, east, 3),
+            level(first, west, 5),
+            level(second, west, 2),
+        ];
+        let lines = vec![line(first, "A", 6), line(second, "B", 2)];
+        let to = destination("62701", None);
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan =
+            AllocationService::with_kind(AllocationStrategyKind::LowestCost).allocate(&request);
+
+        assert!(plan.split);
+        assert_eq!(
+            sources(&plan),
+            vec![
+                vec![("EAST".to_string(), 3), ("WEST".to_string(), 3)],
+                vec![("WEST".to_string(), 2)],
+            ]
+        );
+        assert!(plan.lines.iter().all(|line| line.backordered == 0));
+        assert!(plan.explanation.contains("split across EAST"));
+        assert!(!plan.explanation.contains("backordered"));
+    }
+
+    #[test]
+    fn test_units_no_site_has_are_backordered() {
+        let product = Uuid::new_v4();
+        let warehouses = vec![warehouse("ONLY", "62701", None)];
+        let levels = vec![level(product, &warehouses[0], 3)];
+        let lines = vec![line(product, "A", 5)];
+        let to = destination("62701", None);
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan = AllocationService::with_kind(AllocationStrategyKind::SingleWarehouse)
+            .allocate(&request);
+
+        assert!(!plan.split);
+        assert_eq!(sources(&plan), vec![vec![("ONLY".to_string(), 3)]]);
+        assert_eq!(plan.lines[0].backordered, 2);
+        assert!(plan
+            .explanation
+            .starts_with("No warehouse can fill the whole order; ONLY"));
+        assert!(plan.explanation.ends_with("2 unit(s) backordered"));
+    }
+
+    #[test]
+    fn test_orders_with_no_stock_anywhere_are_fully_backordered() {
+        let product = Uuid::new_v4();
+        let warehouses = vec![warehouse("EMPTY", "62701", None)];
+        let levels = vec![level(product, &warehouses[0], 0)];
+        let lines = vec![line(product, "A", 4)];
+        let to = destination("62701", None);
+        let request = AllocationRequest {
+            lines: &lines,
+            destination: &to,
+            warehouses: &warehouses,
+            levels: &levels,
+        };
+
+        let plan = AllocationService::with_kind(AllocationStrategyKind::Nearest).allocate(&request);
+
+        assert!(!plan.split);
+        assert!(plan.lines[0].sources.is_empty());
+        assert_eq!(plan.lines[0].backordered, 4);
+        assert_eq!(
+            plan.explanation,
+            "No warehouse has stock for the order; 4 unit(s) backordered"
+        );
+    }
+}
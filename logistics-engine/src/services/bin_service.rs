@@ -0,0 +1,453 @@
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::db::repository::{BinRepository, OrderRepository, WarehouseRepository, WaveRepository};
+use crate::errors::{LogisticsError, Result};
+use crate::models::{
+    activity::{ActivitySeverity, NewActivity},
+    bin::{
+        BinMovement, BinMovementType, CreateBinDto, MoveBinStockDto, PickList, PickListLine,
+        PutawayDto, PutawayPlan, PutawaySuggestion, UpdateBinDto, WarehouseBin,
+    },
+};
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::services::ActivityService;
+
+pub struct BinService {
+    repository: Arc<BinRepository>,
+    warehouse_repository: Arc<WarehouseRepository>,
+    order_repository: Arc<OrderRepository>,
+    wave_repository: Arc<WaveRepository>,
+    activity_service: Arc<ActivityService>,
+    pool: Pool<Postgres>,
+}
+
+impl BinService {
+    pub fn new(
+        repository: Arc<BinRepository>,
+        warehouse_repository: Arc<WarehouseRepository>,
+        order_repository: Arc<OrderRepository>,
+        wave_repository: Arc<WaveRepository>,
+        activity_service: Arc<ActivityService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            warehouse_repository,
+            order_repository,
+            wave_repository,
+            activity_service,
+            pool,
+        }
+    }
+
+    async fn ensure_warehouse(&self, warehouse_id: Uuid) -> Result<()> {
+        match self.warehouse_repository.find_by_id(warehouse_id).await? {
+            Some(_) => Ok(()),
+            None => Err(LogisticsError::NotFound(
+                "Warehouse",
+                warehouse_id.to_string(),
+            )),
+        }
+    }
+
+    // Pushes a committed putaway or move to the live feed and the activity log
+    // and hands it back
+    async fn notify(&self, message: String, movement: BinMovement) -> BinMovement {
+        let event_type = format!("inventory.bin_{}", movement.movement_type.as_str());
+        live_feed::publish(
+            LiveTopic::Inventory,
+            &event_type,
+            Some(movement.item_id.to_string()),
+            &movement,
+        );
+
+        let activity = NewActivity::new(
+            "inventory",
+            movement.item_id,
+            &event_type,
+            ActivitySeverity::Info,
+            message,
+        )
+        .with_metadata(serde_json::json!({
+            "warehouse_id": movement.warehouse_id,
+            "from_bin": movement.from_code,
+            "to_bin": movement.to_code,
+            "quantity": movement.quantity,
+        }));
+        self.activity_service.record(activity).await;
+
+        movement
+    }
+
+    pub async fn get_bins(
+        &self,
+        warehouse_id: Uuid,
+        item_id: Option<Uuid>,
+    ) -> Result<Vec<WarehouseBin>> {
+        self.ensure_warehouse(warehouse_id).await?;
+
+        self.repository
+            .find_by_warehouse(warehouse_id, item_id)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    pub async fn get_bin(&self, warehouse_id: Uuid, id: Uuid) -> Result<WarehouseBin> {
+        self.repository
+            .find_by_id(warehouse_id, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Bin", id.to_string()))
+    }
+
+    pub async fn create_bin(
+        &self,
+        warehouse_id: Uuid,
+        mut dto: CreateBinDto,
+    ) -> Result<WarehouseBin> {
+        dto.zone = dto.zone.trim().to_uppercase();
+        dto.aisle = dto.aisle.trim().to_uppercase();
+        dto.bin = dto.bin.trim().to_uppercase();
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        self.ensure_warehouse(warehouse_id).await?;
+
+        self.repository
+            .create(warehouse_id, &dto)
+            .await?
+            .ok_or_else(|| {
+                LogisticsError::Conflict(format!(
+                    "Warehouse {} already has bin {}-{}-{}",
+                    warehouse_id, dto.zone, dto.aisle, dto.bin
+                ))
+            })
+    }
+
+    pub async fn update_bin(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+        dto: UpdateBinDto,
+    ) -> Result<WarehouseBin> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let bin = self.get_bin(warehouse_id, id).await?;
+        if let Some(capacity) = dto.capacity {
+            if capacity < bin.stored {
+                return Err(LogisticsError::ValidationError(format!(
+                    "Bin {} holds {} units, more than a capacity of {}",
+                    bin.code, bin.stored, capacity
+                )));
+            }
+        }
+
+        self.repository
+            .update(warehouse_id, id, &dto)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Bin", id.to_string()))
+    }
+
+    /// Suggests bins to put units of the item away into: bins that already
+    /// hold the item and have room first, then empty bins, each in walking
+    /// order. Defaults to the item's unbinned units in the warehouse.
+    pub async fn suggest_putaway(
+        &self,
+        warehouse_id: Uuid,
+        item_id: Uuid,
+        quantity: Option<i32>,
+    ) -> Result<PutawayPlan> {
+        self.ensure_warehouse(warehouse_id).await?;
+
+        let unbinned = self.repository.find_unbinned(warehouse_id, item_id).await?;
+        let quantity = quantity.unwrap_or(unbinned).max(0);
+        let bins = self
+            .repository
+            .find_by_warehouse(warehouse_id, None)
+            .await?;
+
+        let holds_item =
+            |bin: &WarehouseBin| bin.stock.iter().any(|stock| stock.item_id == item_id);
+        let candidates = bins
+            .iter()
+            .filter(|bin| bin.active && holds_item(bin))
+            .chain(bins.iter().filter(|bin| bin.active && bin.stock.is_empty()));
+
+        let mut remaining = quantity;
+        let mut suggestions = Vec::new();
+        for bin in candidates {
+            if remaining == 0 {
+                break;
+            }
+            let take = bin.room().map_or(remaining, |room| room.min(remaining));
+            if take == 0 {
+                continue;
+            }
+            suggestions.push(PutawaySuggestion {
+                bin_id: bin.id,
+                code: bin.code.clone(),
+                quantity: take,
+                consolidates: holds_item(bin),
+            });
+            remaining -= take;
+        }
+
+        Ok(PutawayPlan {
+            item_id,
+            warehouse_id,
+            unbinned,
+            suggestions,
+            unplaced: remaining,
+        })
+    }
+
+    /// Puts received units that are not in any bin yet away into a bin.
+    pub async fn put_away(&self, warehouse_id: Uuid, dto: PutawayDto) -> Result<BinMovement> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let unbinned = self
+            .repository
+            .lock_unbinned_with_transaction(&mut tx, warehouse_id, dto.item_id)
+            .await?;
+        if dto.quantity > unbinned {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Only {} unit(s) of item {} are waiting to be put away in warehouse {}",
+                unbinned, dto.item_id, warehouse_id
+            )));
+        }
+
+        let bin = match self
+            .repository
+            .lock_bin_with_transaction(&mut tx, warehouse_id, dto.bin_id)
+            .await?
+        {
+            Some(bin) => bin,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::NotFound("Bin", dto.bin_id.to_string()));
+            }
+        };
+        if let Err(e) = Self::check_room(&bin, dto.quantity) {
+            tx.rollback().await.ok();
+            return Err(e);
+        }
+
+        self.repository
+            .add_stock_with_transaction(&mut tx, bin.id, dto.item_id, dto.quantity)
+            .await?;
+        let movement = self
+            .repository
+            .record_movement_with_transaction(
+                &mut tx,
+                warehouse_id,
+                dto.item_id,
+                None,
+                Some(bin.id),
+                dto.quantity,
+                BinMovementType::Putaway,
+                dto.reference.as_deref(),
+                dto.user_id.as_deref(),
+            )
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = format!(
+            "Put {} of {} away into bin {}",
+            movement.quantity, movement.sku, bin.code
+        );
+        Ok(self.notify(message, movement).await)
+    }
+
+    /// Moves units of an item from one bin to another in the same warehouse.
+    pub async fn move_stock(
+        &self,
+        warehouse_id: Uuid,
+        dto: MoveBinStockDto,
+    ) -> Result<BinMovement> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+        if dto.from_bin_id == dto.to_bin_id {
+            return Err(LogisticsError::ValidationError(
+                "Units must move to a different bin".to_string(),
+            ));
+        }
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        // Bins are locked in a fixed order so opposite moves cannot deadlock
+        let mut bins = HashMap::new();
+        let mut ids = [dto.from_bin_id, dto.to_bin_id];
+        ids.sort();
+        for id in ids {
+            match self
+                .repository
+                .lock_bin_with_transaction(&mut tx, warehouse_id, id)
+                .await?
+            {
+                Some(bin) => {
+                    bins.insert(id, bin);
+                }
+                None => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::NotFound("Bin", id.to_string()));
+                }
+            }
+        }
+        let from = &bins[&dto.from_bin_id];
+        let to = &bins[&dto.to_bin_id];
+
+        if let Err(e) = Self::check_room(to, dto.quantity) {
+            tx.rollback().await.ok();
+            return Err(e);
+        }
+        if !self
+            .repository
+            .remove_stock_with_transaction(&mut tx, from.id, dto.item_id, dto.quantity)
+            .await?
+        {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Bin {} holds fewer than {} unit(s) of item {}",
+                from.code, dto.quantity, dto.item_id
+            )));
+        }
+
+        self.repository
+            .add_stock_with_transaction(&mut tx, to.id, dto.item_id, dto.quantity)
+            .await?;
+        let movement = self
+            .repository
+            .record_movement_with_transaction(
+                &mut tx,
+                warehouse_id,
+                dto.item_id,
+                Some(from.id),
+                Some(to.id),
+                dto.quantity,
+                BinMovementType::Move,
+                dto.reference.as_deref(),
+                dto.user_id.as_deref(),
+            )
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = format!(
+            "Moved {} of {} from bin {} to bin {}",
+            movement.quantity, movement.sku, from.code, to.code
+        );
+        Ok(self.notify(message, movement).await)
+    }
+
+    // New stock only goes into active bins with room for it
+    fn check_room(bin: &WarehouseBin, quantity: i32) -> Result<()> {
+        if !bin.active {
+            return Err(LogisticsError::ValidationError(format!(
+                "Bin {} is inactive",
+                bin.code
+            )));
+        }
+        if let Some(room) = bin.room() {
+            if quantity > room {
+                return Err(LogisticsError::ValidationError(format!(
+                    "Bin {} has room for {} more unit(s)",
+                    bin.code, room
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_movements(
+        &self,
+        warehouse_id: Uuid,
+        bin_id: Option<Uuid>,
+        item_id: Option<Uuid>,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<BinMovement>> {
+        self.ensure_warehouse(warehouse_id).await?;
+
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.repository
+            .find_movements(warehouse_id, bin_id, item_id, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// What the order takes out of each warehouse, stop by stop along the
+    /// walking path. This is the same demand waves are built from: the bins
+    /// picked when the order went to `Processing`, then the units that are not
+    /// in any bin.
+    pub async fn get_pick_list(&self, order_id: Uuid) -> Result<PickList> {
+        if self.order_repository.find_by_id(order_id).await?.is_none() {
+            return Err(LogisticsError::NotFound("Order", order_id.to_string()));
+        }
+
+        let mut demand = self.wave_repository.find_order_demand(order_id).await?;
+        demand.sort_by(|a, b| {
+            a.warehouse_id
+                .cmp(&b.warehouse_id)
+                .then_with(|| a.walk_order.is_none().cmp(&b.walk_order.is_none()))
+                .then_with(|| a.walk_order.cmp(&b.walk_order))
+                .then_with(|| a.sku.cmp(&b.sku))
+        });
+
+        Ok(PickList {
+            order_id,
+            lines: demand
+                .into_iter()
+                .map(|line| PickListLine {
+                    warehouse_id: line.warehouse_id,
+                    bin_id: line.bin_id,
+                    code: line.code,
+                    item_id: line.item_id,
+                    sku: line.sku,
+                    name: line.name,
+                    quantity: line.quantity,
+                })
+                .collect(),
+        })
+    }
+
+    /// The codes of the bins holding each item in each warehouse, in walking
+    /// order.
+    pub async fn get_item_locations(
+        &self,
+        item_ids: &[Uuid],
+        warehouse_ids: &[Uuid],
+    ) -> Result<HashMap<(Uuid, Uuid), Vec<String>>> {
+        let locations = self
+            .repository
+            .find_locations(item_ids, warehouse_ids)
+            .await?;
+
+        let mut codes: HashMap<(Uuid, Uuid), Vec<String>> = HashMap::new();
+        for location in locations {
+            codes
+                .entry((location.item_id, location.warehouse_id))
+                .or_default()
+                .push(location.code);
+        }
+
+        Ok(codes)
+    }
+}
@@ -0,0 +1,189 @@
+use crate::config::get as get_config;
+use crate::db::repository::IdempotencyRepository;
+use crate::errors::{LogisticsError, Result};
+use crate::models::idempotency::{IdempotencyStatus, StoredResponse};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::warn;
+
+pub struct IdempotencyService {
+    idempotency_repository: Arc<IdempotencyRepository>,
+}
+
+impl IdempotencyService {
+    pub fn new(idempotency_repository: Arc<IdempotencyRepository>) -> Self {
+        Self {
+            idempotency_repository,
+        }
+    }
+
+    /// Identifies a request by what it asks for, so a retry matches the
+    /// original and a different payload under the same key does not.
+    pub fn fingerprint(scope: &str, body: &[u8]) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(scope.as_bytes());
+        hasher.update([0]);
+        hasher.update(body);
+        hex::encode(hasher.finalize())
+    }
+
+    /// Claims the key for a request. Returns the stored response when an
+    /// identical request already completed, or `None` when the request should
+    /// run. Reusing a key with a different payload is rejected, as is a retry
+    /// that arrives while the original is still running.
+    pub async fn begin(
+        &self,
+        idempotency_key: &str,
+        scope: &str,
+        fingerprint: &str,
+    ) -> Result<Option<StoredResponse>> {
+        let config = get_config();
+
+        // A request cannot run longer than the request timeout, so a key still
+        // in progress after that belongs to a request that never finished
+        let stale_after_seconds = config.server.request_timeout_seconds.max(1) as f64;
+
+        if self
+            .idempotency_repository
+            .claim(
+                idempotency_key,
+                scope,
+                fingerprint,
+                config.idempotency.key_ttl_hours,
+                stale_after_seconds,
+            )
+            .await?
+        {
+            return Ok(None);
+        }
+
+        let record = self
+            .idempotency_repository
+            .find(idempotency_key, scope)
+            .await?
+            .ok_or_else(|| {
+                LogisticsError::Conflict(format!(
+                    "Idempotency key {} changed while it was being claimed, retry the request",
+                    idempotency_key
+                ))
+            })?;
+
+        if record.fingerprint != fingerprint {
+            return Err(LogisticsError::UnprocessableEntity(format!(
+                "Idempotency key {} was already used with a different request",
+                idempotency_key
+            )));
+        }
+
+        match (record.status, record.response_status) {
+            (IdempotencyStatus::Completed, Some(status)) => Ok(Some(StoredResponse {
+                status: status as u16,
+                content_type: record.response_content_type,
+                body: record.response_body.unwrap_or_default(),
+            })),
+            _ => Err(LogisticsError::Conflict(format!(
+                "A request with idempotency key {} is still being processed",
+                idempotency_key
+            ))),
+        }
+    }
+
+    /// Stores the response replayed for retries of the request.
+    pub async fn complete(
+        &self,
+        idempotency_key: &str,
+        scope: &str,
+        response: &StoredResponse,
+    ) -> Result<()> {
+        self.idempotency_repository
+            .complete(
+                idempotency_key,
+                scope,
+                response.status as i32,
+                response.content_type.as_deref(),
+                &response.body,
+            )
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Frees the key of a request that did not produce a response worth
+    /// replaying. Failures are logged, since the key also frees itself once it
+    /// goes stale.
+    pub async fn abandon(&self, idempotency_key: &str, scope: &str) {
+        if let Err(e) = self
+            .idempotency_repository
+            .release(idempotency_key, scope)
+            .await
+        {
+            warn!(
+                "Failed to release idempotency key {} for {}: {}",
+                idempotency_key, scope, e
+            );
+        }
+    }
+
+    pub async fn purge_expired(&self) -> Result<u64> {
+        self.idempotency_repository
+            .delete_expired()
+            .await
+            .map_err(LogisticsError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdempotencyService;
+
+    const SCOPE: &str = "user-1 POST /api/v1/orders";
+
+    #[test]
+    fn test_fingerprint_is_stable_for_the_same_request() {
+        let body = br#"{"customer_id":"c-1"}"#;
+
+        let fingerprint = IdempotencyService::fingerprint(SCOPE, body);
+
+        assert_eq!(fingerprint, IdempotencyService::fingerprint(SCOPE, body));
+        assert_eq!(fingerprint.len(), 64);
+        assert!(fingerprint.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn test_fingerprint_differs_by_body() {
+        assert_ne!(
+            IdempotencyService::fingerprint(SCOPE, br#"{"quantity":1}"#),
+            IdempotencyService::fingerprint(SCOPE, br#"{"quantity":2}"#)
+        );
+        assert_ne!(
+            IdempotencyService::fingerprint(SCOPE, b""),
+            IdempotencyService::fingerprint(SCOPE, b"{}")
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_differs_by_user_method_and_path() {
+        let body = b"{}";
+        let fingerprint = IdempotencyService::fingerprint(SCOPE, body);
+
+        for scope in [
+            "user-2 POST /api/v1/orders",
+            "user-1 PUT /api/v1/orders",
+            "user-1 POST /api/v1/shipments",
+        ] {
+            assert_ne!(
+                fingerprint,
+                IdempotencyService::fingerprint(scope, body),
+                "{}",
+                scope
+            );
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_keeps_scope_and_body_apart() {
+        assert_ne!(
+            IdempotencyService::fingerprint("- POST /a", b"b"),
+            IdempotencyService::fingerprint("- POST /ab", b"")
+        );
+    }
+}
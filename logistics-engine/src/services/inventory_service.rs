@@ -1,20 +1,118 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::config::get as get_config;
+use crate::db::repository::backorder_repository::BackorderRepository;
 use crate::db::repository::inventory_repository::InventoryRepository;
 use crate::errors::{LogisticsError, Result};
+use crate::models::activity::{ActivitySeverity, NewActivity};
+use crate::models::backorder::{BacklogEntry, Backorder, UpdateBackorderDto};
+use crate::models::entities::order::OrderStatus;
 use crate::models::inventory::{
     CreateInventoryItemDto, CreateReservationDto, CreateTransactionDto, InventoryItem,
-    InventoryReservation, ReservationStatus, UpdateInventoryItemDto, UpdateReservationDto,
+    InventoryLevel, InventoryReservation, LedgerPosting, LedgerReconciliation,
+    OrderStockReservation, ReservationStatus, StockAvailability, StockLine, StockReservation,
+    TransactionType, UpdateInventoryItemDto, UpdateReservationDto, ANONYMOUS_ACTOR,
+    BACKORDER_ALLOCATOR_ACTOR, INVENTORY_API_ACTOR,
 };
+use crate::models::lot::{InventoryLot, ReceiveLotDto};
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::services::ActivityService;
+use tracing::warn;
+use validator::Validate;
+
+/// An item is low on stock once its quantity drops below its own threshold.
+pub fn is_low_stock(quantity: i32, low_stock_threshold: Option<i32>) -> bool {
+    low_stock_threshold.map_or(false, |threshold| quantity < threshold)
+}
+
+/// When a reservation made now lapses unless it is committed first.
+pub fn default_reservation_expiry() -> DateTime<Utc> {
+    Utc::now() + Duration::minutes(get_config().reservation_sweeper.reservation_ttl_minutes)
+}
 
 pub struct InventoryService {
     repository: Arc<InventoryRepository>,
+    backorder_repository: Arc<BackorderRepository>,
+    activity_service: Arc<ActivityService>,
+    pool: Pool<Postgres>,
 }
 
 impl InventoryService {
-    pub fn new(repository: Arc<InventoryRepository>) -> Self {
-        Self { repository }
+    pub fn new(
+        repository: Arc<InventoryRepository>,
+        backorder_repository: Arc<BackorderRepository>,
+        activity_service: Arc<ActivityService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            backorder_repository,
+            activity_service,
+            pool,
+        }
+    }
+
+    // Pushes an inventory change to the live feed and the activity log and
+    // hands the record back
+    async fn notify<T: serde::Serialize>(
+        &self,
+        event_type: &str,
+        id: Uuid,
+        message: String,
+        record: T,
+    ) -> T {
+        live_feed::publish(
+            LiveTopic::Inventory,
+            event_type,
+            Some(id.to_string()),
+            &record,
+        );
+
+        self.activity_service
+            .record(NewActivity::new(
+                "inventory",
+                id,
+                event_type,
+                ActivitySeverity::Info,
+                message,
+            ))
+            .await;
+
+        record
+    }
+
+    /// Records a low stock alert when a change takes the item below its
+    /// threshold. `previous_quantity` is `None` for newly created items.
+    async fn check_low_stock(&self, previous_quantity: Option<i32>, item: &InventoryItem) {
+        let was_low = previous_quantity.map_or(false, |quantity| {
+            is_low_stock(quantity, item.low_stock_threshold)
+        });
+
+        if was_low || !is_low_stock(item.quantity, item.low_stock_threshold) {
+            return;
+        }
+
+        let activity = NewActivity::new(
+            "inventory",
+            item.id,
+            "inventory.low_stock",
+            ActivitySeverity::Warning,
+            format!(
+                "Low stock alert: {} (only {} left)",
+                item.name, item.quantity
+            ),
+        )
+        .with_metadata(serde_json::json!({
+            "sku": item.sku,
+            "warehouse_id": item.warehouse_id,
+            "quantity": item.quantity,
+            "low_stock_threshold": item.low_stock_threshold,
+        }));
+        self.activity_service.record(activity).await;
     }
 
     // Inventory Item Methods
@@ -50,23 +148,45 @@ impl InventoryService {
         }
     }
 
-    pub async fn create_item(&self, dto: CreateInventoryItemDto) -> Result<InventoryItem> {
+    pub async fn create_item(
+        &self,
+        dto: CreateInventoryItemDto,
+        user_id: Option<String>,
+    ) -> Result<InventoryItem> {
         if dto.quantity < 0 {
             return Err(LogisticsError::ValidationError(
                 "Inventory item quantity cannot be negative".to_string(),
             ));
         }
 
-        self.repository
-            .create_item(dto)
-            .await
-            .map_err(LogisticsError::from)
+        // The opening quantity is posted to the ledger like any other receipt
+        let opening_quantity = dto.quantity;
+        let item = self
+            .repository
+            .create_item(CreateInventoryItemDto { quantity: 0, ..dto })
+            .await?;
+        let item = self
+            .set_quantity(
+                item,
+                opening_quantity,
+                "opening-balance",
+                user_id.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+            )
+            .await?;
+        self.repository.sync_level(item.id).await?;
+        self.check_low_stock(None, &item).await;
+
+        let message = format!("Inventory item {} ({}) created", item.name, item.sku);
+        Ok(self
+            .notify("inventory.item_created", item.id, message, item)
+            .await)
     }
 
     pub async fn update_item(
         &self,
         id: Uuid,
         dto: UpdateInventoryItemDto,
+        user_id: Option<String>,
     ) -> Result<InventoryItem> {
         if let Some(quantity) = dto.quantity {
             if quantity < 0 {
@@ -76,37 +196,299 @@ impl InventoryService {
             }
         }
 
+        let previous = self.repository.find_item_by_id(id).await?;
+        let quantity = dto.quantity;
         let updated = self.repository.update_item(id, dto).await?;
 
         match updated {
-            Some(item) => Ok(item),
+            Some(item) => {
+                let item = match quantity {
+                    Some(quantity) => {
+                        self.set_quantity(
+                            item,
+                            quantity,
+                            "item-update",
+                            user_id.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+                        )
+                        .await?
+                    }
+                    None => item,
+                };
+                self.repository.sync_level(id).await?;
+                self.check_low_stock(previous.map(|previous| previous.quantity), &item)
+                    .await;
+
+                let message = format!("Inventory item {} ({}) updated", item.name, item.sku);
+                Ok(self
+                    .notify("inventory.item_updated", id, message, item)
+                    .await)
+            }
             None => Err(LogisticsError::NotFound("Inventory Item", id.to_string())),
         }
     }
 
-    pub async fn adjust_quantity(&self, id: Uuid, quantity_delta: i32) -> Result<InventoryItem> {
-        let item = self.repository.find_item_by_id(id).await?;
+    pub async fn adjust_quantity(
+        &self,
+        id: Uuid,
+        quantity_delta: i32,
+        reference: Option<String>,
+        user_id: Option<String>,
+    ) -> Result<InventoryItem> {
+        let item = self.get_item_by_id(id).await?;
 
-        if item.is_none() {
-            return Err(LogisticsError::NotFound("Inventory Item", id.to_string()));
+        if quantity_delta == 0 {
+            return Err(LogisticsError::ValidationError(
+                "Quantity adjustment cannot be zero".to_string(),
+            ));
         }
 
-        let item = item.unwrap();
-        let new_quantity = item.quantity + quantity_delta;
-
-        if new_quantity < 0 {
+        if item.quantity + quantity_delta < 0 {
             return Err(LogisticsError::ValidationError(format!(
                 "Cannot adjust quantity by {} as it would result in negative inventory",
                 quantity_delta
             )));
         }
 
-        let adjusted = self.repository.adjust_quantity(id, quantity_delta).await?;
+        let transaction_type = if quantity_delta > 0 {
+            TransactionType::Add
+        } else {
+            TransactionType::Remove
+        };
+        let posting = LedgerPosting::new(
+            id,
+            item.warehouse_id,
+            quantity_delta.abs(),
+            transaction_type,
+            reference.unwrap_or_else(|| "adjustment".to_string()),
+            user_id.unwrap_or_else(|| ANONYMOUS_ACTOR.to_string()),
+        );
+        self.post_movement(posting).await?;
 
-        match adjusted {
-            Some(item) => Ok(item),
-            None => Err(LogisticsError::NotFound("Inventory Item", id.to_string())),
+        let adjusted = self.get_item_by_id(id).await?;
+        self.repository.sync_level(id).await?;
+        self.check_low_stock(Some(item.quantity), &adjusted).await;
+        if quantity_delta > 0 {
+            self.fill_backorders(id, item.warehouse_id).await;
+        }
+
+        let message = format!(
+            "Quantity of {} adjusted by {} to {}",
+            adjusted.name, quantity_delta, adjusted.quantity
+        );
+        Ok(self
+            .notify("inventory.quantity_adjusted", id, message, adjusted)
+            .await)
+    }
+
+    // Posts a manual stock movement to the ledger in a transaction of its own.
+    // Stock taken out is written off the lots that expire first, expired ones
+    // included.
+    async fn post_movement(
+        &self,
+        posting: LedgerPosting,
+    ) -> Result<crate::models::inventory::InventoryTransaction> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        match self
+            .repository
+            .post_transaction_with_transaction(&mut tx, &posting)
+            .await?
+        {
+            Some(transaction) => {
+                if posting.transaction_type.on_hand_delta(posting.quantity) < 0 {
+                    self.repository
+                        .consume_lots_with_transaction(
+                            &mut tx,
+                            posting.item_id,
+                            posting.warehouse_id,
+                            posting.quantity,
+                            true,
+                        )
+                        .await?;
+                }
+
+                tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+                Ok(transaction)
+            }
+            None => {
+                tx.rollback().await.ok();
+                Err(LogisticsError::ValidationError(format!(
+                    "Cannot {} {} of item {} as it would result in negative inventory",
+                    posting.transaction_type.to_string(),
+                    posting.quantity,
+                    posting.item_id
+                )))
+            }
+        }
+    }
+
+    // Brings the item's on-hand quantity to `quantity` by posting the
+    // difference to the ledger
+    async fn set_quantity(
+        &self,
+        item: InventoryItem,
+        quantity: i32,
+        reference: &str,
+        user_id: &str,
+    ) -> Result<InventoryItem> {
+        let delta = quantity - item.quantity;
+        if delta == 0 {
+            return Ok(item);
+        }
+
+        let transaction_type = if delta > 0 {
+            TransactionType::Add
+        } else {
+            TransactionType::Remove
+        };
+        self.post_movement(LedgerPosting::new(
+            item.id,
+            item.warehouse_id,
+            delta.abs(),
+            transaction_type,
+            reference,
+            user_id,
+        ))
+        .await?;
+
+        self.get_item_by_id(item.id).await
+    }
+
+    /// Reserves stock that just came into `warehouse_id` for the item's pending
+    /// backorders, oldest order first, from that warehouse. Orders already
+    /// being fulfilled have the units committed straight away, since their
+    /// other lines were committed when they moved to processing.
+    pub async fn allocate_backorders(
+        &self,
+        item_id: Uuid,
+        warehouse_id: Uuid,
+    ) -> Result<Vec<Backorder>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let pending = self
+            .backorder_repository
+            .find_pending_for_product_with_transaction(&mut tx, item_id)
+            .await?;
+        if pending.is_empty() {
+            tx.rollback().await.ok();
+            return Ok(Vec::new());
+        }
+
+        let mut available = self
+            .repository
+            .lock_reservable_level_with_transaction(&mut tx, item_id, Some(warehouse_id))
+            .await?
+            .map_or(0, |level| level.available.max(0));
+        let expires_at = default_reservation_expiry();
+        let mut allocated = Vec::new();
+
+        for (backorder, order_status) in pending {
+            if available == 0 {
+                break;
+            }
+
+            let quantity = backorder.outstanding().min(available);
+            let reservation = match self
+                .repository
+                .reserve_stock_with_transaction(
+                    &mut tx,
+                    backorder.order_id,
+                    item_id,
+                    quantity,
+                    Some(warehouse_id),
+                    expires_at,
+                    BACKORDER_ALLOCATOR_ACTOR,
+                )
+                .await?
+            {
+                Some((reservation, _)) => reservation,
+                None => break,
+            };
+
+            if matches!(
+                order_status,
+                OrderStatus::Processing
+                    | OrderStatus::PartiallyShipped
+                    | OrderStatus::Shipped
+                    | OrderStatus::PartiallyDelivered
+            ) {
+                self.repository
+                    .commit_reservation_with_transaction(
+                        &mut tx,
+                        &reservation,
+                        &format!("order:{}", backorder.order_id),
+                        BACKORDER_ALLOCATOR_ACTOR,
+                    )
+                    .await?;
+            }
+
+            available -= quantity;
+            allocated.push((
+                self.backorder_repository
+                    .allocate_with_transaction(&mut tx, backorder.id, quantity)
+                    .await?,
+                quantity,
+            ));
         }
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let mut backorders = Vec::with_capacity(allocated.len());
+        for (backorder, quantity) in allocated {
+            let message = format!(
+                "Allocated {} of {} to order {} ({} of {} backordered units filled)",
+                quantity,
+                backorder.sku,
+                backorder.order_id,
+                backorder.allocated,
+                backorder.quantity
+            );
+            backorders.push(
+                self.notify("inventory.backorder_allocated", item_id, message, backorder)
+                    .await,
+            );
+        }
+
+        Ok(backorders)
+    }
+
+    // Allocates incoming stock to backorders after a restock has already been
+    // recorded, so a failure here does not fail the restock
+    async fn fill_backorders(&self, item_id: Uuid, warehouse_id: Uuid) {
+        if let Err(e) = self.allocate_backorders(item_id, warehouse_id).await {
+            warn!("Failed to allocate backorders for item {}: {}", item_id, e);
+        }
+    }
+
+    /// Pending backorders across all orders, oldest order first.
+    pub async fn get_backlog(
+        &self,
+        product_id: Option<Uuid>,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<BacklogEntry>> {
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.backorder_repository
+            .find_backlog(product_id, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    pub async fn update_backorder(&self, id: Uuid, dto: UpdateBackorderDto) -> Result<Backorder> {
+        self.backorder_repository
+            .update_expected_restock(id, dto.expected_restock_at)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Backorder", id.to_string()))
     }
 
     pub async fn delete_item(&self, id: Uuid) -> Result<bool> {
@@ -145,28 +527,675 @@ impl InventoryService {
         }
     }
 
-    pub async fn create_reservation(
+    /// Per-warehouse stock levels; empty filters match everything.
+    pub async fn get_inventory_levels(
         &self,
-        dto: CreateReservationDto,
-    ) -> Result<InventoryReservation> {
+        item_ids: &[Uuid],
+        skus: &[String],
+        warehouse_id: Option<Uuid>,
+    ) -> Result<Vec<InventoryLevel>> {
         self.repository
-            .create_reservation(dto)
+            .find_levels(item_ids, skus, warehouse_id)
             .await
             .map_err(LogisticsError::from)
     }
 
+    /// The item's lots that still hold stock, in the order they are picked.
+    pub async fn get_item_lots(&self, item_id: Uuid) -> Result<Vec<InventoryLot>> {
+        self.get_item_by_id(item_id).await?;
+
+        self.repository
+            .find_item_lots(item_id)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Active lots with stock left that expire within `days` days.
+    pub async fn get_expiring_lots(
+        &self,
+        days: i32,
+        warehouse_id: Option<Uuid>,
+    ) -> Result<Vec<InventoryLot>> {
+        if days < 0 {
+            return Err(LogisticsError::ValidationError(
+                "Days cannot be negative".to_string(),
+            ));
+        }
+
+        self.repository
+            .find_expiring_lots(days, warehouse_id)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Receives stock of the item into a lot and allocates it to the item's
+    /// backorders.
+    pub async fn receive_lot(&self, item_id: Uuid, dto: ReceiveLotDto) -> Result<InventoryLot> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let item = self.get_item_by_id(item_id).await?;
+
+        if let Some(expires_at) = dto.expires_at {
+            if expires_at <= Utc::now().date_naive() {
+                return Err(LogisticsError::ValidationError(format!(
+                    "Lot {} has already expired",
+                    dto.lot_number
+                )));
+            }
+            if dto
+                .manufactured_at
+                .map_or(false, |manufactured_at| manufactured_at > expires_at)
+            {
+                return Err(LogisticsError::ValidationError(
+                    "A lot cannot expire before it was manufactured".to_string(),
+                ));
+            }
+        }
+
+        let warehouse_id = dto.warehouse_id.unwrap_or(item.warehouse_id);
+        if self
+            .repository
+            .find_warehouse_by_id(warehouse_id)
+            .await?
+            .is_none()
+        {
+            return Err(LogisticsError::NotFound(
+                "Warehouse",
+                warehouse_id.to_string(),
+            ));
+        }
+
+        let reference = dto
+            .reference
+            .clone()
+            .unwrap_or_else(|| format!("lot:{}", dto.lot_number));
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+        let lot = self
+            .repository
+            .receive_lot_with_transaction(
+                &mut tx,
+                item_id,
+                warehouse_id,
+                &dto,
+                &reference,
+                dto.received_by.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+            )
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        self.fill_backorders(item_id, warehouse_id).await;
+
+        let message = format!(
+            "Received {} of {} into lot {}",
+            dto.quantity, item.name, lot.lot_number
+        );
+        Ok(self
+            .notify("inventory.lot_received", item_id, message, lot)
+            .await)
+    }
+
+    /// Expires every lot that has reached its expiry date, blocking its
+    /// unreserved units from allocation.
+    pub async fn expire_lots(&self) -> Result<Vec<InventoryLot>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+        let expired = self
+            .repository
+            .expire_lots_with_transaction(&mut tx, &[])
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        for lot in &expired {
+            live_feed::publish(
+                LiveTopic::Inventory,
+                "inventory.lot_expired",
+                Some(lot.item_id.to_string()),
+                lot,
+            );
+
+            let activity = NewActivity::new(
+                "inventory",
+                lot.item_id,
+                "inventory.lot_expired",
+                ActivitySeverity::Warning,
+                format!(
+                    "Lot {} of {} expired with {} units blocked",
+                    lot.lot_number,
+                    lot.sku,
+                    lot.quantity - lot.reserved
+                ),
+            )
+            .with_metadata(serde_json::json!({
+                "lot_id": lot.id,
+                "warehouse_id": lot.warehouse_id,
+                "expires_at": lot.expires_at,
+                "quantity": lot.quantity,
+                "reserved": lot.reserved,
+            }));
+            self.activity_service.record(activity).await;
+        }
+
+        Ok(expired)
+    }
+
+    /// Moves `quantity` units of the item from available to reserved for the
+    /// order. Fails without reserving anything when not enough are available.
+    pub async fn reserve_stock(
+        &self,
+        order_id: Uuid,
+        item_id: Uuid,
+        quantity: i32,
+        expires_at: Option<DateTime<Utc>>,
+        user_id: &str,
+    ) -> Result<StockReservation> {
+        if quantity <= 0 {
+            return Err(LogisticsError::ValidationError(
+                "Reserved quantity must be greater than 0".to_string(),
+            ));
+        }
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let reserved = self
+            .repository
+            .reserve_stock_with_transaction(
+                &mut tx,
+                order_id,
+                item_id,
+                quantity,
+                None,
+                expires_at.unwrap_or_else(default_reservation_expiry),
+                user_id,
+            )
+            .await?;
+
+        match reserved {
+            Some((reservation, _)) => {
+                tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+                Ok(reservation)
+            }
+            None => {
+                tx.rollback().await.ok();
+                Err(LogisticsError::ValidationError(format!(
+                    "Insufficient available stock for item {}",
+                    item_id
+                )))
+            }
+        }
+    }
+
+    /// Ships the reservation's units out of stock.
+    pub async fn commit_reservation(&self, id: Uuid, user_id: &str) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let reservation = self
+            .repository
+            .find_stock_reservation_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Reservation", id.to_string()))?;
+
+        let committed = self
+            .repository
+            .commit_reservation_with_transaction(
+                &mut tx,
+                &reservation,
+                &format!("reservation:{}", id),
+                user_id,
+            )
+            .await?;
+
+        if !committed {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Insufficient available stock to commit reservation {}",
+                id
+            )));
+        }
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)
+    }
+
+    /// Returns the reservation's units to available stock.
+    pub async fn release_reservation(&self, id: Uuid, user_id: &str) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let reservation = self
+            .repository
+            .find_stock_reservation_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Reservation", id.to_string()))?;
+
+        self.repository
+            .release_reservation_with_transaction(
+                &mut tx,
+                &reservation,
+                &format!("reservation:{}", id),
+                user_id,
+            )
+            .await?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)
+    }
+
+    // Levels the lines draw from, keyed by product
+    async fn line_levels(
+        &self,
+        lines: &[StockLine],
+        warehouse_id: Option<Uuid>,
+    ) -> Result<HashMap<Uuid, InventoryLevel>> {
+        let item_ids = lines.iter().map(|line| line.product_id).collect::<Vec<_>>();
+
+        let levels = self
+            .repository
+            .find_reservable_levels(&item_ids, warehouse_id)
+            .await?;
+
+        Ok(levels
+            .into_iter()
+            .map(|level| (level.item_id, level))
+            .collect())
+    }
+
+    fn shortfall(line: &StockLine, level: Option<&InventoryLevel>) -> StockAvailability {
+        match level {
+            Some(level) => StockAvailability {
+                product_id: line.product_id,
+                sku: level.sku.clone(),
+                requested: line.quantity,
+                available: level.available,
+                in_stock: false,
+                error: Some(format!(
+                    "Requested {} but only {} available",
+                    line.quantity, level.available
+                )),
+            },
+            None => StockAvailability {
+                product_id: line.product_id,
+                sku: String::new(),
+                requested: line.quantity,
+                available: 0,
+                in_stock: false,
+                error: Some(format!(
+                    "Product {} is not stocked in this warehouse",
+                    line.product_id
+                )),
+            },
+        }
+    }
+
+    /// Reserves every line for the order in a single transaction, so either all
+    /// of them are held or none are. Calling it again for an order that already
+    /// holds stock returns the existing reservations instead of reserving twice.
+    pub async fn reserve_order_stock(
+        &self,
+        order_id: Uuid,
+        lines: &[StockLine],
+        warehouse_id: Option<Uuid>,
+    ) -> Result<OrderStockReservation> {
+        if lines.is_empty() {
+            return Err(LogisticsError::ValidationError(
+                "At least one item is required".to_string(),
+            ));
+        }
+        if let Some(line) = lines.iter().find(|line| line.quantity <= 0) {
+            return Err(LogisticsError::ValidationError(format!(
+                "Quantity for product {} must be greater than 0",
+                line.product_id
+            )));
+        }
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let held = self
+            .repository
+            .find_stock_reservations_for_order_with_transaction(&mut tx, order_id)
+            .await?
+            .into_iter()
+            .filter(|reservation| {
+                matches!(
+                    reservation.status,
+                    ReservationStatus::Pending | ReservationStatus::Confirmed
+                )
+            })
+            .collect::<Vec<_>>();
+
+        if !held.is_empty() {
+            tx.rollback().await.ok();
+
+            let levels = self.line_levels(lines, warehouse_id).await?;
+            let lines = lines
+                .iter()
+                .map(|line| {
+                    let level = levels.get(&line.product_id);
+                    if held.iter().any(|r| r.product_id == line.product_id) {
+                        StockAvailability {
+                            product_id: line.product_id,
+                            sku: level.map(|level| level.sku.clone()).unwrap_or_default(),
+                            requested: line.quantity,
+                            available: level.map_or(0, |level| level.available),
+                            in_stock: true,
+                            error: None,
+                        }
+                    } else {
+                        StockAvailability {
+                            error: Some(
+                                "Not part of the existing reservation for this order".to_string(),
+                            ),
+                            ..Self::shortfall(line, level)
+                        }
+                    }
+                })
+                .collect();
+
+            return Ok(OrderStockReservation {
+                order_id,
+                reserved: true,
+                reservations: held,
+                lines,
+            });
+        }
+
+        let expires_at = default_reservation_expiry();
+        let mut reservations = Vec::with_capacity(lines.len());
+        let mut availability = Vec::with_capacity(lines.len());
+
+        for line in lines {
+            let reserved = self
+                .repository
+                .reserve_stock_with_transaction(
+                    &mut tx,
+                    order_id,
+                    line.product_id,
+                    line.quantity,
+                    warehouse_id,
+                    expires_at,
+                    INVENTORY_API_ACTOR,
+                )
+                .await?;
+
+            match reserved {
+                Some((reservation, level)) => {
+                    availability.push(Some(StockAvailability {
+                        product_id: line.product_id,
+                        sku: level.sku,
+                        requested: line.quantity,
+                        available: level.available + line.quantity,
+                        in_stock: true,
+                        error: None,
+                    }));
+                    reservations.push(reservation);
+                }
+                None => availability.push(None),
+            }
+        }
+
+        if availability.iter().any(Option::is_none) {
+            tx.rollback().await.ok();
+
+            // Short lines report what is available now that nothing is held
+            let levels = self.line_levels(lines, warehouse_id).await?;
+            let lines = lines
+                .iter()
+                .zip(availability)
+                .map(|(line, covered)| {
+                    covered.unwrap_or_else(|| Self::shortfall(line, levels.get(&line.product_id)))
+                })
+                .collect();
+
+            return Ok(OrderStockReservation {
+                order_id,
+                reserved: false,
+                reservations: Vec::new(),
+                lines,
+            });
+        }
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        for reservation in &reservations {
+            let message = format!(
+                "Reserved {} x {} for order {}",
+                reservation.quantity, reservation.sku, order_id
+            );
+            self.notify(
+                "inventory.reservation_created",
+                reservation.id,
+                message,
+                reservation,
+            )
+            .await;
+        }
+
+        Ok(OrderStockReservation {
+            order_id,
+            reserved: true,
+            reservations,
+            lines: availability.into_iter().flatten().collect(),
+        })
+    }
+
+    /// Commits every reservation of the order in one transaction. Fails without
+    /// committing any of them when a lapsed reservation can no longer be covered.
+    pub async fn commit_order_stock(&self, order_id: Uuid) -> Result<Vec<StockReservation>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let reservations = self
+            .repository
+            .find_stock_reservations_for_order_with_transaction(&mut tx, order_id)
+            .await?;
+
+        if reservations.is_empty() {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::NotFound(
+                "Order reservation",
+                order_id.to_string(),
+            ));
+        }
+
+        let reference = format!("order:{}", order_id);
+        let mut committed = Vec::new();
+        for reservation in &reservations {
+            if reservation.status == ReservationStatus::Confirmed {
+                continue;
+            }
+
+            let covered = self
+                .repository
+                .commit_reservation_with_transaction(
+                    &mut tx,
+                    reservation,
+                    &reference,
+                    INVENTORY_API_ACTOR,
+                )
+                .await?;
+
+            if !covered {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Insufficient available stock to commit reservation {}",
+                    reservation.id
+                )));
+            }
+
+            committed.push(StockReservation {
+                status: ReservationStatus::Confirmed,
+                ..reservation.clone()
+            });
+        }
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        for reservation in &committed {
+            let message = format!(
+                "Committed {} x {} for order {}",
+                reservation.quantity, reservation.sku, order_id
+            );
+            self.notify(
+                "inventory.reservation_committed",
+                reservation.id,
+                message,
+                reservation,
+            )
+            .await;
+        }
+
+        Ok(committed)
+    }
+
+    /// Releases the order's pending reservations and returns the ones that were
+    /// released. Committed stock has already left and is not touched.
+    pub async fn release_order_stock(
+        &self,
+        order_id: Uuid,
+        reason: &str,
+    ) -> Result<Vec<StockReservation>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let reservations = self
+            .repository
+            .find_stock_reservations_for_order_with_transaction(&mut tx, order_id)
+            .await?;
+
+        let reference = format!("order:{}", order_id);
+        let mut released = Vec::new();
+        for reservation in &reservations {
+            if reservation.status != ReservationStatus::Pending {
+                continue;
+            }
+
+            self.repository
+                .release_reservation_with_transaction(
+                    &mut tx,
+                    reservation,
+                    &reference,
+                    INVENTORY_API_ACTOR,
+                )
+                .await?;
+
+            released.push(StockReservation {
+                status: ReservationStatus::Released,
+                ..reservation.clone()
+            });
+        }
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        for reservation in &released {
+            let message = format!(
+                "Released {} x {} held for order {}: {}",
+                reservation.quantity, reservation.sku, order_id, reason
+            );
+            self.notify(
+                "inventory.reservation_released",
+                reservation.id,
+                message,
+                reservation,
+            )
+            .await;
+        }
+
+        Ok(released)
+    }
+
+    pub async fn create_reservation(
+        &self,
+        dto: CreateReservationDto,
+        user_id: Option<String>,
+    ) -> Result<InventoryReservation> {
+        let reserved = self
+            .reserve_stock(
+                dto.order_id,
+                dto.product_id,
+                dto.quantity,
+                dto.expires_at,
+                user_id.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+            )
+            .await?;
+        let reservation = self.get_reservation_by_id(reserved.id).await?;
+
+        let message = format!(
+            "Reserved {} x {} for order {}",
+            reservation.quantity, reservation.sku, reservation.order_id
+        );
+        Ok(self
+            .notify(
+                "inventory.reservation_created",
+                reservation.id,
+                message,
+                reservation,
+            )
+            .await)
+    }
+
     pub async fn update_reservation_status(
         &self,
         id: Uuid,
         status: ReservationStatus,
+        user_id: Option<String>,
     ) -> Result<InventoryReservation> {
-        let updated = self
-            .repository
-            .update_reservation_status(id, status)
-            .await?;
+        let user_id = user_id.as_deref().unwrap_or(ANONYMOUS_ACTOR);
+        // Confirming or releasing moves stock, the other statuses are labels
+        let updated = match status {
+            ReservationStatus::Confirmed => {
+                self.commit_reservation(id, user_id).await?;
+                self.repository.find_reservation_by_id(id).await?
+            }
+            ReservationStatus::Released => {
+                self.release_reservation(id, user_id).await?;
+                self.repository.find_reservation_by_id(id).await?
+            }
+            _ => {
+                self.repository
+                    .update_reservation_status(id, status)
+                    .await?
+            }
+        };
 
         match updated {
-            Some(reservation) => Ok(reservation),
+            Some(reservation) => {
+                let message = format!("Reservation {} is now {}", id, reservation.status);
+                Ok(self
+                    .notify(
+                        "inventory.reservation_status_changed",
+                        id,
+                        message,
+                        reservation,
+                    )
+                    .await)
+            }
             None => Err(LogisticsError::NotFound("Reservation", id.to_string())),
         }
     }
@@ -184,10 +1213,16 @@ impl InventoryService {
         }
     }
 
-    pub async fn delete_reservation(&self, id: Uuid) -> Result<bool> {
-        let reservation = self.repository.find_reservation_by_id(id).await?;
-        if reservation.is_none() {
-            return Err(LogisticsError::NotFound("Reservation", id.to_string()));
+    pub async fn delete_reservation(&self, id: Uuid, user_id: Option<String>) -> Result<bool> {
+        let reservation = match self.repository.find_reservation_by_id(id).await? {
+            Some(reservation) => reservation,
+            None => return Err(LogisticsError::NotFound("Reservation", id.to_string())),
+        };
+
+        // Units still held by a pending reservation go back to available stock
+        if reservation.get_status() == ReservationStatus::Pending {
+            self.release_reservation(id, user_id.as_deref().unwrap_or(ANONYMOUS_ACTOR))
+                .await?;
         }
 
         self.repository
@@ -239,18 +1274,22 @@ impl InventoryService {
             .map_err(LogisticsError::DatabaseError)
     }
 
+    /// Posts a manual `add` or `remove` to the ledger. Allocations, releases
+    /// and transfer legs are only posted by the workflows that own them.
     pub async fn create_transaction(
         &self,
         dto: CreateTransactionDto,
     ) -> Result<crate::models::inventory::InventoryTransaction> {
         let item_id = dto.item_id;
-        let item = self.repository.find_item_by_id(item_id).await?;
-        if item.is_none() {
-            return Err(LogisticsError::NotFound(
-                "Inventory Item",
-                item_id.to_string(),
-            ));
-        }
+        let item = match self.repository.find_item_by_id(item_id).await? {
+            Some(item) => item,
+            None => {
+                return Err(LogisticsError::NotFound(
+                    "Inventory Item",
+                    item_id.to_string(),
+                ))
+            }
+        };
 
         let warehouse = self
             .repository
@@ -263,29 +1302,118 @@ impl InventoryService {
             ));
         }
 
-        let transaction = self.repository.create_transaction(dto).await?;
-
-        let quantity_delta = match transaction.transaction_type.as_str() {
-            "add" | "release" => transaction.quantity,
-            "remove" | "allocate" => -transaction.quantity,
-            _ => {
+        let transaction_type = TransactionType::ALL
+            .into_iter()
+            .find(|transaction_type| {
+                transaction_type
+                    .to_string()
+                    .eq_ignore_ascii_case(dto.transaction_type.trim())
+            })
+            .ok_or_else(|| {
+                LogisticsError::ValidationError("Invalid transaction type".to_string())
+            })?;
+        match transaction_type {
+            TransactionType::Add | TransactionType::Remove => {}
+            TransactionType::Allocate | TransactionType::Release => {
                 return Err(LogisticsError::ValidationError(
-                    "Invalid transaction type".to_string(),
+                    "Allocate and release transactions are recorded by reservations".to_string(),
                 ))
             }
-        };
+            // Transfer legs are only written in pairs by the transfer workflow
+            TransactionType::TransferOut | TransactionType::TransferIn => {
+                return Err(LogisticsError::ValidationError(
+                    "Transfer transactions are recorded by dispatching and receiving stock transfers"
+                        .to_string(),
+                ))
+            }
+        }
 
-        if let Some(_) = self
-            .repository
-            .adjust_quantity(item_id, quantity_delta)
-            .await?
-        {
-            Ok(transaction)
-        } else {
-            Err(LogisticsError::NotFound(
-                "Inventory Item",
-                item_id.to_string(),
-            ))
+        if dto.quantity <= 0 {
+            return Err(LogisticsError::ValidationError(
+                "Transaction quantity must be positive".to_string(),
+            ));
         }
+
+        let posting = LedgerPosting::new(
+            item_id,
+            dto.warehouse_id,
+            dto.quantity,
+            transaction_type,
+            dto.reference.unwrap_or_else(|| "manual".to_string()),
+            dto.user_id.unwrap_or_else(|| ANONYMOUS_ACTOR.to_string()),
+        );
+        let transaction = self.post_movement(posting).await?;
+        let adjusted = self.get_item_by_id(item_id).await?;
+
+        self.repository.sync_level(item_id).await?;
+        self.check_low_stock(Some(item.quantity), &adjusted).await;
+        if transaction_type.on_hand_delta(transaction.quantity) > 0 {
+            self.fill_backorders(item_id, transaction.warehouse_id)
+                .await;
+        }
+
+        let message = format!(
+            "Recorded {} of {} for {}",
+            transaction.transaction_type, transaction.quantity, adjusted.name
+        );
+        Ok(self
+            .notify(
+                "inventory.transaction_recorded",
+                transaction.id,
+                message,
+                transaction,
+            )
+            .await)
+    }
+
+    /// Items whose on-hand quantity disagrees with the sum of their ledger
+    /// entries.
+    pub async fn reconcile_ledger(&self) -> Result<LedgerReconciliation> {
+        let (items_checked, discrepancies) = self.repository.find_ledger_discrepancies().await?;
+
+        if !discrepancies.is_empty() {
+            let activity = NewActivity::new(
+                "inventory",
+                Uuid::nil(),
+                "inventory.ledger_drift",
+                ActivitySeverity::Warning,
+                format!(
+                    "Inventory ledger disagrees with on-hand quantity for {} of {} items",
+                    discrepancies.len(),
+                    items_checked
+                ),
+            )
+            .with_metadata(serde_json::json!({
+                "skus": discrepancies
+                    .iter()
+                    .map(|discrepancy| discrepancy.sku.as_str())
+                    .collect::<Vec<_>>(),
+            }));
+            self.activity_service.record(activity).await;
+        }
+
+        Ok(LedgerReconciliation {
+            checked_at: Utc::now(),
+            items_checked,
+            discrepancies,
+        })
+    }
+
+    /// The item's ledger entries with their running balance, latest first.
+    pub async fn get_item_ledger(
+        &self,
+        item_id: Uuid,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<crate::models::inventory::InventoryTransaction>> {
+        self.get_item_by_id(item_id).await?;
+
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.repository
+            .find_item_transactions(item_id, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
     }
 }
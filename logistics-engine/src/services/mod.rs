@@ -1,17 +1,37 @@
+pub mod activity_service;
+pub mod allocation_service;
 pub mod analytics_service;
+pub mod bin_service;
 pub mod customer_service;
+pub mod idempotency_service;
 pub mod inventory_service;
 pub mod order_producer_service;
 pub mod order_service;
+pub mod outbox_relay_service;
 pub mod payment_service;
+pub mod reservation_sweeper_service;
+pub mod return_service;
+pub mod serial_service;
 pub mod shipping_service;
+pub mod transfer_service;
 pub mod warehouse_service;
+pub mod wave_service;
 
+pub use activity_service::ActivityService;
+pub use allocation_service::AllocationService;
 pub use analytics_service::AnalyticsService;
+pub use bin_service::BinService;
 pub use customer_service::CustomerService;
+pub use idempotency_service::IdempotencyService;
 pub use inventory_service::InventoryService;
 pub use order_producer_service::OrderProducerService;
 pub use order_service::OrderService;
+pub use outbox_relay_service::OutboxRelayService;
 pub use payment_service::PaymentService;
+pub use reservation_sweeper_service::ReservationSweeperService;
+pub use return_service::ReturnService;
+pub use serial_service::SerialService;
 pub use shipping_service::ShippingService;
+pub use transfer_service::TransferService;
 pub use warehouse_service::WarehouseService;
+pub use wave_service::WaveService;
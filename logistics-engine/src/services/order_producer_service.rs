@@ -236,7 +236,7 @@ impl OrderProducerService {
             {
                 Ok(order_dto) => {
                     if let Some(order_service) = &order_service {
-                        match order_service.create_order(order_dto).await {
+                        match order_service.create_order(order_dto, None).await {
                             Ok(order) => {
                                 info!("Created order: {}", order.id);
                                 successful_orders += 1;
@@ -411,6 +411,7 @@ impl OrderProducerService {
             )),
             shipping_method: Self::random_shipping_method(),
             shipping_cost: rng.gen_range(5.0..20.0),
+            items: Vec::new(),
         };
 
         let payment_info = CreatePaymentInfoDto {
@@ -1,27 +1,53 @@
+use crate::config::{get as get_config, InventoryUnavailablePolicy};
 use crate::db::repository::{
-    OrderItemRepository, OrderRepository, PaymentRepository, ShippingRepository,
+    ActivityRepository, BackorderRepository, CancellationRepository, InventoryRepository,
+    OrderItemRepository, OrderRepository, OutboxRepository, PaymentRepository, SerialRepository,
+    ShippingRepository, WarehouseRepository, WaveRepository,
 };
 use crate::errors::{LogisticsError, Result};
-use crate::grpc::inventory;
+use crate::grpc::inventory::{self, client::InventoryClient};
 use crate::models::order_item::OrderItem;
 use crate::models::{
-    dto::order::{CreateOrderDto, UpdateOrderDto},
+    activity::{ActivitySeverity, NewActivity},
+    allocation::{AllocationLine, AllocationPlan, OrderAllocation, ShippingDestination},
+    backorder::Backorder,
+    cancellation::{
+        CancellationStatus, CancellationStep, OrderCancellation, RemoteReservation, StepStatus,
+    },
+    dto::order::{
+        CreateOrderDto, OrderListFilter, OrderPriorityDto, OrderTransitionsDto, UpdateOrderDto,
+    },
     dto::payment::CreatePaymentInfoDto,
     dto::shipping::CreateShippingInfoDto,
-    entities::order::{Order, OrderStatus},
+    entities::order::{Order, OrderDetails, OrderStatus},
+    entities::order_status_history::OrderStatusHistory,
+    entities::shipment_item::ShipmentItem,
+    entities::shipping_info::Shipment,
+    inventory::{InventoryLevel, ORDER_SERVICE_ACTOR},
+    outbox::NewOutboxEvent,
+    payment::PaymentStatus,
+    shipping::ShippingStatus,
 };
 use crate::mq::events::{
     EventType, OrderCancelledEvent, OrderCreatedEvent, OrderStatusChangedEvent,
 };
 use crate::mq::publisher;
 use crate::proto::inventory::ProductItem;
-use chrono;
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::realtime::order_updates::{self, OrderUpdate};
+use crate::services::allocation_service::{AllocationRequest, AllocationService};
+use crate::services::inventory_service::{default_reservation_expiry, is_low_stock};
+use crate::services::payment_service::PaymentService;
+use chrono::{Duration, Utc};
 use num_traits::FromPrimitive;
 use rust_decimal::Decimal;
+use serde::Serialize;
 use sqlx::types::BigDecimal;
 use sqlx::{Pool, Postgres, Transaction};
+use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use tonic::Status;
 use tracing::{info, warn};
 use uuid::Uuid;
 
@@ -30,15 +56,55 @@ pub struct OrderService {
     order_item_repository: Arc<OrderItemRepository>,
     payment_repository: Arc<PaymentRepository>,
     shipping_repository: Arc<ShippingRepository>,
+    inventory_repository: Arc<InventoryRepository>,
+    outbox_repository: Arc<OutboxRepository>,
+    activity_repository: Arc<ActivityRepository>,
+    cancellation_repository: Arc<CancellationRepository>,
+    backorder_repository: Arc<BackorderRepository>,
+    warehouse_repository: Arc<WarehouseRepository>,
+    serial_repository: Arc<SerialRepository>,
+    wave_repository: Arc<WaveRepository>,
+    allocation_service: Arc<AllocationService>,
+    payment_service: Arc<PaymentService>,
     pool: Pool<Postgres>,
 }
 
+/// Outcome of asking the inventory service to reserve stock for a new order.
+enum InventoryCheck {
+    /// Nothing to check, or the stock is checked locally
+    Skipped,
+    /// The inventory service holds reservations for the order, one for each
+    /// warehouse the allocation plan draws on
+    Reserved(Vec<RemoteHold>),
+    /// The service could not be reached and the check is deferred
+    Deferred(String),
+}
+
+/// Stock the inventory service reserved for a new order in one warehouse.
+struct RemoteHold {
+    warehouse_id: Uuid,
+    reservation_id: String,
+    remote_order_id: String,
+    /// Units held, by product
+    units: HashMap<Uuid, i32>,
+}
+
 impl OrderService {
     pub fn new(
         order_repository: Arc<OrderRepository>,
         order_item_repository: Arc<OrderItemRepository>,
         payment_repository: Arc<PaymentRepository>,
         shipping_repository: Arc<ShippingRepository>,
+        inventory_repository: Arc<InventoryRepository>,
+        outbox_repository: Arc<OutboxRepository>,
+        activity_repository: Arc<ActivityRepository>,
+        cancellation_repository: Arc<CancellationRepository>,
+        backorder_repository: Arc<BackorderRepository>,
+        warehouse_repository: Arc<WarehouseRepository>,
+        serial_repository: Arc<SerialRepository>,
+        wave_repository: Arc<WaveRepository>,
+        allocation_service: Arc<AllocationService>,
+        payment_service: Arc<PaymentService>,
         pool: Pool<Postgres>,
     ) -> Self {
         Self {
@@ -46,6 +112,16 @@ impl OrderService {
             order_item_repository,
             payment_repository,
             shipping_repository,
+            inventory_repository,
+            outbox_repository,
+            activity_repository,
+            cancellation_repository,
+            backorder_repository,
+            warehouse_repository,
+            serial_repository,
+            wave_repository,
+            allocation_service,
+            payment_service,
             pool,
         }
     }
@@ -82,6 +158,220 @@ impl OrderService {
         }
     }
 
+    pub async fn get_order_details(&self, id: Uuid) -> Result<OrderDetails> {
+        let order = self.get_order_by_id(id).await?;
+        self.load_order_details(order).await
+    }
+
+    pub async fn load_order_details(&self, order: Order) -> Result<OrderDetails> {
+        let items = self
+            .order_item_repository
+            .find_by_order_id(order.id)
+            .await?;
+        let shipments = self.load_shipments(order.id).await?;
+        // Payments come back newest first
+        let payment_info = self
+            .payment_repository
+            .find_by_order_id(order.id)
+            .await?
+            .into_iter()
+            .next();
+
+        Ok(OrderDetails {
+            order,
+            items,
+            shipments,
+            payment_info,
+        })
+    }
+
+    /// Details of a page of orders, loaded with one query per kind of detail
+    /// rather than per order.
+    pub async fn load_orders_details(&self, orders: Vec<Order>) -> Result<Vec<OrderDetails>> {
+        let order_ids = orders.iter().map(|order| order.id).collect::<Vec<_>>();
+
+        let mut items: HashMap<Uuid, Vec<OrderItem>> = HashMap::new();
+        for item in self
+            .order_item_repository
+            .find_by_order_ids(&order_ids)
+            .await?
+        {
+            items.entry(item.order_id).or_default().push(item);
+        }
+
+        let mut payments = self
+            .payment_repository
+            .find_latest_by_order_ids(&order_ids)
+            .await?
+            .into_iter()
+            .map(|payment| (payment.order_id, payment))
+            .collect::<HashMap<_, _>>();
+
+        let shipping = self
+            .shipping_repository
+            .find_by_order_ids(&order_ids)
+            .await?;
+        let shipping_ids = shipping
+            .iter()
+            .map(|shipping| shipping.id)
+            .collect::<Vec<_>>();
+        let mut shipment_items: HashMap<Uuid, Vec<ShipmentItem>> = HashMap::new();
+        for item in self.shipping_repository.find_items(&shipping_ids).await? {
+            shipment_items
+                .entry(item.shipping_id)
+                .or_default()
+                .push(item);
+        }
+        let mut shipments: HashMap<Uuid, Vec<Shipment>> = HashMap::new();
+        for shipping_info in shipping {
+            shipments
+                .entry(shipping_info.order_id)
+                .or_default()
+                .push(Shipment {
+                    items: shipment_items.remove(&shipping_info.id).unwrap_or_default(),
+                    shipping_info,
+                });
+        }
+
+        Ok(orders
+            .into_iter()
+            .map(|order| OrderDetails {
+                items: items.remove(&order.id).unwrap_or_default(),
+                shipments: shipments.remove(&order.id).unwrap_or_default(),
+                payment_info: payments.remove(&order.id),
+                order,
+            })
+            .collect())
+    }
+
+    async fn load_shipments(&self, order_id: Uuid) -> Result<Vec<Shipment>> {
+        let shipments = self.shipping_repository.find_by_order_id(order_id).await?;
+        let shipping_ids = shipments
+            .iter()
+            .map(|shipping| shipping.id)
+            .collect::<Vec<_>>();
+        let items = self.shipping_repository.find_items(&shipping_ids).await?;
+
+        Ok(shipments
+            .into_iter()
+            .map(|shipping_info| Shipment {
+                items: items
+                    .iter()
+                    .filter(|item| item.shipping_id == shipping_info.id)
+                    .cloned()
+                    .collect(),
+                shipping_info,
+            })
+            .collect())
+    }
+
+    /// The order's shipments and the items each one carries, oldest first.
+    pub async fn get_order_shipments(&self, order_id: Uuid) -> Result<Vec<Shipment>> {
+        self.get_order_by_id(order_id).await?;
+        self.load_shipments(order_id).await
+    }
+
+    /// The order's backorders in any status, oldest first.
+    pub async fn get_order_backorders(&self, order_id: Uuid) -> Result<Vec<Backorder>> {
+        self.get_order_by_id(order_id).await?;
+
+        self.backorder_repository
+            .find_by_order_id(order_id)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Which warehouses fulfil the order and why they were picked.
+    pub async fn get_order_allocation(&self, order_id: Uuid) -> Result<OrderAllocation> {
+        self.get_order_by_id(order_id).await?;
+
+        self.order_repository
+            .find_allocation(order_id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Order allocation", order_id.to_string()))
+    }
+
+    /// Runs the allocation strategy over the given stock levels.
+    async fn plan_allocation(
+        &self,
+        lines: &[AllocationLine],
+        destination: &ShippingDestination,
+        levels: &[InventoryLevel],
+    ) -> Result<AllocationPlan> {
+        let mut destination = destination.clone();
+        if destination.coordinates.is_none() {
+            destination.coordinates = self
+                .warehouse_repository
+                .find_postal_code_location(&destination.postal_code, Some(&destination.country))
+                .await?
+                .map(|location| (location.latitude, location.longitude));
+        }
+
+        let mut warehouse_ids = levels
+            .iter()
+            .map(|level| level.warehouse_id)
+            .collect::<Vec<_>>();
+        warehouse_ids.sort();
+        warehouse_ids.dedup();
+        let warehouses = self
+            .warehouse_repository
+            .find_by_ids(&warehouse_ids)
+            .await?;
+
+        Ok(self.allocation_service.allocate(&AllocationRequest {
+            lines,
+            destination: &destination,
+            warehouses: &warehouses,
+            levels,
+        }))
+    }
+
+    /// Returns one page of orders matching `filter` and the total number of
+    /// matching orders.
+    pub async fn list_orders(
+        &self,
+        filter: &OrderListFilter,
+        page: u32,
+        page_size: u32,
+    ) -> Result<(Vec<Order>, i64)> {
+        let sort_column = filter
+            .sort_column()
+            .map_err(LogisticsError::ValidationError)?;
+
+        let limit = page_size as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        let orders = self
+            .order_repository
+            .find_filtered(filter, sort_column, limit, offset)
+            .await?;
+        let total = self.order_repository.count_filtered(filter).await?;
+
+        Ok((orders, total))
+    }
+
+    /// Orders accepted while the inventory service was unreachable that still
+    /// wait for their stock check.
+    pub async fn count_pending_stock_checks(&self) -> Result<i64> {
+        self.order_repository
+            .count_pending_stock_checks()
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    pub async fn update_order_priority(&self, id: Uuid, priority: i32) -> Result<OrderPriorityDto> {
+        let priority = self
+            .order_repository
+            .update_priority(id, priority)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Order", id.to_string()))?;
+
+        Ok(OrderPriorityDto {
+            order_id: id,
+            priority,
+        })
+    }
+
     pub async fn get_orders_by_customer(
         &self,
         customer_id: Uuid,
@@ -97,51 +387,237 @@ impl OrderService {
             .map_err(LogisticsError::from)
     }
 
-    pub async fn create_order(&self, dto: CreateOrderDto) -> Result<Order> {
-        if let Ok(inventory_client) = crate::grpc::get_inventory_client().await {
-            // Filter out items without product_id before checking inventory
-            let items_with_product_id = dto
-                .items
-                .iter()
-                .filter(|item| item.product_id != Uuid::nil())
-                .collect::<Vec<_>>();
+    /// Asks the inventory service to confirm and reserve stock for the order
+    /// in the warehouses the allocation plan picks, applying the unavailable
+    /// policy when the service cannot be reached.
+    async fn check_inventory_service(&self, dto: &CreateOrderDto) -> Result<InventoryCheck> {
+        // Filter out items without product_id before checking inventory
+        let lines = dto
+            .items
+            .iter()
+            .filter(|item| item.product_id != Uuid::nil())
+            .map(|item| AllocationLine {
+                order_item_id: None,
+                product_id: item.product_id,
+                sku: item.sku.clone(),
+                quantity: item.quantity,
+            })
+            .collect::<Vec<_>>();
+
+        // Reserving with the inventory service as well would hold the stock
+        // twice when this engine's levels are the system of record
+        if lines.is_empty() || get_config().inventory_client.engine_authoritative {
+            return Ok(InventoryCheck::Skipped);
+        }
+
+        let unavailable = match crate::grpc::get_inventory_client().await {
+            Ok(inventory_client) => {
+                let mut item_ids = lines.iter().map(|line| line.product_id).collect::<Vec<_>>();
+                item_ids.sort();
+                item_ids.dedup();
+                let levels = self
+                    .inventory_repository
+                    .find_stock_sites(&item_ids)
+                    .await?;
+                let plan = self
+                    .plan_allocation(
+                        &lines,
+                        &ShippingDestination::from(&dto.shipping_info),
+                        &levels,
+                    )
+                    .await?;
+
+                match Self::reserve_remote_stock(&inventory_client, &plan).await {
+                    Ok(holds) => return Ok(InventoryCheck::Reserved(holds)),
+                    Err(status) if inventory::client::is_unavailable(&status) => {
+                        status.message().to_string()
+                    }
+                    Err(status) => {
+                        warn!("Failed to check inventory: {}", status);
+                        return Ok(InventoryCheck::Skipped);
+                    }
+                }
+            }
+            Err(e) => e.to_string(),
+        };
+
+        match get_config().inventory_client.unavailable_policy {
+            InventoryUnavailablePolicy::Reject => Err(LogisticsError::ServiceUnavailable(
+                "Inventory service is unavailable, please retry later".to_string(),
+            )),
+            InventoryUnavailablePolicy::AcceptPending => {
+                warn!(
+                    "Inventory service unavailable, accepting order pending a stock check: {}",
+                    unavailable
+                );
+                Ok(InventoryCheck::Deferred(unavailable))
+            }
+            InventoryUnavailablePolicy::LocalCheck => {
+                warn!(
+                    "Inventory service unavailable, checking stock locally: {}",
+                    unavailable
+                );
+                Ok(InventoryCheck::Skipped)
+            }
+        }
+    }
 
-            if !items_with_product_id.is_empty() {
-                let product_items = items_with_product_id
+    /// Reserves the plan's units with the inventory service, one reservation
+    /// for each warehouse the plan draws on. A warehouse that is short is
+    /// asked again for what it has, leaving the shortfall to be backordered.
+    /// When a call fails, the reservations already made are released again.
+    async fn reserve_remote_stock(
+        inventory_client: &InventoryClient,
+        plan: &AllocationPlan,
+    ) -> std::result::Result<Vec<RemoteHold>, Status> {
+        let mut holds = Vec::new();
+
+        for (warehouse_id, mut lines) in plan.by_warehouse() {
+            while !lines.is_empty() {
+                // The order does not exist yet, so the reservation is made
+                // under an ID of its own. Reusing the order ID would count the
+                // stock twice when this engine serves the inventory service.
+                let remote_order_id = Uuid::new_v4().to_string();
+                let items = lines
                     .iter()
-                    .map(|item| ProductItem {
-                        product_id: item.product_id.to_string(),
-                        sku: item.sku.clone(),
-                        quantity: item.quantity,
+                    .map(|line| ProductItem {
+                        product_id: line.product_id.to_string(),
+                        sku: line.sku.clone(),
+                        quantity: line.quantity,
                     })
                     .collect::<Vec<_>>();
 
-                // Generate a temporary order ID
-                let temp_order_id = Uuid::new_v4().to_string();
-
-                // Warehouse ID (use a default if not provided)
-                let warehouse_id = "default-warehouse".to_string();
-
-                // Try to reserve inventory
-                match inventory_client
-                    .check_and_reserve_stock(temp_order_id, product_items, warehouse_id)
+                let reservation = match inventory_client
+                    .check_and_reserve_stock(
+                        remote_order_id.clone(),
+                        items,
+                        warehouse_id.to_string(),
+                    )
                     .await
                 {
-                    Ok(reservation) => {
-                        if !reservation.success {
-                            return Err(LogisticsError::BadRequest(format!(
-                                "Inventory check failed: {}",
-                                reservation.message
-                            )));
-                        }
-                    }
-                    Err(e) => {
-                        warn!("Failed to check inventory: {}", e);
+                    Ok(reservation) => reservation,
+                    Err(status) => {
+                        Self::release_remote_holds(inventory_client, &holds).await;
+                        return Err(status);
                     }
+                };
+
+                if reservation.success {
+                    holds.push(RemoteHold {
+                        warehouse_id,
+                        reservation_id: reservation.reservation_id,
+                        remote_order_id,
+                        units: lines
+                            .iter()
+                            .map(|line| (line.product_id, line.quantity))
+                            .collect(),
+                    });
+                    break;
+                }
+
+                // The service reserves all or nothing, so ask for what it
+                // reports available. Each round asks for fewer units, and
+                // a round that would not ends the warehouse's share here.
+                let units = lines.iter().map(|line| line.quantity).sum::<i32>();
+                lines = lines
+                    .into_iter()
+                    .filter_map(|mut line| {
+                        let product_id = line.product_id.to_string();
+                        let available = reservation
+                            .items
+                            .iter()
+                            .find(|item| item.product_id == product_id)
+                            .map_or(0, |item| item.available_quantity);
+                        line.quantity = line.quantity.min(available);
+                        (line.quantity > 0).then_some(line)
+                    })
+                    .collect();
+
+                if lines.iter().map(|line| line.quantity).sum::<i32>() == units {
+                    warn!(
+                        "Inventory service refused stock it reports available in warehouse {}: {}",
+                        warehouse_id, reservation.message
+                    );
+                    break;
                 }
+                info!(
+                    "Inventory service is short in warehouse {}, asking for what it has: {}",
+                    warehouse_id, reservation.message
+                );
+            }
+        }
+
+        Ok(holds)
+    }
+
+    /// Hands back reservations made for an order that is not placed after all.
+    async fn release_remote_holds(inventory_client: &InventoryClient, holds: &[RemoteHold]) {
+        for hold in holds {
+            if let Err(status) = inventory_client
+                .release_reserved_stock(
+                    hold.reservation_id.clone(),
+                    hold.remote_order_id.clone(),
+                    "Order was not placed".to_string(),
+                )
+                .await
+            {
+                warn!(
+                    "Failed to release inventory reservation {}: {}",
+                    hold.reservation_id, status
+                );
             }
         }
+    }
+
+    pub async fn create_order(
+        &self,
+        dto: CreateOrderDto,
+        created_by: Option<String>,
+    ) -> Result<Order> {
+        let inventory_check = self.check_inventory_service(&dto).await?;
+
+        // Whatever the inventory service holds for an order that fails to be
+        // placed is handed back, or the stock would stay blocked upstream
+        let order = match self
+            .place_order(dto, &inventory_check, created_by.as_deref())
+            .await
+        {
+            Ok(order) => order,
+            Err(e) => {
+                if let InventoryCheck::Reserved(holds) = &inventory_check {
+                    match crate::grpc::get_inventory_client().await {
+                        Ok(inventory_client) => {
+                            Self::release_remote_holds(&inventory_client, holds).await
+                        }
+                        Err(client_error) => warn!(
+                            "Failed to release inventory reservations of an unplaced order: {}",
+                            client_error
+                        ),
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        Self::broadcast_status_change(OrderUpdate {
+            order_id: order.id,
+            customer_id: order.customer_id,
+            previous_status: None,
+            new_status: order.status,
+            notes: order.notes.clone(),
+            timestamp: order.created_at,
+        });
+
+        Ok(order)
+    }
 
+    /// Writes the order and everything recorded with it in one transaction.
+    async fn place_order(
+        &self,
+        dto: CreateOrderDto,
+        inventory_check: &InventoryCheck,
+        created_by: Option<&str>,
+    ) -> Result<Order> {
         // Start a database transaction
         let mut tx = self
             .pool
@@ -149,33 +625,9 @@ impl OrderService {
             .await
             .map_err(LogisticsError::DatabaseError)?;
 
-        // Create a vector to store IDs of inventory items that need to be updated
-        let inventory_updates = dto
-            .items
-            .iter()
-            .map(|item| (item.product_id, item.quantity))
-            .collect::<Vec<_>>();
-
-        // Lock the inventory items for updating to prevent concurrency issues
-        // This query doesn't modify anything but acquires row-level locks on the specified inventory items
-        for (product_id, _) in &inventory_updates {
-            // Using SELECT FOR UPDATE to lock the rows while the transaction is in progress
-            let lock_result = sqlx::query(
-                r#"
-                SELECT id, quantity FROM inventory_items 
-                WHERE id = $1
-                FOR UPDATE
-                "#,
-            )
-            .bind(product_id)
-            .execute(&mut *tx)
-            .await;
-
-            if let Err(e) = lock_result {
-                tx.rollback().await.ok(); // Rollback the transaction
-                return Err(LogisticsError::DatabaseError(e));
-            }
-        }
+        // Stock is reserved once the order exists; nothing is reserved for an
+        // order whose stock check is deferred
+        let reserve_stock = !matches!(inventory_check, InventoryCheck::Deferred(_));
 
         // Create the order first
         let order = self
@@ -200,81 +652,233 @@ impl OrderService {
         self.create_shipping_in_transaction(&mut tx, shipping_dto)
             .await?;
 
-        // Now update the inventory quantities for each product
-        for (product_id, quantity) in inventory_updates {
-            // Reduce inventory by the ordered quantity
-            let update_result = sqlx::query(
-                r#"
-                UPDATE inventory_items
-                SET 
-                    quantity = quantity - $1,
-                    updated_at = NOW()
-                WHERE id = $2 AND quantity >= $1
-                RETURNING id, quantity
-                "#,
-            )
-            .bind(quantity)
-            .bind(product_id)
-            .fetch_optional(&mut *tx)
-            .await;
-
-            match update_result {
-                Ok(Some(_)) => {
-                    // Successfully updated the inventory
-                    info!(
-                        "Reduced inventory for product {} by {}",
-                        product_id, quantity
-                    );
+        // The allocation strategy picks the warehouse(s) each line is reserved
+        // from and anything no warehouse has is backordered; the units stay on
+        // hand until the order is processed. Locking the levels first means
+        // concurrent orders cannot reserve the same units.
+        let mut backorders = Vec::new();
+        let mut allocation = None;
+        if reserve_stock {
+            let expires_at = default_reservation_expiry();
+            let expected_restock_at =
+                Utc::now() + Duration::days(get_config().backorder.default_restock_days);
+            let lines = self
+                .order_item_repository
+                .find_by_order_id_with_transaction(&mut tx, order.id)
+                .await?
+                .into_iter()
+                .filter(|item| item.product_id != Uuid::nil())
+                .map(|item| AllocationLine {
+                    order_item_id: Some(item.id),
+                    product_id: item.product_id,
+                    sku: item.sku,
+                    quantity: item.quantity,
+                })
+                .collect::<Vec<_>>();
+
+            if !lines.is_empty() {
+                let mut item_ids = lines.iter().map(|line| line.product_id).collect::<Vec<_>>();
+                item_ids.sort();
+                item_ids.dedup();
+                let mut levels = self
+                    .inventory_repository
+                    .lock_stock_sites_with_transaction(&mut tx, &item_ids)
+                    .await?;
+
+                // Only units the inventory service holds for the order can be
+                // reserved here; the plan backorders the rest
+                if let InventoryCheck::Reserved(holds) = inventory_check {
+                    for level in &mut levels {
+                        let held = holds
+                            .iter()
+                            .filter(|hold| hold.warehouse_id == level.warehouse_id)
+                            .filter_map(|hold| hold.units.get(&level.item_id))
+                            .sum::<i32>();
+                        level.available = level.available.min(held);
+                    }
                 }
-                Ok(None) => {
-                    // This means the WHERE condition failed (not enough quantity)
-                    // Rollback the inventory changes but keep the order with OutOfStock status
-                    tx.rollback().await.ok();
 
-                    // Update order status to OutOfStock
-                    let out_of_stock_update = UpdateOrderDto {
-                        status: Some("out_of_stock".to_string()),
-                        tracking_number: None,
-                        notes: Some(format!("Insufficient inventory for product {}", product_id)),
-                    };
-
-                    match self
-                        .order_repository
-                        .update(order.id, out_of_stock_update)
-                        .await
-                    {
-                        Ok(Some(updated_order)) => {
-                            info!("Order {} marked as OutOfStock due to insufficient inventory for product {}", 
-                                  order.id, product_id);
-                            return Ok(updated_order);
-                        }
-                        Ok(None) => {
-                            warn!("Could not update order {} to OutOfStock status", order.id);
-                        }
-                        Err(e) => {
-                            warn!(
-                                "Error updating order {} to OutOfStock status: {}",
-                                order.id, e
-                            );
+                let plan = self
+                    .plan_allocation(
+                        &lines,
+                        &ShippingDestination::from(&dto.shipping_info),
+                        &levels,
+                    )
+                    .await?;
+
+                for line in &plan.lines {
+                    let mut reserved = 0;
+
+                    for source in &line.sources {
+                        let Some((_, level)) = self
+                            .inventory_repository
+                            .reserve_stock_with_transaction(
+                                &mut tx,
+                                order.id,
+                                line.product_id,
+                                source.quantity,
+                                Some(source.warehouse_id),
+                                expires_at,
+                                created_by.unwrap_or(ORDER_SERVICE_ACTOR),
+                            )
+                            .await?
+                        else {
+                            continue;
+                        };
+
+                        reserved += source.quantity;
+                        info!(
+                            "Reserved {} units of product {} in warehouse {} for order {}",
+                            source.quantity, line.product_id, source.warehouse_code, order.id
+                        );
+
+                        if is_low_stock(level.available, level.low_stock_threshold)
+                            && !is_low_stock(
+                                level.available + source.quantity,
+                                level.low_stock_threshold,
+                            )
+                        {
+                            let activity = NewActivity::new(
+                                "inventory",
+                                line.product_id,
+                                "inventory.low_stock",
+                                ActivitySeverity::Warning,
+                                format!(
+                                    "Low stock alert: {} (only {} available)",
+                                    level.name, level.available
+                                ),
+                            )
+                            .with_metadata(serde_json::json!({
+                                "order_id": order.id,
+                                "warehouse_id": level.warehouse_id,
+                                "available": level.available,
+                                "reserved": level.reserved,
+                                "low_stock_threshold": level.low_stock_threshold,
+                            }));
+                            self.activity_repository
+                                .create_with_transaction(&mut tx, &activity)
+                                .await?;
                         }
                     }
 
-                    return Err(LogisticsError::BadRequest(format!(
-                        "Insufficient inventory for product {}",
-                        product_id
-                    )));
-                }
-                Err(e) => {
-                    tx.rollback().await.ok(); // Rollback the transaction
-                    return Err(LogisticsError::DatabaseError(e));
+                    let shortfall = line.requested - reserved;
+                    if shortfall > 0 {
+                        let backorder = self
+                            .backorder_repository
+                            .create_with_transaction(
+                                &mut tx,
+                                order.id,
+                                line.order_item_id.unwrap_or_default(),
+                                line.product_id,
+                                &line.sku,
+                                shortfall,
+                                Some(expected_restock_at),
+                            )
+                            .await?;
+                        info!(
+                            "Backordered {} units of product {} for order {}",
+                            shortfall, line.product_id, order.id
+                        );
+                        backorders.push(backorder);
+                    }
                 }
+
+                self.order_repository
+                    .create_allocation_with_transaction(&mut tx, order.id, &plan)
+                    .await?;
+                allocation = Some(plan);
             }
         }
 
-        // Commit the transaction
-        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+        if let Some(plan) = &allocation {
+            let activity = NewActivity::new(
+                "order",
+                order.id,
+                "order.allocated",
+                ActivitySeverity::Info,
+                format!(
+                    "Order {} allocated by {}: {}",
+                    order.id, plan.strategy, plan.explanation
+                ),
+            )
+            .with_metadata(serde_json::json!({
+                "strategy": plan.strategy,
+                "split": plan.split,
+                "lines": plan.lines,
+            }));
+            self.activity_repository
+                .create_with_transaction(&mut tx, &activity)
+                .await?;
+        }
 
-        // Publish order created event
+        if !backorders.is_empty() {
+            let units: i32 = backorders.iter().map(|backorder| backorder.quantity).sum();
+            let activity = NewActivity::new(
+                "order",
+                order.id,
+                "order.backordered",
+                ActivitySeverity::Warning,
+                format!(
+                    "Order {} has {} unit(s) on backorder across {} line(s)",
+                    order.id,
+                    units,
+                    backorders.len()
+                ),
+            )
+            .with_metadata(serde_json::json!({
+                "backorders": backorders
+                    .iter()
+                    .map(|backorder| serde_json::json!({
+                        "backorder_id": backorder.id,
+                        "product_id": backorder.product_id,
+                        "sku": backorder.sku,
+                        "quantity": backorder.quantity,
+                    }))
+                    .collect::<Vec<_>>(),
+                "expected_restock_at": backorders[0].expected_restock_at,
+            }));
+            self.activity_repository
+                .create_with_transaction(&mut tx, &activity)
+                .await?;
+        }
+
+        if let InventoryCheck::Reserved(holds) = inventory_check {
+            for hold in holds {
+                let reservation = RemoteReservation {
+                    order_id: order.id,
+                    reservation_id: hold.reservation_id.clone(),
+                    remote_order_id: hold.remote_order_id.clone(),
+                    warehouse_id: Some(hold.warehouse_id),
+                    committed: false,
+                };
+                self.order_repository
+                    .create_remote_reservation_with_transaction(&mut tx, &reservation)
+                    .await?;
+            }
+        }
+
+        if let InventoryCheck::Deferred(reason) = inventory_check {
+            self.order_repository
+                .create_pending_stock_check_with_transaction(&mut tx, order.id, reason)
+                .await?;
+
+            let activity = NewActivity::new(
+                "order",
+                order.id,
+                "order.stock_check_pending",
+                ActivitySeverity::Warning,
+                format!(
+                    "Order {} accepted without a stock check: inventory service unavailable",
+                    order.id
+                ),
+            )
+            .with_metadata(serde_json::json!({ "reason": reason }));
+            self.activity_repository
+                .create_with_transaction(&mut tx, &activity)
+                .await?;
+        }
+
+        // Record the order created event in the same transaction as the order
         let event_data = OrderCreatedEvent {
             order_id: order.id,
             customer_id: order.customer_id,
@@ -282,15 +886,89 @@ impl OrderService {
             total_amount: order.total_amount.to_string(),
             items_count: dto.items.len() as i32,
         };
+        self.enqueue_event(
+            &mut tx,
+            order.id,
+            EventType::OrderCreated,
+            "order.created",
+            event_data,
+        )
+        .await?;
+
+        let activity = NewActivity::new(
+            "order",
+            order.id,
+            "order.created",
+            ActivitySeverity::Info,
+            format!(
+                "Order {} created with {} item(s) totalling {}",
+                order.id,
+                dto.items.len(),
+                order.total_amount
+            ),
+        )
+        .with_metadata(serde_json::json!({
+            "customer_id": order.customer_id,
+            "total_amount": order.total_amount.to_string(),
+        }));
+        self.activity_repository
+            .create_with_transaction(&mut tx, &activity)
+            .await?;
+
+        // Commit the transaction
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        Ok(order)
+    }
+
+    /// Pushes a committed status change to gRPC subscribers and the live feed.
+    fn broadcast_status_change(update: OrderUpdate) {
+        let event_type = if update.previous_status.is_none() {
+            "order.created"
+        } else {
+            "order.status_changed"
+        };
+        live_feed::publish(
+            LiveTopic::Orders,
+            event_type,
+            Some(update.order_id.to_string()),
+            &update,
+        );
+        order_updates::publish(update);
+    }
 
-        if let Err(e) =
-            publisher::publish_event(EventType::OrderCreated, "order.created", event_data).await
-        {
-            // Log error but don't fail the request
-            warn!("Failed to publish order created event: {}", e);
+    fn status_severity(status: OrderStatus) -> ActivitySeverity {
+        match status {
+            OrderStatus::Delivered => ActivitySeverity::Success,
+            OrderStatus::Cancelled | OrderStatus::Returned | OrderStatus::OutOfStock => {
+                ActivitySeverity::Warning
+            }
+            _ => ActivitySeverity::Info,
         }
+    }
 
-        Ok(order)
+    /// Stores an order event in the outbox; the outbox relay publishes it once
+    /// the surrounding transaction commits.
+    async fn enqueue_event<T: Serialize>(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        event_type: EventType,
+        routing_key: &str,
+        data: T,
+    ) -> Result<()> {
+        let event = NewOutboxEvent::from_event(
+            "order",
+            order_id,
+            routing_key,
+            publisher::build_event(event_type, data),
+        )
+        .map_err(|e| LogisticsError::InternalError(format!("Failed to serialize event: {}", e)))?;
+
+        self.outbox_repository
+            .create_with_transaction(tx, &event)
+            .await
+            .map_err(LogisticsError::from)
     }
 
     async fn create_order_in_transaction(
@@ -330,20 +1008,54 @@ impl OrderService {
         Ok(())
     }
 
+    /// Creates the order's first shipment, which carries all of its items.
     async fn create_shipping_in_transaction(
         &self,
         tx: &mut Transaction<'_, Postgres>,
         shipping_dto: CreateShippingInfoDto,
     ) -> Result<()> {
-        self.shipping_repository
+        let order_id = shipping_dto.order_id;
+        let shipping = self
+            .shipping_repository
             .create_with_transaction(tx, shipping_dto)
             .await
             .map_err(LogisticsError::from)?;
 
+        let items = self
+            .shipping_repository
+            .find_unallocated_with_transaction(tx, order_id, None)
+            .await?
+            .into_iter()
+            .filter(|(_, remaining)| *remaining > 0)
+            .collect::<Vec<_>>();
+        self.shipping_repository
+            .replace_items_with_transaction(tx, shipping.id, &items)
+            .await?;
+
         Ok(())
     }
 
-    pub async fn update_order(&self, id: Uuid, dto: UpdateOrderDto) -> Result<Order> {
+    /// Updates the order's tracking number and notes. A status change goes
+    /// through the same transition guard as the status endpoint, in the same
+    /// transaction as the other fields, and is recorded as made by `changed_by`.
+    pub async fn update_order(
+        &self,
+        id: Uuid,
+        mut dto: UpdateOrderDto,
+        changed_by: Option<String>,
+    ) -> Result<Order> {
+        if let Some(status) = dto.status.take() {
+            let status = OrderStatus::from_str(&status).map_err(LogisticsError::ValidationError)?;
+            let notes = dto.notes.clone();
+            return if status == OrderStatus::Cancelled {
+                self.cancel_order_with_details(id, notes, changed_by, Some(&dto))
+                    .await
+            } else {
+                self.change_order_status(id, status, notes, changed_by, Some(&dto))
+                    .await
+            };
+        }
+
         let updated = self.order_repository.update(id, dto).await?;
 
         match updated {
@@ -352,6 +1064,19 @@ impl OrderService {
         }
     }
 
+    // Applies the non-status fields of an order update alongside a status change
+    async fn update_details_in_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        details: &UpdateOrderDto,
+    ) -> Result<Order> {
+        self.order_repository
+            .update_details_with_transaction(tx, id, details)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Order", id.to_string()))
+    }
+
     pub async fn get_order_items(&self, order_id: Uuid) -> Result<Vec<OrderItem>> {
         let order = self.order_repository.find_by_id(order_id).await?;
         if order.is_none() {
@@ -425,244 +1150,1041 @@ impl OrderService {
             .map_err(LogisticsError::from)
     }
 
+    pub async fn get_allowed_transitions(&self, id: Uuid) -> Result<OrderTransitionsDto> {
+        let order = self.get_order_by_id(id).await?;
+
+        Ok(OrderTransitionsDto {
+            order_id: order.id,
+            current_status: order.status.to_string(),
+            allowed_transitions: order
+                .status
+                .allowed_transitions()
+                .iter()
+                .map(|status| status.to_string())
+                .collect(),
+        })
+    }
+
     pub async fn update_order_status(
         &self,
         id: Uuid,
         status: OrderStatus,
         notes: Option<String>,
+        changed_by: Option<String>,
     ) -> Result<Order> {
-        let order = self.order_repository.find_by_id(id).await?;
-
-        if order.is_none() {
-            return Err(LogisticsError::NotFound("Order", id.to_string()));
+        // Cancelling has to undo what the order set in motion
+        if status == OrderStatus::Cancelled {
+            return self.cancel_order(id, notes, changed_by).await;
         }
 
-        let old_order = order.unwrap();
-        let old_status = old_order.status.clone();
+        self.change_order_status(id, status, notes, changed_by, None)
+            .await
+    }
+
+    // Moves the order to `status`, applying `details` in the same transaction
+    async fn change_order_status(
+        &self,
+        id: Uuid,
+        status: OrderStatus,
+        notes: Option<String>,
+        changed_by: Option<String>,
+        details: Option<&UpdateOrderDto>,
+    ) -> Result<Order> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        // Lock the order row so concurrent status changes are validated against
+        // the status they actually replace
+        let old_status = match self
+            .order_repository
+            .lock_status_with_transaction(&mut tx, id)
+            .await?
+        {
+            Some(status) => status,
+            None => return Err(LogisticsError::NotFound("Order", id.to_string())),
+        };
 
-        // If the order is already in the requested status, just return it
+        // If the order is already in the requested status, only the other
+        // fields change
         if old_status == status {
-            return Ok(old_order);
+            let Some(details) = details else {
+                tx.rollback().await.ok();
+                return self.get_order_by_id(id).await;
+            };
+            let order = self
+                .update_details_in_transaction(&mut tx, id, details)
+                .await?;
+            tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+            return Ok(order);
         }
 
-        // Start a transaction if we're cancelling to handle inventory restoration
-        let mut tx_option = None;
+        if !old_status.can_transition_to(status) {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::InvalidStatusTransition(old_status, status));
+        }
 
-        // Special handling for cancellation: restore inventory
-        if status == OrderStatus::Cancelled && old_status != OrderStatus::Cancelled {
-            // Start a transaction
-            let tx = self
-                .pool
-                .begin()
-                .await
-                .map_err(LogisticsError::DatabaseError)?;
-            tx_option = Some(tx);
-
-            // Get all order items
-            let order_items = self.order_item_repository.find_by_order_id(id).await?;
-
-            // Lock the inventory items for updating
-            for item in &order_items {
-                let lock_result = sqlx::query(
-                    r#"
-                    SELECT id, quantity FROM inventory_items 
-                    WHERE id = $1
-                    FOR UPDATE
-                    "#,
-                )
-                .bind(item.product_id)
-                .execute(&mut **tx_option.as_mut().unwrap())
-                .await;
+        // An order in a pick wave moves on once it is picked and packed
+        if old_status == OrderStatus::Processing
+            && self
+                .wave_repository
+                .is_awaiting_packing_with_transaction(&mut tx, id)
+                .await?
+        {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Order {} is still being picked and packed in a wave",
+                id
+            )));
+        }
 
-                if let Err(e) = lock_result {
-                    if let Some(mut tx) = tx_option.take() {
-                        tx.rollback().await.ok();
-                    }
-                    return Err(LogisticsError::DatabaseError(e));
-                }
-            }
-
-            // Restore inventory quantities for each item
-            for item in order_items {
-                let restore_result = sqlx::query(
-                    r#"
-                    UPDATE inventory_items
-                    SET 
-                        quantity = quantity + $1,
-                        updated_at = NOW()
-                    WHERE id = $2
-                    RETURNING id, quantity
-                    "#,
-                )
-                .bind(item.quantity)
-                .bind(item.product_id)
-                .fetch_optional(&mut **tx_option.as_mut().unwrap())
-                .await;
-
-                match restore_result {
-                    Ok(Some(_)) => {
-                        info!(
-                            "Restored inventory for product {} by {}",
-                            item.product_id, item.quantity
-                        );
-                    }
-                    Ok(None) => {
-                        warn!("Could not restore inventory for product {}. Item may have been deleted.", item.product_id);
-                    }
-                    Err(e) => {
-                        if let Some(mut tx) = tx_option.take() {
-                            tx.rollback().await.ok();
-                        }
-                        return Err(LogisticsError::DatabaseError(e));
-                    }
-                }
+        // Processing ships the reserved stock out, here and at the inventory
+        // service
+        if status == OrderStatus::Processing {
+            if let Err(e) = self.commit_remote_reservations(id).await {
+                tx.rollback().await.ok();
+                return Err(e);
             }
+            self.commit_inventory_in_transaction(
+                &mut tx,
+                id,
+                changed_by.as_deref().unwrap_or(ORDER_SERVICE_ACTOR),
+            )
+            .await?;
         }
 
-        // Update the order status
-        let updated_order = if let Some(mut tx) = tx_option {
-            // If we're in a transaction, update the order status within it
-            let update_result = sqlx::query!(
-                r#"
-                UPDATE orders
-                SET 
-                    status = $1,
-                    notes = COALESCE($2, notes),
-                    updated_at = NOW()
-                WHERE id = $3
-                RETURNING 
-                    id, 
-                    customer_id, 
-                    total_amount as "total_amount!: BigDecimal", 
-                    status as "status!: OrderStatus",
-                    currency,
-                    tracking_number,
-                    notes,
-                    created_at, 
-                    updated_at
-                "#,
-                status as OrderStatus,
-                notes,
-                id
+        let mut updated_order = self
+            .record_status_change_in_transaction(
+                &mut tx,
+                id,
+                old_status,
+                status,
+                notes.clone(),
+                changed_by,
             )
-            .fetch_one(&mut *tx)
-            .await;
+            .await?;
+        if let Some(details) = details {
+            updated_order = self
+                .update_details_in_transaction(&mut tx, id, details)
+                .await?;
+        }
 
-            // Commit the transaction
-            match update_result {
-                Ok(row) => {
-                    tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
 
-                    // Convert the datetime values ourselves
-                    let created_dt = row.created_at;
-                    let updated_dt = row.updated_at;
-                    let created_at = chrono::DateTime::<chrono::Utc>::from_timestamp(
-                        created_dt.unix_timestamp(),
-                        created_dt.nanosecond(),
-                    )
-                    .unwrap_or_else(|| chrono::Utc::now());
-                    let updated_at = chrono::DateTime::<chrono::Utc>::from_timestamp(
-                        updated_dt.unix_timestamp(),
-                        updated_dt.nanosecond(),
-                    )
-                    .unwrap_or_else(|| chrono::Utc::now());
-
-                    Order {
-                        id: row.id,
-                        customer_id: row.customer_id,
-                        customer_name: None,
-                        total_amount: Decimal::from_str(&row.total_amount.to_string())
-                            .unwrap_or_default(),
-                        status: row.status,
-                        currency: row.currency,
-                        tracking_number: row.tracking_number,
-                        notes: row.notes,
-                        created_at,
-                        updated_at,
-                    }
-                }
-                Err(e) => {
-                    tx.rollback().await.ok();
-                    return Err(LogisticsError::DatabaseError(e));
-                }
+        Self::broadcast_status_change(OrderUpdate {
+            order_id: id,
+            customer_id: updated_order.customer_id,
+            previous_status: Some(old_status),
+            new_status: status,
+            notes,
+            timestamp: updated_order.updated_at,
+        });
+
+        Ok(updated_order)
+    }
+
+    /// Moves the order along as its shipments progress, through
+    /// `PartiallyShipped` and `PartiallyDelivered` while only some of its
+    /// units have shipped or arrived. Returns `None` when the order stays as
+    /// it is, e.g. because it is not being fulfilled, is further along or
+    /// waits to be packed in a pick wave.
+    pub async fn sync_fulfillment_status(&self, order_id: Uuid) -> Result<Option<Order>> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let old_status = match self
+            .order_repository
+            .lock_status_with_transaction(&mut tx, order_id)
+            .await?
+        {
+            Some(status) => status,
+            None => return Err(LogisticsError::NotFound("Order", order_id.to_string())),
+        };
+
+        let progress = self
+            .shipping_repository
+            .fulfillment_progress_with_transaction(&mut tx, order_id)
+            .await?;
+        let target = match progress.order_status() {
+            Some(target) => target,
+            None => {
+                tx.rollback().await.ok();
+                return Ok(None);
             }
-        } else {
-            // Use the regular repository method if we're not in a transaction
-            let updated = self
-                .order_repository
-                .update(
-                    id,
-                    UpdateOrderDto {
-                        status: Some(status.to_string()),
-                        tracking_number: None,
-                        notes: notes.clone(),
-                    },
+        };
+
+        // An order in a pick wave waits for its packing; packing it catches
+        // the order up
+        if old_status == OrderStatus::Processing
+            && self
+                .wave_repository
+                .is_awaiting_packing_with_transaction(&mut tx, order_id)
+                .await?
+        {
+            tx.rollback().await.ok();
+            return Ok(None);
+        }
+
+        // Each step is recorded, so the history shows e.g. Shipped before
+        // Delivered even when both happen at once
+        let notes = Some("Updated from shipment progress".to_string());
+        let mut changes = Vec::new();
+        let mut current = old_status;
+        let mut updated_order = None;
+        while let Some(next) = Self::next_fulfillment_status(current, target) {
+            let order = self
+                .record_status_change_in_transaction(
+                    &mut tx,
+                    order_id,
+                    current,
+                    next,
+                    notes.clone(),
+                    None,
                 )
                 .await?;
+            changes.push((current, next, order.updated_at));
+            current = next;
+            updated_order = Some(order);
+        }
 
-            match updated {
-                Some(order) => order,
-                None => return Err(LogisticsError::NotFound("Order", id.to_string())),
+        let updated_order = match updated_order {
+            Some(order) => order,
+            None => {
+                tx.rollback().await.ok();
+                return Ok(None);
             }
         };
 
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        for (previous_status, new_status, timestamp) in changes {
+            Self::broadcast_status_change(OrderUpdate {
+                order_id,
+                customer_id: updated_order.customer_id,
+                previous_status: Some(previous_status),
+                new_status,
+                notes: notes.clone(),
+                timestamp,
+            });
+        }
+
+        Ok(Some(updated_order))
+    }
+
+    /// Statuses an order goes through while it is shipped, in order.
+    const FULFILLMENT_STATUSES: [OrderStatus; 5] = [
+        OrderStatus::Processing,
+        OrderStatus::PartiallyShipped,
+        OrderStatus::Shipped,
+        OrderStatus::PartiallyDelivered,
+        OrderStatus::Delivered,
+    ];
+
+    /// The furthest status `current` may move to without passing `target`,
+    /// or `None` when it cannot get any closer.
+    fn next_fulfillment_status(current: OrderStatus, target: OrderStatus) -> Option<OrderStatus> {
+        let rank = |status: OrderStatus| {
+            Self::FULFILLMENT_STATUSES
+                .iter()
+                .position(|candidate| *candidate == status)
+        };
+        let current_rank = rank(current)?;
+        let target_rank = rank(target)?;
+
+        current
+            .allowed_transitions()
+            .iter()
+            .filter_map(|next| rank(*next).map(|next_rank| (next_rank, *next)))
+            .filter(|(next_rank, _)| *next_rank > current_rank && *next_rank <= target_rank)
+            .max_by_key(|(next_rank, _)| *next_rank)
+            .map(|(_, next)| next)
+    }
+
+    /// Writes the new status along with its history entry, status event and
+    /// activity.
+    async fn record_status_change_in_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: Uuid,
+        old_status: OrderStatus,
+        status: OrderStatus,
+        notes: Option<String>,
+        changed_by: Option<String>,
+    ) -> Result<Order> {
+        let updated_order = self
+            .order_repository
+            .update_status_with_transaction(tx, id, status, notes.clone())
+            .await?;
+
+        let history = OrderStatusHistory::new(
+            id,
+            Some(old_status),
+            status,
+            notes.clone(),
+            changed_by.clone(),
+        );
+        self.order_repository
+            .create_status_history_with_transaction(tx, &history)
+            .await?;
+
         let event_data = OrderStatusChangedEvent {
             order_id: id,
             previous_status: Some(format!("{:?}", old_status)),
             new_status: format!("{:?}", status),
-            changed_by: None,
+            changed_by: changed_by.clone(),
             notes: notes.clone(),
         };
-
-        if let Err(e) = publisher::publish_event(
+        self.enqueue_event(
+            tx,
+            id,
             EventType::OrderStatusChanged,
             &format!("order.status.{}", status.to_string().to_lowercase()),
             event_data,
         )
-        .await
+        .await?;
+
+        let activity = NewActivity::new(
+            "order",
+            id,
+            "order.status_changed",
+            Self::status_severity(status),
+            format!(
+                "Order {} moved from {} to {}",
+                id,
+                old_status.to_string(),
+                status.to_string()
+            ),
+        )
+        .with_actor(changed_by)
+        .with_metadata(serde_json::json!({
+            "previous_status": old_status.to_string(),
+            "new_status": status.to_string(),
+            "notes": notes,
+        }));
+        self.activity_repository
+            .create_with_transaction(tx, &activity)
+            .await?;
+
+        Ok(updated_order)
+    }
+
+    /// Cancels the order and runs the compensating steps: releasing its stock
+    /// here and at the inventory service, refunding or voiding its payments,
+    /// cancelling its shipment and publishing `OrderCancelledEvent`. The order
+    /// is cancelled as soon as the request is accepted; a step that fails
+    /// leaves the cancellation `failed` so it can be resumed. Cancelling an
+    /// order that is already cancelled resumes its unfinished cancellation.
+    pub async fn cancel_order(
+        &self,
+        id: Uuid,
+        notes: Option<String>,
+        changed_by: Option<String>,
+    ) -> Result<Order> {
+        self.cancel_order_with_details(id, notes, changed_by, None)
+            .await
+    }
+
+    // Cancels the order, applying `details` in the same transaction
+    async fn cancel_order_with_details(
+        &self,
+        id: Uuid,
+        notes: Option<String>,
+        changed_by: Option<String>,
+        details: Option<&UpdateOrderDto>,
+    ) -> Result<Order> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let old_status = match self
+            .order_repository
+            .lock_status_with_transaction(&mut tx, id)
+            .await?
         {
-            warn!("Failed to publish order status changed event: {}", e);
+            Some(status) => status,
+            None => return Err(LogisticsError::NotFound("Order", id.to_string())),
+        };
+
+        if old_status == OrderStatus::Cancelled {
+            match details {
+                Some(details) => {
+                    self.update_details_in_transaction(&mut tx, id, details)
+                        .await?;
+                    tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+                }
+                None => {
+                    tx.rollback().await.ok();
+                }
+            }
+
+            // Orders cancelled before cancellations were tracked have none
+            if let Some(cancellation) = self.cancellation_repository.find_by_order_id(id).await? {
+                if cancellation.status != CancellationStatus::Completed
+                    && self.cancellation_repository.claim_for_retry(id).await?
+                {
+                    self.run_cancellation(&cancellation).await?;
+                }
+            }
+            return self.get_order_by_id(id).await;
         }
 
-        if status == OrderStatus::Cancelled {
-            let cancel_event = OrderCancelledEvent {
-                order_id: id,
-                reason: notes
-                    .clone()
-                    .unwrap_or_else(|| "No reason provided".to_string()),
-                cancelled_by: None,
+        if !old_status.can_transition_to(OrderStatus::Cancelled) {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::InvalidStatusTransition(
+                old_status,
+                OrderStatus::Cancelled,
+            ));
+        }
+
+        // The order status lags behind its shipments, which may already be on
+        // their way
+        let shipments = self.shipping_repository.find_by_order_id(id).await?;
+        if shipments
+            .iter()
+            .any(|shipping| Self::has_left_warehouse(shipping.status()))
+        {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::BadRequest(format!(
+                "Order {} has already shipped and cannot be cancelled",
+                id
+            )));
+        }
+
+        let mut updated_order = self
+            .record_status_change_in_transaction(
+                &mut tx,
+                id,
+                old_status,
+                OrderStatus::Cancelled,
+                notes.clone(),
+                changed_by.clone(),
+            )
+            .await?;
+        if let Some(details) = details {
+            updated_order = self
+                .update_details_in_transaction(&mut tx, id, details)
+                .await?;
+        }
+
+        let reason = notes
+            .clone()
+            .unwrap_or_else(|| "No reason provided".to_string());
+        let cancellation = self
+            .cancellation_repository
+            .create_with_transaction(&mut tx, id, &reason, changed_by.as_deref())
+            .await?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        Self::broadcast_status_change(OrderUpdate {
+            order_id: id,
+            customer_id: updated_order.customer_id,
+            previous_status: Some(old_status),
+            new_status: OrderStatus::Cancelled,
+            notes,
+            timestamp: updated_order.updated_at,
+        });
+
+        // The order stays cancelled even if a step fails; the failure is
+        // recorded on the cancellation
+        self.run_cancellation(&cancellation).await?;
+
+        Ok(updated_order)
+    }
+
+    pub async fn get_cancellation(&self, order_id: Uuid) -> Result<OrderCancellation> {
+        self.cancellation_repository
+            .find_by_order_id(order_id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Order cancellation", order_id.to_string()))
+    }
+
+    /// Runs the steps of a failed or abandoned cancellation again, starting
+    /// from the first one that did not finish.
+    pub async fn resume_cancellation(&self, order_id: Uuid) -> Result<OrderCancellation> {
+        let cancellation = self.get_cancellation(order_id).await?;
+
+        if cancellation.status == CancellationStatus::Completed {
+            return Ok(cancellation);
+        }
+
+        if !self
+            .cancellation_repository
+            .claim_for_retry(order_id)
+            .await?
+        {
+            return Err(LogisticsError::BadRequest(format!(
+                "Cancellation of order {} is already in progress",
+                order_id
+            )));
+        }
+
+        self.run_cancellation(&cancellation).await?;
+        self.get_cancellation(order_id).await
+    }
+
+    fn has_left_warehouse(status: ShippingStatus) -> bool {
+        matches!(
+            status,
+            ShippingStatus::Shipped
+                | ShippingStatus::InTransit
+                | ShippingStatus::OutForDelivery
+                | ShippingStatus::Delivered
+                | ShippingStatus::Returned
+        )
+    }
+
+    /// Runs the cancellation's remaining steps in order and stops at the first
+    /// one that fails, so a retry picks up from there.
+    async fn run_cancellation(
+        &self,
+        cancellation: &OrderCancellation,
+    ) -> Result<CancellationStatus> {
+        let order_id = cancellation.order_id;
+
+        for step in cancellation.remaining_steps() {
+            let outcome = match self.run_cancellation_step(cancellation, step).await {
+                Ok(status) => status,
+                Err(e) => {
+                    let error = e.to_string();
+                    warn!(
+                        "Cancellation of order {} failed at {}: {}",
+                        order_id,
+                        step.as_str(),
+                        error
+                    );
+
+                    self.cancellation_repository
+                        .fail_step(cancellation.id, step, &error)
+                        .await?;
+                    self.cancellation_repository
+                        .finish(cancellation.id, CancellationStatus::Failed, Some(&error))
+                        .await?;
+
+                    live_feed::publish(
+                        LiveTopic::Orders,
+                        "order.cancellation_failed",
+                        Some(order_id.to_string()),
+                        &serde_json::json!({
+                            "order_id": order_id,
+                            "step": step,
+                            "error": error,
+                        }),
+                    );
+                    let activity = NewActivity::new(
+                        "order",
+                        order_id,
+                        "order.cancellation_failed",
+                        ActivitySeverity::Error,
+                        format!(
+                            "Cancellation of order {} stopped at {}: {}",
+                            order_id,
+                            step.as_str(),
+                            error
+                        ),
+                    )
+                    .with_metadata(serde_json::json!({
+                        "cancellation_id": cancellation.id,
+                        "step": step,
+                        "error": error,
+                    }));
+                    self.activity_repository.create(&activity).await?;
+
+                    return Ok(CancellationStatus::Failed);
+                }
             };
 
-            if let Err(e) =
-                publisher::publish_event(EventType::OrderCancelled, "order.cancelled", cancel_event)
-                    .await
-            {
-                warn!("Failed to publish order cancelled event: {}", e);
-            }
+            info!(
+                "Cancellation of order {}: {} {}",
+                order_id,
+                step.as_str(),
+                outcome.as_str()
+            );
+            live_feed::publish(
+                LiveTopic::Orders,
+                "order.cancellation_step",
+                Some(order_id.to_string()),
+                &serde_json::json!({
+                    "order_id": order_id,
+                    "step": step,
+                    "status": outcome,
+                }),
+            );
+        }
 
-            if let Ok(inventory_client) = crate::grpc::get_inventory_client().await {
-                let reservation_id = format!("reservation-{}", id);
+        self.cancellation_repository
+            .finish(cancellation.id, CancellationStatus::Completed, None)
+            .await?;
 
-                match inventory_client
-                    .release_reserved_stock(
-                        reservation_id,
-                        id.to_string(),
-                        "Order cancelled".to_string(),
+        live_feed::publish(
+            LiveTopic::Orders,
+            "order.cancellation_completed",
+            Some(order_id.to_string()),
+            &serde_json::json!({ "order_id": order_id }),
+        );
+        let activity = NewActivity::new(
+            "order",
+            order_id,
+            "order.cancellation_completed",
+            ActivitySeverity::Info,
+            format!("Cancellation of order {} completed", order_id),
+        )
+        .with_metadata(serde_json::json!({ "cancellation_id": cancellation.id }));
+        self.activity_repository.create(&activity).await?;
+
+        Ok(CancellationStatus::Completed)
+    }
+
+    /// Runs one compensating step and records it as done. Each step can run
+    /// again after a failure without undoing anything twice.
+    async fn run_cancellation_step(
+        &self,
+        cancellation: &OrderCancellation,
+        step: CancellationStep,
+    ) -> Result<StepStatus> {
+        let order_id = cancellation.order_id;
+
+        // Steps that only touch this database finish in the same transaction
+        // as their changes
+        match step {
+            CancellationStep::ReleaseInventory => {
+                let mut tx = self
+                    .pool
+                    .begin()
+                    .await
+                    .map_err(LogisticsError::DatabaseError)?;
+                self.restore_inventory_in_transaction(
+                    &mut tx,
+                    order_id,
+                    cancellation
+                        .requested_by
+                        .as_deref()
+                        .unwrap_or(ORDER_SERVICE_ACTOR),
+                )
+                .await?;
+                self.cancellation_repository
+                    .finish_step_with_transaction(
+                        &mut tx,
+                        cancellation.id,
+                        step,
+                        StepStatus::Completed,
+                        None,
                     )
+                    .await?;
+                tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+                return Ok(StepStatus::Completed);
+            }
+            CancellationStep::PublishEvent => {
+                let mut tx = self
+                    .pool
+                    .begin()
                     .await
-                {
-                    Ok(_) => info!(
-                        "Successfully released inventory reservation for cancelled order {}",
-                        id
-                    ),
-                    Err(e) => warn!(
-                        "Failed to release inventory for cancelled order {}: {}",
-                        id, e
-                    ),
+                    .map_err(LogisticsError::DatabaseError)?;
+                let cancel_event = OrderCancelledEvent {
+                    order_id,
+                    reason: cancellation.reason.clone(),
+                    cancelled_by: cancellation.requested_by.clone(),
+                };
+                self.enqueue_event(
+                    &mut tx,
+                    order_id,
+                    EventType::OrderCancelled,
+                    "order.cancelled",
+                    cancel_event,
+                )
+                .await?;
+                self.cancellation_repository
+                    .finish_step_with_transaction(
+                        &mut tx,
+                        cancellation.id,
+                        step,
+                        StepStatus::Completed,
+                        None,
+                    )
+                    .await?;
+                tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+                return Ok(StepStatus::Completed);
+            }
+            _ => {}
+        }
+
+        let detail = match step {
+            CancellationStep::ReleaseRemoteReservation => {
+                self.release_remote_reservation(order_id, &cancellation.reason)
+                    .await?
+            }
+            CancellationStep::RefundPayment => self.refund_order_payments(order_id).await?,
+            CancellationStep::CancelShipment => self.cancel_order_shipment(order_id).await?,
+            CancellationStep::ReleaseInventory | CancellationStep::PublishEvent => None,
+        };
+
+        let status = if detail.is_some() {
+            StepStatus::Completed
+        } else {
+            StepStatus::Skipped
+        };
+        self.cancellation_repository
+            .finish_step(cancellation.id, step, status, detail)
+            .await?;
+
+        Ok(status)
+    }
+
+    /// Releases the reservations the inventory service made when the order was
+    /// placed. Returns `None` when it holds none for the order any more.
+    async fn release_remote_reservation(
+        &self,
+        order_id: Uuid,
+        reason: &str,
+    ) -> Result<Option<serde_json::Value>> {
+        // Committed reservations went out with the order
+        let reservations = self
+            .order_repository
+            .find_remote_reservations(order_id)
+            .await?
+            .into_iter()
+            .filter(|reservation| !reservation.committed)
+            .collect::<Vec<_>>();
+        if reservations.is_empty() {
+            return Ok(None);
+        }
+
+        let inventory_client = crate::grpc::get_inventory_client()
+            .await
+            .map_err(|e| LogisticsError::ServiceUnavailable(e.to_string()))?;
+
+        let mut released = Vec::with_capacity(reservations.len());
+        for reservation in reservations {
+            let response = inventory_client
+                .release_reserved_stock(
+                    reservation.reservation_id.clone(),
+                    reservation.remote_order_id.clone(),
+                    reason.to_string(),
+                )
+                .await
+                .map_err(|status| Self::inventory_call_error(status, "release"))?;
+
+            // An unsuccessful release means the reservation is gone already,
+            // committed or expired, which is what the step is after
+            released.push(serde_json::json!({
+                "reservation_id": reservation.reservation_id,
+                "warehouse_id": reservation.warehouse_id,
+                "released": response.success,
+                "message": response.message,
+            }));
+        }
+
+        Ok(Some(serde_json::json!({ "reservations": released })))
+    }
+
+    /// Commits the reservations the inventory service made when the order was
+    /// placed, so the stock leaves there as it does here instead of going back
+    /// when the reservations expire. Each commit is recorded as it happens, so
+    /// a retry after a failure only commits the rest.
+    async fn commit_remote_reservations(&self, order_id: Uuid) -> Result<()> {
+        let reservations = self
+            .order_repository
+            .find_remote_reservations(order_id)
+            .await?
+            .into_iter()
+            .filter(|reservation| !reservation.committed)
+            .collect::<Vec<_>>();
+        if reservations.is_empty() {
+            return Ok(());
+        }
+
+        let inventory_client = crate::grpc::get_inventory_client()
+            .await
+            .map_err(|e| LogisticsError::ServiceUnavailable(e.to_string()))?;
+
+        for reservation in reservations {
+            let response = inventory_client
+                .commit_reservation(
+                    reservation.reservation_id.clone(),
+                    reservation.remote_order_id.clone(),
+                )
+                .await
+                .map_err(|status| Self::inventory_call_error(status, "commit"))?;
+
+            if !response.success {
+                return Err(LogisticsError::BadRequest(format!(
+                    "Inventory service could not commit reservation {}: {}",
+                    reservation.reservation_id, response.message
+                )));
+            }
+
+            self.order_repository
+                .mark_remote_reservation_committed(order_id, &reservation.remote_order_id)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn inventory_call_error(status: Status, action: &str) -> LogisticsError {
+        if inventory::client::is_unavailable(&status) {
+            LogisticsError::ServiceUnavailable(status.message().to_string())
+        } else {
+            LogisticsError::InternalError(format!(
+                "Inventory service rejected the {}: {}",
+                action,
+                status.message()
+            ))
+        }
+    }
+
+    /// Refunds settled payments and cancels the ones that have not settled.
+    /// Returns `None` when no payment needed either.
+    async fn refund_order_payments(&self, order_id: Uuid) -> Result<Option<serde_json::Value>> {
+        let payments = self.payment_repository.find_by_order_id(order_id).await?;
+
+        // Refunds and cancellations go through the payment service, so the
+        // refunded amount, activity and payment events are recorded as usual
+        let mut changed = Vec::new();
+        for payment in payments {
+            let previous = payment.status();
+            let updated = match previous {
+                PaymentStatus::Succeeded | PaymentStatus::PartiallyRefunded => {
+                    let outstanding = self
+                        .payment_repository
+                        .find_refundable_amount(payment.id)
+                        .await?
+                        .unwrap_or_default();
+                    if outstanding <= Decimal::ZERO {
+                        continue;
+                    }
+                    self.payment_service
+                        .refund_amount(&payment.id, outstanding)
+                        .await?
+                }
+                PaymentStatus::Pending => {
+                    match self.payment_service.cancel_payment(&payment.id).await? {
+                        Some(payment) => payment,
+                        None => continue,
+                    }
+                }
+                // The provider may still settle it; the step is retried once
+                // it has
+                PaymentStatus::Processing => {
+                    return Err(LogisticsError::Conflict(format!(
+                        "Payment {} is still processing and cannot be cancelled yet",
+                        payment.id
+                    )));
                 }
+                _ => continue,
+            };
+
+            changed.push(serde_json::json!({
+                "payment_id": payment.id,
+                "from": previous.as_str(),
+                "to": updated.status,
+            }));
+        }
+
+        Ok((!changed.is_empty()).then(|| serde_json::json!({ "payments": changed })))
+    }
+
+    /// Cancels the order's shipments that have not left the warehouse.
+    /// Returns `None` when there is nothing to cancel.
+    async fn cancel_order_shipment(&self, order_id: Uuid) -> Result<Option<serde_json::Value>> {
+        let shipments = self.shipping_repository.find_by_order_id(order_id).await?;
+
+        if let Some(shipping) = shipments
+            .iter()
+            .find(|shipping| Self::has_left_warehouse(shipping.status()))
+        {
+            return Err(LogisticsError::BadRequest(format!(
+                "Shipment {} is already {} and cannot be cancelled",
+                shipping.id,
+                shipping.status().as_str()
+            )));
+        }
+
+        let mut cancelled = Vec::new();
+        for shipping in shipments {
+            let status = shipping.status();
+            if !matches!(status, ShippingStatus::Pending | ShippingStatus::Processing) {
+                continue;
             }
+
+            self.shipping_repository
+                .update_status(shipping.id, ShippingStatus::Cancelled)
+                .await?;
+            cancelled.push(serde_json::json!({
+                "shipping_id": shipping.id,
+                "from": status.as_str(),
+            }));
         }
 
-        Ok(updated_order)
+        Ok((!cancelled.is_empty()).then(|| serde_json::json!({ "shipments": cancelled })))
+    }
+
+    /// Turns the order's reservations into stock movements. Reservations that
+    /// lapsed in the meantime take their units from available stock, and orders
+    /// accepted without a stock check take theirs now.
+    async fn commit_inventory_in_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        user_id: &str,
+    ) -> Result<()> {
+        if self
+            .order_repository
+            .take_pending_stock_check_with_transaction(tx, order_id)
+            .await?
+        {
+            return self
+                .deduct_unchecked_stock_in_transaction(tx, order_id, user_id)
+                .await;
+        }
+
+        // Orders placed before reservations were tracked had their stock
+        // deducted when they were created and have nothing to commit
+        let reservations = self
+            .inventory_repository
+            .find_stock_reservations_for_order_with_transaction(tx, order_id)
+            .await?;
+
+        let reference = format!("order:{}", order_id);
+        for reservation in &reservations {
+            let committed = self
+                .inventory_repository
+                .commit_reservation_with_transaction(tx, reservation, &reference, user_id)
+                .await?;
+
+            if !committed {
+                return Err(LogisticsError::BadRequest(format!(
+                    "Insufficient inventory for product {}",
+                    reservation.product_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Takes the stock for an order that was accepted while the inventory
+    /// service was unreachable and never had any reserved.
+    async fn deduct_unchecked_stock_in_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        user_id: &str,
+    ) -> Result<()> {
+        let reference = format!("order:{}", order_id);
+        let order_items = self
+            .order_item_repository
+            .find_by_order_id(order_id)
+            .await?;
+
+        for item in order_items
+            .iter()
+            .filter(|item| item.product_id != Uuid::nil())
+        {
+            let deducted = self
+                .inventory_repository
+                .deduct_stock_with_transaction(
+                    tx,
+                    item.product_id,
+                    item.quantity,
+                    &reference,
+                    user_id,
+                )
+                .await?;
+
+            if deducted.is_none() {
+                return Err(LogisticsError::BadRequest(format!(
+                    "Insufficient inventory for product {}",
+                    item.product_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn restore_inventory_in_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        order_id: Uuid,
+        user_id: &str,
+    ) -> Result<()> {
+        let reference = format!("order:{}", order_id);
+
+        // Serialized units picked for the order go back on the shelf
+        self.serial_repository
+            .release_for_order_with_transaction(tx, order_id, None, &reference)
+            .await?;
+
+        // Nothing was taken for an order still waiting for its stock check
+        if self
+            .order_repository
+            .take_pending_stock_check_with_transaction(tx, order_id)
+            .await?
+        {
+            return Ok(());
+        }
+
+        // Units still owed to the order are no longer needed
+        let backorders = self
+            .backorder_repository
+            .cancel_for_order_with_transaction(tx, order_id)
+            .await?;
+
+        let reservations = self
+            .inventory_repository
+            .find_stock_reservations_for_order_with_transaction(tx, order_id)
+            .await?;
+
+        // A fully backordered order may hold no reservations yet, but it never
+        // had stock deducted either
+        if !reservations.is_empty() || backorders > 0 {
+            for reservation in &reservations {
+                self.inventory_repository
+                    .release_reservation_with_transaction(tx, reservation, &reference, user_id)
+                    .await?;
+            }
+            return Ok(());
+        }
+
+        // Orders placed before reservations were tracked had their stock
+        // deducted at creation, so it goes straight back on hand
+        let order_items = self
+            .order_item_repository
+            .find_by_order_id(order_id)
+            .await?;
+
+        for item in order_items {
+            let restored = self
+                .inventory_repository
+                .return_stock_with_transaction(
+                    tx,
+                    item.product_id,
+                    item.quantity,
+                    &reference,
+                    user_id,
+                )
+                .await?;
+
+            match restored {
+                Some(_) => info!(
+                    "Restored inventory for product {} by {}",
+                    item.product_id, item.quantity
+                ),
+                None => warn!(
+                    "Could not restore inventory for product {}. Item may have been deleted.",
+                    item.product_id
+                ),
+            }
+        }
+
+        Ok(())
     }
 }
@@ -0,0 +1,233 @@
+use crate::config;
+use crate::db::repository::OutboxRepository;
+use crate::error::AppError;
+use crate::mq::publisher;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone)]
+pub struct OutboxRelayConfig {
+    pub poll_interval_ms: u64,
+    pub batch_size: u32,
+    pub retention_hours: i32,
+    /// Failed attempts after which an event is parked
+    pub max_attempts: i32,
+}
+
+impl From<config::OutboxConfig> for OutboxRelayConfig {
+    fn from(conf: config::OutboxConfig) -> Self {
+        Self {
+            poll_interval_ms: conf.poll_interval_ms,
+            batch_size: conf.batch_size,
+            retention_hours: conf.retention_hours,
+            max_attempts: conf.max_attempts,
+        }
+    }
+}
+
+/// Drains `outbox_events` into RabbitMQ. Events are marked as published only
+/// after the broker confirms them, so a crash between publish and commit leads
+/// to a redelivery with the same message id rather than a lost event. Events
+/// keep their order within an aggregate; an event that keeps failing is parked
+/// after `max_attempts` so the ones behind it can move on.
+pub struct OutboxRelayService {
+    config: OutboxRelayConfig,
+    outbox_repository: Arc<OutboxRepository>,
+    pool: Pool<Postgres>,
+    running: Arc<Mutex<bool>>,
+    task_handle: Option<JoinHandle<()>>,
+    shutdown_tx: Option<mpsc::Sender<()>>,
+}
+
+impl OutboxRelayService {
+    pub fn new(
+        config: OutboxRelayConfig,
+        outbox_repository: Arc<OutboxRepository>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            config,
+            outbox_repository,
+            pool,
+            running: Arc::new(Mutex::new(false)),
+            task_handle: None,
+            shutdown_tx: None,
+        }
+    }
+
+    pub async fn start(&mut self) -> Result<(), AppError> {
+        let mut running = self.running.lock().await;
+        if *running {
+            return Ok(());
+        }
+
+        let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
+        self.shutdown_tx = Some(shutdown_tx);
+
+        let config = self.config.clone();
+        let running_clone = self.running.clone();
+        let outbox_repository = self.outbox_repository.clone();
+        let pool = self.pool.clone();
+
+        let handle = tokio::spawn(async move {
+            *running_clone.lock().await = true;
+            info!("Outbox relay started");
+
+            let mut interval = time::interval(Duration::from_millis(config.poll_interval_ms));
+            let mut cleanup_interval = time::interval(Duration::from_secs(3600));
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        // Keep draining while full batches come back so a backlog
+                        // does not wait for the next tick
+                        loop {
+                            match Self::relay_batch(&config, &outbox_repository, &pool).await {
+                                Ok(published) if published == config.batch_size as usize => continue,
+                                Ok(_) => break,
+                                Err(e) => {
+                                    warn!("Outbox relay batch failed: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    _ = cleanup_interval.tick() => {
+                        match outbox_repository
+                            .delete_published_older_than(config.retention_hours)
+                            .await
+                        {
+                            Ok(0) => {}
+                            Ok(deleted) => info!("Purged {} published outbox events", deleted),
+                            Err(e) => warn!("Failed to purge published outbox events: {}", e),
+                        }
+                    }
+                    _ = shutdown_rx.recv() => {
+                        info!("Shutting down outbox relay");
+                        break;
+                    }
+                }
+            }
+
+            *running_clone.lock().await = false;
+            info!("Outbox relay stopped");
+        });
+
+        self.task_handle = Some(handle);
+        *running = true;
+
+        Ok(())
+    }
+
+    pub async fn stop(&mut self) -> Result<(), AppError> {
+        // The guard is dropped right away: the task clears `running` itself on
+        // its way out and would otherwise wait on us for the whole grace period
+        if !*self.running.lock().await {
+            return Ok(());
+        }
+
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(()).await;
+        }
+
+        // Let an in-flight batch commit so confirmed events are not republished
+        if let Some(handle) = self.task_handle.take() {
+            let grace = Duration::from_secs(config::get().server.graceful_shutdown_seconds);
+            if time::timeout(grace, handle).await.is_err() {
+                error!("Outbox relay did not stop within {:?}", grace);
+            }
+        }
+
+        *self.running.lock().await = false;
+        Ok(())
+    }
+
+    pub async fn is_running(&self) -> bool {
+        *self.running.lock().await
+    }
+
+    async fn relay_batch(
+        config: &OutboxRelayConfig,
+        outbox_repository: &OutboxRepository,
+        pool: &Pool<Postgres>,
+    ) -> Result<usize, AppError> {
+        let mut tx = pool.begin().await?;
+
+        let events = outbox_repository
+            .claim_pending_with_transaction(&mut tx, config.batch_size as i64)
+            .await?;
+
+        if events.is_empty() {
+            tx.commit().await?;
+            return Ok(0);
+        }
+
+        let channel = publisher::create_confirm_channel().await?;
+        let mut published = 0;
+        // Aggregates whose events have to wait for one that failed
+        let mut held_back = HashSet::new();
+
+        for event in &events {
+            if held_back.contains(&event.aggregate_id) {
+                continue;
+            }
+
+            let payload = serde_json::to_vec(&event.payload).map_err(|e| {
+                AppError::InternalServerError(format!("JSON serialization error: {}", e))
+            })?;
+
+            match publisher::publish_confirmed(
+                &channel,
+                &event.routing_key,
+                &event.id.to_string(),
+                &payload,
+            )
+            .await
+            {
+                Ok(()) => {
+                    outbox_repository
+                        .mark_published_with_transaction(&mut tx, event.id)
+                        .await?;
+                    published += 1;
+                }
+                Err(e) => {
+                    warn!(
+                        "Failed to publish outbox event {} ({}): {}",
+                        event.id, event.event_type, e
+                    );
+                    let parked = outbox_repository
+                        .record_failure_with_transaction(
+                            &mut tx,
+                            event.id,
+                            &e.to_string(),
+                            config.max_attempts,
+                        )
+                        .await?;
+                    if parked {
+                        error!(
+                            "Parked outbox event {} ({}) after {} failed attempts",
+                            event.id,
+                            event.event_type,
+                            event.attempts + 1
+                        );
+                    }
+                    // Later events of the same aggregate wait for this one, the
+                    // rest of the batch goes on
+                    held_back.insert(event.aggregate_id);
+                }
+            }
+        }
+
+        tx.commit().await?;
+
+        let _ = channel.close(200, "OK").await;
+
+        Ok(published)
+    }
+}
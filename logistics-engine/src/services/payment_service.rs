@@ -1,3 +1,5 @@
+use rust_decimal::Decimal;
+use sqlx::{Postgres, Transaction};
 use std::sync::Arc;
 use uuid::Uuid;
 
@@ -5,10 +7,13 @@ use crate::{
     db::repository::payment_repository::PaymentRepository,
     errors::{LogisticsError, Result},
     models::{
+        activity::{ActivitySeverity, NewActivity},
         dto::payment::{CreatePaymentInfoDto, PaymentInfoDto as PaymentDto, UpdatePaymentInfoDto},
         entities::payment_info::PaymentInfo,
         payment::PaymentStatus,
     },
+    realtime::live_feed::{self, LiveTopic},
+    services::ActivityService,
 };
 
 fn convert_to_dto(payment: PaymentInfo) -> PaymentDto {
@@ -31,11 +36,53 @@ fn convert_to_dto(payment: PaymentInfo) -> PaymentDto {
 
 pub struct PaymentService {
     repository: Arc<PaymentRepository>,
+    activity_service: Arc<ActivityService>,
 }
 
 impl PaymentService {
-    pub fn new(repository: Arc<PaymentRepository>) -> Self {
-        Self { repository }
+    pub fn new(repository: Arc<PaymentRepository>, activity_service: Arc<ActivityService>) -> Self {
+        Self {
+            repository,
+            activity_service,
+        }
+    }
+
+    // Pushes a payment change to the live feed and the activity log and hands
+    // the payment back
+    async fn notify(
+        &self,
+        event_type: &str,
+        severity: ActivitySeverity,
+        payment: PaymentDto,
+    ) -> PaymentDto {
+        live_feed::publish(
+            LiveTopic::Payments,
+            event_type,
+            Some(payment.id.to_string()),
+            &payment,
+        );
+
+        let activity = NewActivity::new(
+            "payment",
+            payment.id,
+            event_type,
+            severity,
+            format!(
+                "Payment {} for order {} {} ({} {})",
+                payment.id,
+                payment.order_id,
+                event_type.trim_start_matches("payment."),
+                payment.amount,
+                payment.currency
+            ),
+        )
+        .with_metadata(serde_json::json!({
+            "order_id": payment.order_id,
+            "status": payment.status,
+        }));
+        self.activity_service.record(activity).await;
+
+        payment
     }
 
     pub async fn get_all_payments(&self, limit: i64, offset: i64) -> Result<Vec<PaymentDto>> {
@@ -96,7 +143,13 @@ impl PaymentService {
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(convert_to_dto(payment))
+        Ok(self
+            .notify(
+                "payment.created",
+                ActivitySeverity::Info,
+                convert_to_dto(payment),
+            )
+            .await)
     }
 
     pub async fn update_payment(
@@ -118,7 +171,17 @@ impl PaymentService {
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(updated.map(convert_to_dto))
+        match updated {
+            Some(payment) => Ok(Some(
+                self.notify(
+                    "payment.updated",
+                    ActivitySeverity::Info,
+                    convert_to_dto(payment),
+                )
+                .await,
+            )),
+            None => Ok(None),
+        }
     }
 
     pub async fn process_payment(
@@ -132,7 +195,17 @@ impl PaymentService {
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(updated.map(convert_to_dto))
+        match updated {
+            Some(payment) => Ok(Some(
+                self.notify(
+                    "payment.succeeded",
+                    ActivitySeverity::Success,
+                    convert_to_dto(payment),
+                )
+                .await,
+            )),
+            None => Ok(None),
+        }
     }
 
     pub async fn refund_payment(&self, id: &Uuid) -> Result<Option<PaymentDto>> {
@@ -151,12 +224,100 @@ impl PaymentService {
                 .await
                 .map_err(LogisticsError::from)?;
 
-            Ok(updated.map(convert_to_dto))
+            match updated {
+                Some(payment) => Ok(Some(
+                    self.notify(
+                        "payment.refunded",
+                        ActivitySeverity::Warning,
+                        convert_to_dto(payment),
+                    )
+                    .await,
+                )),
+                None => Ok(None),
+            }
         } else {
             Err(LogisticsError::NotFound("Payment", id.to_string()))
         }
     }
 
+    // Checks the payment can take a refund of `amount`
+    async fn check_refundable(&self, id: &Uuid, amount: Decimal) -> Result<()> {
+        if amount <= Decimal::ZERO {
+            return Err(LogisticsError::ValidationError(
+                "Refund amount must be greater than 0".to_string(),
+            ));
+        }
+
+        let payment = self
+            .repository
+            .find_by_id(*id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Payment", id.to_string()))?;
+
+        if !matches!(
+            payment.status(),
+            PaymentStatus::Succeeded | PaymentStatus::PartiallyRefunded
+        ) {
+            return Err(LogisticsError::ValidationError(
+                "Only succeeded payments can be refunded".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn refund_exceeded(id: &Uuid, amount: Decimal) -> LogisticsError {
+        LogisticsError::ValidationError(format!(
+            "Refund of {} exceeds what is left to refund on payment {}",
+            amount, id
+        ))
+    }
+
+    /// Refunds part of a succeeded payment. The payment is `partially_refunded`
+    /// until refunds add up to its amount, then `refunded`.
+    pub async fn refund_amount(&self, id: &Uuid, amount: Decimal) -> Result<PaymentDto> {
+        self.check_refundable(id, amount).await?;
+
+        let refunded = self
+            .repository
+            .record_refund(*id, amount)
+            .await?
+            .ok_or_else(|| Self::refund_exceeded(id, amount))?;
+
+        Ok(self.notify_refund(refunded).await)
+    }
+
+    /// Refunds part of a succeeded payment as part of a larger change, which
+    /// announces it with `notify_refund` once committed.
+    pub async fn refund_amount_with_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        id: &Uuid,
+        amount: Decimal,
+    ) -> Result<PaymentInfo> {
+        self.check_refundable(id, amount).await?;
+
+        self.repository
+            .record_refund_with_transaction(tx, *id, amount)
+            .await?
+            .ok_or_else(|| Self::refund_exceeded(id, amount))
+    }
+
+    /// Announces a committed refund.
+    pub async fn notify_refund(&self, refunded: PaymentInfo) -> PaymentDto {
+        let event_type = if refunded.status() == PaymentStatus::Refunded {
+            "payment.refunded"
+        } else {
+            "payment.partially_refunded"
+        };
+        self.notify(
+            event_type,
+            ActivitySeverity::Warning,
+            convert_to_dto(refunded),
+        )
+        .await
+    }
+
     pub async fn cancel_payment(&self, id: &Uuid) -> Result<Option<PaymentDto>> {
         let payment = self.get_payment_by_id(id).await?;
 
@@ -173,7 +334,17 @@ impl PaymentService {
                 .await
                 .map_err(LogisticsError::from)?;
 
-            Ok(updated.map(convert_to_dto))
+            match updated {
+                Some(payment) => Ok(Some(
+                    self.notify(
+                        "payment.cancelled",
+                        ActivitySeverity::Warning,
+                        convert_to_dto(payment),
+                    )
+                    .await,
+                )),
+                None => Ok(None),
+            }
         } else {
             Err(LogisticsError::NotFound("Payment", id.to_string()))
         }
@@ -0,0 +1,319 @@
+use crate::config;
+use crate::db::repository::{ActivityRepository, InventoryRepository, OutboxRepository};
+use crate::error::AppError;
+use crate::models::activity::{ActivitySeverity, NewActivity};
+use crate::models::inventory::{StockReservation, RESERVATION_SWEEPER_ACTOR};
+use crate::models::outbox::NewOutboxEvent;
+use crate::mq::events::{EventType, InventoryReleasedEvent};
+use crate::mq::publisher;
+use crate::realtime::live_feed::{self, LiveTopic};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone)]
+pub struct ReservationSweeperConfig {
+    pub interval_seconds: u64,
+    pub batch_size: u32,
+}
+
+impl From<config::ReservationSweeperConfig> for ReservationSweeperConfig {
+    fn from(conf: config::ReservationSweeperConfig) -> Self {
+        Self {
+            interval_seconds: conf.interval_seconds,
+            batch_size: conf.batch_size.max(1),
+        }
+    }
+}
+
+/// Counters shared between the sweeper task and the admin endpoint.
+#[derive(Debug, Default)]
+pub struct ReservationSweeperMetrics {
+    running: AtomicBool,
+    sweeps: AtomicU64,
+    failed_sweeps: AtomicU64,
+    reservations_released: AtomicU64,
+    quantity_released: AtomicU64,
+    // Unix millis of the last completed sweep, 0 before the first one
+    last_sweep_at: AtomicI64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReservationSweeperSnapshot {
+    pub running: bool,
+    pub sweeps: u64,
+    pub failed_sweeps: u64,
+    pub reservations_released: u64,
+    pub quantity_released: u64,
+    pub last_sweep_at: Option<DateTime<Utc>>,
+}
+
+impl ReservationSweeperMetrics {
+    fn record_sweep(&self, released: &[StockReservation]) {
+        let quantity: i64 = released.iter().map(|r| r.quantity as i64).sum();
+
+        self.sweeps.fetch_add(1, Ordering::Relaxed);
+        self.reservations_released
+            .fetch_add(released.len() as u64, Ordering::Relaxed);
+        self.quantity_released
+            .fetch_add(quantity.max(0) as u64, Ordering::Relaxed);
+        self.last_sweep_at
+            .store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::Relaxed);
+    }
+
+    fn record_failure(&self) {
+        self.failed_sweeps.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> ReservationSweeperSnapshot {
+        let last_sweep_at = match self.last_sweep_at.load(Ordering::Relaxed) {
+            0 => None,
+            millis => DateTime::<Utc>::from_timestamp_millis(millis),
+        };
+
+        ReservationSweeperSnapshot {
+            running: self.running.load(Ordering::Relaxed),
+            sweeps: self.sweeps.load(Ordering::Relaxed),
+            failed_sweeps: self.failed_sweeps.load(Ordering::Relaxed),
+            reservations_released: self.reservations_released.load(Ordering::Relaxed),
+            quantity_released: self.quantity_released.load(Ordering::Relaxed),
+            last_sweep_at,
+        }
+    }
+}
+
+/// Releases pending inventory reservations once they pass `expires_at`, so
+/// stock held for abandoned orders goes back on sale.
+pub struct ReservationSweeperService {
+    config: ReservationSweeperConfig,
+    inventory_repository: Arc<InventoryRepository>,
+    outbox_repository: Arc<OutboxRepository>,
+    activity_repository: Arc<ActivityRepository>,
+    pool: Pool<Postgres>,
+    metrics: Arc<ReservationSweeperMetrics>,
+    running: Arc<Mutex<bool>>,
+    task_handle: Option<JoinHandle<()>>,
+    shutdown_tx: Option<mpsc::Sender<()>>,
+}
+
+impl ReservationSweeperService {
+    pub fn new(
+        config: ReservationSweeperConfig,
+        inventory_repository: Arc<InventoryRepository>,
+        outbox_repository: Arc<OutboxRepository>,
+        activity_repository: Arc<ActivityRepository>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            config,
+            inventory_repository,
+            outbox_repository,
+            activity_repository,
+            pool,
+            metrics: Arc::new(ReservationSweeperMetrics::default()),
+            running: Arc::new(Mutex::new(false)),
+            task_handle: None,
+            shutdown_tx: None,
+        }
+    }
+
+    pub fn metrics(&self) -> Arc<ReservationSweeperMetrics> {
+        self.metrics.clone()
+    }
+
+    pub async fn start(&mut self) -> Result<(), AppError> {
+        let mut running = self.running.lock().await;
+        if *running {
+            return Ok(());
+        }
+
+        let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
+        self.shutdown_tx = Some(shutdown_tx);
+
+        let config = self.config.clone();
+        let running_clone = self.running.clone();
+        let inventory_repository = self.inventory_repository.clone();
+        let outbox_repository = self.outbox_repository.clone();
+        let activity_repository = self.activity_repository.clone();
+        let pool = self.pool.clone();
+        let metrics = self.metrics.clone();
+
+        let handle = tokio::spawn(async move {
+            *running_clone.lock().await = true;
+            metrics.set_running(true);
+            info!("Reservation sweeper started");
+
+            let mut interval = time::interval(Duration::from_secs(config.interval_seconds.max(1)));
+
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        // Keep sweeping while full batches come back so a backlog
+                        // does not wait for the next tick
+                        loop {
+                            match Self::sweep_batch(
+                                &config,
+                                &inventory_repository,
+                                &outbox_repository,
+                                &activity_repository,
+                                &pool,
+                            )
+                            .await
+                            {
+                                Ok(released) => {
+                                    metrics.record_sweep(&released);
+                                    if !released.is_empty() {
+                                        info!("Released {} expired reservations", released.len());
+                                    }
+                                    if released.len() < config.batch_size as usize {
+                                        break;
+                                    }
+                                }
+                                Err(e) => {
+                                    metrics.record_failure();
+                                    warn!("Reservation sweep failed: {}", e);
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    _ = shutdown_rx.recv() => {
+                        info!("Shutting down reservation sweeper");
+                        break;
+                    }
+                }
+            }
+
+            *running_clone.lock().await = false;
+            metrics.set_running(false);
+            info!("Reservation sweeper stopped");
+        });
+
+        self.task_handle = Some(handle);
+        *running = true;
+
+        Ok(())
+    }
+
+    pub async fn stop(&mut self) -> Result<(), AppError> {
+        // Not held while waiting below, since the task clears `running` itself
+        if !*self.running.lock().await {
+            return Ok(());
+        }
+
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(()).await;
+        }
+
+        // Let an in-flight batch commit rather than rolling back half a sweep
+        if let Some(handle) = self.task_handle.take() {
+            let grace = Duration::from_secs(config::get().server.graceful_shutdown_seconds);
+            if time::timeout(grace, handle).await.is_err() {
+                error!("Reservation sweeper did not stop within {:?}", grace);
+            }
+        }
+
+        *self.running.lock().await = false;
+        Ok(())
+    }
+
+    pub async fn is_running(&self) -> bool {
+        *self.running.lock().await
+    }
+
+    /// Releases one batch of expired reservations in a single transaction and
+    /// returns the reservations that were released.
+    async fn sweep_batch(
+        config: &ReservationSweeperConfig,
+        inventory_repository: &InventoryRepository,
+        outbox_repository: &OutboxRepository,
+        activity_repository: &ActivityRepository,
+        pool: &Pool<Postgres>,
+    ) -> Result<Vec<StockReservation>, AppError> {
+        let mut tx = pool.begin().await?;
+
+        let expired = inventory_repository
+            .claim_expired_reservations_with_transaction(&mut tx, config.batch_size as i64)
+            .await?;
+
+        if expired.is_empty() {
+            tx.commit().await?;
+            return Ok(expired);
+        }
+
+        for reservation in &expired {
+            inventory_repository
+                .release_reservation_with_transaction(
+                    &mut tx,
+                    reservation,
+                    &format!("reservation-expired:{}", reservation.id),
+                    RESERVATION_SWEEPER_ACTOR,
+                )
+                .await?;
+
+            let event = NewOutboxEvent::from_event(
+                "inventory",
+                reservation.id,
+                "inventory.released",
+                publisher::build_event(
+                    EventType::InventoryReleased,
+                    InventoryReleasedEvent {
+                        reservation_id: reservation.id.to_string(),
+                        order_id: reservation.order_id,
+                        reason: "Reservation expired".to_string(),
+                    },
+                ),
+            )
+            .map_err(|e| {
+                AppError::InternalServerError(format!("JSON serialization error: {}", e))
+            })?;
+            outbox_repository
+                .create_with_transaction(&mut tx, &event)
+                .await?;
+
+            let activity = NewActivity::new(
+                "inventory",
+                reservation.product_id,
+                "inventory.reservation_expired",
+                ActivitySeverity::Info,
+                format!(
+                    "Released {} x {} held by expired reservation for order {}",
+                    reservation.quantity, reservation.sku, reservation.order_id
+                ),
+            )
+            .with_actor(Some("reservation-sweeper".to_string()))
+            .with_metadata(serde_json::json!({
+                "reservation_id": reservation.id,
+                "order_id": reservation.order_id,
+                "quantity": reservation.quantity,
+            }));
+            activity_repository
+                .create_with_transaction(&mut tx, &activity)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        for reservation in &expired {
+            live_feed::publish(
+                LiveTopic::Inventory,
+                "inventory.reservation_expired",
+                Some(reservation.id.to_string()),
+                reservation,
+            );
+        }
+
+        Ok(expired)
+    }
+}
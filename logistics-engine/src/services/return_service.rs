@@ -0,0 +1,793 @@
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::db::repository::{
+    InventoryRepository, OrderItemRepository, OutboxRepository, PaymentRepository,
+    ReturnRepository, SerialRepository,
+};
+use crate::errors::{LogisticsError, Result};
+use crate::models::{
+    activity::{ActivitySeverity, NewActivity},
+    entities::order::OrderStatus,
+    inventory::ANONYMOUS_ACTOR,
+    outbox::NewOutboxEvent,
+    payment::PaymentStatus,
+    rma::{CreateReturnDto, Disposition, ReceiveReturnDto, ReturnAuthorization, ReturnStatus},
+    serial::{is_serial_tracked, normalize_serial_numbers, SerialStatus},
+};
+use crate::mq::events::{
+    EventType, ReturnAuthorizedEvent, ReturnCancelledEvent, ReturnReceivedEvent,
+    ReturnRefundedEvent, ReturnedItem,
+};
+use crate::mq::publisher;
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::services::{ActivityService, InventoryService, OrderService, PaymentService};
+
+pub struct ReturnService {
+    repository: Arc<ReturnRepository>,
+    order_item_repository: Arc<OrderItemRepository>,
+    inventory_repository: Arc<InventoryRepository>,
+    payment_repository: Arc<PaymentRepository>,
+    outbox_repository: Arc<OutboxRepository>,
+    serial_repository: Arc<SerialRepository>,
+    order_service: Arc<OrderService>,
+    inventory_service: Arc<InventoryService>,
+    payment_service: Arc<PaymentService>,
+    activity_service: Arc<ActivityService>,
+    pool: Pool<Postgres>,
+}
+
+impl ReturnService {
+    pub fn new(
+        repository: Arc<ReturnRepository>,
+        order_item_repository: Arc<OrderItemRepository>,
+        inventory_repository: Arc<InventoryRepository>,
+        payment_repository: Arc<PaymentRepository>,
+        outbox_repository: Arc<OutboxRepository>,
+        serial_repository: Arc<SerialRepository>,
+        order_service: Arc<OrderService>,
+        inventory_service: Arc<InventoryService>,
+        payment_service: Arc<PaymentService>,
+        activity_service: Arc<ActivityService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            order_item_repository,
+            inventory_repository,
+            payment_repository,
+            outbox_repository,
+            serial_repository,
+            order_service,
+            inventory_service,
+            payment_service,
+            activity_service,
+            pool,
+        }
+    }
+
+    fn returned_items(rma: &ReturnAuthorization) -> Vec<ReturnedItem> {
+        rma.items
+            .iter()
+            .map(|item| ReturnedItem {
+                order_item_id: item.order_item_id,
+                product_id: item.product_id,
+                sku: item.sku.clone(),
+                quantity: item.received_quantity.unwrap_or(item.quantity),
+                condition: item
+                    .condition
+                    .map(|condition| condition.as_str().to_string()),
+                disposition: item
+                    .disposition
+                    .map(|disposition| disposition.as_str().to_string()),
+            })
+            .collect()
+    }
+
+    /// Stores a return event in the outbox; the relay publishes it on the
+    /// order exchange once the surrounding transaction commits.
+    async fn enqueue_event<T: Serialize>(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        return_id: Uuid,
+        event_type: EventType,
+        routing_key: &str,
+        data: T,
+    ) -> Result<()> {
+        let event = NewOutboxEvent::from_event(
+            "return",
+            return_id,
+            routing_key,
+            publisher::build_event(event_type, data),
+        )
+        .map_err(|e| LogisticsError::InternalError(format!("Failed to serialize event: {}", e)))?;
+
+        self.outbox_repository
+            .create_with_transaction(tx, &event)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    // Pushes a committed return change to the live feed and the activity log
+    // and hands the return back
+    async fn notify(
+        &self,
+        event_type: &str,
+        severity: ActivitySeverity,
+        message: String,
+        rma: ReturnAuthorization,
+    ) -> ReturnAuthorization {
+        live_feed::publish(
+            LiveTopic::Orders,
+            event_type,
+            Some(rma.order_id.to_string()),
+            &rma,
+        );
+
+        let activity = NewActivity::new("return", rma.id, event_type, severity, message)
+            .with_metadata(serde_json::json!({
+                "rma_number": rma.rma_number,
+                "order_id": rma.order_id,
+                "status": rma.status,
+            }));
+        self.activity_service.record(activity).await;
+
+        rma
+    }
+
+    pub async fn get_return(&self, id: Uuid) -> Result<ReturnAuthorization> {
+        self.repository
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))
+    }
+
+    pub async fn get_returns(
+        &self,
+        status: Option<ReturnStatus>,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<ReturnAuthorization>> {
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.repository
+            .find_all(status, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    pub async fn get_order_returns(&self, order_id: Uuid) -> Result<Vec<ReturnAuthorization>> {
+        self.order_service.get_order_by_id(order_id).await?;
+
+        self.repository
+            .find_by_order_id(order_id)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Authorizes the return of some of a delivered order's units. A line can
+    /// only be returned up to what was ordered, counting earlier returns that
+    /// were not cancelled.
+    pub async fn create_return(
+        &self,
+        order_id: Uuid,
+        dto: CreateReturnDto,
+    ) -> Result<ReturnAuthorization> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let status = self
+            .repository
+            .lock_order_with_transaction(&mut tx, order_id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Order", order_id.to_string()))?;
+
+        if !matches!(
+            status,
+            OrderStatus::Delivered | OrderStatus::PartiallyDelivered
+        ) {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Only delivered orders can be returned; order {} is {}",
+                order_id,
+                status.to_string()
+            )));
+        }
+
+        let order_items = self
+            .order_item_repository
+            .find_by_order_id_with_transaction(&mut tx, order_id)
+            .await?;
+        let returned = self
+            .repository
+            .returned_quantities_with_transaction(&mut tx, order_id)
+            .await?;
+
+        let id = Uuid::new_v4();
+        let rma_number = format!(
+            "RMA-{}-{}",
+            Utc::now().format("%Y%m%d"),
+            id.simple().to_string()[..8].to_uppercase()
+        );
+        self.repository
+            .create_with_transaction(
+                &mut tx,
+                id,
+                &rma_number,
+                order_id,
+                dto.reason_code,
+                dto.notes.as_deref(),
+                dto.requested_by.as_deref(),
+            )
+            .await?;
+
+        let mut seen = HashSet::new();
+        for line in &dto.items {
+            let order_item = match order_items
+                .iter()
+                .find(|item| item.id == line.order_item_id)
+            {
+                Some(order_item) if seen.insert(order_item.id) => order_item,
+                Some(_) => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Order item {} is listed more than once",
+                        line.order_item_id
+                    )));
+                }
+                None => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Order item {} does not belong to order {}",
+                        line.order_item_id, order_id
+                    )));
+                }
+            };
+
+            let returnable =
+                order_item.quantity as i64 - returned.get(&order_item.id).copied().unwrap_or(0);
+            if line.quantity as i64 > returnable {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Cannot return {} of {}: only {} left to return",
+                    line.quantity,
+                    order_item.sku,
+                    returnable.max(0)
+                )));
+            }
+
+            let unit_price =
+                Decimal::from_str(&order_item.unit_price.to_string()).unwrap_or_default();
+            self.repository
+                .add_item_with_transaction(
+                    &mut tx,
+                    id,
+                    order_item.id,
+                    order_item.product_id,
+                    &order_item.sku,
+                    line.quantity,
+                    unit_price,
+                    line.reason_code.unwrap_or(dto.reason_code),
+                )
+                .await?;
+        }
+
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        self.enqueue_event(
+            &mut tx,
+            rma.id,
+            EventType::ReturnAuthorized,
+            "return.authorized",
+            ReturnAuthorizedEvent {
+                return_id: rma.id,
+                rma_number: rma.rma_number.clone(),
+                order_id,
+                reason_code: rma.reason_code.as_str().to_string(),
+                items: Self::returned_items(&rma),
+            },
+        )
+        .await?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = format!(
+            "Return {} authorized for order {} ({} line(s))",
+            rma.rma_number,
+            order_id,
+            rma.items.len()
+        );
+        Ok(self
+            .notify("return.authorized", ActivitySeverity::Info, message, rma)
+            .await)
+    }
+
+    /// Records what arrived, its condition and what happens to it. Restocked
+    /// units go back into inventory with an `add` transaction, and the refund
+    /// for the units that arrived is issued right away.
+    pub async fn receive_return(
+        &self,
+        id: Uuid,
+        dto: ReceiveReturnDto,
+    ) -> Result<ReturnAuthorization> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        if let Some(warehouse_id) = dto.warehouse_id {
+            if self
+                .inventory_repository
+                .find_warehouse_by_id(warehouse_id)
+                .await?
+                .is_none()
+            {
+                return Err(LogisticsError::NotFound(
+                    "Warehouse",
+                    warehouse_id.to_string(),
+                ));
+            }
+        }
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        if rma.status != ReturnStatus::Authorized {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Return {} is {} and cannot be received",
+                rma.rma_number,
+                rma.status.as_str()
+            )));
+        }
+
+        let mut received = HashMap::new();
+        let mut returned_serials = HashMap::new();
+        for line in &dto.items {
+            let item = match rma.items.iter().find(|item| item.id == line.return_item_id) {
+                Some(item) => item,
+                None => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Item {} is not part of return {}",
+                        line.return_item_id, rma.rma_number
+                    )));
+                }
+            };
+
+            let quantity = line.quantity.unwrap_or(item.quantity);
+            if quantity > item.quantity {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Received {} of {} but only {} were authorized",
+                    quantity, item.sku, item.quantity
+                )));
+            }
+            if received.insert(item.id, (quantity, line)).is_some() {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Item {} is listed more than once",
+                    item.id
+                )));
+            }
+
+            // Serialized units must be the ones that shipped on the line
+            let tracked = match self.inventory_service.get_item_by_id(item.product_id).await {
+                Ok(product) => is_serial_tracked(product.attributes.as_ref()),
+                Err(_) => false,
+            };
+            if !tracked {
+                if !line.serial_numbers.is_empty() {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "{} is not serial-tracked",
+                        item.sku
+                    )));
+                }
+                continue;
+            }
+
+            let serial_numbers = match normalize_serial_numbers(&line.serial_numbers) {
+                Ok(serial_numbers) if serial_numbers.len() == quantity as usize => serial_numbers,
+                Ok(serial_numbers) => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Received {} of {} but {} serial number(s) were given",
+                        quantity,
+                        item.sku,
+                        serial_numbers.len()
+                    )));
+                }
+                Err(e) => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(e));
+                }
+            };
+            let serials = self
+                .serial_repository
+                .lock_by_numbers_with_transaction(&mut tx, item.product_id, &serial_numbers)
+                .await?;
+            if let Some(serial_number) = serial_numbers.iter().find(|number| {
+                !serials.iter().any(|serial| {
+                    &serial.serial_number == *number
+                        && serial.status == SerialStatus::Shipped
+                        && serial.order_item_id == Some(item.order_item_id)
+                })
+            }) {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Serial number {} of {} was not shipped on this order",
+                    serial_number, item.sku
+                )));
+            }
+            returned_serials.insert(
+                item.id,
+                serials.iter().map(|serial| serial.id).collect::<Vec<_>>(),
+            );
+        }
+
+        let reference = format!("rma:{}", rma.rma_number);
+        let order_reference = format!("order:{}", rma.order_id);
+        let mut refund_amount = Decimal::ZERO;
+        let mut restocked = Vec::new();
+        for item in &rma.items {
+            let (quantity, condition, disposition) = match received.get(&item.id) {
+                Some((quantity, line)) => (*quantity, Some(line.condition), Some(line.disposition)),
+                None => (0, None, None),
+            };
+
+            self.repository
+                .receive_item_with_transaction(&mut tx, item.id, quantity, condition, disposition)
+                .await?;
+            refund_amount += item.refund_for(quantity);
+
+            let mut restock_warehouse_id = None;
+            if quantity > 0 && disposition == Some(Disposition::Restock) {
+                // The units go back where the parcel arrived, or else where
+                // they shipped from
+                let warehouse_id = match dto.warehouse_id {
+                    Some(warehouse_id) => Some(warehouse_id),
+                    None => {
+                        self.inventory_repository
+                            .find_shipped_from_warehouse_with_transaction(
+                                &mut tx,
+                                item.product_id,
+                                &order_reference,
+                            )
+                            .await?
+                    }
+                };
+                let level = self
+                    .inventory_repository
+                    .return_stock_to_with_transaction(
+                        &mut tx,
+                        item.product_id,
+                        warehouse_id,
+                        quantity,
+                        &reference,
+                        dto.received_by.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+                    )
+                    .await?;
+                match level {
+                    Some(level) => {
+                        restock_warehouse_id = Some(level.warehouse_id);
+                        restocked.push((item.product_id, level.warehouse_id));
+                    }
+                    None => {
+                        tx.rollback().await.ok();
+                        return Err(LogisticsError::NotFound(
+                            "Inventory Item",
+                            item.product_id.to_string(),
+                        ));
+                    }
+                }
+            }
+
+            if let Some(serial_ids) = returned_serials.get(&item.id) {
+                self.serial_repository
+                    .return_with_transaction(
+                        &mut tx,
+                        serial_ids,
+                        id,
+                        restock_warehouse_id,
+                        &reference,
+                    )
+                    .await?;
+            }
+        }
+
+        self.repository
+            .mark_received_with_transaction(&mut tx, id, refund_amount)
+            .await?;
+
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        self.enqueue_event(
+            &mut tx,
+            rma.id,
+            EventType::ReturnReceived,
+            "return.received",
+            ReturnReceivedEvent {
+                return_id: rma.id,
+                rma_number: rma.rma_number.clone(),
+                order_id: rma.order_id,
+                items: Self::returned_items(&rma),
+                refund_amount: refund_amount.to_string(),
+            },
+        )
+        .await?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = format!(
+            "Return {} received; {} product(s) restocked, {} to refund",
+            rma.rma_number,
+            restocked.len(),
+            refund_amount
+        );
+        self.notify("return.received", ActivitySeverity::Info, message, rma)
+            .await;
+
+        // Restocked units may be owed to backorders
+        for (product_id, warehouse_id) in restocked {
+            if let Err(e) = self
+                .inventory_service
+                .allocate_backorders(product_id, warehouse_id)
+                .await
+            {
+                warn!(
+                    "Failed to allocate backorders for item {}: {}",
+                    product_id, e
+                );
+            }
+        }
+
+        self.refund_return(id).await
+    }
+
+    /// Refunds a received return through its order's payment. Retrying a
+    /// return that is already refunded returns it unchanged.
+    pub async fn refund_return(&self, id: Uuid) -> Result<ReturnAuthorization> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        // The lock is held while the payment is refunded, so a concurrent retry
+        // waits and then finds the return refunded
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        match rma.status {
+            ReturnStatus::Refunded => {
+                tx.rollback().await.ok();
+                return Ok(rma);
+            }
+            ReturnStatus::Received => {}
+            status => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Return {} is {} and cannot be refunded",
+                    rma.rma_number,
+                    status.as_str()
+                )));
+            }
+        }
+
+        let amount = rma.refund_amount.unwrap_or_default();
+        let payment = if amount > Decimal::ZERO {
+            let payment = self
+                .payment_repository
+                .find_by_order_id(rma.order_id)
+                .await?
+                .into_iter()
+                .find(|payment| {
+                    matches!(
+                        payment.status(),
+                        PaymentStatus::Succeeded | PaymentStatus::PartiallyRefunded
+                    )
+                });
+
+            let payment = match payment {
+                Some(payment) => payment,
+                None => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Order {} has no settled payment to refund",
+                        rma.order_id
+                    )));
+                }
+            };
+
+            // Recorded with the return, so a failure leaves neither refunded
+            // and a retry cannot refund the customer twice
+            Some(
+                self.payment_service
+                    .refund_amount_with_transaction(&mut tx, &payment.id, amount)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        self.repository
+            .mark_refunded_with_transaction(&mut tx, id, payment.as_ref().map(|payment| payment.id))
+            .await?;
+
+        if let Some(payment) = &payment {
+            self.enqueue_event(
+                &mut tx,
+                rma.id,
+                EventType::ReturnRefunded,
+                "return.refunded",
+                ReturnRefundedEvent {
+                    return_id: rma.id,
+                    rma_number: rma.rma_number.clone(),
+                    order_id: rma.order_id,
+                    payment_id: payment.id,
+                    amount: amount.to_string(),
+                    currency: payment.currency.clone(),
+                },
+            )
+            .await?;
+        }
+
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        if let Some(payment) = payment {
+            self.payment_service.notify_refund(payment).await;
+        }
+        self.mark_order_returned(&rma).await;
+
+        let message = format!("Return {} refunded {}", rma.rma_number, amount);
+        Ok(self
+            .notify("return.refunded", ActivitySeverity::Success, message, rma)
+            .await)
+    }
+
+    /// Whether refunded returns cover every unit the order was sold.
+    async fn is_fully_returned(&self, order_id: Uuid) -> Result<bool> {
+        let order_items = self
+            .order_item_repository
+            .find_by_order_id(order_id)
+            .await?;
+        let returns = self.repository.find_by_order_id(order_id).await?;
+
+        let mut returned: HashMap<Uuid, i32> = HashMap::new();
+        for item in returns
+            .iter()
+            .filter(|rma| rma.status == ReturnStatus::Refunded)
+            .flat_map(|rma| rma.items.iter())
+        {
+            *returned.entry(item.order_item_id).or_default() += item.received_quantity.unwrap_or(0);
+        }
+
+        Ok(order_items
+            .iter()
+            .all(|item| returned.get(&item.id).copied().unwrap_or(0) >= item.quantity))
+    }
+
+    // Moves the order to Returned once every unit it was sold has come back
+    async fn mark_order_returned(&self, rma: &ReturnAuthorization) {
+        match self.is_fully_returned(rma.order_id).await {
+            Ok(true) => {
+                if let Err(e) = self
+                    .order_service
+                    .update_order_status(
+                        rma.order_id,
+                        OrderStatus::Returned,
+                        Some(format!("All items returned under {}", rma.rma_number)),
+                        None,
+                    )
+                    .await
+                {
+                    warn!("Failed to mark order {} returned: {}", rma.order_id, e);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => warn!(
+                "Failed to check whether order {} is fully returned: {}",
+                rma.order_id, e
+            ),
+        }
+    }
+
+    /// Cancels a return that has not been received yet.
+    pub async fn cancel_return(
+        &self,
+        id: Uuid,
+        reason: Option<String>,
+    ) -> Result<ReturnAuthorization> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        if rma.status != ReturnStatus::Authorized {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Return {} is {} and can no longer be cancelled",
+                rma.rma_number,
+                rma.status.as_str()
+            )));
+        }
+
+        self.repository.cancel_with_transaction(&mut tx, id).await?;
+        self.enqueue_event(
+            &mut tx,
+            rma.id,
+            EventType::ReturnCancelled,
+            "return.cancelled",
+            ReturnCancelledEvent {
+                return_id: rma.id,
+                rma_number: rma.rma_number.clone(),
+                order_id: rma.order_id,
+                reason: reason.clone(),
+            },
+        )
+        .await?;
+
+        let rma = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Return", id.to_string()))?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = match &reason {
+            Some(reason) => format!("Return {} cancelled: {}", rma.rma_number, reason),
+            None => format!("Return {} cancelled", rma.rma_number),
+        };
+        Ok(self
+            .notify("return.cancelled", ActivitySeverity::Warning, message, rma)
+            .await)
+    }
+}
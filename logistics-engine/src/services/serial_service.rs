@@ -0,0 +1,418 @@
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::db::repository::{InventoryRepository, SerialRepository};
+use crate::errors::{LogisticsError, Result};
+use crate::models::{
+    activity::{ActivitySeverity, NewActivity},
+    entities::order::OrderStatus,
+    inventory::{InventoryItem, ANONYMOUS_ACTOR},
+    serial::{
+        is_serial_tracked, normalize_serial_numbers, AllocateSerialsDto, ReceiveSerialsDto,
+        SerialHistory, SerialNumber, SerialStatus,
+    },
+};
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::services::{ActivityService, InventoryService};
+
+pub struct SerialService {
+    repository: Arc<SerialRepository>,
+    inventory_repository: Arc<InventoryRepository>,
+    inventory_service: Arc<InventoryService>,
+    activity_service: Arc<ActivityService>,
+    pool: Pool<Postgres>,
+}
+
+impl SerialService {
+    pub fn new(
+        repository: Arc<SerialRepository>,
+        inventory_repository: Arc<InventoryRepository>,
+        inventory_service: Arc<InventoryService>,
+        activity_service: Arc<ActivityService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            inventory_repository,
+            inventory_service,
+            activity_service,
+            pool,
+        }
+    }
+
+    async fn get_tracked_item(&self, item_id: Uuid) -> Result<InventoryItem> {
+        let item = self.inventory_service.get_item_by_id(item_id).await?;
+        if !is_serial_tracked(item.attributes.as_ref()) {
+            return Err(LogisticsError::ValidationError(format!(
+                "Item {} is not serial-tracked",
+                item.sku
+            )));
+        }
+
+        Ok(item)
+    }
+
+    // Pushes a committed serial change to the live feed and the activity log
+    async fn notify<T: serde::Serialize>(
+        &self,
+        topic: LiveTopic,
+        entity_type: &str,
+        event_type: &str,
+        entity_id: Uuid,
+        message: String,
+        metadata: serde_json::Value,
+        payload: &T,
+    ) {
+        live_feed::publish(topic, event_type, Some(entity_id.to_string()), payload);
+
+        let activity = NewActivity::new(
+            entity_type,
+            entity_id,
+            event_type,
+            ActivitySeverity::Info,
+            message,
+        )
+        .with_metadata(metadata);
+        self.activity_service.record(activity).await;
+    }
+
+    fn serial_numbers(serials: &[SerialNumber]) -> serde_json::Value {
+        serde_json::json!({
+            "serial_numbers": serials
+                .iter()
+                .map(|serial| serial.serial_number.as_str())
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Receives one unit of a serial-tracked item per serial number, posting
+    /// them to the ledger like any other receipt.
+    pub async fn receive_serials(
+        &self,
+        item_id: Uuid,
+        dto: ReceiveSerialsDto,
+    ) -> Result<Vec<SerialNumber>> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+        let serial_numbers = normalize_serial_numbers(&dto.serial_numbers)
+            .map_err(LogisticsError::ValidationError)?;
+
+        let item = self.get_tracked_item(item_id).await?;
+        let warehouse_id = dto.warehouse_id.unwrap_or(item.warehouse_id);
+        if self
+            .inventory_repository
+            .find_warehouse_by_id(warehouse_id)
+            .await?
+            .is_none()
+        {
+            return Err(LogisticsError::NotFound(
+                "Warehouse",
+                warehouse_id.to_string(),
+            ));
+        }
+
+        let reference = dto
+            .reference
+            .clone()
+            .unwrap_or_else(|| format!("serials:{}", item.sku));
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let serials = match self
+            .repository
+            .receive_with_transaction(&mut tx, item_id, warehouse_id, &serial_numbers, &reference)
+            .await?
+        {
+            Ok(serials) => serials,
+            Err(existing) => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::Conflict(format!(
+                    "{} already has serial number(s) {}",
+                    item.sku,
+                    existing.join(", ")
+                )));
+            }
+        };
+
+        self.inventory_repository
+            .receive_stock_with_transaction(
+                &mut tx,
+                item_id,
+                warehouse_id,
+                serials.len() as i32,
+                &reference,
+                dto.received_by.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+            )
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        if let Err(e) = self
+            .inventory_service
+            .allocate_backorders(item_id, warehouse_id)
+            .await
+        {
+            warn!("Failed to allocate backorders for item {}: {}", item_id, e);
+        }
+
+        let message = format!(
+            "Received {} serialized unit(s) of {}",
+            serials.len(),
+            item.name
+        );
+        self.notify(
+            LiveTopic::Inventory,
+            "inventory",
+            "inventory.serials_received",
+            item_id,
+            message,
+            Self::serial_numbers(&serials),
+            &serials,
+        )
+        .await;
+
+        Ok(serials)
+    }
+
+    pub async fn get_item_serials(
+        &self,
+        item_id: Uuid,
+        status: Option<SerialStatus>,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<SerialNumber>> {
+        self.inventory_service.get_item_by_id(item_id).await?;
+
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.repository
+            .find_by_item(item_id, status, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Where the units with the serial number have been, from receipt to the
+    /// order, shipment and return they went out and came back on.
+    pub async fn get_serial_history(&self, serial_number: &str) -> Result<Vec<SerialHistory>> {
+        let history = self.repository.find_history(serial_number.trim()).await?;
+        if history.is_empty() {
+            return Err(LogisticsError::NotFound(
+                "Serial number",
+                serial_number.to_string(),
+            ));
+        }
+
+        Ok(history)
+    }
+
+    /// Allocates in-stock units to an order line as they are picked. A line
+    /// takes at most as many serial numbers as it has units, and only units in
+    /// a warehouse the order's reservations of the item hold stock in (the
+    /// item's home warehouse when it has none).
+    pub async fn allocate_serials(
+        &self,
+        order_id: Uuid,
+        order_item_id: Uuid,
+        dto: AllocateSerialsDto,
+    ) -> Result<Vec<SerialNumber>> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+        let serial_numbers = normalize_serial_numbers(&dto.serial_numbers)
+            .map_err(LogisticsError::ValidationError)?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let (status, product_id, quantity) = match self
+            .repository
+            .lock_order_item_with_transaction(&mut tx, order_id, order_item_id)
+            .await?
+        {
+            Some(line) => line,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::NotFound(
+                    "Order item",
+                    order_item_id.to_string(),
+                ));
+            }
+        };
+
+        if !matches!(
+            status,
+            OrderStatus::Pending
+                | OrderStatus::Processing
+                | OrderStatus::OutOfStock
+                | OrderStatus::PartiallyShipped
+        ) {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Serial numbers cannot be allocated to order {} while it is {}",
+                order_id,
+                status.to_string()
+            )));
+        }
+
+        let item = match self.get_tracked_item(product_id).await {
+            Ok(item) => item,
+            Err(e) => {
+                tx.rollback().await.ok();
+                return Err(e);
+            }
+        };
+
+        let serials = self
+            .repository
+            .lock_by_numbers_with_transaction(&mut tx, product_id, &serial_numbers)
+            .await?;
+        if let Some(missing) = serial_numbers.iter().find(|number| {
+            !serials
+                .iter()
+                .any(|serial| &serial.serial_number == *number)
+        }) {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::NotFound(
+                "Serial number",
+                format!("{} ({})", missing, item.sku),
+            ));
+        }
+        if let Some(unavailable) = serials
+            .iter()
+            .find(|serial| serial.status != SerialStatus::InStock)
+        {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::Conflict(format!(
+                "Serial number {} is {} and cannot be allocated",
+                unavailable.serial_number,
+                unavailable.status.as_str()
+            )));
+        }
+
+        let mut warehouses = self
+            .repository
+            .find_order_warehouses_with_transaction(&mut tx, order_id, product_id)
+            .await?;
+        if warehouses.is_empty() {
+            warehouses.push(item.warehouse_id);
+        }
+        if let Some(misplaced) = serials.iter().find(|serial| {
+            !serial
+                .warehouse_id
+                .map_or(false, |warehouse_id| warehouses.contains(&warehouse_id))
+        }) {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Serial number {} is not in a warehouse order {} ships {} from",
+                misplaced.serial_number, order_id, item.sku
+            )));
+        }
+
+        let assigned = self
+            .repository
+            .count_for_order_item_with_transaction(&mut tx, order_item_id)
+            .await?;
+        if assigned + serials.len() as i64 > quantity as i64 {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Order item {} has {} unit(s) of {} and {} already have serial numbers",
+                order_item_id, quantity, item.sku, assigned
+            )));
+        }
+
+        let ids = serials.iter().map(|serial| serial.id).collect::<Vec<_>>();
+        self.repository
+            .allocate_with_transaction(&mut tx, &ids, order_item_id, &format!("order:{}", order_id))
+            .await?;
+        let serials = self
+            .repository
+            .lock_by_numbers_with_transaction(&mut tx, product_id, &serial_numbers)
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = format!(
+            "Allocated {} serialized unit(s) of {} to order {}",
+            serials.len(),
+            item.sku,
+            order_id
+        );
+        self.notify(
+            LiveTopic::Orders,
+            "order",
+            "order.serials_allocated",
+            order_id,
+            message,
+            Self::serial_numbers(&serials),
+            &serials,
+        )
+        .await;
+
+        Ok(serials)
+    }
+
+    /// Puts the units allocated to an order line back in stock, e.g. when the
+    /// wrong ones were picked. Returns how many were released.
+    pub async fn release_serials(&self, order_id: Uuid, order_item_id: Uuid) -> Result<u64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        if self
+            .repository
+            .lock_order_item_with_transaction(&mut tx, order_id, order_item_id)
+            .await?
+            .is_none()
+        {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::NotFound(
+                "Order item",
+                order_item_id.to_string(),
+            ));
+        }
+
+        let released = self
+            .repository
+            .release_for_order_with_transaction(
+                &mut tx,
+                order_id,
+                Some(order_item_id),
+                &format!("order:{}", order_id),
+            )
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        if released > 0 {
+            let message = format!(
+                "Released {} serialized unit(s) from order item {}",
+                released, order_item_id
+            );
+            let metadata = serde_json::json!({
+                "order_item_id": order_item_id,
+                "released": released,
+            });
+            self.notify(
+                LiveTopic::Orders,
+                "order",
+                "order.serials_released",
+                order_id,
+                message,
+                metadata.clone(),
+                &metadata,
+            )
+            .await;
+        }
+
+        Ok(released)
+    }
+}
@@ -1,18 +1,33 @@
+use sqlx::{Pool, Postgres};
 use std::sync::Arc;
+use tracing::warn;
 use uuid::Uuid;
 
 use crate::{
-    db::repository::shipping_repository::ShippingRepository,
+    db::repository::{shipping_repository::ShippingRepository, SerialRepository},
     errors::{LogisticsError, Result},
     models::{
+        activity::{ActivitySeverity, NewActivity},
         dto::shipping::{
-            CreateShippingInfoDto, ShippingInfoDto as ShippingDto, UpdateShippingInfoDto,
+            CreateShippingInfoDto, ShipmentDto, ShipmentItemAllocationDto,
+            ShippingInfoDto as ShippingDto, UpdateShipmentItemsDto, UpdateShippingInfoDto,
         },
         entities::shipping_info::ShippingInfo,
         shipping::ShippingStatus,
     },
+    realtime::live_feed::{self, LiveTopic},
+    services::{ActivityService, OrderService},
 };
 
+fn status_severity(status: ShippingStatus) -> ActivitySeverity {
+    match status {
+        ShippingStatus::Delivered => ActivitySeverity::Success,
+        ShippingStatus::Failed => ActivitySeverity::Error,
+        ShippingStatus::Returned | ShippingStatus::Cancelled => ActivitySeverity::Warning,
+        _ => ActivitySeverity::Info,
+    }
+}
+
 fn convert_to_dto(shipping: ShippingInfo) -> ShippingDto {
     ShippingDto {
         id: shipping.id,
@@ -39,11 +54,150 @@ fn convert_to_dto(shipping: ShippingInfo) -> ShippingDto {
 
 pub struct ShippingService {
     repository: Arc<ShippingRepository>,
+    serial_repository: Arc<SerialRepository>,
+    activity_service: Arc<ActivityService>,
+    order_service: Arc<OrderService>,
+    pool: Pool<Postgres>,
 }
 
 impl ShippingService {
-    pub fn new(repository: Arc<ShippingRepository>) -> Self {
-        Self { repository }
+    pub fn new(
+        repository: Arc<ShippingRepository>,
+        serial_repository: Arc<SerialRepository>,
+        activity_service: Arc<ActivityService>,
+        order_service: Arc<OrderService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            serial_repository,
+            activity_service,
+            order_service,
+            pool,
+        }
+    }
+
+    // Pushes a shipment change to the live feed and the activity log and hands
+    // the shipment back
+    async fn notify(
+        &self,
+        event_type: &str,
+        severity: ActivitySeverity,
+        description: &str,
+        shipment: ShippingDto,
+    ) -> ShippingDto {
+        live_feed::publish(
+            LiveTopic::Shipments,
+            event_type,
+            Some(shipment.id.to_string()),
+            &shipment,
+        );
+
+        let activity = NewActivity::new(
+            "shipment",
+            shipment.id,
+            event_type,
+            severity,
+            format!(
+                "Shipment {} for order {} {}",
+                shipment.id, shipment.order_id, description
+            ),
+        )
+        .with_metadata(serde_json::json!({
+            "order_id": shipment.order_id,
+            "status": shipment.status,
+            "carrier": shipment.carrier,
+            "tracking_number": shipment.tracking_number,
+        }));
+        self.activity_service.record(activity).await;
+
+        shipment
+    }
+
+    async fn with_items(&self, shipment: ShippingDto) -> Result<ShipmentDto> {
+        let items = self.repository.find_items(&[shipment.id]).await?;
+
+        Ok(ShipmentDto {
+            shipping_info: shipment,
+            items,
+        })
+    }
+
+    // The order's status follows its shipments. The shipment change stands
+    // even if the order cannot be updated.
+    async fn sync_order_status(&self, order_id: Uuid) {
+        if let Err(e) = self.order_service.sync_fulfillment_status(order_id).await {
+            warn!(
+                "Failed to update order {} from its shipments: {}",
+                order_id, e
+            );
+        }
+    }
+
+    /// Checks the requested items against what the order has left to ship,
+    /// given as order item and remaining units. Nothing requested means all
+    /// of it.
+    fn allocate(
+        order_id: Uuid,
+        requested: &[ShipmentItemAllocationDto],
+        unallocated: &[(Uuid, i32)],
+    ) -> Result<Vec<(Uuid, i32)>> {
+        if requested.is_empty() {
+            let items = unallocated
+                .iter()
+                .copied()
+                .filter(|(_, remaining)| *remaining > 0)
+                .collect::<Vec<_>>();
+
+            if items.is_empty() {
+                return Err(LogisticsError::BadRequest(format!(
+                    "Every item of order {} is already in a shipment",
+                    order_id
+                )));
+            }
+            return Ok(items);
+        }
+
+        let mut items: Vec<(Uuid, i32)> = Vec::new();
+        for item in requested {
+            if item.quantity <= 0 {
+                return Err(LogisticsError::ValidationError(
+                    "Shipment item quantity must be positive".to_string(),
+                ));
+            }
+
+            match items
+                .iter_mut()
+                .find(|(order_item_id, _)| *order_item_id == item.order_item_id)
+            {
+                Some((_, quantity)) => *quantity += item.quantity,
+                None => items.push((item.order_item_id, item.quantity)),
+            }
+        }
+
+        for (order_item_id, quantity) in &items {
+            let remaining = unallocated
+                .iter()
+                .find(|(id, _)| id == order_item_id)
+                .map(|(_, remaining)| *remaining)
+                .ok_or_else(|| {
+                    LogisticsError::BadRequest(format!(
+                        "Order item {} does not belong to order {}",
+                        order_item_id, order_id
+                    ))
+                })?;
+
+            if *quantity > remaining {
+                return Err(LogisticsError::BadRequest(format!(
+                    "Only {} unit(s) of order item {} are left to ship, {} requested",
+                    remaining.max(0),
+                    order_item_id,
+                    quantity
+                )));
+            }
+        }
+
+        Ok(items)
     }
 
     pub async fn get_all_shipments(&self, limit: i64, offset: i64) -> Result<Vec<ShippingDto>> {
@@ -56,14 +210,17 @@ impl ShippingService {
         Ok(shipments.into_iter().map(convert_to_dto).collect())
     }
 
-    pub async fn get_shipment_by_id(&self, id: &Uuid) -> Result<Option<ShippingDto>> {
+    pub async fn get_shipment_by_id(&self, id: &Uuid) -> Result<Option<ShipmentDto>> {
         let shipping = self
             .repository
             .find_by_id(*id)
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(shipping.map(convert_to_dto))
+        match shipping {
+            Some(shipping) => Ok(Some(self.with_items(convert_to_dto(shipping)).await?)),
+            None => Ok(None),
+        }
     }
 
     pub async fn get_shipment_by_tracking(
@@ -79,14 +236,163 @@ impl ShippingService {
         Ok(shipping.map(convert_to_dto))
     }
 
-    pub async fn create_shipment(&self, dto: CreateShippingInfoDto) -> Result<ShippingDto> {
+    /// Creates another shipment for an order, carrying the requested items
+    /// or everything the order's other shipments do not carry.
+    pub async fn create_shipment(&self, mut dto: CreateShippingInfoDto) -> Result<ShipmentDto> {
+        let order_id = dto.order_id;
+        self.order_service.get_order_by_id(order_id).await?;
+
+        let requested = std::mem::take(&mut dto.items);
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let unallocated = self
+            .repository
+            .find_unallocated_with_transaction(&mut tx, order_id, None)
+            .await?;
+        let items = Self::allocate(order_id, &requested, &unallocated)?;
+
         let shipping = self
             .repository
-            .create(dto)
+            .create_with_transaction(&mut tx, dto)
             .await
             .map_err(LogisticsError::from)?;
+        self.repository
+            .replace_items_with_transaction(&mut tx, shipping.id, &items)
+            .await?;
 
-        Ok(convert_to_dto(shipping))
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let shipment = self
+            .notify(
+                "shipment.created",
+                ActivitySeverity::Info,
+                "created",
+                convert_to_dto(shipping),
+            )
+            .await;
+        self.with_items(shipment).await
+    }
+
+    /// Changes the items a shipment carries. Only shipments that have not
+    /// left the warehouse can change, so items can be moved between them.
+    pub async fn update_shipment_items(
+        &self,
+        id: &Uuid,
+        dto: UpdateShipmentItemsDto,
+    ) -> Result<ShipmentDto> {
+        if dto.items.is_empty() {
+            return Err(LogisticsError::ValidationError(
+                "A shipment needs at least one item".to_string(),
+            ));
+        }
+
+        let order_id = self
+            .repository
+            .find_by_id(*id)
+            .await?
+            .map(|shipping| shipping.order_id)
+            .ok_or_else(|| LogisticsError::NotFound("Shipment", id.to_string()))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        // Locks the order before the shipment, like creating a shipment does
+        let unallocated = self
+            .repository
+            .find_unallocated_with_transaction(&mut tx, order_id, Some(*id))
+            .await?;
+
+        let status = match self.repository.lock_with_transaction(&mut tx, *id).await? {
+            Some((_, status)) => status,
+            None => return Err(LogisticsError::NotFound("Shipment", id.to_string())),
+        };
+        if !matches!(status, ShippingStatus::Pending | ShippingStatus::Processing) {
+            return Err(LogisticsError::BadRequest(format!(
+                "Shipment {} is already {} and its items can no longer change",
+                id,
+                status.as_str()
+            )));
+        }
+
+        let items = Self::allocate(order_id, &dto.items, &unallocated)?;
+        self.repository
+            .replace_items_with_transaction(&mut tx, *id, &items)
+            .await?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let shipping = self
+            .repository
+            .find_by_id(*id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Shipment", id.to_string()))?;
+        let shipment = self
+            .notify(
+                "shipment.items_updated",
+                ActivitySeverity::Info,
+                &format!("now carries {} order item(s)", items.len()),
+                convert_to_dto(shipping),
+            )
+            .await;
+        self.with_items(shipment).await
+    }
+
+    // Records which serialized units leave on the shipment once it ships,
+    // taking them from the units allocated to each line. A shipment cannot
+    // leave while a serial-tracked line is short of allocated units.
+    async fn record_serials(&self, id: Uuid, status: ShippingStatus) -> Result<()> {
+        if !matches!(
+            status,
+            ShippingStatus::Shipped
+                | ShippingStatus::InTransit
+                | ShippingStatus::OutForDelivery
+                | ShippingStatus::Delivered
+        ) {
+            return Ok(());
+        }
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let lines = self
+            .serial_repository
+            .find_shipment_lines_with_transaction(&mut tx, id)
+            .await?;
+        let reference = format!("shipment:{}", id);
+        for line in lines {
+            let missing = line.quantity - line.recorded;
+            if missing <= 0 {
+                continue;
+            }
+            if line.allocated < missing {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::BadRequest(format!(
+                    "Shipment {} needs {} more serial number(s) of {} allocated to order item {}",
+                    id,
+                    missing - line.allocated,
+                    line.sku,
+                    line.order_item_id
+                )));
+            }
+
+            self.serial_repository
+                .ship_with_transaction(&mut tx, id, line.order_item_id, missing, &reference)
+                .await?;
+        }
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+        Ok(())
     }
 
     pub async fn update_shipment_status(
@@ -94,13 +400,30 @@ impl ShippingService {
         id: &Uuid,
         status: ShippingStatus,
     ) -> Result<Option<ShippingDto>> {
+        self.record_serials(*id, status).await?;
+
         let updated = self
             .repository
             .update_status(*id, status)
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(updated.map(convert_to_dto))
+        match updated {
+            Some(shipping) => {
+                let order_id = shipping.order_id;
+                let shipment = self
+                    .notify(
+                        "shipment.status_changed",
+                        status_severity(status),
+                        &format!("is now {}", status.as_str()),
+                        convert_to_dto(shipping),
+                    )
+                    .await;
+                self.sync_order_status(order_id).await;
+                Ok(Some(shipment))
+            }
+            None => Ok(None),
+        }
     }
 
     pub async fn update_shipment(
@@ -114,7 +437,18 @@ impl ShippingService {
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(updated.map(convert_to_dto))
+        match updated {
+            Some(shipping) => Ok(Some(
+                self.notify(
+                    "shipment.updated",
+                    ActivitySeverity::Info,
+                    "updated",
+                    convert_to_dto(shipping),
+                )
+                .await,
+            )),
+            None => Ok(None),
+        }
     }
 
     pub async fn mark_as_delivered(&self, id: &Uuid) -> Result<Option<ShippingDto>> {
@@ -136,12 +470,29 @@ impl ShippingService {
         id: &Uuid,
         status: ShippingStatus,
     ) -> Result<Option<ShippingDto>> {
+        self.record_serials(*id, status).await?;
+
         let updated = self
             .repository
             .update_status(*id, status)
             .await
             .map_err(LogisticsError::from)?;
 
-        Ok(updated.map(convert_to_dto))
+        match updated {
+            Some(shipping) => {
+                let order_id = shipping.order_id;
+                let shipment = self
+                    .notify(
+                        "shipment.status_changed",
+                        status_severity(status),
+                        &format!("is now {}", status.as_str()),
+                        convert_to_dto(shipping),
+                    )
+                    .await;
+                self.sync_order_status(order_id).await;
+                Ok(Some(shipment))
+            }
+            None => Ok(None),
+        }
     }
 }
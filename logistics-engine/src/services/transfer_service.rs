@@ -0,0 +1,564 @@
+use chrono::Utc;
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::db::repository::{
+    InventoryRepository, SerialRepository, TransferRepository, WarehouseRepository,
+};
+use crate::errors::{LogisticsError, Result};
+use crate::models::{
+    activity::{ActivitySeverity, NewActivity},
+    inventory::ANONYMOUS_ACTOR,
+    serial::normalize_serial_numbers,
+    transfer::{CreateTransferDto, ReceiveTransferDto, StockTransfer, TransferStatus},
+};
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::services::{ActivityService, InventoryService};
+
+pub struct TransferService {
+    repository: Arc<TransferRepository>,
+    inventory_repository: Arc<InventoryRepository>,
+    warehouse_repository: Arc<WarehouseRepository>,
+    serial_repository: Arc<SerialRepository>,
+    inventory_service: Arc<InventoryService>,
+    activity_service: Arc<ActivityService>,
+    pool: Pool<Postgres>,
+}
+
+impl TransferService {
+    pub fn new(
+        repository: Arc<TransferRepository>,
+        inventory_repository: Arc<InventoryRepository>,
+        warehouse_repository: Arc<WarehouseRepository>,
+        serial_repository: Arc<SerialRepository>,
+        inventory_service: Arc<InventoryService>,
+        activity_service: Arc<ActivityService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            inventory_repository,
+            warehouse_repository,
+            serial_repository,
+            inventory_service,
+            activity_service,
+            pool,
+        }
+    }
+
+    fn reference(transfer: &StockTransfer) -> String {
+        format!("transfer:{}", transfer.transfer_number)
+    }
+
+    // Pushes a committed transfer change to the live feed and the activity log
+    // and hands the transfer back
+    async fn notify(
+        &self,
+        event_type: &str,
+        severity: ActivitySeverity,
+        message: String,
+        transfer: StockTransfer,
+    ) -> StockTransfer {
+        live_feed::publish(
+            LiveTopic::Inventory,
+            event_type,
+            Some(transfer.id.to_string()),
+            &transfer,
+        );
+
+        let activity = NewActivity::new("transfer", transfer.id, event_type, severity, message)
+            .with_metadata(serde_json::json!({
+                "transfer_number": transfer.transfer_number,
+                "source_warehouse_id": transfer.source_warehouse_id,
+                "destination_warehouse_id": transfer.destination_warehouse_id,
+                "status": transfer.status,
+            }));
+        self.activity_service.record(activity).await;
+
+        transfer
+    }
+
+    pub async fn get_transfer(&self, id: Uuid) -> Result<StockTransfer> {
+        self.repository
+            .find_by_id(id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))
+    }
+
+    pub async fn get_transfers(
+        &self,
+        status: Option<TransferStatus>,
+        warehouse_id: Option<Uuid>,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<StockTransfer>> {
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.repository
+            .find_all(status, warehouse_id, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Requests a transfer between two active warehouses. No stock moves until
+    /// the transfer is dispatched.
+    pub async fn create_transfer(&self, dto: CreateTransferDto) -> Result<StockTransfer> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        if dto.source_warehouse_id == dto.destination_warehouse_id {
+            return Err(LogisticsError::ValidationError(
+                "A transfer needs different source and destination warehouses".to_string(),
+            ));
+        }
+
+        let warehouses = self
+            .warehouse_repository
+            .find_by_ids(&[dto.source_warehouse_id, dto.destination_warehouse_id])
+            .await?;
+        for warehouse_id in [dto.source_warehouse_id, dto.destination_warehouse_id] {
+            match warehouses
+                .iter()
+                .find(|warehouse| warehouse.id == warehouse_id)
+            {
+                Some(warehouse) if !warehouse.active => {
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Warehouse {} is inactive",
+                        warehouse.code
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    return Err(LogisticsError::NotFound(
+                        "Warehouse",
+                        warehouse_id.to_string(),
+                    ))
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut lines = Vec::with_capacity(dto.lines.len());
+        for line in &dto.lines {
+            if !seen.insert(line.item_id) {
+                return Err(LogisticsError::ValidationError(format!(
+                    "Item {} is listed more than once",
+                    line.item_id
+                )));
+            }
+
+            let item = self
+                .inventory_repository
+                .find_item_by_id(line.item_id)
+                .await?
+                .ok_or_else(|| {
+                    LogisticsError::NotFound("Inventory Item", line.item_id.to_string())
+                })?;
+            lines.push((item, line.quantity));
+        }
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let id = Uuid::new_v4();
+        let transfer_number = format!(
+            "TRF-{}-{}",
+            Utc::now().format("%Y%m%d"),
+            id.simple().to_string()[..8].to_uppercase()
+        );
+        self.repository
+            .create_with_transaction(
+                &mut tx,
+                id,
+                &transfer_number,
+                dto.source_warehouse_id,
+                dto.destination_warehouse_id,
+                dto.notes.as_deref(),
+                dto.requested_by.as_deref(),
+            )
+            .await?;
+
+        for (item, quantity) in &lines {
+            self.repository
+                .add_line_with_transaction(&mut tx, id, item.id, &item.sku, *quantity)
+                .await?;
+        }
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = format!(
+            "Transfer {} requested ({} line(s))",
+            transfer.transfer_number,
+            transfer.lines.len()
+        );
+        Ok(self
+            .notify(
+                "transfer.requested",
+                ActivitySeverity::Info,
+                message,
+                transfer,
+            )
+            .await)
+    }
+
+    /// Takes every line out of the source warehouse with a `transfer_out`
+    /// transaction, recording the lots the units came from. Serialized units go
+    /// with it, the longest held first. The units are in transit until the
+    /// transfer is received. Nothing moves unless the source has all of it
+    /// available.
+    pub async fn dispatch_transfer(
+        &self,
+        id: Uuid,
+        dispatched_by: Option<String>,
+    ) -> Result<StockTransfer> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        if transfer.status != TransferStatus::Requested {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Transfer {} is {} and cannot be dispatched",
+                transfer.transfer_number,
+                transfer.status.as_str()
+            )));
+        }
+
+        let reference = Self::reference(&transfer);
+        for line in &transfer.lines {
+            let dispatched = self
+                .inventory_repository
+                .dispatch_transfer_stock_with_transaction(
+                    &mut tx,
+                    line.item_id,
+                    transfer.source_warehouse_id,
+                    line.quantity,
+                    &reference,
+                    dispatched_by.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+                )
+                .await?;
+            let Some((_, lots)) = dispatched else {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Not enough {} available in the source warehouse to transfer {}",
+                    line.sku, line.quantity
+                )));
+            };
+
+            self.repository
+                .ship_line_with_transaction(&mut tx, line.id, line.quantity, &lots)
+                .await?;
+            self.serial_repository
+                .dispatch_for_transfer_with_transaction(
+                    &mut tx,
+                    line.item_id,
+                    transfer.source_warehouse_id,
+                    line.id,
+                    line.quantity,
+                    &reference,
+                )
+                .await?;
+        }
+
+        self.repository
+            .update_status_with_transaction(&mut tx, id, TransferStatus::InTransit)
+            .await?;
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let in_transit: i32 = transfer.lines.iter().map(|line| line.in_transit).sum();
+        let message = format!(
+            "Transfer {} dispatched; {} unit(s) in transit",
+            transfer.transfer_number, in_transit
+        );
+        Ok(self
+            .notify(
+                "transfer.dispatched",
+                ActivitySeverity::Info,
+                message,
+                transfer,
+            )
+            .await)
+    }
+
+    /// Puts what arrived into the destination warehouse with a `transfer_in`
+    /// transaction for each line, back into the lots it was dispatched from.
+    /// Serialized units that arrived are put in stock there. Lines counted
+    /// differently from what was shipped are recorded as discrepancies.
+    pub async fn receive_transfer(
+        &self,
+        id: Uuid,
+        dto: ReceiveTransferDto,
+    ) -> Result<StockTransfer> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        if transfer.status != TransferStatus::InTransit {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Transfer {} is {} and cannot be received",
+                transfer.transfer_number,
+                transfer.status.as_str()
+            )));
+        }
+
+        let mut counted = HashMap::new();
+        for count in &dto.lines {
+            if !transfer.lines.iter().any(|line| line.id == count.line_id) {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Line {} is not part of transfer {}",
+                    count.line_id, transfer.transfer_number
+                )));
+            }
+            if counted.insert(count.line_id, count).is_some() {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Line {} is listed more than once",
+                    count.line_id
+                )));
+            }
+        }
+
+        let reference = Self::reference(&transfer);
+        for line in &transfer.lines {
+            let shipped = line.shipped_quantity.unwrap_or(0);
+            let (received, reason) = match counted.get(&line.id) {
+                Some(count) => (count.quantity, count.reason.as_deref()),
+                None => (shipped, None),
+            };
+
+            let in_transit = self
+                .serial_repository
+                .lock_in_transit_with_transaction(&mut tx, line.id)
+                .await?;
+            let listed = counted
+                .get(&line.id)
+                .and_then(|count| count.serial_numbers.as_deref());
+            let arrived = match listed {
+                Some(serial_numbers) => {
+                    let serial_numbers = match normalize_serial_numbers(serial_numbers) {
+                        Ok(serial_numbers) => serial_numbers,
+                        Err(e) => {
+                            tx.rollback().await.ok();
+                            return Err(LogisticsError::ValidationError(e));
+                        }
+                    };
+                    if serial_numbers.len() > received as usize {
+                        tx.rollback().await.ok();
+                        return Err(LogisticsError::ValidationError(format!(
+                            "Line {} lists {} serial number(s) for {} unit(s) received",
+                            line.id,
+                            serial_numbers.len(),
+                            received
+                        )));
+                    }
+
+                    let mut arrived = Vec::with_capacity(serial_numbers.len());
+                    for serial_number in &serial_numbers {
+                        match in_transit
+                            .iter()
+                            .find(|serial| &serial.serial_number == serial_number)
+                        {
+                            Some(serial) => arrived.push(serial.id),
+                            None => {
+                                tx.rollback().await.ok();
+                                return Err(LogisticsError::ValidationError(format!(
+                                    "Serial number {} is not in transit on line {}",
+                                    serial_number, line.id
+                                )));
+                            }
+                        }
+                    }
+                    arrived
+                }
+                None if in_transit.len() <= received.max(0) as usize => {
+                    in_transit.iter().map(|serial| serial.id).collect()
+                }
+                None => {
+                    tx.rollback().await.ok();
+                    return Err(LogisticsError::ValidationError(format!(
+                        "Line {} of {} came up short; list the serial_numbers of the units \
+                         that arrived",
+                        line.id, line.sku
+                    )));
+                }
+            };
+            if !arrived.is_empty() {
+                self.serial_repository
+                    .receive_transfer_with_transaction(
+                        &mut tx,
+                        &arrived,
+                        transfer.destination_warehouse_id,
+                        &reference,
+                    )
+                    .await?;
+            }
+
+            self.inventory_repository
+                .receive_transfer_stock_with_transaction(
+                    &mut tx,
+                    line.item_id,
+                    transfer.destination_warehouse_id,
+                    shipped,
+                    received,
+                    &line.received_lots(received),
+                    &reference,
+                    dto.received_by.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+                )
+                .await?;
+            self.repository
+                .receive_line_with_transaction(&mut tx, line.id, received, reason)
+                .await?;
+        }
+
+        self.repository
+            .update_status_with_transaction(&mut tx, id, TransferStatus::Received)
+            .await?;
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let discrepancies = transfer
+            .lines
+            .iter()
+            .filter(|line| line.discrepancy() != 0)
+            .map(|line| format!("{} {:+}", line.sku, line.discrepancy()))
+            .collect::<Vec<_>>();
+        let (severity, message) = if discrepancies.is_empty() {
+            (
+                ActivitySeverity::Success,
+                format!("Transfer {} received in full", transfer.transfer_number),
+            )
+        } else {
+            (
+                ActivitySeverity::Warning,
+                format!(
+                    "Transfer {} received with discrepancies: {}",
+                    transfer.transfer_number,
+                    discrepancies.join(", ")
+                ),
+            )
+        };
+        let received = transfer
+            .lines
+            .iter()
+            .filter(|line| line.received_quantity.unwrap_or(0) > 0)
+            .map(|line| line.item_id)
+            .collect::<Vec<_>>();
+        let destination_warehouse_id = transfer.destination_warehouse_id;
+        let transfer = self
+            .notify("transfer.received", severity, message, transfer)
+            .await;
+
+        // Received units may be owed to backorders
+        for item_id in received {
+            if let Err(e) = self
+                .inventory_service
+                .allocate_backorders(item_id, destination_warehouse_id)
+                .await
+            {
+                warn!("Failed to allocate backorders for item {}: {}", item_id, e);
+            }
+        }
+
+        Ok(transfer)
+    }
+
+    /// Cancels a transfer that has not been dispatched yet.
+    pub async fn cancel_transfer(&self, id: Uuid, reason: Option<String>) -> Result<StockTransfer> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        if transfer.status != TransferStatus::Requested {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Transfer {} is {} and can no longer be cancelled",
+                transfer.transfer_number,
+                transfer.status.as_str()
+            )));
+        }
+
+        self.repository
+            .update_status_with_transaction(&mut tx, id, TransferStatus::Cancelled)
+            .await?;
+
+        let transfer = self
+            .repository
+            .find_by_id_with_transaction(&mut tx, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Stock Transfer", id.to_string()))?;
+
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let message = match &reason {
+            Some(reason) => format!(
+                "Transfer {} cancelled: {}",
+                transfer.transfer_number, reason
+            ),
+            None => format!("Transfer {} cancelled", transfer.transfer_number),
+        };
+        Ok(self
+            .notify(
+                "transfer.cancelled",
+                ActivitySeverity::Warning,
+                message,
+                transfer,
+            )
+            .await)
+    }
+}
@@ -3,7 +3,9 @@ use uuid::Uuid;
 
 use crate::db::repository::WarehouseRepository;
 use crate::errors::{LogisticsError, Result};
-use crate::models::warehouse::{CreateWarehouseDto, UpdateWarehouseDto, Warehouse};
+use crate::models::warehouse::{
+    CreateWarehouseDto, NearbyWarehouse, UpdateWarehouseDto, Warehouse,
+};
 
 pub struct WarehouseService {
     repository: Arc<WarehouseRepository>,
@@ -56,7 +58,19 @@ impl WarehouseService {
         }
     }
 
-    pub async fn create_warehouse(&self, dto: CreateWarehouseDto) -> Result<Warehouse> {
+    pub async fn create_warehouse(&self, mut dto: CreateWarehouseDto) -> Result<Warehouse> {
+        Self::validate_coordinates(dto.latitude, dto.longitude)?;
+        if dto.latitude.is_none() {
+            let location = self
+                .repository
+                .find_postal_code_location(&dto.postal_code, Some(&dto.country))
+                .await?;
+            if let Some(location) = location {
+                dto.latitude = Some(location.latitude);
+                dto.longitude = Some(location.longitude);
+            }
+        }
+
         let existing = self.repository.find_by_code(&dto.code).await?;
         if existing.is_some() {
             return Err(LogisticsError::ValidationError(format!(
@@ -71,7 +85,30 @@ impl WarehouseService {
             .map_err(LogisticsError::from)
     }
 
-    pub async fn update_warehouse(&self, id: Uuid, dto: UpdateWarehouseDto) -> Result<Warehouse> {
+    pub async fn update_warehouse(
+        &self,
+        id: Uuid,
+        mut dto: UpdateWarehouseDto,
+    ) -> Result<Warehouse> {
+        Self::validate_coordinates(dto.latitude, dto.longitude)?;
+
+        // A warehouse that moves is placed at its new postal code unless
+        // coordinates come with the move
+        let moved = dto.postal_code.is_some() || dto.country.is_some();
+        if moved && dto.latitude.is_none() && dto.longitude.is_none() {
+            let current = self.get_warehouse_by_id(id).await?;
+            let postal_code = dto.postal_code.as_deref().unwrap_or(&current.postal_code);
+            let country = dto.country.as_deref().unwrap_or(&current.country);
+            let location = self
+                .repository
+                .find_postal_code_location(postal_code, Some(country))
+                .await?;
+            if let Some(location) = location {
+                dto.latitude = Some(location.latitude);
+                dto.longitude = Some(location.longitude);
+            }
+        }
+
         if let Some(ref code) = dto.code {
             let existing = self.repository.find_by_code(code).await?;
             if let Some(existing) = existing {
@@ -101,6 +138,58 @@ impl WarehouseService {
         }
     }
 
+    /// Active warehouses closest to a point, nearest first.
+    pub async fn get_nearest_warehouses(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        limit: u32,
+    ) -> Result<Vec<NearbyWarehouse>> {
+        Self::validate_coordinates(Some(latitude), Some(longitude))?;
+
+        self.repository
+            .find_nearest(latitude, longitude, limit as i64)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Active warehouses closest to a postal code from the geocoding table.
+    pub async fn get_nearest_to_postal_code(
+        &self,
+        postal_code: &str,
+        country: Option<&str>,
+        limit: u32,
+    ) -> Result<Vec<NearbyWarehouse>> {
+        let location = self
+            .repository
+            .find_postal_code_location(postal_code, country)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Postal code", postal_code.to_string()))?;
+
+        self.get_nearest_warehouses(location.latitude, location.longitude, limit)
+            .await
+    }
+
+    fn validate_coordinates(latitude: Option<f64>, longitude: Option<f64>) -> Result<()> {
+        if latitude.is_some() != longitude.is_some() {
+            return Err(LogisticsError::ValidationError(
+                "Latitude and longitude must be given together".to_string(),
+            ));
+        }
+        if latitude.is_some_and(|latitude| !(-90.0..=90.0).contains(&latitude)) {
+            return Err(LogisticsError::ValidationError(
+                "Latitude must be between -90 and 90".to_string(),
+            ));
+        }
+        if longitude.is_some_and(|longitude| !(-180.0..=180.0).contains(&longitude)) {
+            return Err(LogisticsError::ValidationError(
+                "Longitude must be between -180 and 180".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
     pub async fn delete_warehouse(&self, id: Uuid) -> Result<bool> {
         let warehouse = self.repository.find_by_id(id).await?;
         if warehouse.is_none() {
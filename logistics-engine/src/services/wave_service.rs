@@ -0,0 +1,822 @@
+use chrono::NaiveTime;
+use sqlx::{Pool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::db::repository::{InventoryRepository, WarehouseRepository, WaveRepository};
+use crate::errors::{LogisticsError, Result};
+use crate::models::{
+    activity::{ActivitySeverity, NewActivity},
+    inventory::ANONYMOUS_ACTOR,
+    wave::{
+        CarrierCutoff, ConfirmPickDto, CreateWaveDto, NewPickLine, PickLineStatus, PickWave,
+        PickWaveLine, PickWaveSummary, SetCarrierCutoffDto, WaveActionDto, WaveDemand,
+        WaveOrderStatus, WaveStatus,
+    },
+};
+use crate::realtime::live_feed::{self, LiveTopic};
+use crate::services::{ActivityService, OrderService};
+
+pub struct WaveService {
+    repository: Arc<WaveRepository>,
+    warehouse_repository: Arc<WarehouseRepository>,
+    inventory_repository: Arc<InventoryRepository>,
+    order_service: Arc<OrderService>,
+    activity_service: Arc<ActivityService>,
+    pool: Pool<Postgres>,
+}
+
+impl WaveService {
+    pub fn new(
+        repository: Arc<WaveRepository>,
+        warehouse_repository: Arc<WarehouseRepository>,
+        inventory_repository: Arc<InventoryRepository>,
+        order_service: Arc<OrderService>,
+        activity_service: Arc<ActivityService>,
+        pool: Pool<Postgres>,
+    ) -> Self {
+        Self {
+            repository,
+            warehouse_repository,
+            inventory_repository,
+            order_service,
+            activity_service,
+            pool,
+        }
+    }
+
+    // Pushes a committed wave change to the live feed and the activity log and
+    // hands the wave back
+    async fn notify(
+        &self,
+        event_type: &str,
+        severity: ActivitySeverity,
+        message: String,
+        actor: Option<String>,
+        wave: PickWave,
+    ) -> PickWave {
+        live_feed::publish(
+            LiveTopic::Orders,
+            event_type,
+            Some(wave.id.to_string()),
+            &wave,
+        );
+
+        let activity = NewActivity::new("wave", wave.id, event_type, severity, message)
+            .with_actor(actor)
+            .with_metadata(serde_json::json!({
+                "warehouse_id": wave.warehouse_id,
+                "status": wave.status,
+                "orders": wave.orders.len(),
+                "lines": wave.lines.len(),
+            }));
+        self.activity_service.record(activity).await;
+
+        wave
+    }
+
+    // Orders released by a wave move along with shipments that went out while
+    // they waited. The wave change stands even if an order cannot be updated.
+    async fn sync_orders(&self, order_ids: Vec<Uuid>) {
+        for order_id in order_ids {
+            if let Err(e) = self.order_service.sync_fulfillment_status(order_id).await {
+                warn!(
+                    "Failed to update order {} from its shipments: {}",
+                    order_id, e
+                );
+            }
+        }
+    }
+
+    pub async fn get_wave(&self, warehouse_id: Uuid, id: Uuid) -> Result<PickWave> {
+        self.repository
+            .find_by_id(warehouse_id, id)
+            .await?
+            .ok_or_else(|| LogisticsError::NotFound("Pick Wave", id.to_string()))
+    }
+
+    async fn ensure_warehouse(&self, warehouse_id: Uuid) -> Result<()> {
+        if self
+            .warehouse_repository
+            .find_by_id(warehouse_id)
+            .await?
+            .is_none()
+        {
+            return Err(LogisticsError::NotFound(
+                "Warehouse",
+                warehouse_id.to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_waves(
+        &self,
+        warehouse_id: Uuid,
+        status: Option<WaveStatus>,
+        page: u32,
+        limit: u32,
+    ) -> Result<Vec<PickWaveSummary>> {
+        self.ensure_warehouse(warehouse_id).await?;
+
+        let limit = limit as i64;
+        let offset = (page.max(1) - 1) as i64 * limit;
+
+        self.repository
+            .find_by_warehouse(warehouse_id, status, limit, offset)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    pub async fn get_carrier_cutoffs(&self, warehouse_id: Uuid) -> Result<Vec<CarrierCutoff>> {
+        self.ensure_warehouse(warehouse_id).await?;
+
+        self.repository
+            .find_carrier_cutoffs(warehouse_id)
+            .await
+            .map_err(LogisticsError::from)
+    }
+
+    /// Sets the time of day, in UTC, the carrier collects from the warehouse.
+    pub async fn set_carrier_cutoff(
+        &self,
+        warehouse_id: Uuid,
+        carrier: &str,
+        dto: SetCarrierCutoffDto,
+    ) -> Result<CarrierCutoff> {
+        let carrier = carrier.trim();
+        if carrier.is_empty() || carrier.len() > 100 {
+            return Err(LogisticsError::ValidationError(
+                "Carrier must be 1-100 characters".to_string(),
+            ));
+        }
+        let cutoff_time =
+            NaiveTime::parse_from_str(dto.cutoff_time.trim(), "%H:%M").map_err(|_| {
+                LogisticsError::ValidationError(format!(
+                    "Cutoff time must be HH:MM, got '{}'",
+                    dto.cutoff_time
+                ))
+            })?;
+        self.ensure_warehouse(warehouse_id).await?;
+
+        let cutoff = self
+            .repository
+            .upsert_carrier_cutoff(
+                warehouse_id,
+                carrier,
+                &cutoff_time.format("%H:%M:%S").to_string(),
+                dto.user_id.as_deref(),
+            )
+            .await?;
+
+        let activity = NewActivity::new(
+            "warehouse",
+            warehouse_id,
+            "wave.cutoff_set",
+            ActivitySeverity::Info,
+            format!(
+                "{} collects from warehouse {} at {} UTC",
+                cutoff.carrier, warehouse_id, cutoff.cutoff_time
+            ),
+        )
+        .with_actor(dto.user_id)
+        .with_metadata(serde_json::json!({
+            "carrier": cutoff.carrier,
+            "cutoff_time": cutoff.cutoff_time,
+        }));
+        self.activity_service.record(activity).await;
+
+        Ok(cutoff)
+    }
+
+    pub async fn delete_carrier_cutoff(&self, warehouse_id: Uuid, carrier: &str) -> Result<()> {
+        if !self
+            .repository
+            .delete_carrier_cutoff(warehouse_id, carrier.trim())
+            .await?
+        {
+            return Err(LogisticsError::NotFound(
+                "Carrier Cutoff",
+                format!("{} in warehouse {}", carrier, warehouse_id),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Batches the warehouse's `Processing` orders that are not in a wave yet
+    /// into a new one and builds its pick list: the units to take from each
+    /// bin, summed over the orders, in walking order. Orders are taken by
+    /// their carrier's next collection, then highest priority first. Without
+    /// a `cutoff_at` the wave only takes the orders of one collection, the
+    /// next one; with it, every order collected by then.
+    pub async fn create_wave(
+        &self,
+        warehouse_id: Uuid,
+        mut dto: CreateWaveDto,
+    ) -> Result<PickWave> {
+        dto.carrier = dto
+            .carrier
+            .map(|carrier| carrier.trim().to_string())
+            .filter(|carrier| !carrier.is_empty());
+        dto.zone = dto
+            .zone
+            .map(|zone| zone.trim().to_uppercase())
+            .filter(|zone| !zone.is_empty());
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        if !self
+            .repository
+            .lock_warehouse_with_transaction(&mut tx, warehouse_id)
+            .await?
+        {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::NotFound(
+                "Warehouse",
+                warehouse_id.to_string(),
+            ));
+        }
+
+        let candidates = self
+            .repository
+            .find_candidates_with_transaction(
+                &mut tx,
+                warehouse_id,
+                dto.carrier.as_deref(),
+                dto.min_priority,
+                dto.cutoff_at,
+            )
+            .await?;
+        let cutoffs = candidates
+            .iter()
+            .map(|candidate| (candidate.order_id, candidate.cutoff_at))
+            .collect::<HashMap<_, _>>();
+        let candidate_ids = candidates
+            .into_iter()
+            .map(|candidate| candidate.order_id)
+            .collect::<Vec<_>>();
+        let mut demand: HashMap<Uuid, Vec<WaveDemand>> = HashMap::new();
+        for line in self
+            .repository
+            .find_demand_with_transaction(&mut tx, warehouse_id, &candidate_ids)
+            .await?
+        {
+            demand.entry(line.order_id).or_default().push(line);
+        }
+
+        // A zone wave only takes orders picked entirely within the zone
+        let eligible = candidate_ids
+            .into_iter()
+            .filter(|order_id| {
+                demand.get(order_id).map_or(false, |lines| match &dto.zone {
+                    Some(zone) => lines
+                        .iter()
+                        .all(|line| line.zone.as_deref() == Some(zone.as_str())),
+                    None => true,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Candidates come collected soonest first, so the first one's cutoff
+        // is the next collection
+        let cutoff_of = |order_id: &Uuid| cutoffs.get(order_id).copied().flatten();
+        let next_cutoff = eligible.first().and_then(cutoff_of);
+        let order_ids = eligible
+            .into_iter()
+            .filter(|order_id| dto.cutoff_at.is_some() || cutoff_of(order_id) == next_cutoff)
+            .take(dto.max_orders.unwrap_or(usize::MAX))
+            .collect::<Vec<_>>();
+        if order_ids.is_empty() {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "No Processing orders in warehouse {} are waiting for a wave",
+                warehouse_id
+            )));
+        }
+
+        // The wave goes out with the first collection any of its orders needs
+        dto.cutoff_at = order_ids
+            .iter()
+            .filter_map(cutoff_of)
+            .min()
+            .or(dto.cutoff_at);
+
+        let lines = Self::plan_lines(&order_ids, &mut demand);
+        let id = self
+            .repository
+            .create_with_transaction(&mut tx, warehouse_id, &dto, &order_ids, &lines)
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let wave = self.get_wave(warehouse_id, id).await?;
+        let message = format!(
+            "Wave {} created with {} order(s) and {} pick line(s)",
+            wave.id,
+            wave.orders.len(),
+            wave.lines.len()
+        );
+        Ok(self
+            .notify(
+                "wave.created",
+                ActivitySeverity::Info,
+                message,
+                dto.user_id,
+                wave,
+            )
+            .await)
+    }
+
+    // Sums the orders' units by bin and item, bins in walking order and units
+    // without a bin last
+    fn plan_lines(
+        order_ids: &[Uuid],
+        demand: &mut HashMap<Uuid, Vec<WaveDemand>>,
+    ) -> Vec<NewPickLine> {
+        let mut stops: HashMap<(Option<Uuid>, Uuid), (Option<i64>, String, NewPickLine)> =
+            HashMap::new();
+        for order_id in order_ids {
+            for line in demand.remove(order_id).unwrap_or_default() {
+                let stop = stops.entry((line.bin_id, line.item_id)).or_insert_with(|| {
+                    (
+                        line.walk_order,
+                        line.sku.clone(),
+                        NewPickLine {
+                            line_number: 0,
+                            bin_id: line.bin_id,
+                            code: line.code.clone(),
+                            item_id: line.item_id,
+                            quantity: 0,
+                            orders: Vec::new(),
+                        },
+                    )
+                });
+                stop.2.quantity += line.quantity;
+                stop.2.orders.push((*order_id, line.quantity));
+            }
+        }
+
+        let mut stops = stops.into_values().collect::<Vec<_>>();
+        stops.sort_by(|(a_order, a_sku, _), (b_order, b_sku, _)| {
+            a_order
+                .is_none()
+                .cmp(&b_order.is_none())
+                .then_with(|| a_order.cmp(b_order))
+                .then_with(|| a_sku.cmp(b_sku))
+        });
+
+        stops
+            .into_iter()
+            .enumerate()
+            .map(|(index, (_, _, mut line))| {
+                line.line_number = index as i32 + 1;
+                line
+            })
+            .collect()
+    }
+
+    /// Confirms what was picked for a line of the wave's pick list. Picking
+    /// fewer units than the line asks for records a short pick: the missing
+    /// units are handed back from the orders last in the wave and go back on
+    /// hand outside any bin, until they are found or counted off. Confirming
+    /// the line again once they turn up takes them for the orders again.
+    pub async fn confirm_pick(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+        line_id: Uuid,
+        dto: ConfirmPickDto,
+    ) -> Result<PickWave> {
+        dto.validate()
+            .map_err(|e| LogisticsError::ValidationError(e.to_string()))?;
+
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let wave = match self
+            .repository
+            .find_by_id_with_transaction(&mut tx, warehouse_id, id)
+            .await?
+        {
+            Some(wave) => wave,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::NotFound("Pick Wave", id.to_string()));
+            }
+        };
+        if let Err(e) = Self::ensure_picking(&wave) {
+            tx.rollback().await.ok();
+            return Err(e);
+        }
+
+        let line = match wave.lines.iter().find(|line| line.id == line_id) {
+            Some(line) => line,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Line {} is not part of wave {}",
+                    line_id, wave.id
+                )));
+            }
+        };
+        if line.status == PickLineStatus::Picked {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Line {} of wave {} is already picked",
+                line.line_number, wave.id
+            )));
+        }
+
+        let picked_quantity = dto.picked_quantity.unwrap_or(line.quantity);
+        if picked_quantity > line.quantity {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(format!(
+                "Line {} asks for {} unit(s) of {}, fewer than the {} confirmed",
+                line.line_number, line.quantity, line.sku, picked_quantity
+            )));
+        }
+
+        if let Err(e) = self
+            .settle_shortfall(
+                &mut tx,
+                &wave,
+                line,
+                picked_quantity,
+                dto.user_id.as_deref().unwrap_or(ANONYMOUS_ACTOR),
+            )
+            .await
+        {
+            tx.rollback().await.ok();
+            return Err(e);
+        }
+
+        self.repository
+            .confirm_line_with_transaction(
+                &mut tx,
+                line.id,
+                picked_quantity,
+                dto.user_id.as_deref(),
+            )
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        let location = line.code.as_deref().unwrap_or("unbinned stock");
+        let (event_type, severity, message) = if picked_quantity < line.quantity {
+            (
+                "wave.short_picked",
+                ActivitySeverity::Warning,
+                format!(
+                    "Short pick on wave {}: {} of {} {} found at {}",
+                    wave.id, picked_quantity, line.quantity, line.sku, location
+                ),
+            )
+        } else {
+            (
+                "wave.picked",
+                ActivitySeverity::Info,
+                format!(
+                    "Picked {} {} from {} for wave {}",
+                    picked_quantity, line.sku, location, wave.id
+                ),
+            )
+        };
+
+        let wave = self.get_wave(warehouse_id, id).await?;
+        Ok(self
+            .notify(event_type, severity, message, dto.user_id, wave)
+            .await)
+    }
+
+    // Moves the units a confirmation leaves short, or finds again, between the
+    // line's orders and stock. Orders whose stock was taken without a
+    // reservation keep their units; there is nothing to hand them back from.
+    async fn settle_shortfall(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        wave: &PickWave,
+        line: &PickWaveLine,
+        picked_quantity: i32,
+        user_id: &str,
+    ) -> Result<()> {
+        let before = line.shortfall(line.picked_quantity.unwrap_or(line.quantity));
+        let after = line.shortfall(picked_quantity);
+
+        for ((order_id, was_short), (_, now_short)) in before.into_iter().zip(after) {
+            let handed_back = now_short - was_short;
+            if handed_back == 0 {
+                continue;
+            }
+
+            let adjusted = self
+                .inventory_repository
+                .adjust_committed_reservation_with_transaction(
+                    tx,
+                    order_id,
+                    line.item_id,
+                    wave.warehouse_id,
+                    handed_back,
+                    &format!("order:{}", order_id),
+                    user_id,
+                )
+                .await?;
+            if adjusted {
+                continue;
+            }
+
+            if handed_back < 0 {
+                return Err(LogisticsError::Conflict(format!(
+                    "Not enough {} available in warehouse {} to take {} found unit(s) for order {}",
+                    line.sku, wave.warehouse_id, -handed_back, order_id
+                )));
+            }
+            warn!(
+                "Order {} holds no committed reservation of {} to hand {} short unit(s) back from",
+                order_id, line.sku, handed_back
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Confirms an order of the wave is packed once all its units are picked.
+    /// The order can then move past `Processing`; the wave completes when its
+    /// last order is packed.
+    pub async fn pack_order(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+        order_id: Uuid,
+        dto: WaveActionDto,
+    ) -> Result<PickWave> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let mut wave = match self
+            .repository
+            .find_by_id_with_transaction(&mut tx, warehouse_id, id)
+            .await?
+        {
+            Some(wave) => wave,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::NotFound("Pick Wave", id.to_string()));
+            }
+        };
+        if let Err(e) = Self::ensure_picking(&wave) {
+            tx.rollback().await.ok();
+            return Err(e);
+        }
+
+        let order = match wave
+            .orders
+            .iter_mut()
+            .find(|order| order.order_id == order_id)
+        {
+            Some(order) => order,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::ValidationError(format!(
+                    "Order {} is not part of wave {}",
+                    order_id, wave.id
+                )));
+            }
+        };
+        let refusal = match order.status {
+            WaveOrderStatus::Picked => None,
+            WaveOrderStatus::Picking => Some(format!(
+                "Order {} still has units to pick in wave {}",
+                order_id, id
+            )),
+            WaveOrderStatus::Short => Some(format!(
+                "Order {} is short {} unit(s); confirm them on the pick list before packing",
+                order_id, order.short
+            )),
+            WaveOrderStatus::Packed => Some(format!("Order {} is already packed", order_id)),
+            WaveOrderStatus::Withdrawn => Some(format!(
+                "Order {} is {} and no longer needs packing",
+                order_id,
+                order.order_status.to_string()
+            )),
+        };
+        if let Some(refusal) = refusal {
+            tx.rollback().await.ok();
+            return Err(LogisticsError::ValidationError(refusal));
+        }
+
+        self.repository
+            .pack_order_with_transaction(&mut tx, id, order_id, dto.user_id.as_deref())
+            .await?;
+        order.status = WaveOrderStatus::Packed;
+        let completed = wave.is_done();
+        if completed {
+            self.repository
+                .update_status_with_transaction(&mut tx, id, WaveStatus::Completed)
+                .await?;
+        }
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        self.sync_orders(vec![order_id]).await;
+
+        let wave = self.get_wave(warehouse_id, id).await?;
+        let (event_type, severity, message) = if completed {
+            (
+                "wave.completed",
+                ActivitySeverity::Success,
+                format!("Wave {} is picked and packed", wave.id),
+            )
+        } else {
+            (
+                "wave.order_packed",
+                ActivitySeverity::Info,
+                format!("Order {} packed in wave {}", order_id, wave.id),
+            )
+        };
+        Ok(self
+            .notify(event_type, severity, message, dto.user_id, wave)
+            .await)
+    }
+
+    /// Cancels a wave still being picked. Its orders that are not packed yet
+    /// can join another wave.
+    pub async fn cancel_wave(
+        &self,
+        warehouse_id: Uuid,
+        id: Uuid,
+        user_id: Option<String>,
+    ) -> Result<PickWave> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(LogisticsError::DatabaseError)?;
+
+        let wave = match self
+            .repository
+            .find_by_id_with_transaction(&mut tx, warehouse_id, id)
+            .await?
+        {
+            Some(wave) => wave,
+            None => {
+                tx.rollback().await.ok();
+                return Err(LogisticsError::NotFound("Pick Wave", id.to_string()));
+            }
+        };
+        if let Err(e) = Self::ensure_picking(&wave) {
+            tx.rollback().await.ok();
+            return Err(e);
+        }
+
+        self.repository
+            .update_status_with_transaction(&mut tx, id, WaveStatus::Cancelled)
+            .await?;
+        tx.commit().await.map_err(LogisticsError::DatabaseError)?;
+
+        self.sync_orders(
+            wave.orders
+                .iter()
+                .filter(|order| order.status != WaveOrderStatus::Packed)
+                .map(|order| order.order_id)
+                .collect(),
+        )
+        .await;
+
+        let wave = self.get_wave(warehouse_id, id).await?;
+        let message = format!("Wave {} cancelled", wave.id);
+        Ok(self
+            .notify(
+                "wave.cancelled",
+                ActivitySeverity::Warning,
+                message,
+                user_id,
+                wave,
+            )
+            .await)
+    }
+
+    fn ensure_picking(wave: &PickWave) -> Result<()> {
+        if wave.status != WaveStatus::Picking {
+            return Err(LogisticsError::ValidationError(format!(
+                "Wave {} is {}",
+                wave.id,
+                wave.status.as_str()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taken(
+        order_id: Uuid,
+        item_id: Uuid,
+        sku: &str,
+        bin: Option<(Uuid, i64)>,
+        quantity: i32,
+    ) -> WaveDemand {
+        WaveDemand {
+            order_id,
+            warehouse_id: Uuid::nil(),
+            item_id,
+            sku: sku.to_string(),
+            name: sku.to_string(),
+            bin_id: bin.map(|(bin_id, _)| bin_id),
+            code: bin.map(|(_, walk_order)| format!("A-01-{:02}", walk_order)),
+            zone: bin.map(|_| "A".to_string()),
+            walk_order: bin.map(|(_, walk_order)| walk_order),
+            quantity,
+        }
+    }
+
+    #[test]
+    fn test_plan_lines_sums_orders_per_bin_and_item_in_walking_order() {
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let (widget, gadget) = (Uuid::new_v4(), Uuid::new_v4());
+        let (near, far) = ((Uuid::new_v4(), 1), (Uuid::new_v4(), 2));
+
+        let mut demand = HashMap::from([
+            (
+                first,
+                vec![
+                    taken(first, widget, "WIDGET", Some(far), 2),
+                    taken(first, gadget, "GADGET", None, 1),
+                ],
+            ),
+            (
+                second,
+                vec![
+                    taken(second, widget, "WIDGET", Some(far), 3),
+                    taken(second, gadget, "GADGET", Some(near), 4),
+                ],
+            ),
+        ]);
+
+        let lines = WaveService::plan_lines(&[first, second], &mut demand)
+            .into_iter()
+            .map(|line| {
+                (
+                    line.line_number,
+                    line.bin_id,
+                    line.item_id,
+                    line.quantity,
+                    line.orders,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            vec![
+                (1, Some(near.0), gadget, 4, vec![(second, 4)]),
+                (2, Some(far.0), widget, 5, vec![(first, 2), (second, 3)]),
+                // Units outside any bin are picked last
+                (3, None, gadget, 1, vec![(first, 1)]),
+            ]
+        );
+        assert!(demand.is_empty());
+    }
+
+    #[test]
+    fn test_plan_lines_orders_the_same_stop_by_sku_and_skips_orders_without_demand() {
+        let (first, idle) = (Uuid::new_v4(), Uuid::new_v4());
+        let (widget, gadget) = (Uuid::new_v4(), Uuid::new_v4());
+        let bin = (Uuid::new_v4(), 7);
+
+        let mut demand = HashMap::from([(
+            first,
+            vec![
+                taken(first, widget, "WIDGET", Some(bin), 1),
+                taken(first, gadget, "GADGET", Some(bin), 2),
+            ],
+        )]);
+
+        let lines = WaveService::plan_lines(&[first, idle], &mut demand)
+            .into_iter()
+            .map(|line| (line.line_number, line.item_id, line.orders))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            vec![(1, gadget, vec![(first, 2)]), (2, widget, vec![(first, 1)])]
+        );
+    }
+}